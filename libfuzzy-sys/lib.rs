@@ -24,6 +24,8 @@ extern crate libc;
 use libc::c_char;
 use libc::c_int;
 use libc::c_uchar;
+use libc::c_uint;
+use libc::size_t;
 
 // From fuzzy.h:
 
@@ -33,7 +35,46 @@ const SPAMSUM_LENGTH: usize = 64;
 /// The longest possible length for a fuzzy hash signature.
 pub const FUZZY_MAX_RESULT: usize = 2 * SPAMSUM_LENGTH + 20;
 
+/// `fuzzy_digest()` flag indicating to eliminate sequences of more than three
+/// identical characters.
+pub const FUZZY_FLAG_ELIMSEQ: c_uint = 0x1;
+
+/// `fuzzy_digest()` flag indicating not to truncate the second part to
+/// `SPAMSUM_LENGTH/2` characters.
+pub const FUZZY_FLAG_NOTRUNC: c_uint = 0x2;
+
+/// An opaque state for computing a fuzzy hash incrementally.
+#[repr(C)]
+#[allow(non_camel_case_types)]
+pub struct fuzzy_state {
+    _private: [u8; 0],
+}
+
 extern "C" {
+    /// Constructs a new state for computing a fuzzy hash incrementally.
+    // struct fuzzy_state *fuzzy_new(void);
+    pub fn fuzzy_new() -> *mut fuzzy_state;
+
+    /// Creates a copy of a state.
+    // struct fuzzy_state *fuzzy_clone(const struct fuzzy_state *state);
+    pub fn fuzzy_clone(state: *const fuzzy_state) -> *mut fuzzy_state;
+
+    /// Sets a fixed length of the input.
+    // int fuzzy_set_total_input_length(struct fuzzy_state *state, uint_least64_t total_fixed_length);
+    pub fn fuzzy_set_total_input_length(state: *mut fuzzy_state, total_fixed_length: u64) -> c_int;
+
+    /// Feeds the data contained in the given buffer to the state.
+    // int fuzzy_update(struct fuzzy_state *state, const unsigned char *buffer, size_t buffer_size);
+    pub fn fuzzy_update(state: *mut fuzzy_state, buffer: *const c_uchar, buffer_size: size_t) -> c_int;
+
+    /// Obtains the fuzzy hash from the state.
+    // int fuzzy_digest(const struct fuzzy_state *state, char *result, unsigned int flags);
+    pub fn fuzzy_digest(state: *const fuzzy_state, result: *mut c_char, flags: c_uint) -> c_int;
+
+    /// Disposes a state.
+    // void fuzzy_free(struct fuzzy_state *state);
+    pub fn fuzzy_free(state: *mut fuzzy_state);
+
     /// Computes the match score between two fuzzy hashes.
     // int fuzzy_compare(const char *sig1, const char *sig2);
     pub fn fuzzy_compare(sig1: *const c_char, sig2: *const c_char) -> c_int;
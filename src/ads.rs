@@ -0,0 +1,142 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Fuzzy hashing of NTFS alternate data streams.
+//!
+//! This module is available only on Windows. Apart from its main (unnamed)
+//! data stream, a file on NTFS can have named alternate data streams, which
+//! are not visible when the file is accessed via its path and are thus
+//! commonly used to hide payloads. This module enumerates and hashes them.
+
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::os::windows::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use windows_sys::Win32::Foundation::ERROR_HANDLE_EOF;
+use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+use windows_sys::Win32::Storage::FileSystem::FindClose;
+use windows_sys::Win32::Storage::FileSystem::FindFirstStreamW;
+use windows_sys::Win32::Storage::FileSystem::FindNextStreamW;
+use windows_sys::Win32::Storage::FileSystem::FindStreamInfoStandard;
+use windows_sys::Win32::Storage::FileSystem::WIN32_FIND_STREAM_DATA;
+
+use super::hasher::hash_and_copy;
+use super::Result;
+
+/// A hash of an alternate data stream of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamHash {
+    /// Path to the stream in the form `path:stream`, which can be used to open
+    /// the stream.
+    pub path: PathBuf,
+    /// Name of the stream.
+    pub stream_name: String,
+    /// Size of the stream (in bytes).
+    pub size: u64,
+    /// Fuzzy hash of the stream.
+    pub hash: String,
+}
+
+/// Computes fuzzy hashes of all alternate data streams of the given file.
+///
+/// The main (unnamed) data stream of the file is not included, so the result
+/// is empty for files without alternate data streams (and for files on file
+/// systems other than NTFS). Streams are returned in the order in which they
+/// are enumerated by the file system.
+///
+/// When the streams cannot be enumerated or read, it returns an error.
+pub fn hash_alternate_data_streams<P: AsRef<Path>>(file_path: P) -> Result<Vec<StreamHash>> {
+    let file_path = file_path.as_ref();
+    let mut hashes = Vec::new();
+    for (stream_name, size) in alternate_data_streams(file_path)? {
+        let mut path = file_path.as_os_str().to_os_string();
+        path.push(":");
+        path.push(&stream_name);
+        let path = PathBuf::from(path);
+        let hash = hash_and_copy(File::open(&path)?, io::sink())?;
+        hashes.push(StreamHash {
+            path,
+            stream_name,
+            size,
+            hash,
+        });
+    }
+    Ok(hashes)
+}
+
+/// Returns names and sizes of alternate data streams of the given file.
+fn alternate_data_streams(file_path: &Path) -> io::Result<Vec<(String, u64)>> {
+    let wide_path: Vec<u16> = OsStr::new(file_path).encode_wide().chain(Some(0)).collect();
+    let mut data: WIN32_FIND_STREAM_DATA = unsafe { mem::zeroed() };
+    let handle = unsafe {
+        FindFirstStreamW(
+            wide_path.as_ptr(),
+            FindStreamInfoStandard,
+            &mut data as *mut _ as *mut _,
+            0,
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        let e = io::Error::last_os_error();
+        return if e.raw_os_error() == Some(ERROR_HANDLE_EOF as i32) {
+            Ok(Vec::new())
+        } else {
+            Err(e)
+        };
+    }
+
+    let mut streams = Vec::new();
+    loop {
+        if let Some(name) = stream_name(&data) {
+            streams.push((name, data.StreamSize as u64));
+        }
+        if unsafe { FindNextStreamW(handle, &mut data as *mut _ as *mut _) } == 0 {
+            let e = io::Error::last_os_error();
+            unsafe { FindClose(handle) };
+            return if e.raw_os_error() == Some(ERROR_HANDLE_EOF as i32) {
+                Ok(streams)
+            } else {
+                Err(e)
+            };
+        }
+    }
+}
+
+/// Returns the name of the stream from the given data, or `None` for the main
+/// data stream.
+///
+/// Stream names are of the form `:name:$DATA` (`::$DATA` for the main data
+/// stream).
+fn stream_name(data: &WIN32_FIND_STREAM_DATA) -> Option<String> {
+    let len = data
+        .cStreamName
+        .iter()
+        .position(|&c| c == 0)
+        .unwrap_or(data.cStreamName.len());
+    let name = String::from_utf16_lossy(&data.cStreamName[..len]);
+    let name = name.strip_prefix(':').unwrap_or(&name);
+    let name = name.strip_suffix(":$DATA").unwrap_or(name);
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
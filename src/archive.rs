@@ -0,0 +1,170 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Fuzzy hashing of members of (possibly nested) archives.
+//!
+//! This module is available only when the `archive` feature is enabled.
+//! Supported are ZIP archives, including formats based on them (e.g. JAR or
+//! APK). Members that are archives themselves are recursively hashed as well,
+//! which produces a tree of member hashes. To guard against archive bombs, the
+//! processing is restricted by [limits](struct.ArchiveLimits.html).
+
+use std::fs::File;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Seek;
+use std::path::Path;
+
+use zip::ZipArchive;
+
+use super::hash;
+use super::Error;
+use super::Result;
+
+/// Magic bytes of ZIP archives.
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Limits guarding against archive bombs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveLimits {
+    /// The maximal nesting depth of archives. Members of archives nested
+    /// deeper are hashed only as a whole. Default: 5.
+    pub max_depth: usize,
+    /// The maximal total size of all decompressed members (in bytes), on all
+    /// nesting levels. Default: 1 GiB.
+    pub max_total_size: u64,
+    /// The maximal total number of members, on all nesting levels. Default:
+    /// 10000.
+    pub max_file_count: usize,
+}
+
+impl Default for ArchiveLimits {
+    fn default() -> Self {
+        ArchiveLimits {
+            max_depth: 5,
+            max_total_size: 1024 * 1024 * 1024,
+            max_file_count: 10_000,
+        }
+    }
+}
+
+/// Fuzzy hash of a single member of an archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveMember {
+    /// Path to the member in its archive.
+    pub path: String,
+    /// Length of the decompressed member (in bytes).
+    pub size: u64,
+    /// Fuzzy hash of the decompressed member.
+    pub hash: String,
+    /// Members of the member when it is an archive itself (empty otherwise).
+    pub members: Vec<ArchiveMember>,
+}
+
+/// Computes fuzzy hashes of all members of the given archive, recursively.
+///
+/// Directories are skipped. When a limit on the total size or number of
+/// members is exceeded,
+/// [`Error::LimitExceeded`](../enum.Error.html#variant.LimitExceeded) is
+/// returned. When the archive cannot be parsed,
+/// [`Error::ArchiveParsingFailed`](../enum.Error.html#variant.ArchiveParsingFailed)
+/// is returned.
+pub fn hash_archive<R: Read + Seek>(
+    reader: R,
+    limits: &ArchiveLimits,
+) -> Result<Vec<ArchiveMember>> {
+    let mut budget = Budget {
+        limits,
+        total_size: 0,
+        file_count: 0,
+    };
+    hash_members(reader, 0, &mut budget)
+}
+
+/// Computes fuzzy hashes of all members of the given archive file,
+/// recursively.
+///
+/// See [`hash_archive()`](fn.hash_archive.html) for more details.
+pub fn hash_archive_file<P: AsRef<Path>>(
+    file_path: P,
+    limits: &ArchiveLimits,
+) -> Result<Vec<ArchiveMember>> {
+    hash_archive(File::open(file_path)?, limits)
+}
+
+/// The remaining resources that can be spent on processing an archive.
+struct Budget<'a> {
+    limits: &'a ArchiveLimits,
+    total_size: u64,
+    file_count: usize,
+}
+
+fn hash_members<R: Read + Seek>(
+    reader: R,
+    depth: usize,
+    budget: &mut Budget,
+) -> Result<Vec<ArchiveMember>> {
+    let mut archive = ZipArchive::new(reader).map_err(parsing_failed)?;
+    let mut members = Vec::new();
+    for i in 0..archive.len() {
+        let member = archive.by_index(i).map_err(parsing_failed)?;
+        if member.is_dir() {
+            continue;
+        }
+
+        budget.file_count += 1;
+        if budget.file_count > budget.limits.max_file_count {
+            return Err(Error::LimitExceeded {
+                name: "max_file_count".to_string(),
+                limit: budget.limits.max_file_count as u64,
+            });
+        }
+
+        // Do not trust the size declared in the archive; read at most one
+        // byte over the limit to detect that the limit has been exceeded.
+        let path = member.name().to_string();
+        let remaining_size = budget.limits.max_total_size - budget.total_size;
+        let mut data = Vec::new();
+        member.take(remaining_size + 1).read_to_end(&mut data)?;
+        budget.total_size += data.len() as u64;
+        if budget.total_size > budget.limits.max_total_size {
+            return Err(Error::LimitExceeded {
+                name: "max_total_size".to_string(),
+                limit: budget.limits.max_total_size,
+            });
+        }
+
+        let nested_members = if data.starts_with(ZIP_MAGIC) && depth < budget.limits.max_depth {
+            hash_members(Cursor::new(&data), depth + 1, budget)?
+        } else {
+            Vec::new()
+        };
+        members.push(ArchiveMember {
+            path,
+            size: data.len() as u64,
+            hash: hash(&data)?,
+            members: nested_members,
+        });
+    }
+    Ok(members)
+}
+
+fn parsing_failed(e: zip::result::ZipError) -> Error {
+    Error::ArchiveParsingFailed {
+        reason: e.to_string(),
+    }
+}
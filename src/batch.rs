@@ -0,0 +1,257 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Scoring of one fuzzy hash against many fuzzy hashes.
+
+use std::str;
+
+use super::progress::COMPARISONS_PER_REPORT;
+use super::score::invalid_hash;
+use super::score::BatchScorer;
+use super::score::ParsedHash;
+use super::score::SPAMSUM_LENGTH;
+use super::Phase;
+use super::Progress;
+use super::Result;
+
+/// A batch of fuzzy hashes laid out for fast scoring of a query against all of
+/// them.
+///
+/// Instead of storing the hashes as separate strings, the batch stores their
+/// block sizes, parts, and lengths of parts in separate contiguous arrays
+/// (every part occupies a fixed-size slot). The query is processed only once,
+/// and the scoring then walks the arrays sequentially.
+///
+/// The scores are the same as the scores computed by
+/// [`compare()`](fn.compare.html). The edit distances of the parts of the
+/// query against the parts of several hashes are computed at once (via AVX2
+/// when it is available).
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::HashBatch;
+///
+/// let mut batch = HashBatch::new();
+/// batch.push("3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C").unwrap();
+/// batch.push("3:u+N:u+N").unwrap();
+///
+/// let scores = batch.scores("3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx").unwrap();
+/// assert_eq!(scores, vec![22, 0]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HashBatch {
+    block_sizes: Vec<u64>,
+    parts: [Vec<u8>; 2],
+    part_lens: [Vec<u8>; 2],
+}
+
+impl HashBatch {
+    /// Creates an empty batch.
+    pub fn new() -> Self {
+        HashBatch::default()
+    }
+
+    /// Returns the number of hashes in the batch.
+    pub fn len(&self) -> usize {
+        self.block_sizes.len()
+    }
+
+    /// Checks whether the batch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.block_sizes.is_empty()
+    }
+
+    /// Adds a hash into the batch and returns its index.
+    ///
+    /// When the hash is invalid, it returns an error.
+    pub fn push(&mut self, hash: &str) -> Result<usize> {
+        let hash = ParsedHash::parse(hash)?;
+        for (i, part) in hash.parts.iter().enumerate() {
+            let start = self.parts[i].len();
+            self.parts[i].resize(start + SPAMSUM_LENGTH, 0);
+            self.parts[i][start..start + part.len()].copy_from_slice(part);
+            self.part_lens[i].push(part.len() as u8);
+        }
+        self.block_sizes.push(hash.block_size);
+        Ok(self.len() - 1)
+    }
+
+    /// Computes the scores between the query and all hashes in the batch (in
+    /// the order in which they were added).
+    ///
+    /// When the query is invalid, it returns an error.
+    pub fn scores(&self, query: &str) -> Result<Vec<u8>> {
+        self.scores_with_progress(query, |_| {})
+    }
+
+    /// Computes the scores between the query and all hashes in the batch,
+    /// reporting the progress (in the [`Comparing`](enum.Phase.html) phase)
+    /// to the given callback.
+    ///
+    /// See [`scores()`](#method.scores) for more details.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(batch_size = self.len()))
+    )]
+    pub fn scores_with_progress<F>(&self, query: &str, mut progress: F) -> Result<Vec<u8>>
+    where
+        F: FnMut(&Progress),
+    {
+        let query = ParsedHash::parse(query)?;
+        let mut scorer = BatchScorer::new(&query);
+        let mut scores = vec![0; self.len()];
+
+        let total = self.len() as u64;
+        let mut report = |done| {
+            progress(&Progress {
+                phase: Phase::Comparing,
+                done,
+                total: Some(total),
+                current_path: None,
+            })
+        };
+        for (i, &block_size) in self.block_sizes.iter().enumerate() {
+            if i > 0 && (i as u64).is_multiple_of(COMPARISONS_PER_REPORT) {
+                scorer.finish(&mut scores);
+                report(i as u64);
+            }
+            let part = |j: usize| {
+                let start = i * SPAMSUM_LENGTH;
+                &self.parts[j][start..start + self.part_lens[j][i] as usize]
+            };
+            scorer.push(i, block_size, [part(0), part(1)], &mut scores);
+        }
+        scorer.finish(&mut scores);
+        report(total);
+        Ok(scores)
+    }
+}
+
+/// The number of hashes that [`compare_batch()`](fn.compare_batch.html) and
+/// [`compare_many()`](fn.compare_many.html) parse at once.
+const PARSED_HASHES_PER_BLOCK: usize = 1024;
+
+/// Computes the match scores between the query and each of the given hashes.
+///
+/// The scores are the same as the scores computed by
+/// [`compare()`](fn.compare.html) for each of the hashes, but the scoring is
+/// done natively in Rust, and the edit distances of the parts of the query
+/// against the parts of several hashes are computed at once (via AVX2 when it
+/// is available). When the query is compared with the same hashes repeatedly,
+/// use [`HashBatch`](struct.HashBatch.html), which parses the hashes only
+/// once.
+///
+/// When the query or any of the hashes is invalid, it returns
+/// [`Error::InvalidHash`](enum.Error.html#variant.InvalidHash).
+///
+/// # Examples
+///
+/// ```
+/// let scores = ssdeep::compare_batch(
+///     "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx",
+///     &["3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C", "3:u+N:u+N"],
+/// )
+/// .unwrap();
+/// assert_eq!(scores, vec![22, 0]);
+/// ```
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(batch_size = hashes.len()))
+)]
+pub fn compare_batch<H: AsRef<str>>(query: &str, hashes: &[H]) -> Result<Vec<u8>> {
+    let query = ParsedHash::parse(query)?;
+    scores_in_blocks(&query, hashes.len(), |i| {
+        ParsedHash::parse(hashes[i].as_ref()).map(Some)
+    })
+}
+
+/// Computes the match scores between the probe and each of the given
+/// candidate hashes, with a separate result for every candidate.
+///
+/// The probe is parsed only once. Unlike
+/// [`compare_batch()`](fn.compare_batch.html), an invalid candidate does not
+/// stop the scoring of the other candidates: its result is
+/// [`Error::InvalidHash`](enum.Error.html#variant.InvalidHash) (also when it
+/// is not valid UTF-8). The scores are the same as the scores computed by
+/// [`compare()`](fn.compare.html).
+///
+/// When the probe is invalid, it returns
+/// [`Error::InvalidHash`](enum.Error.html#variant.InvalidHash).
+///
+/// # Examples
+///
+/// ```
+/// let results = ssdeep::compare_many(
+///     "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx",
+///     &["3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C", "XYZ"],
+/// )
+/// .unwrap();
+/// assert_eq!(results[0], Ok(22));
+/// assert!(results[1].is_err());
+/// ```
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(batch_size = candidates.len()))
+)]
+pub fn compare_many<C: AsRef<[u8]>>(probe: &str, candidates: &[C]) -> Result<Vec<Result<u8>>> {
+    let probe = ParsedHash::parse(probe)?;
+    let mut errors = Vec::new();
+    let scores = scores_in_blocks(&probe, candidates.len(), |i| {
+        let parsed = str::from_utf8(candidates[i].as_ref())
+            .map_err(|_| invalid_hash("invalid UTF-8"))
+            .and_then(ParsedHash::parse);
+        match parsed {
+            Ok(hash) => Ok(Some(hash)),
+            Err(e) => {
+                errors.push((i, e));
+                Ok(None)
+            }
+        }
+    })?;
+    let mut results: Vec<Result<u8>> = scores.into_iter().map(Ok).collect();
+    for (i, e) in errors {
+        results[i] = Err(e);
+    }
+    Ok(results)
+}
+
+/// Computes the scores between the query and the hashes returned by `parse`
+/// for their indexes (`None` for hashes that are not scored, whose score is
+/// zero), which are parsed in blocks.
+fn scores_in_blocks<F>(query: &ParsedHash, count: usize, mut parse: F) -> Result<Vec<u8>>
+where
+    F: FnMut(usize) -> Result<Option<ParsedHash>>,
+{
+    let mut scores = vec![0; count];
+    let mut parsed = Vec::with_capacity(PARSED_HASHES_PER_BLOCK.min(count));
+    for start in (0..count).step_by(PARSED_HASHES_PER_BLOCK) {
+        parsed.clear();
+        for i in start..count.min(start + PARSED_HASHES_PER_BLOCK) {
+            if let Some(hash) = parse(i)? {
+                parsed.push((i, hash));
+            }
+        }
+        let mut scorer = BatchScorer::new(query);
+        for (i, hash) in &parsed {
+            let parts = [&hash.parts[0][..], &hash.parts[1][..]];
+            scorer.push(*i, hash.block_size, parts, &mut scores);
+        }
+        scorer.finish(&mut scores);
+    }
+    Ok(scores)
+}
@@ -0,0 +1,25 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! A worker process for hashing untrusted data in a sandbox (see the
+//! `ssdeep::sandbox` module).
+
+extern crate ssdeep;
+
+fn main() {
+    std::process::exit(ssdeep::sandbox::run_worker());
+}
@@ -0,0 +1,167 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! A command-line tool built on the `ssdeep` crate.
+//!
+//! ```text
+//! ssdeep report [--markdown] [--title TITLE] [--threshold SCORE] FILE...
+//! ssdeep mutation [--trials COUNT] [--seed SEED] FILE
+//! ```
+//!
+//! The `report` subcommand hashes the given files, finds the pairs of files
+//! whose match score is strictly greater than the threshold (default: 0), and
+//! prints a report of the matches (see the `ssdeep::report` module) in HTML
+//! or, with `--markdown`, in Markdown.
+//!
+//! The `mutation` subcommand mutates the given file by all kinds of mutations
+//! at various rates (see the `ssdeep::mutation` module) and prints the
+//! distributions of the scores between the file and its mutations, one
+//! tab-separated line per kind and rate.
+
+extern crate ssdeep;
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+
+use ssdeep::matrix::pairs_above;
+use ssdeep::mutation::run_experiment;
+use ssdeep::mutation::ExperimentOptions;
+use ssdeep::report::Match;
+use ssdeep::report::Report;
+
+const USAGE: &str = "usage: ssdeep report [--markdown] [--title TITLE] [--threshold SCORE] FILE...
+       ssdeep mutation [--trials COUNT] [--seed SEED] FILE";
+
+fn main() {
+    let mut args = env::args_os().skip(1);
+    let result = match args.next().as_ref().and_then(|command| command.to_str()) {
+        Some("report") => report(args),
+        Some("mutation") => mutation(args),
+        _ => Err(USAGE.to_string()),
+    };
+    if let Err(e) = result {
+        eprintln!("ssdeep: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Options of the `report` subcommand.
+struct ReportOptions {
+    markdown: bool,
+    title: String,
+    threshold: u8,
+    files: Vec<PathBuf>,
+}
+
+fn parse_report_options<I: Iterator<Item = OsString>>(
+    mut args: I,
+) -> Result<ReportOptions, String> {
+    let mut options = ReportOptions {
+        markdown: false,
+        title: "ssdeep report".to_string(),
+        threshold: 0,
+        files: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--markdown") => options.markdown = true,
+            Some("--title") => options.title = option_value(&mut args, "--title")?,
+            Some("--threshold") => options.threshold = parse_value(&mut args, "--threshold")?,
+            _ => options.files.push(PathBuf::from(arg)),
+        }
+    }
+    if options.files.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    Ok(options)
+}
+
+/// Returns the value of the given option, which is the next argument.
+fn option_value<I: Iterator<Item = OsString>>(
+    args: &mut I,
+    option: &str,
+) -> Result<String, String> {
+    args.next()
+        .and_then(|value| value.into_string().ok())
+        .ok_or_else(|| format!("missing or invalid value of {}", option))
+}
+
+/// Returns the value of the given option parsed from the next argument.
+fn parse_value<I, T>(args: &mut I, option: &str) -> Result<T, String>
+where
+    I: Iterator<Item = OsString>,
+    T: FromStr,
+{
+    let value = option_value(args, option)?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value of {}: {}", option, value))
+}
+
+fn report<I: Iterator<Item = OsString>>(args: I) -> Result<(), String> {
+    let options = parse_report_options(args)?;
+    let hashes = options
+        .files
+        .iter()
+        .map(|path| ssdeep::hash_from_file(path).map_err(|e| format!("{}: {}", path.display(), e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut report = Report::new(&options.title);
+    for pair in pairs_above(&hashes, options.threshold).map_err(|e| e.to_string())? {
+        report.matches.push(Match {
+            name1: options.files[pair.i].display().to_string(),
+            hash1: hashes[pair.i].clone(),
+            name2: options.files[pair.j].display().to_string(),
+            hash2: hashes[pair.j].clone(),
+            score: pair.score,
+        });
+    }
+    if options.markdown {
+        print!("{}", report.to_markdown());
+    } else {
+        print!("{}", report.to_html());
+    }
+    Ok(())
+}
+
+fn mutation<I: Iterator<Item = OsString>>(mut args: I) -> Result<(), String> {
+    let mut options = ExperimentOptions::default();
+    let mut file = None;
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--trials") => options.trials = parse_value(&mut args, "--trials")?,
+            Some("--seed") => options.seed = parse_value(&mut args, "--seed")?,
+            _ if file.is_none() => file = Some(PathBuf::from(arg)),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let file = file.ok_or_else(|| USAGE.to_string())?;
+    let input = fs::read(&file).map_err(|e| format!("{}: {}", file.display(), e))?;
+    let distributions =
+        run_experiment(&input, &options).map_err(|e| format!("{}: {}", file.display(), e))?;
+    println!("kind\trate\tmin\tmax\tmean");
+    for d in distributions {
+        println!(
+            "{:?}\t{}\t{}\t{}\t{:.2}",
+            d.kind, d.rate, d.min, d.max, d.mean
+        );
+    }
+    Ok(())
+}
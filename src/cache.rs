@@ -0,0 +1,144 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Memoization of match scores.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use super::compare;
+use super::Result;
+
+/// A bounded cache of match scores between pairs of hashes, which evicts the
+/// least recently used scores.
+///
+/// Deduplication and clustering often compare the same pairs of hashes
+/// repeatedly (e.g. in several stages of a workflow). The cache computes the
+/// score of each pair via [`compare()`](fn.compare.html) only once, as long
+/// as the pair is among the `capacity` most recently used pairs. As the score
+/// does not depend on the order of the hashes, both orders of a pair share a
+/// single entry. Errors are not cached.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::ComparisonCache;
+///
+/// let mut cache = ComparisonCache::new(1000);
+/// let h1 = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C";
+/// let h2 = "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx";
+/// assert_eq!(cache.compare(h1, h2).unwrap(), 22);
+/// assert_eq!(cache.compare(h2, h1).unwrap(), 22);
+/// assert_eq!((cache.hits(), cache.misses()), (1, 1));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ComparisonCache {
+    capacity: usize,
+    /// Scores of the cached pairs with the times of their last uses.
+    scores: HashMap<(String, String), (u8, u64)>,
+    /// The cached pairs by the times of their last uses.
+    uses: BTreeMap<u64, (String, String)>,
+    /// The time of the next use.
+    time: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl ComparisonCache {
+    /// Creates an empty cache holding the scores of at most `capacity` pairs.
+    ///
+    /// With zero capacity, nothing is cached.
+    pub fn new(capacity: usize) -> Self {
+        ComparisonCache {
+            capacity,
+            scores: HashMap::new(),
+            uses: BTreeMap::new(),
+            time: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Returns the maximal number of cached scores.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of cached scores.
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    /// Checks whether no score is cached.
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// Returns the number of scores that were returned from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Returns the number of scores that had to be computed.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Removes all cached scores (the numbers of hits and misses are kept).
+    pub fn clear(&mut self) {
+        self.scores.clear();
+        self.uses.clear();
+    }
+
+    /// Returns the match score between the given hashes, computing it only
+    /// when it is not cached.
+    ///
+    /// When a hash is invalid, it returns the error of
+    /// [`compare()`](fn.compare.html).
+    pub fn compare<H1: AsRef<str>, H2: AsRef<str>>(&mut self, hash1: H1, hash2: H2) -> Result<u8> {
+        let (hash1, hash2) = (hash1.as_ref(), hash2.as_ref());
+        let key = if hash1 <= hash2 {
+            (hash1.to_string(), hash2.to_string())
+        } else {
+            (hash2.to_string(), hash1.to_string())
+        };
+        let time = self.time;
+        self.time += 1;
+
+        if let Some(&mut (score, ref mut last_use)) = self.scores.get_mut(&key) {
+            let entry = self.uses.remove(last_use).expect("a cached pair has a use");
+            self.uses.insert(time, entry);
+            *last_use = time;
+            self.hits += 1;
+            return Ok(score);
+        }
+
+        self.misses += 1;
+        let score = compare(hash1, hash2)?;
+        if self.capacity == 0 {
+            return Ok(score);
+        }
+        if self.scores.len() == self.capacity {
+            let oldest = *self.uses.keys().next().expect("a full cache has a use");
+            let evicted = self.uses.remove(&oldest).expect("the use exists");
+            self.scores.remove(&evicted);
+        }
+        self.scores.insert(key.clone(), (score, time));
+        self.uses.insert(time, key);
+        Ok(score)
+    }
+}
@@ -0,0 +1,209 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Content-defined chunking of inputs and fuzzy hashing of the chunks.
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use super::compare;
+use super::hash;
+use super::Result;
+
+/// Options for [`hash_chunks()`](fn.hash_chunks.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkingOptions {
+    /// The minimal size of a chunk (except for the last chunk).
+    ///
+    /// Default: 16 KiB.
+    pub min_size: usize,
+    /// The average size of a chunk (it should be a power of two).
+    ///
+    /// Default: 64 KiB.
+    pub avg_size: usize,
+    /// The maximal size of a chunk.
+    ///
+    /// Default: 256 KiB.
+    pub max_size: usize,
+}
+
+impl Default for ChunkingOptions {
+    fn default() -> Self {
+        ChunkingOptions {
+            min_size: 16 * 1024,
+            avg_size: 64 * 1024,
+            max_size: 256 * 1024,
+        }
+    }
+}
+
+/// A chunk of an input with its fuzzy hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Chunk {
+    /// Offset of the chunk in the input.
+    pub offset: u64,
+    /// Size of the chunk.
+    pub size: u64,
+    /// Fuzzy hash of the chunk.
+    pub hash: String,
+}
+
+/// Splits the input into variable-size chunks and computes the fuzzy hash of
+/// each of them.
+///
+/// The boundaries of the chunks are determined by the content of the input
+/// (via a FastCDC-style gear hash with normalized chunking), not by fixed
+/// offsets. Hence, an insertion or removal of bytes changes only the chunks
+/// around it and the other chunks stay the same even though they are shifted.
+/// This complements the fuzzy hash of the whole input, which is of little use
+/// for very large inputs because of its large block size. The resulting
+/// chunks can be compared via [`compare_chunks()`](fn.compare_chunks.html).
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::{hash_chunks, ChunkingOptions};
+///
+/// let chunks = hash_chunks(b"Hello there!", &ChunkingOptions::default()).unwrap();
+/// assert_eq!(chunks.len(), 1);
+/// assert_eq!(chunks[0].hash, "3:aNRn:aNRn");
+/// ```
+///
+/// # Panics
+///
+/// When the options do not satisfy `0 < min_size <= avg_size <= max_size`,
+/// or in the same situations as [`hash()`](fn.hash.html).
+pub fn hash_chunks(buf: &[u8], options: &ChunkingOptions) -> Result<Vec<Chunk>> {
+    assert!(
+        0 < options.min_size
+            && options.min_size <= options.avg_size
+            && options.avg_size <= options.max_size,
+        "invalid chunking options"
+    );
+
+    let gear = gear_table();
+    // Normalized chunking: before reaching the average size, boundaries are
+    // harder to find (more bits have to be zero), and after reaching it, they
+    // are easier to find, which narrows the distribution of chunk sizes.
+    let bits = options.avg_size.next_power_of_two().trailing_zeros();
+    let mask_small = mask(bits + 1);
+    let mask_large = mask(bits.saturating_sub(1));
+
+    let mut chunks = Vec::new();
+    let mut offset = 0;
+    while offset < buf.len() {
+        let rest = &buf[offset..];
+        let size = cut_point(rest, options, &gear, mask_small, mask_large);
+        chunks.push(Chunk {
+            offset: offset as u64,
+            size: size as u64,
+            hash: hash(&rest[..size])?,
+        });
+        offset += size;
+    }
+    Ok(chunks)
+}
+
+/// Compares two lists of chunk hashes and returns a match score between 0 (no
+/// match) and 100 (full match).
+///
+/// Every chunk is matched with the most similar chunk from the other list
+/// (regardless of their positions), and the score is the average of the
+/// scores of these matches weighted by the sizes of the chunks. Two empty
+/// lists are a full match.
+///
+/// When any of the hashes is invalid, it returns an error.
+pub fn compare_chunks(chunks1: &[Chunk], chunks2: &[Chunk]) -> Result<u8> {
+    if chunks1.is_empty() && chunks2.is_empty() {
+        return Ok(100);
+    }
+    let mut weighted_sum = 0.0;
+    let mut total_size = 0.0;
+    for (chunks, others) in &[(chunks1, chunks2), (chunks2, chunks1)] {
+        for chunk in chunks.iter() {
+            let mut best = 0;
+            for other in others.iter() {
+                best = best.max(compare(&chunk.hash, &other.hash)?);
+                if best == 100 {
+                    break;
+                }
+            }
+            weighted_sum += chunk.size as f64 * f64::from(best);
+            total_size += chunk.size as f64;
+        }
+    }
+    if total_size == 0.0 {
+        return Ok(0);
+    }
+    Ok((weighted_sum / total_size).round() as u8)
+}
+
+/// Returns the size of the chunk at the start of the given input.
+fn cut_point(
+    buf: &[u8],
+    options: &ChunkingOptions,
+    gear: &[u64; 256],
+    mask_small: u64,
+    mask_large: u64,
+) -> usize {
+    if buf.len() <= options.min_size {
+        return buf.len();
+    }
+    let max = buf.len().min(options.max_size);
+    let normal = max.min(options.avg_size);
+    let mut h: u64 = 0;
+    for i in options.min_size..max {
+        h = (h << 1).wrapping_add(gear[buf[i] as usize]);
+        let mask = if i < normal { mask_small } else { mask_large };
+        if h & mask == 0 {
+            return i + 1;
+        }
+    }
+    max
+}
+
+/// Returns a mask with the given number of the most significant bits set.
+///
+/// The most significant bits are used because the gear hash shifts the
+/// contributions of older bytes towards them.
+fn mask(bits: u32) -> u64 {
+    if bits == 0 {
+        0
+    } else {
+        !0 << (64 - bits.min(64))
+    }
+}
+
+/// Returns a table of pseudo-random values for the gear hash.
+///
+/// The values are generated via SplitMix64 from a fixed seed, so the chunk
+/// boundaries are the same across runs and platforms.
+fn gear_table() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut state: u64 = 0x5353_4445_4550_4344;
+    for value in table.iter_mut() {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        *value = z ^ (z >> 31);
+    }
+    table
+}
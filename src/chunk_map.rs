@@ -0,0 +1,153 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::ops::Range;
+
+use super::hash;
+use super::Result;
+
+/// Size of the window of the rolling hash (from fuzzy.c).
+const ROLLING_WINDOW: usize = 7;
+
+/// Length of an individual fuzzy hash signature component (from fuzzy.h).
+const SPAMSUM_LENGTH: usize = 64;
+
+/// A map from characters of a fuzzy hash to byte ranges of the input.
+///
+/// Every character of a fuzzy hash is a hash of a piece (chunk) of the input.
+/// The map contains the byte range of this piece for every character of both
+/// parts of the fuzzy hash. This allows mapping matched characters back to
+/// offsets in the input, e.g. for visualization or carving.
+///
+/// Note that a range may be empty. This happens when the input ends right at
+/// a piece boundary because the fuzzy hash still contains a character for the
+/// (empty) rest of the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkMap {
+    /// Block size of the fuzzy hash.
+    pub block_size: u64,
+    /// Byte ranges for characters of the first part of the fuzzy hash (block
+    /// size `block_size`).
+    pub chunks: Vec<Range<u64>>,
+    /// Byte ranges for characters of the second part of the fuzzy hash (block
+    /// size `2 * block_size`).
+    pub double_chunks: Vec<Range<u64>>,
+}
+
+/// Computes the fuzzy hash of bytes together with its
+/// [`ChunkMap`](struct.ChunkMap.html).
+///
+/// # Examples
+///
+/// ```
+/// let (h, map) = ssdeep::hash_with_chunk_map(b"Hello there!").unwrap();
+/// assert_eq!(h, "3:aNRn:aNRn");
+/// assert_eq!(map.block_size, 3);
+/// assert_eq!(map.chunks, vec![0..3, 3..9, 9..12, 12..12]);
+/// ```
+///
+/// # Panics
+///
+/// In the same situations as [`hash()`](fn.hash.html).
+pub fn hash_with_chunk_map(buf: &[u8]) -> Result<(String, ChunkMap)> {
+    let h = hash(buf)?;
+    // The block size is always present because the hash was computed by
+    // libfuzzy.
+    let block_size: u32 = h[..h.find(':').unwrap()].parse().unwrap();
+
+    let mut roll = RollingHash::new();
+    let mut triggers = Vec::new();
+    let mut double_triggers = Vec::new();
+    for (i, &c) in buf.iter().enumerate() {
+        roll.update(c);
+        let h = roll.sum().wrapping_add(1);
+        if h != 0 && h.is_multiple_of(block_size) {
+            triggers.push(i as u64 + 1);
+            if h.is_multiple_of(2 * block_size) {
+                double_triggers.push(i as u64 + 1);
+            }
+        }
+    }
+    let has_tail = roll.sum() != 0;
+    let len = buf.len() as u64;
+    let map = ChunkMap {
+        block_size: u64::from(block_size),
+        chunks: chunk_ranges(&triggers, SPAMSUM_LENGTH, has_tail, len),
+        // By default, libfuzzy truncates the second part to half the length.
+        double_chunks: chunk_ranges(&double_triggers, SPAMSUM_LENGTH / 2, has_tail, len),
+    };
+    Ok((h, map))
+}
+
+/// Computes byte ranges for characters of a signature part of the given
+/// maximal length, mirroring what fuzzy_engine_step() and fuzzy_digest() do.
+fn chunk_ranges(triggers: &[u64], max_len: usize, has_tail: bool, len: u64) -> Vec<Range<u64>> {
+    // Every trigger (a reset point) ends a piece, but only until the
+    // signature part is full. The last character of the part then covers
+    // everything after the last piece that fit.
+    let mut ranges = Vec::with_capacity(max_len);
+    let mut start = 0;
+    for &end in triggers.iter().take(max_len - 1) {
+        ranges.push(start..end);
+        start = end;
+    }
+    if has_tail {
+        ranges.push(start..len);
+    } else if triggers.len() >= max_len {
+        ranges.push(start..triggers[triggers.len() - 1]);
+    }
+    ranges
+}
+
+/// The rolling hash from fuzzy.c, based on the Adler checksum.
+struct RollingHash {
+    window: [u8; ROLLING_WINDOW],
+    h1: u32,
+    h2: u32,
+    h3: u32,
+    n: usize,
+}
+
+impl RollingHash {
+    fn new() -> Self {
+        RollingHash {
+            window: [0; ROLLING_WINDOW],
+            h1: 0,
+            h2: 0,
+            h3: 0,
+            n: 0,
+        }
+    }
+
+    fn update(&mut self, c: u8) {
+        self.h2 = self.h2.wrapping_sub(self.h1);
+        self.h2 = self.h2.wrapping_add(ROLLING_WINDOW as u32 * u32::from(c));
+
+        self.h1 = self.h1.wrapping_add(u32::from(c));
+        self.h1 = self.h1.wrapping_sub(u32::from(self.window[self.n]));
+
+        self.window[self.n] = c;
+        self.n = (self.n + 1) % ROLLING_WINDOW;
+
+        self.h3 <<= 5;
+        self.h3 ^= u32::from(c);
+    }
+
+    fn sum(&self) -> u32 {
+        self.h1.wrapping_add(self.h2).wrapping_add(self.h3)
+    }
+}
@@ -0,0 +1,252 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Classification of files against sets of known-good and known-bad hashes.
+
+use std::path::Path;
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use super::compare;
+use super::hash;
+use super::hash_from_file;
+use super::Result;
+
+/// Thresholds of scores for individual classes.
+///
+/// A class is selected when the best score against the corresponding reference
+/// set is at least the threshold of the class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Thresholds {
+    /// The minimal score against a known-bad hash for the file to be
+    /// classified as known-bad. Default: 90.
+    pub known_bad: u8,
+    /// The minimal score against a known-good hash for the file to be
+    /// classified as known-good. Default: 90.
+    pub known_good: u8,
+    /// The minimal score against a known-bad hash for the file to be
+    /// classified as similar to a known-bad file. Default: 40.
+    pub similar_to_bad: u8,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            known_bad: 90,
+            known_good: 90,
+            similar_to_bad: 40,
+        }
+    }
+}
+
+/// A reference hash from a known-good or known-bad set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Reference {
+    /// Name of the reference (e.g. its file name or SHA-256).
+    pub name: String,
+    /// Fuzzy hash of the reference.
+    pub hash: String,
+}
+
+/// Result of a classification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Classification {
+    /// The file matches a known-bad reference.
+    KnownBad {
+        /// The best matching reference.
+        reference: Reference,
+        /// The score between the file and the reference.
+        score: u8,
+    },
+    /// The file matches a known-good reference.
+    KnownGood {
+        /// The best matching reference.
+        reference: Reference,
+        /// The score between the file and the reference.
+        score: u8,
+    },
+    /// The file is similar to a known-bad reference.
+    SimilarToBad {
+        /// The best matching reference.
+        reference: Reference,
+        /// The score between the file and the reference.
+        score: u8,
+    },
+    /// The file does not match any reference.
+    Unknown,
+}
+
+/// A classified scanned file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ScanResult {
+    /// Path to the file (preferably relative to the root of the scanned
+    /// artifact or repository).
+    pub path: String,
+    /// Fuzzy hash of the file.
+    pub hash: String,
+    /// Classification of the file.
+    pub classification: Classification,
+}
+
+/// A classifier of files against sets of known-good and known-bad hashes.
+///
+/// The classes are tried in the following order: known-bad, known-good,
+/// similar-to-bad. Hence, a file that matches both sets sufficiently is
+/// classified as known-bad, and a known-good file is never reported as similar
+/// to a known-bad one.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::classify::{Classification, Classifier};
+///
+/// let mut classifier = Classifier::new();
+/// classifier
+///     .add_known_bad("dropper", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C")
+///     .unwrap();
+/// classifier.add_known_good("hello", "3:aNRn:aNRn").unwrap();
+///
+/// assert!(matches!(
+///     classifier.classify(b"Hello there!").unwrap(),
+///     Classification::KnownGood { score: 100, .. }
+/// ));
+/// assert_eq!(
+///     classifier.classify_hash("3:u+N:u+N").unwrap(),
+///     Classification::Unknown
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Classifier {
+    /// Thresholds of scores for individual classes.
+    pub thresholds: Thresholds,
+    known_good: Vec<Reference>,
+    known_bad: Vec<Reference>,
+}
+
+impl Classifier {
+    /// Creates a classifier with empty reference sets and default thresholds.
+    pub fn new() -> Self {
+        Classifier::default()
+    }
+
+    /// Creates a classifier with empty reference sets and the given
+    /// thresholds.
+    pub fn with_thresholds(thresholds: Thresholds) -> Self {
+        Classifier {
+            thresholds,
+            ..Default::default()
+        }
+    }
+
+    /// Adds a hash into the known-good set.
+    ///
+    /// When the hash is invalid, it returns an error.
+    pub fn add_known_good(&mut self, name: &str, hash: &str) -> Result<()> {
+        self.known_good.push(reference(name, hash)?);
+        Ok(())
+    }
+
+    /// Adds a hash into the known-bad set.
+    ///
+    /// When the hash is invalid, it returns an error.
+    pub fn add_known_bad(&mut self, name: &str, hash: &str) -> Result<()> {
+        self.known_bad.push(reference(name, hash)?);
+        Ok(())
+    }
+
+    /// Returns the known-good set.
+    pub fn known_good(&self) -> &[Reference] {
+        &self.known_good
+    }
+
+    /// Returns the known-bad set.
+    pub fn known_bad(&self) -> &[Reference] {
+        &self.known_bad
+    }
+
+    /// Classifies a file by its fuzzy hash.
+    ///
+    /// When the hash is invalid, it returns an error.
+    pub fn classify_hash(&self, hash: &str) -> Result<Classification> {
+        let bad = best_match(&self.known_bad, hash)?;
+        if let Some((reference, score)) = bad {
+            if score >= self.thresholds.known_bad {
+                return Ok(Classification::KnownBad {
+                    reference: reference.clone(),
+                    score,
+                });
+            }
+        }
+        if let Some((reference, score)) = best_match(&self.known_good, hash)? {
+            if score >= self.thresholds.known_good {
+                return Ok(Classification::KnownGood {
+                    reference: reference.clone(),
+                    score,
+                });
+            }
+        }
+        if let Some((reference, score)) = bad {
+            if score >= self.thresholds.similar_to_bad {
+                return Ok(Classification::SimilarToBad {
+                    reference: reference.clone(),
+                    score,
+                });
+            }
+        }
+        Ok(Classification::Unknown)
+    }
+
+    /// Classifies the given bytes.
+    ///
+    /// When the hash cannot be computed, it returns an error.
+    pub fn classify(&self, buf: &[u8]) -> Result<Classification> {
+        self.classify_hash(&hash(buf)?)
+    }
+
+    /// Classifies the given file.
+    ///
+    /// When the file cannot be hashed, it returns an error.
+    pub fn classify_file<P: AsRef<Path>>(&self, file_path: P) -> Result<Classification> {
+        self.classify_hash(&hash_from_file(file_path)?)
+    }
+}
+
+fn reference(name: &str, hash: &str) -> Result<Reference> {
+    // Comparing the hash with itself validates it.
+    compare(hash, hash)?;
+    Ok(Reference {
+        name: name.to_string(),
+        hash: hash.to_string(),
+    })
+}
+
+fn best_match<'a>(references: &'a [Reference], hash: &str) -> Result<Option<(&'a Reference, u8)>> {
+    let mut best: Option<(&Reference, u8)> = None;
+    for reference in references {
+        let score = compare(hash, &reference.hash)?;
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((reference, score));
+        }
+    }
+    Ok(best)
+}
@@ -0,0 +1,330 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Clustering of fuzzy hashes (e.g. for grouping of samples into malware
+//! families).
+
+#[cfg(feature = "graph")]
+use petgraph::graph::NodeIndex;
+#[cfg(feature = "graph")]
+use petgraph::graph::UnGraph;
+use std::collections::VecDeque;
+
+#[cfg(feature = "graph")]
+use super::matrix::pairs_above;
+use super::matrix::pairs_above_with_progress;
+use super::Phase;
+use super::Progress;
+use super::Result;
+
+/// Groups the given hashes into connected components of hashes whose
+/// pairwise score is strictly greater than the threshold (single-linkage
+/// clustering).
+///
+/// Two hashes are in the same cluster when there is a chain of hashes between
+/// them in which every two consecutive hashes have a score greater than the
+/// threshold. Every hash is in exactly one cluster (a hash without similar
+/// hashes forms a cluster of its own). A cluster is a list of indexes of its
+/// hashes in ascending order, and clusters are ordered by their first
+/// indexes.
+///
+/// The pairs are scored lazily via
+/// [`matrix::pairs_above()`](../matrix/fn.pairs_above.html), so apart from the
+/// clusters, only the parsed hashes and a single row of pairs are kept in
+/// memory.
+///
+/// When a hash is invalid, it returns
+/// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash).
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::cluster::single_linkage;
+///
+/// let clusters = single_linkage(
+///     &[
+///         "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+///         "3:u+N:u+N",
+///         "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx",
+///     ],
+///     0,
+/// )
+/// .unwrap();
+/// assert_eq!(clusters, vec![vec![0, 2], vec![1]]);
+/// ```
+pub fn single_linkage<H: AsRef<str>>(hashes: &[H], threshold: u8) -> Result<Vec<Vec<usize>>> {
+    single_linkage_with_progress(hashes, threshold, |_| {})
+}
+
+/// Groups the given hashes into connected components of hashes whose
+/// pairwise score is strictly greater than the threshold, reporting the
+/// progress to the given callback.
+///
+/// The progress is reported in the [`Comparing`](../enum.Phase.html) phase
+/// (after each row of pairs is scored, see
+/// [`matrix::pairs_above_with_progress()`](../matrix/fn.pairs_above_with_progress.html))
+/// and in the [`Clustering`](../enum.Phase.html) phase (once the clusters are
+/// formed). See [`single_linkage()`](fn.single_linkage.html) for more
+/// details.
+pub fn single_linkage_with_progress<H, F>(
+    hashes: &[H],
+    threshold: u8,
+    mut progress: F,
+) -> Result<Vec<Vec<usize>>>
+where
+    H: AsRef<str>,
+    F: FnMut(&Progress),
+{
+    let mut sets = DisjointSets::new(hashes.len());
+    for pair in pairs_above_with_progress(hashes, threshold, &mut progress)? {
+        sets.union(pair.i, pair.j);
+    }
+    let clusters = sets.into_components();
+    progress(&Progress {
+        phase: Phase::Clustering,
+        done: hashes.len() as u64,
+        total: Some(hashes.len() as u64),
+        current_path: None,
+    });
+    Ok(clusters)
+}
+
+/// Clusters found by [`dbscan()`](fn.dbscan.html).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DensityClusters {
+    /// The clusters, as lists of indexes of their hashes in ascending order,
+    /// ordered by their first indexes.
+    pub clusters: Vec<Vec<usize>>,
+    /// Indexes of hashes that are not in any cluster, in ascending order.
+    pub noise: Vec<usize>,
+}
+
+/// Groups the given hashes into clusters of densely connected hashes
+/// (DBSCAN-like clustering).
+///
+/// Two hashes are neighbors when their score is at least `min_score` (and
+/// non-zero). A hash with at least `min_neighbors` neighbors is a core hash.
+/// A cluster consists of core hashes connected via chains of neighboring core
+/// hashes, together with all their neighbors. Unlike with
+/// [`single_linkage()`](fn.single_linkage.html), a chain of hashes with few
+/// neighbors thus does not merge otherwise unrelated clusters, which makes the
+/// clustering more robust on noisy corpora. A non-core hash that is a neighbor
+/// of core hashes of several clusters is put into the cluster with the lowest
+/// first index. Hashes that are neither core hashes nor neighbors of core
+/// hashes are noise.
+///
+/// The pairs are scored lazily via
+/// [`matrix::pairs_above()`](../matrix/fn.pairs_above.html), but the lists of
+/// neighbors of all hashes are kept in memory.
+///
+/// When a hash is invalid, it returns
+/// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash).
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::cluster::dbscan;
+///
+/// let result = dbscan(
+///     &[
+///         "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+///         "3:u+N:u+N",
+///         "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx",
+///     ],
+///     20,
+///     1,
+/// )
+/// .unwrap();
+/// assert_eq!(result.clusters, vec![vec![0, 2]]);
+/// assert_eq!(result.noise, vec![1]);
+/// ```
+pub fn dbscan<H: AsRef<str>>(
+    hashes: &[H],
+    min_score: u8,
+    min_neighbors: usize,
+) -> Result<DensityClusters> {
+    dbscan_with_progress(hashes, min_score, min_neighbors, |_| {})
+}
+
+/// Groups the given hashes into clusters of densely connected hashes,
+/// reporting the progress to the given callback.
+///
+/// The progress is reported in the [`Comparing`](../enum.Phase.html) phase
+/// (after each row of pairs is scored, see
+/// [`matrix::pairs_above_with_progress()`](../matrix/fn.pairs_above_with_progress.html))
+/// and in the [`Clustering`](../enum.Phase.html) phase (after each cluster is
+/// formed, with the number of hashes visited so far, and at the end). See
+/// [`dbscan()`](fn.dbscan.html) for more details.
+pub fn dbscan_with_progress<H, F>(
+    hashes: &[H],
+    min_score: u8,
+    min_neighbors: usize,
+    mut progress: F,
+) -> Result<DensityClusters>
+where
+    H: AsRef<str>,
+    F: FnMut(&Progress),
+{
+    let n = hashes.len() as u64;
+    let mut neighbors = vec![Vec::new(); hashes.len()];
+    for pair in pairs_above_with_progress(hashes, min_score.max(1) - 1, &mut progress)? {
+        neighbors[pair.i].push(pair.j);
+        neighbors[pair.j].push(pair.i);
+    }
+    let is_core: Vec<bool> = neighbors.iter().map(|n| n.len() >= min_neighbors).collect();
+
+    let mut result = DensityClusters::default();
+    let mut assigned = vec![false; hashes.len()];
+    let mut queue = VecDeque::new();
+    for start in 0..hashes.len() {
+        if assigned[start] || !is_core[start] {
+            continue;
+        }
+        let mut cluster = vec![start];
+        assigned[start] = true;
+        queue.push_back(start);
+        while let Some(i) = queue.pop_front() {
+            for &j in &neighbors[i] {
+                if assigned[j] {
+                    continue;
+                }
+                assigned[j] = true;
+                cluster.push(j);
+                if is_core[j] {
+                    queue.push_back(j);
+                }
+            }
+        }
+        cluster.sort_unstable();
+        result.clusters.push(cluster);
+        progress(&Progress {
+            phase: Phase::Clustering,
+            done: start as u64 + 1,
+            total: Some(n),
+            current_path: None,
+        });
+    }
+    result.clusters.sort_unstable_by_key(|cluster| cluster[0]);
+    result.noise = (0..hashes.len()).filter(|&i| !assigned[i]).collect();
+    progress(&Progress {
+        phase: Phase::Clustering,
+        done: n,
+        total: Some(n),
+        current_path: None,
+    });
+    Ok(result)
+}
+
+/// Returns an undirected graph whose nodes are the given hashes and whose
+/// edges connect pairs of hashes whose score is strictly greater than the
+/// threshold, weighted by their scores.
+///
+/// The index of the node of a hash is its index in `hashes` (i.e.
+/// `NodeIndex::new(i)` is the node of `hashes[i]`), so the graph can be used
+/// to run arbitrary graph algorithms of
+/// [petgraph](https://crates.io/crates/petgraph) on the similarities. The
+/// pairs are scored lazily via
+/// [`matrix::pairs_above()`](../matrix/fn.pairs_above.html).
+///
+/// When a hash is invalid, it returns
+/// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash).
+///
+/// # Examples
+///
+/// ```
+/// extern crate petgraph;
+/// extern crate ssdeep;
+///
+/// use ssdeep::cluster::to_graph;
+///
+/// let graph = to_graph(
+///     &[
+///         "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+///         "3:u+N:u+N",
+///         "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx",
+///     ],
+///     0,
+/// )
+/// .unwrap();
+/// assert_eq!(graph.node_count(), 3);
+/// assert_eq!(graph.edge_count(), 1);
+/// assert_eq!(graph.raw_edges()[0].weight, 22);
+/// assert_eq!(petgraph::algo::connected_components(&graph), 2);
+/// ```
+#[cfg(feature = "graph")]
+pub fn to_graph<H: AsRef<str>>(hashes: &[H], threshold: u8) -> Result<UnGraph<String, u8>> {
+    let pairs = pairs_above(hashes, threshold)?;
+    let mut graph = UnGraph::with_capacity(hashes.len(), 0);
+    for hash in hashes {
+        graph.add_node(hash.as_ref().to_string());
+    }
+    for pair in pairs {
+        graph.add_edge(NodeIndex::new(pair.i), NodeIndex::new(pair.j), pair.score);
+    }
+    Ok(graph)
+}
+
+/// Disjoint sets of indexes (union-find with path halving and union by size).
+struct DisjointSets {
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(len: usize) -> Self {
+        DisjointSets {
+            parents: (0..len).collect(),
+            sizes: vec![1; len],
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    fn union(&mut self, i: usize, j: usize) {
+        let (mut i, mut j) = (self.find(i), self.find(j));
+        if i == j {
+            return;
+        }
+        if self.sizes[i] < self.sizes[j] {
+            std::mem::swap(&mut i, &mut j);
+        }
+        self.parents[j] = i;
+        self.sizes[i] += self.sizes[j];
+    }
+
+    /// Returns the sets as lists of indexes in ascending order, ordered by
+    /// their first indexes.
+    fn into_components(mut self) -> Vec<Vec<usize>> {
+        let mut components: Vec<Vec<usize>> = Vec::new();
+        let mut component_of_root = vec![usize::MAX; self.parents.len()];
+        for i in 0..self.parents.len() {
+            let root = self.find(i);
+            if component_of_root[root] == usize::MAX {
+                component_of_root[root] = components.len();
+                components.push(Vec::new());
+            }
+            components[component_of_root[root]].push(i);
+        }
+        components
+    }
+}
@@ -0,0 +1,136 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Composite similarity scoring combining fuzzy hashes with other heuristics.
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use super::compare;
+use super::Result;
+
+/// An input (e.g. a file) to be scored by a
+/// [`CompositeScorer`](struct.CompositeScorer.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompositeInput<'a> {
+    /// Fuzzy hash of the input.
+    pub hash: &'a str,
+    /// Size of the input in bytes.
+    pub size: u64,
+    /// Exact digest of the input (e.g. its SHA-256), if known.
+    pub digest: Option<&'a str>,
+}
+
+/// A composite score with the breakdown of how it was computed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CompositeScore {
+    /// The resulting confidence that the inputs are similar, between 0 and
+    /// 100.
+    pub confidence: u8,
+    /// The match score of the fuzzy hashes (see
+    /// [`compare()`](../fn.compare.html)).
+    pub ssdeep_score: u8,
+    /// The ratio of the smaller size to the larger size, between 0.0 and 1.0.
+    pub size_ratio: f64,
+    /// Whether the exact digests are equal, or `None` when any of them is not
+    /// known.
+    pub digest_match: Option<bool>,
+}
+
+/// A scorer blending the match score of fuzzy hashes with the ratio of the
+/// sizes of the inputs and the equality of their exact digests.
+///
+/// The confidence is the match score of the fuzzy hashes lowered based on how
+/// much the sizes of the inputs differ. The match score alone can rank pairs
+/// of inputs with very different sizes too high, which the size ratio
+/// corrects. The size ratio only lowers the score, so inputs whose hashes do
+/// not match have confidence 0 regardless of their sizes. When both exact
+/// digests are known and equal, the inputs are identical, so the confidence is
+/// 100.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::{CompositeInput, CompositeScorer};
+///
+/// let input1 = CompositeInput {
+///     hash: "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+///     size: 100,
+///     digest: None,
+/// };
+/// let input2 = CompositeInput {
+///     hash: "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx",
+///     size: 200,
+///     digest: None,
+/// };
+/// let score = CompositeScorer::default().score(&input1, &input2).unwrap();
+/// assert_eq!(score.ssdeep_score, 22);
+/// assert_eq!(score.size_ratio, 0.5);
+/// assert_eq!(score.confidence, 19);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompositeScorer {
+    /// How much the match score is lowered when the inputs have different
+    /// sizes (between 0.0 and 1.0). The score is multiplied by `1 -
+    /// size_penalty * (1 - size_ratio)`, so 0.0 disables the penalty and 1.0
+    /// scales the score by the size ratio. Default: 0.3.
+    pub size_penalty: f64,
+}
+
+impl Default for CompositeScorer {
+    fn default() -> Self {
+        CompositeScorer { size_penalty: 0.3 }
+    }
+}
+
+impl CompositeScorer {
+    /// Computes the composite score of the given inputs.
+    ///
+    /// When any of the hashes is invalid, it returns an error.
+    pub fn score(
+        &self,
+        input1: &CompositeInput,
+        input2: &CompositeInput,
+    ) -> Result<CompositeScore> {
+        let ssdeep_score = compare(input1.hash, input2.hash)?;
+        let size_ratio = if input1.size == input2.size {
+            1.0
+        } else {
+            input1.size.min(input2.size) as f64 / input1.size.max(input2.size) as f64
+        };
+        let digest_match = match (input1.digest, input2.digest) {
+            (Some(digest1), Some(digest2)) => Some(digest1.eq_ignore_ascii_case(digest2)),
+            _ => None,
+        };
+
+        let confidence = if digest_match == Some(true) {
+            100
+        } else {
+            let size_factor = 1.0 - self.size_penalty.clamp(0.0, 1.0) * (1.0 - size_ratio);
+            (f64::from(ssdeep_score) * size_factor).round() as u8
+        };
+        Ok(CompositeScore {
+            confidence,
+            ssdeep_score,
+            size_ratio,
+            digest_match,
+        })
+    }
+}
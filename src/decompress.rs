@@ -0,0 +1,223 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Fuzzy hashing of compressed data after their transparent decompression.
+//!
+//! This module is available only when the `decompress` feature is enabled.
+//! Matching fuzzy hashes of compressed data is useless because even a tiny
+//! change in the uncompressed data changes the compressed data completely.
+//! Therefore, functions from this module detect the compression format from
+//! the magic bytes at the beginning of the data and hash the decompressed data
+//! instead. Supported formats are gzip, zstd, xz, and bzip2.
+
+use std::cell::Cell;
+use std::io;
+use std::io::Cursor;
+use std::io::Read;
+use std::rc::Rc;
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use xz2::read::XzDecoder;
+
+use super::hasher::hash_and_copy;
+use super::Error;
+use super::Result;
+
+/// The longest magic-bytes sequence of the supported formats.
+const MAX_MAGIC_LEN: usize = 6;
+
+/// The size of the decompressed data under which the compression ratio is not
+/// checked. Small inputs may legitimately have huge ratios (e.g. a few bytes
+/// of zeros), and they are harmless.
+const RATIO_CHECK_THRESHOLD: u64 = 1024 * 1024;
+
+/// A compression format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// The data are not compressed (or compressed in an unsupported format).
+    None,
+    /// [gzip](https://www.rfc-editor.org/rfc/rfc1952).
+    Gzip,
+    /// [Zstandard](https://www.rfc-editor.org/rfc/rfc8878).
+    Zstd,
+    /// [xz](https://tukaani.org/xz/).
+    Xz,
+    /// [bzip2](https://sourceware.org/bzip2/).
+    Bzip2,
+}
+
+/// Limits guarding against decompression bombs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecompressionLimits {
+    /// The maximal size of the decompressed data (in bytes). Default: 1 GiB.
+    pub max_output_size: u64,
+    /// The maximal ratio between the sizes of the decompressed and compressed
+    /// data. It is checked only after at least 1 MiB of data has been
+    /// decompressed. Default: 1000.
+    pub max_ratio: u64,
+}
+
+impl Default for DecompressionLimits {
+    fn default() -> Self {
+        DecompressionLimits {
+            max_output_size: 1024 * 1024 * 1024,
+            max_ratio: 1000,
+        }
+    }
+}
+
+/// Detects the compression format from the magic bytes at the beginning of
+/// the data.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::decompress::{detect_compression, Compression};
+///
+/// assert_eq!(detect_compression(b"\x1f\x8b\x08\x00"), Compression::Gzip);
+/// assert_eq!(detect_compression(b"Hello there!"), Compression::None);
+/// ```
+pub fn detect_compression(data: &[u8]) -> Compression {
+    if data.starts_with(b"\x1f\x8b") {
+        Compression::Gzip
+    } else if data.starts_with(b"\x28\xb5\x2f\xfd") {
+        Compression::Zstd
+    } else if data.starts_with(b"\xfd7zXZ\x00") {
+        Compression::Xz
+    } else if data.starts_with(b"BZh") {
+        Compression::Bzip2
+    } else {
+        Compression::None
+    }
+}
+
+/// Computes the fuzzy hash of the decompressed data from the reader, using the
+/// default [limits](struct.DecompressionLimits.html).
+///
+/// Data that are not compressed in a supported format are hashed as they are.
+/// When the data are corrupted, [`Error::Io`](../enum.Error.html#variant.Io)
+/// is returned. When a limit is exceeded,
+/// [`Error::LimitExceeded`](../enum.Error.html#variant.LimitExceeded) is
+/// returned.
+///
+/// # Examples
+///
+/// ```
+/// // "Hello there!" compressed by gzip.
+/// let compressed = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xf3\x48\xcd\xc9\
+///                    \xc9\x57\x28\xc9\x48\x2d\x4a\x55\x04\x00\x36\x67\x5c\xed\
+///                    \x0c\x00\x00\x00";
+/// let h = ssdeep::decompress::hash_decompressed(&compressed[..]).unwrap();
+/// assert_eq!(h, "3:aNRn:aNRn");
+/// ```
+pub fn hash_decompressed<R: Read>(reader: R) -> Result<String> {
+    hash_decompressed_with_limits(reader, &DecompressionLimits::default())
+}
+
+/// Computes the fuzzy hash of the decompressed data from the reader, using the
+/// given limits.
+///
+/// See [`hash_decompressed()`](fn.hash_decompressed.html) for more details.
+pub fn hash_decompressed_with_limits<R: Read>(
+    mut reader: R,
+    limits: &DecompressionLimits,
+) -> Result<String> {
+    let mut magic = Vec::with_capacity(MAX_MAGIC_LEN);
+    (&mut reader)
+        .take(MAX_MAGIC_LEN as u64)
+        .read_to_end(&mut magic)?;
+    let compression = detect_compression(&magic);
+    let input_size = Rc::new(Cell::new(0));
+    let input = CountingReader {
+        inner: Cursor::new(magic).chain(reader),
+        count: input_size.clone(),
+    };
+    let decompressed: Box<dyn Read> = match compression {
+        Compression::None => return hash_and_copy(input, io::sink()),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(input)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(input)?),
+        Compression::Xz => Box::new(XzDecoder::new_multi_decoder(input)),
+        Compression::Bzip2 => Box::new(MultiBzDecoder::new(input)),
+    };
+    let mut output = LimitingReader {
+        inner: decompressed,
+        input_size,
+        output_size: 0,
+        limits: limits.clone(),
+        exceeded_limit: None,
+    };
+    let result = hash_and_copy(&mut output, io::sink());
+    match output.exceeded_limit {
+        Some(exceeded_limit) => Err(exceeded_limit),
+        None => result,
+    }
+}
+
+/// A reader counting the number of bytes that have been read from it.
+struct CountingReader<R> {
+    inner: R,
+    count: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.set(self.count.get() + n as u64);
+        Ok(n)
+    }
+}
+
+/// A reader of decompressed data that fails when a limit is exceeded.
+struct LimitingReader<R> {
+    inner: R,
+    input_size: Rc<Cell<u64>>,
+    output_size: u64,
+    limits: DecompressionLimits,
+    exceeded_limit: Option<Error>,
+}
+
+impl<R: Read> LimitingReader<R> {
+    fn check_limits(&self) -> Option<Error> {
+        if self.output_size > self.limits.max_output_size {
+            return Some(Error::LimitExceeded {
+                name: "max_output_size".to_string(),
+                limit: self.limits.max_output_size,
+            });
+        }
+        let max_output_size_by_ratio = self.input_size.get().saturating_mul(self.limits.max_ratio);
+        if self.output_size > RATIO_CHECK_THRESHOLD && self.output_size > max_output_size_by_ratio {
+            return Some(Error::LimitExceeded {
+                name: "max_ratio".to_string(),
+                limit: self.limits.max_ratio,
+            });
+        }
+        None
+    }
+}
+
+impl<R: Read> Read for LimitingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.output_size += n as u64;
+        if let Some(exceeded_limit) = self.check_limits() {
+            self.exceeded_limit = Some(exceeded_limit);
+            return Err(io::Error::other("decompression limit exceeded"));
+        }
+        Ok(n)
+    }
+}
@@ -0,0 +1,235 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Grouping of near-duplicate files or hashes (e.g. for deduplication of
+//! storage or reduction of evidence).
+
+use std::cmp::Reverse;
+use std::fs;
+use std::path::Path;
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use super::cluster::single_linkage;
+use super::compare;
+use super::hash_from_file;
+use super::Result;
+
+/// An item (e.g. a file) with its fuzzy hash and size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Item {
+    /// Name of the item (e.g. its path).
+    pub name: String,
+    /// Fuzzy hash of the item.
+    pub hash: String,
+    /// Size of the item in bytes.
+    pub size: u64,
+}
+
+/// A way of choosing the representative of a group of near-duplicates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Representative {
+    /// The largest item (the first one for equal sizes).
+    Largest,
+    /// The first item (in the order in which the items were given).
+    First,
+    /// The item whose sum of scores to all other items of the group is the
+    /// highest (the first one for equal sums).
+    Medoid,
+}
+
+/// Options for [`find_duplicates()`](fn.find_duplicates.html) and
+/// [`find_duplicate_files()`](fn.find_duplicate_files.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DedupeOptions {
+    /// Items are near-duplicates when their score is strictly greater than the
+    /// threshold (directly or via a chain of near-duplicates).
+    ///
+    /// Default: 90.
+    pub threshold: u8,
+    /// The way of choosing the representative of a group.
+    ///
+    /// Default: [`Representative::Largest`](enum.Representative.html).
+    pub representative: Representative,
+}
+
+impl Default for DedupeOptions {
+    fn default() -> Self {
+        DedupeOptions {
+            threshold: 90,
+            representative: Representative::Largest,
+        }
+    }
+}
+
+/// A member of a group of near-duplicates (other than the representative).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Member {
+    /// The member.
+    pub item: Item,
+    /// The score between the member and the representative of the group.
+    pub score: u8,
+}
+
+/// A group of near-duplicates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DuplicateGroup {
+    /// The item that represents the group (e.g. the one to keep).
+    pub representative: Item,
+    /// The other items of the group, in the order in which they were given.
+    pub members: Vec<Member>,
+    /// The total size of the members (without the representative) in bytes.
+    pub redundant_bytes: u64,
+}
+
+/// A report of groups of near-duplicates.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DedupeReport {
+    /// Groups of at least two near-duplicates, ordered by descending
+    /// redundant bytes (and by the order of their first items for equal
+    /// redundant bytes).
+    pub groups: Vec<DuplicateGroup>,
+    /// The total redundant bytes of all groups.
+    pub redundant_bytes: u64,
+}
+
+/// Finds groups of near-duplicates among the given items.
+///
+/// The groups are the connected components of near-duplicates (see
+/// [`cluster::single_linkage()`](../cluster/fn.single_linkage.html)) with at
+/// least two items. The scores of members are their scores with the
+/// representative, so a member that is a near-duplicate of the representative
+/// only via other members can have a score below the threshold.
+///
+/// When any of the hashes is invalid, it returns
+/// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash).
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::dedupe::{find_duplicates, DedupeOptions, Item};
+///
+/// let item = |name: &str, hash: &str, size| Item {
+///     name: name.to_string(),
+///     hash: hash.to_string(),
+///     size,
+/// };
+/// let items = vec![
+///     item("a", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C", 100),
+///     item("b", "3:u+N:u+N", 10),
+///     item("c", "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx", 120),
+/// ];
+/// let options = DedupeOptions {
+///     threshold: 20,
+///     ..Default::default()
+/// };
+/// let report = find_duplicates(&items, &options).unwrap();
+/// assert_eq!(report.groups.len(), 1);
+/// assert_eq!(report.groups[0].representative.name, "c");
+/// assert_eq!(report.groups[0].members[0].item.name, "a");
+/// assert_eq!(report.groups[0].members[0].score, 22);
+/// assert_eq!(report.redundant_bytes, 100);
+/// ```
+pub fn find_duplicates(items: &[Item], options: &DedupeOptions) -> Result<DedupeReport> {
+    let hashes: Vec<&str> = items.iter().map(|item| item.hash.as_str()).collect();
+    let mut report = DedupeReport::default();
+    for cluster in single_linkage(&hashes, options.threshold)? {
+        if cluster.len() < 2 {
+            continue;
+        }
+        let group = duplicate_group(items, &cluster, options.representative)?;
+        report.redundant_bytes += group.redundant_bytes;
+        report.groups.push(group);
+    }
+    report
+        .groups
+        .sort_by_key(|group| Reverse(group.redundant_bytes));
+    Ok(report)
+}
+
+/// Computes the fuzzy hashes and sizes of the given files and finds groups of
+/// near-duplicates among them.
+///
+/// The names of the items are the paths of the files. See
+/// [`find_duplicates()`](fn.find_duplicates.html) for more details.
+///
+/// When a file cannot be read or hashed, it returns an error.
+pub fn find_duplicate_files<P: AsRef<Path>>(
+    paths: &[P],
+    options: &DedupeOptions,
+) -> Result<DedupeReport> {
+    let mut items = Vec::with_capacity(paths.len());
+    for path in paths {
+        let path = path.as_ref();
+        items.push(Item {
+            name: path.display().to_string(),
+            hash: hash_from_file(path)?,
+            size: fs::metadata(path)?.len(),
+        });
+    }
+    find_duplicates(&items, options)
+}
+
+/// Creates a group from the items with the given indexes (in ascending order).
+fn duplicate_group(
+    items: &[Item],
+    cluster: &[usize],
+    representative: Representative,
+) -> Result<DuplicateGroup> {
+    let representative = match representative {
+        Representative::Largest => cluster
+            .iter()
+            .cloned()
+            .max_by_key(|&i| (items[i].size, usize::MAX - i))
+            .unwrap(),
+        Representative::First => cluster[0],
+        Representative::Medoid => {
+            let mut sums = vec![0u64; cluster.len()];
+            for (k, &i) in cluster.iter().enumerate() {
+                for (l, &j) in cluster.iter().enumerate().skip(k + 1) {
+                    let score = u64::from(compare(&items[i].hash, &items[j].hash)?);
+                    sums[k] += score;
+                    sums[l] += score;
+                }
+            }
+            let k = (0..cluster.len())
+                .max_by_key(|&k| (sums[k], usize::MAX - k))
+                .unwrap();
+            cluster[k]
+        }
+    };
+    let mut members = Vec::with_capacity(cluster.len() - 1);
+    for &i in cluster.iter().filter(|&&i| i != representative) {
+        members.push(Member {
+            item: items[i].clone(),
+            score: compare(&items[representative].hash, &items[i].hash)?,
+        });
+    }
+    Ok(DuplicateGroup {
+        representative: items[representative].clone(),
+        redundant_bytes: members.iter().map(|member| member.item.size).sum(),
+        members,
+    })
+}
@@ -0,0 +1,234 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Visual diffs of fuzzy hashes.
+
+use std::fmt::Write;
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use super::score::ParsedHash;
+use super::Result;
+
+/// A run of characters in a diff of two parts of fuzzy hashes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DiffRun {
+    /// Characters present in both parts.
+    Common(String),
+    /// Characters present only in the part of the first hash.
+    Removed(String),
+    /// Characters present only in the part of the second hash.
+    Added(String),
+}
+
+/// A diff of two parts of fuzzy hashes with a common block size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TrackDiff {
+    /// The common block size.
+    pub block_size: u64,
+    /// Runs of the diff, in the order of the characters in the parts.
+    pub runs: Vec<DiffRun>,
+}
+
+/// A diff of two fuzzy hashes, see
+/// [`diff_signatures()`](fn.diff_signatures.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SignatureDiff {
+    /// Diffs of the parts with a common block size, the smaller block size
+    /// first. It is empty when the hashes have no block size in common.
+    pub tracks: Vec<TrackDiff>,
+}
+
+/// Computes a diff of two fuzzy hashes.
+///
+/// The parts of the hashes with a common block size (the same parts that
+/// [`compare()`](fn.compare.html) compares) are aligned via their longest
+/// common subsequence and split into runs of common and differing characters.
+/// The diff can be rendered for terminals via
+/// [`to_ansi()`](struct.SignatureDiff.html#method.to_ansi) and for HTML
+/// reports via [`to_html()`](struct.SignatureDiff.html#method.to_html).
+///
+/// When any of the hashes is invalid, it returns an error.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::{diff_signatures, DiffRun};
+///
+/// let diff = diff_signatures("3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C", "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx").unwrap();
+/// assert_eq!(diff.tracks.len(), 2);
+/// assert_eq!(diff.tracks[0].runs[0], DiffRun::Common("AXGBicFl".to_string()));
+/// ```
+pub fn diff_signatures(hash1: &str, hash2: &str) -> Result<SignatureDiff> {
+    // Parsing validates the hashes, but the diff is computed over the raw
+    // parts so that it shows the hashes as they are.
+    let h1 = ParsedHash::parse(hash1)?;
+    let h2 = ParsedHash::parse(hash2)?;
+    let parts1 = raw_parts(hash1);
+    let parts2 = raw_parts(hash2);
+    let (bs1, bs2) = (h1.block_size, h2.block_size);
+
+    let mut pairs = Vec::new();
+    if bs1 == bs2 {
+        pairs.push((parts1[0], parts2[0], bs1));
+        if let Some(double_bs) = bs1.checked_mul(2) {
+            pairs.push((parts1[1], parts2[1], double_bs));
+        }
+    } else if bs1.checked_mul(2) == Some(bs2) {
+        pairs.push((parts1[1], parts2[0], bs2));
+    } else if bs1 % 2 == 0 && bs1 / 2 == bs2 {
+        pairs.push((parts1[0], parts2[1], bs1));
+    }
+    Ok(SignatureDiff {
+        tracks: pairs
+            .into_iter()
+            .map(|(part1, part2, block_size)| TrackDiff {
+                block_size,
+                runs: diff_parts(part1.as_bytes(), part2.as_bytes()),
+            })
+            .collect(),
+    })
+}
+
+impl SignatureDiff {
+    /// Renders the diff for terminals.
+    ///
+    /// Every track is rendered as two aligned lines (the part of the first hash
+    /// and the part of the second hash) prefixed with the block size. Common
+    /// characters are rendered normally, removed characters in red, and added
+    /// characters in green (via ANSI escape sequences), with spaces in the
+    /// other line to keep the lines aligned.
+    pub fn to_ansi(&self) -> String {
+        let mut output = String::new();
+        for track in &self.tracks {
+            let prefix = format!("{}:", track.block_size);
+            let mut line1 = prefix.clone();
+            let mut line2 = " ".repeat(prefix.len());
+            for run in &track.runs {
+                match *run {
+                    DiffRun::Common(ref s) => {
+                        line1.push_str(s);
+                        line2.push_str(s);
+                    }
+                    DiffRun::Removed(ref s) => {
+                        let _ = write!(line1, "\x1b[31m{}\x1b[0m", s);
+                        line2.push_str(&" ".repeat(s.len()));
+                    }
+                    DiffRun::Added(ref s) => {
+                        line1.push_str(&" ".repeat(s.len()));
+                        let _ = write!(line2, "\x1b[32m{}\x1b[0m", s);
+                    }
+                }
+            }
+            let _ = writeln!(output, "{}", line1);
+            let _ = writeln!(output, "{}", line2);
+        }
+        output
+    }
+
+    /// Renders the diff as an HTML fragment.
+    ///
+    /// Every track is rendered as a `<div class="ssdeep-diff">` starting with
+    /// the block size, with removed characters in `<del>` and added characters
+    /// in `<ins>` elements.
+    pub fn to_html(&self) -> String {
+        let mut output = String::new();
+        for track in &self.tracks {
+            let _ = write!(
+                output,
+                "<div class=\"ssdeep-diff\"><span class=\"block-size\">{}</span>:",
+                track.block_size
+            );
+            for run in &track.runs {
+                match *run {
+                    DiffRun::Common(ref s) => output.push_str(&html_escape(s)),
+                    DiffRun::Removed(ref s) => {
+                        let _ = write!(output, "<del>{}</del>", html_escape(s));
+                    }
+                    DiffRun::Added(ref s) => {
+                        let _ = write!(output, "<ins>{}</ins>", html_escape(s));
+                    }
+                }
+            }
+            output.push_str("</div>\n");
+        }
+        output
+    }
+}
+
+/// Returns the two parts of a valid hash (without a trailing `,suffix`).
+fn raw_parts(hash: &str) -> [&str; 2] {
+    let mut parts = hash.splitn(3, ':').skip(1);
+    let part1 = parts.next().unwrap_or("");
+    let part2 = parts.next().unwrap_or("");
+    [part1, part2.split(',').next().unwrap_or("")]
+}
+
+/// Computes runs of a diff of two parts via their longest common subsequence.
+fn diff_parts(s1: &[u8], s2: &[u8]) -> Vec<DiffRun> {
+    // lcs[i][j] is the length of the longest common subsequence of s1[i..]
+    // and s2[j..].
+    let mut lcs = vec![vec![0usize; s2.len() + 1]; s1.len() + 1];
+    for i in (0..s1.len()).rev() {
+        for j in (0..s2.len()).rev() {
+            lcs[i][j] = if s1[i] == s2[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut runs: Vec<DiffRun> = Vec::new();
+    let mut push = |run: DiffRun| {
+        // Merge the run with the previous one when they are of the same kind.
+        match (runs.last_mut(), run) {
+            (Some(DiffRun::Common(prev)), DiffRun::Common(s))
+            | (Some(DiffRun::Removed(prev)), DiffRun::Removed(s))
+            | (Some(DiffRun::Added(prev)), DiffRun::Added(s)) => prev.push_str(&s),
+            (_, run) => runs.push(run),
+        }
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < s1.len() || j < s2.len() {
+        if i < s1.len() && j < s2.len() && s1[i] == s2[j] {
+            push(DiffRun::Common((s1[i] as char).to_string()));
+            i += 1;
+            j += 1;
+        } else if j == s2.len() || (i < s1.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            push(DiffRun::Removed((s1[i] as char).to_string()));
+            i += 1;
+        } else {
+            push(DiffRun::Added((s2[j] as char).to_string()));
+            j += 1;
+        }
+    }
+    runs
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,211 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Rendering of similarities between fuzzy hashes and of clusters of hashes
+//! into the [DOT](https://graphviz.org/doc/info/lang.html) language of
+//! Graphviz.
+//!
+//! Nodes are hashes and edges connect pairs of hashes whose score is strictly
+//! greater than a threshold. The edges are labeled by their scores and their
+//! widths grow with the scores, so stronger relationships stand out. With
+//! [`write_clusters_dot()`](fn.write_clusters_dot.html), the hashes of every
+//! cluster are rendered in a separate box.
+
+use std::io::Write;
+
+use super::matrix::pairs_above;
+use super::Result;
+
+/// Labels of nodes in rendered graphs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeLabels {
+    /// Nodes are labeled by their hashes.
+    Hashes,
+    /// Nodes are labeled by the indexes of their hashes.
+    Indexes,
+    /// Nodes are labeled by the given labels (e.g. file names), indexed by
+    /// the indexes of their hashes. Nodes without a label are labeled by their
+    /// hashes.
+    Custom(Vec<String>),
+}
+
+/// Options for [`write_dot()`](fn.write_dot.html) and
+/// [`write_clusters_dot()`](fn.write_clusters_dot.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DotOptions {
+    /// Name of the graph.
+    ///
+    /// Default: `similarities`.
+    pub graph_name: String,
+    /// Labels of nodes.
+    ///
+    /// Default: [`NodeLabels::Hashes`](enum.NodeLabels.html).
+    pub node_labels: NodeLabels,
+    /// Whether edges are labeled by their scores.
+    ///
+    /// Default: `true`.
+    pub edge_labels: bool,
+    /// The width of edges with score 100. The width of an edge grows linearly
+    /// with its score from 1 (for score 0) to this width, so when it is 1,
+    /// all edges have the same width.
+    ///
+    /// Default: 5.
+    pub max_edge_width: f64,
+}
+
+impl Default for DotOptions {
+    fn default() -> Self {
+        DotOptions {
+            graph_name: "similarities".to_string(),
+            node_labels: NodeLabels::Hashes,
+            edge_labels: true,
+            max_edge_width: 5.0,
+        }
+    }
+}
+
+/// Writes the graph of the given hashes, with edges between hashes whose score
+/// is strictly greater than the threshold, in the DOT language into the given
+/// writer.
+///
+/// When a hash is invalid, it returns
+/// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash). When
+/// writing fails, it returns [`Error::Io`](../enum.Error.html#variant.Io).
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::dot::{write_dot, DotOptions, NodeLabels};
+///
+/// let options = DotOptions {
+///     node_labels: NodeLabels::Custom(vec!["a.exe".to_string(), "b.exe".to_string()]),
+///     ..Default::default()
+/// };
+/// let mut dot = Vec::new();
+/// write_dot(
+///     &mut dot,
+///     &[
+///         "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+///         "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx",
+///     ],
+///     0,
+///     &options,
+/// )
+/// .unwrap();
+/// let dot = String::from_utf8(dot).unwrap();
+/// assert!(dot.starts_with("graph \"similarities\" {\n"));
+/// assert!(dot.contains("  n0 [label=\"a.exe\"];\n"));
+/// assert!(dot.contains("  n0 -- n1 [label=\"22\", penwidth=1.88];\n"));
+/// ```
+pub fn write_dot<W: Write, H: AsRef<str>>(
+    writer: W,
+    hashes: &[H],
+    threshold: u8,
+    options: &DotOptions,
+) -> Result<()> {
+    write_graph(writer, hashes, None, threshold, options)
+}
+
+/// Writes the given clusters of hashes (e.g. returned by
+/// [`cluster::single_linkage()`](../cluster/fn.single_linkage.html)), with
+/// edges between hashes whose score is strictly greater than the threshold,
+/// in the DOT language into the given writer.
+///
+/// Clusters are lists of indexes of hashes. Every cluster is rendered as a
+/// separate subgraph named `cluster_<number of the cluster>` (so Graphviz
+/// draws a box around it). Hashes that are not in any cluster are rendered
+/// outside of the subgraphs.
+///
+/// When a hash is invalid, it returns
+/// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash). When
+/// writing fails, it returns [`Error::Io`](../enum.Error.html#variant.Io).
+pub fn write_clusters_dot<W: Write, H: AsRef<str>>(
+    writer: W,
+    hashes: &[H],
+    clusters: &[Vec<usize>],
+    threshold: u8,
+    options: &DotOptions,
+) -> Result<()> {
+    write_graph(writer, hashes, Some(clusters), threshold, options)
+}
+
+fn write_graph<W: Write, H: AsRef<str>>(
+    mut writer: W,
+    hashes: &[H],
+    clusters: Option<&[Vec<usize>]>,
+    threshold: u8,
+    options: &DotOptions,
+) -> Result<()> {
+    let pairs = pairs_above(hashes, threshold)?;
+    writeln!(writer, "graph \"{}\" {{", dot_escape(&options.graph_name))?;
+    let mut in_cluster = vec![false; hashes.len()];
+    for (number, cluster) in clusters.unwrap_or(&[]).iter().enumerate() {
+        writeln!(writer, "  subgraph cluster_{} {{", number)?;
+        for &i in cluster {
+            write!(writer, "  ")?;
+            write_node(&mut writer, hashes, i, options)?;
+            in_cluster[i] = true;
+        }
+        writeln!(writer, "  }}")?;
+    }
+    for i in (0..hashes.len()).filter(|&i| !in_cluster[i]) {
+        write_node(&mut writer, hashes, i, options)?;
+    }
+    for pair in pairs {
+        write!(writer, "  n{} -- n{} [", pair.i, pair.j)?;
+        if options.edge_labels {
+            write!(writer, "label=\"{}\", ", pair.score)?;
+        }
+        let width = 1.0 + (options.max_edge_width - 1.0) * f64::from(pair.score) / 100.0;
+        writeln!(writer, "penwidth={:.2}];", width)?;
+    }
+    writeln!(writer, "}}")?;
+    Ok(())
+}
+
+fn write_node<W: Write, H: AsRef<str>>(
+    writer: &mut W,
+    hashes: &[H],
+    i: usize,
+    options: &DotOptions,
+) -> Result<()> {
+    let index;
+    let label = match options.node_labels {
+        NodeLabels::Indexes => {
+            index = i.to_string();
+            &index
+        }
+        NodeLabels::Custom(ref labels) if i < labels.len() => &labels[i],
+        _ => hashes[i].as_ref(),
+    };
+    writeln!(writer, "  n{} [label=\"{}\"];", i, dot_escape(label))?;
+    Ok(())
+}
+
+fn dot_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
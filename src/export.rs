@@ -0,0 +1,277 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Summarization of clusters of related samples for detection engineering.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use super::compare;
+use super::Phase;
+use super::Progress;
+use super::Result;
+
+/// A sample (e.g. a file) with its fuzzy hash and arbitrary metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Sample {
+    /// Name of the sample (e.g. its file name or SHA-256).
+    pub name: String,
+    /// Fuzzy hash of the sample.
+    pub hash: String,
+    /// Metadata of the sample (e.g. its family or source).
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// A summary of a cluster of related samples.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ClusterSummary {
+    /// The number of samples in the cluster.
+    pub sample_count: usize,
+    /// Representative samples of the cluster, the most central one first.
+    pub representatives: Vec<Sample>,
+    /// Metadata whose values are the same for all samples in the cluster.
+    pub common_metadata: BTreeMap<String, String>,
+    /// The lowest score between a sample and its most similar representative.
+    pub min_score: u8,
+}
+
+/// Summarizes a cluster of related samples.
+///
+/// The first representative is the medoid of the cluster, i.e. the sample
+/// whose sum of scores to all other samples is the highest. Each subsequent
+/// representative is the sample that is the least similar to the
+/// representatives selected so far, which makes the representatives cover the
+/// whole cluster even when it is not compact. At most `max_representatives`
+/// representatives are selected, and the selection stops earlier when every
+/// sample is identical to a representative.
+///
+/// When any of the hashes is invalid, it returns an error.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::export::{summarize_cluster, Sample};
+///
+/// let sample = |name: &str, hash: &str| Sample {
+///     name: name.to_string(),
+///     hash: hash.to_string(),
+///     metadata: Default::default(),
+/// };
+/// let samples = vec![
+///     sample("a", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C"),
+///     sample("b", "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx"),
+/// ];
+/// let summary = summarize_cluster(&samples, 1).unwrap();
+/// assert_eq!(summary.sample_count, 2);
+/// assert_eq!(summary.representatives.len(), 1);
+/// assert_eq!(summary.min_score, 22);
+/// ```
+pub fn summarize_cluster(samples: &[Sample], max_representatives: usize) -> Result<ClusterSummary> {
+    summarize_cluster_with_progress(samples, max_representatives, |_| {})
+}
+
+/// Summarizes a cluster of related samples, reporting the progress to the
+/// given callback.
+///
+/// The progress is reported in the [`Comparing`](../enum.Phase.html) phase
+/// (after comparing each sample with the remaining samples) and in the
+/// [`Summarizing`](../enum.Phase.html) phase (after selecting each
+/// representative). See [`summarize_cluster()`](fn.summarize_cluster.html)
+/// for more details.
+pub fn summarize_cluster_with_progress<F>(
+    samples: &[Sample],
+    max_representatives: usize,
+    mut progress: F,
+) -> Result<ClusterSummary>
+where
+    F: FnMut(&Progress),
+{
+    let n = samples.len();
+    let total_comparisons = (n as u64) * (n as u64).saturating_sub(1) / 2;
+    let mut comparisons = 0;
+    let mut scores = vec![vec![100; n]; n];
+    for i in 0..n {
+        for j in i + 1..n {
+            let score = compare(&samples[i].hash, &samples[j].hash)?;
+            scores[i][j] = score;
+            scores[j][i] = score;
+        }
+        comparisons += (n - i - 1) as u64;
+        progress(&Progress {
+            phase: Phase::Comparing,
+            done: comparisons,
+            total: Some(total_comparisons),
+            current_path: None,
+        });
+    }
+
+    // For each sample, the score to its most similar representative.
+    let mut coverage = vec![0; n];
+    let mut representatives: Vec<usize> = Vec::new();
+    while representatives.len() < max_representatives && coverage.iter().any(|&c| c < 100) {
+        let next = if representatives.is_empty() {
+            (0..n).max_by_key(|&i| (scores[i].iter().map(|&s| u64::from(s)).sum::<u64>(), n - i))
+        } else {
+            (0..n)
+                .filter(|i| !representatives.contains(i))
+                .min_by_key(|&i| (coverage[i], i))
+        };
+        let next = match next {
+            Some(next) => next,
+            None => break,
+        };
+        for i in 0..n {
+            coverage[i] = coverage[i].max(scores[i][next]);
+        }
+        representatives.push(next);
+        progress(&Progress {
+            phase: Phase::Summarizing,
+            done: representatives.len() as u64,
+            total: None,
+            current_path: None,
+        });
+    }
+
+    Ok(ClusterSummary {
+        sample_count: n,
+        representatives: representatives
+            .iter()
+            .map(|&i| samples[i].clone())
+            .collect(),
+        common_metadata: common_metadata(samples),
+        min_score: if representatives.is_empty() {
+            0
+        } else {
+            coverage.iter().cloned().min().unwrap_or(0)
+        },
+    })
+}
+
+impl ClusterSummary {
+    /// Generates a skeleton of a [YARA](https://virustotal.github.io/yara/)
+    /// rule for the cluster.
+    ///
+    /// The fuzzy hashes of the representatives and the common metadata are
+    /// embedded into the `meta` section of the rule. The condition of the rule
+    /// is `false` as it is meant to be completed by a detection engineer.
+    /// Characters that are not allowed in YARA identifiers are replaced with
+    /// underscores in the rule name and metadata keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ssdeep::export::ClusterSummary;
+    ///
+    /// let summary = ClusterSummary {
+    ///     sample_count: 1,
+    ///     representatives: vec![],
+    ///     common_metadata: vec![("family".to_string(), "x".to_string())]
+    ///         .into_iter()
+    ///         .collect(),
+    ///     min_score: 0,
+    /// };
+    /// let rule = summary.to_yara_rule("my-cluster");
+    /// assert!(rule.starts_with("rule my_cluster\n"));
+    /// assert!(rule.contains("family = \"x\""));
+    /// ```
+    pub fn to_yara_rule(&self, rule_name: &str) -> String {
+        let mut rule = String::new();
+        // Writing into a String cannot fail, so we can ignore the results.
+        let _ = writeln!(rule, "rule {}", yara_identifier(rule_name));
+        let _ = writeln!(rule, "{{");
+        let _ = writeln!(rule, "    meta:");
+        let _ = writeln!(
+            rule,
+            "        description = \"Cluster of {} sample(s)\"",
+            self.sample_count
+        );
+        let _ = writeln!(rule, "        min_score = {}", self.min_score);
+        for (i, sample) in self.representatives.iter().enumerate() {
+            let _ = writeln!(
+                rule,
+                "        ssdeep_{} = \"{}\"",
+                i + 1,
+                yara_string(&sample.hash)
+            );
+            let _ = writeln!(
+                rule,
+                "        sample_{} = \"{}\"",
+                i + 1,
+                yara_string(&sample.name)
+            );
+        }
+        for (key, value) in &self.common_metadata {
+            let _ = writeln!(
+                rule,
+                "        {} = \"{}\"",
+                yara_identifier(key),
+                yara_string(value)
+            );
+        }
+        let _ = writeln!(rule, "    condition:");
+        let _ = writeln!(rule, "        // TODO: Add strings and a condition.");
+        let _ = writeln!(rule, "        false");
+        let _ = writeln!(rule, "}}");
+        rule
+    }
+}
+
+fn common_metadata(samples: &[Sample]) -> BTreeMap<String, String> {
+    let mut common = match samples.first() {
+        Some(sample) => sample.metadata.clone(),
+        None => return BTreeMap::new(),
+    };
+    for sample in &samples[1..] {
+        common.retain(|key, value| sample.metadata.get(key) == Some(value));
+    }
+    common
+}
+
+fn yara_identifier(s: &str) -> String {
+    let mut identifier: String = s
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !identifier.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        identifier.insert(0, '_');
+    }
+    identifier
+}
+
+fn yara_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\x{:02x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
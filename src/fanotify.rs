@@ -0,0 +1,256 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! On-access hashing of files via the fanotify API of Linux.
+//!
+//! This module is available only on Linux when the `fanotify` feature is
+//! enabled. A [`Monitor`](struct.Monitor.html) receives events when watched
+//! files are opened or closed after writing, hashes the files, and classifies
+//! them via a [`Classifier`](../classify/struct.Classifier.html). It is a
+//! building block for lightweight on-access scanners.
+//!
+//! The fanotify API is privileged: creating a monitor requires the
+//! `CAP_SYS_ADMIN` capability.
+
+use std::ffi::CString;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::FromRawFd;
+use std::os::unix::io::RawFd;
+use std::path::Path;
+use std::path::PathBuf;
+
+use libc::c_int;
+
+use super::classify::Classification;
+use super::classify::Classifier;
+use super::hasher::hash_and_copy;
+use super::Error;
+use super::Result;
+
+/// Size of the buffer into which events are read.
+const BUFFER_SIZE: usize = 16 * 1024;
+
+/// The access to a file that triggered an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// The file was opened.
+    Open,
+    /// The file was closed after it had been opened for writing.
+    CloseWrite,
+}
+
+/// The result of hashing and classifying a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verdict {
+    /// Fuzzy hash of the file.
+    pub hash: String,
+    /// Classification of the file.
+    pub classification: Classification,
+}
+
+/// An event about an access to a watched file.
+#[derive(Debug)]
+pub struct Event {
+    /// Path to the file.
+    pub path: PathBuf,
+    /// ID of the process that accessed the file.
+    pub pid: i32,
+    /// The access that triggered the event.
+    pub trigger: Trigger,
+    /// The verdict about the file, or an error when the file could not be
+    /// hashed or classified. Files that are not regular files (e.g. FIFOs or
+    /// device nodes) are not hashed; an error of the
+    /// [`InvalidInput`](https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.InvalidInput)
+    /// kind is reported for them.
+    pub verdict: Result<Verdict>,
+}
+
+/// A monitor of accesses to files that hashes and classifies the accessed
+/// files.
+///
+/// Accesses by the process running the monitor are not reported, so the
+/// hashing of files does not trigger further events.
+pub struct Monitor {
+    fd: RawFd,
+    classifier: Classifier,
+}
+
+impl Monitor {
+    /// Creates a monitor that classifies files via the given classifier.
+    ///
+    /// No files are watched until [`watch_path()`](#method.watch_path) or
+    /// [`watch_mount()`](#method.watch_mount) is called. When the fanotify
+    /// API is not available or the process lacks the needed privileges, it
+    /// returns an error.
+    pub fn new(classifier: Classifier) -> Result<Monitor> {
+        let fd = unsafe {
+            libc::fanotify_init(
+                libc::FAN_CLASS_NOTIF | libc::FAN_CLOEXEC,
+                (libc::O_RDONLY | libc::O_LARGEFILE | libc::O_CLOEXEC) as u32,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(Monitor { fd, classifier })
+    }
+
+    /// Returns the classifier of the monitor.
+    pub fn classifier(&self) -> &Classifier {
+        &self.classifier
+    }
+
+    /// Starts watching the given file or the files in the given directory
+    /// (not recursively).
+    ///
+    /// When the path cannot be watched, it returns an error.
+    pub fn watch_path<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.mark(path.as_ref(), 0, libc::FAN_EVENT_ON_CHILD)
+    }
+
+    /// Starts watching all files on the mount point containing the given path.
+    ///
+    /// When the path cannot be watched, it returns an error.
+    pub fn watch_mount<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.mark(path.as_ref(), libc::FAN_MARK_MOUNT, 0)
+    }
+
+    /// Waits for events and returns them.
+    ///
+    /// It blocks until at least one event is available. When the events
+    /// cannot be read, it returns an error.
+    pub fn next_events(&mut self) -> Result<Vec<Event>> {
+        // A buffer of u64s to have the events properly aligned.
+        let mut buf = vec![0u64; BUFFER_SIZE / 8];
+        let len = loop {
+            let len = unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut _, BUFFER_SIZE) };
+            if len >= 0 {
+                break len as usize;
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(e.into());
+            }
+        };
+
+        let mut events = Vec::new();
+        let metadata_len = mem::size_of::<libc::fanotify_event_metadata>();
+        let mut offset = 0;
+        while offset + metadata_len <= len {
+            let metadata = unsafe {
+                &*((buf.as_ptr() as *const u8).add(offset) as *const libc::fanotify_event_metadata)
+            };
+            let event_len = metadata.event_len as usize;
+            if metadata.vers != libc::FANOTIFY_METADATA_VERSION
+                || event_len < metadata_len
+                || offset + event_len > len
+            {
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid fanotify event",
+                )));
+            }
+            offset += event_len;
+
+            // The queue overflow event does not carry any file.
+            if metadata.fd < 0 {
+                continue;
+            }
+            let file = unsafe { File::from_raw_fd(metadata.fd) };
+            if metadata.pid as u32 == std::process::id() {
+                continue;
+            }
+            let trigger = if metadata.mask & libc::FAN_CLOSE_WRITE != 0 {
+                Trigger::CloseWrite
+            } else {
+                Trigger::Open
+            };
+            events.push(Event {
+                path: fd_path(metadata.fd),
+                pid: metadata.pid,
+                trigger,
+                verdict: self.verdict(file),
+            });
+        }
+        Ok(events)
+    }
+
+    /// Processes events until the given callback returns `false`.
+    ///
+    /// When the events cannot be read, it returns an error.
+    pub fn run<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(Event) -> bool,
+    {
+        loop {
+            for event in self.next_events()? {
+                if !f(event) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn mark(&mut self, path: &Path, flags: u32, extra_mask: u64) -> Result<()> {
+        let path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let rc = unsafe {
+            libc::fanotify_mark(
+                self.fd,
+                libc::FAN_MARK_ADD | flags,
+                libc::FAN_OPEN | libc::FAN_CLOSE_WRITE | extra_mask,
+                libc::AT_FDCWD,
+                path.as_ptr(),
+            )
+        };
+        if rc < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    fn verdict(&self, file: File) -> Result<Verdict> {
+        // FIFOs and device nodes (e.g. /dev/zero) may block or never end, so
+        // reading them would stop the processing of all events.
+        if !file.metadata()?.is_file() {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a regular file",
+            )));
+        }
+        let hash = hash_and_copy(file, io::sink())?;
+        let classification = self.classifier.classify_hash(&hash)?;
+        Ok(Verdict {
+            hash,
+            classification,
+        })
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+fn fd_path(fd: c_int) -> PathBuf {
+    fs::read_link(format!("/proc/self/fd/{}", fd)).unwrap_or_default()
+}
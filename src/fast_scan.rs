@@ -0,0 +1,225 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Fast enumeration of files on NTFS volumes via the master file table.
+//!
+//! This module is available only on Windows when the `fast-scan` feature is
+//! enabled. Instead of walking directories, it reads all file records of a
+//! volume from its master file table (MFT) in large batches (via
+//! `FSCTL_ENUM_USN_DATA`), which is much faster on large volumes. Accessing
+//! the volume requires administrator privileges.
+//!
+//! The enumerated files can be fed into the stages of the
+//! [`pipeline`](../pipeline/index.html) module.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io;
+use std::mem;
+use std::os::windows::ffi::OsStrExt;
+use std::os::windows::ffi::OsStringExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::ptr;
+
+use crossbeam_channel::Receiver;
+use windows_sys::Win32::Foundation::CloseHandle;
+use windows_sys::Win32::Foundation::ERROR_HANDLE_EOF;
+use windows_sys::Win32::Foundation::GENERIC_READ;
+use windows_sys::Win32::Foundation::HANDLE;
+use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+use windows_sys::Win32::Storage::FileSystem::CreateFileW;
+use windows_sys::Win32::Storage::FileSystem::FILE_ATTRIBUTE_DIRECTORY;
+use windows_sys::Win32::Storage::FileSystem::FILE_SHARE_READ;
+use windows_sys::Win32::Storage::FileSystem::FILE_SHARE_WRITE;
+use windows_sys::Win32::Storage::FileSystem::OPEN_EXISTING;
+use windows_sys::Win32::System::Ioctl::FSCTL_ENUM_USN_DATA;
+use windows_sys::Win32::System::Ioctl::MFT_ENUM_DATA_V0;
+use windows_sys::Win32::System::Ioctl::USN_RECORD_V2;
+use windows_sys::Win32::System::IO::DeviceIoControl;
+
+use super::pipeline::read_files;
+use super::pipeline::Item;
+use super::Result;
+
+/// Size of the buffer into which file records are read.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// A file record from the master file table.
+struct Record {
+    parent: u64,
+    name: OsString,
+    is_dir: bool,
+}
+
+/// Returns paths to all files (not directories) on the given NTFS volume,
+/// sorted by the paths.
+///
+/// The volume is specified by its drive letter followed by a colon (e.g.
+/// `C:`). When the volume cannot be opened (e.g. because of insufficient
+/// privileges or because it is not an NTFS volume) or its records cannot be
+/// read, it returns an error.
+pub fn enumerate_files(volume: &str) -> Result<Vec<PathBuf>> {
+    let volume = volume.trim_end_matches('\\');
+    let records = read_records(volume)?;
+
+    // Paths of directories, computed lazily. Records whose parent is not in
+    // the table are in the root directory.
+    let mut dir_paths: HashMap<u64, PathBuf> = HashMap::new();
+    let root = PathBuf::from(format!("{}\\", volume));
+    let mut paths = Vec::new();
+    for record in records.values().filter(|r| !r.is_dir) {
+        let mut path = dir_path(record.parent, &records, &mut dir_paths, &root);
+        path.push(&record.name);
+        paths.push(path);
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Reads all files on the given NTFS volume in a new thread (see
+/// [`pipeline::read_files()`](../pipeline/fn.read_files.html)).
+///
+/// The files are enumerated via [`enumerate_files()`](fn.enumerate_files.html)
+/// before the reading starts, so an error is returned when they cannot be
+/// enumerated.
+pub fn read_volume_files(volume: &str, capacity: usize) -> Result<Receiver<Item<Vec<u8>>>> {
+    Ok(read_files(enumerate_files(volume)?, capacity))
+}
+
+fn dir_path(
+    id: u64,
+    records: &HashMap<u64, Record>,
+    dir_paths: &mut HashMap<u64, PathBuf>,
+    root: &Path,
+) -> PathBuf {
+    // Collect the ancestors whose paths are not known yet, stopping at the
+    // root (or at a cycle, which would mean a corrupted table).
+    let mut missing = Vec::new();
+    let mut current = id;
+    let mut path = loop {
+        if let Some(path) = dir_paths.get(&current) {
+            break path.clone();
+        }
+        match records.get(&current) {
+            Some(record) if !missing.contains(&current) => {
+                missing.push(current);
+                current = record.parent;
+            }
+            _ => break root.to_path_buf(),
+        }
+    };
+    for id in missing.into_iter().rev() {
+        path.push(&records[&id].name);
+        dir_paths.insert(id, path.clone());
+    }
+    path
+}
+
+fn read_records(volume: &str) -> io::Result<HashMap<u64, Record>> {
+    let volume_path: Vec<u16> = OsString::from(format!("\\\\.\\{}", volume))
+        .encode_wide()
+        .chain(Some(0))
+        .collect();
+    let handle = unsafe {
+        CreateFileW(
+            volume_path.as_ptr(),
+            GENERIC_READ,
+            FILE_SHARE_READ | FILE_SHARE_WRITE,
+            ptr::null(),
+            OPEN_EXISTING,
+            0,
+            ptr::null_mut(),
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        return Err(io::Error::last_os_error());
+    }
+    let records = read_records_from(handle);
+    unsafe { CloseHandle(handle) };
+    records
+}
+
+fn read_records_from(handle: HANDLE) -> io::Result<HashMap<u64, Record>> {
+    let mut records = HashMap::new();
+    let mut enum_data = MFT_ENUM_DATA_V0 {
+        StartFileReferenceNumber: 0,
+        LowUsn: 0,
+        HighUsn: i64::MAX,
+    };
+    // A buffer of u64s to have the records properly aligned.
+    let mut buf = vec![0u64; BUFFER_SIZE / 8];
+    loop {
+        let mut returned = 0;
+        let ok = unsafe {
+            DeviceIoControl(
+                handle,
+                FSCTL_ENUM_USN_DATA,
+                &enum_data as *const _ as *const _,
+                mem::size_of::<MFT_ENUM_DATA_V0>() as u32,
+                buf.as_mut_ptr() as *mut _,
+                BUFFER_SIZE as u32,
+                &mut returned,
+                ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            let e = io::Error::last_os_error();
+            if e.raw_os_error() == Some(ERROR_HANDLE_EOF as i32) {
+                return Ok(records);
+            }
+            return Err(e);
+        }
+
+        // The output starts with the reference number to continue from,
+        // followed by the records.
+        let bytes =
+            unsafe { std::slice::from_raw_parts(buf.as_ptr() as *const u8, returned as usize) };
+        if bytes.len() < 8 {
+            return Ok(records);
+        }
+        enum_data.StartFileReferenceNumber = buf[0];
+        let mut offset = 8;
+        while offset + mem::size_of::<USN_RECORD_V2>() <= bytes.len() {
+            let record =
+                unsafe { ptr::read_unaligned(bytes[offset..].as_ptr() as *const USN_RECORD_V2) };
+            let len = record.RecordLength as usize;
+            if len == 0 || offset + len > bytes.len() {
+                break;
+            }
+            if record.MajorVersion == 2 {
+                let name_start = offset + record.FileNameOffset as usize;
+                let name_end = name_start + record.FileNameLength as usize;
+                if name_end <= offset + len {
+                    let name: Vec<u16> = bytes[name_start..name_end]
+                        .chunks(2)
+                        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                        .collect();
+                    records.insert(
+                        record.FileReferenceNumber,
+                        Record {
+                            parent: record.ParentFileReferenceNumber,
+                            name: OsString::from_wide(&name),
+                            is_dir: record.FileAttributes & FILE_ATTRIBUTE_DIRECTORY != 0,
+                        },
+                    );
+                }
+            }
+            offset += len;
+        }
+    }
+}
@@ -0,0 +1,154 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Comparison of structured records (e.g. emails or executables) by fuzzy
+//! hashes of their individual fields or sections.
+//!
+//! Comparing records field by field is more robust than comparing a single
+//! hash of the whole record: a large change in one field (e.g. an attachment)
+//! does not hide the similarity of the other fields (e.g. the body).
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use super::compare;
+use super::hash;
+use super::Result;
+
+/// Fuzzy hashes of fields of a record, keyed by field names.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecordHash {
+    /// Fuzzy hashes of the fields.
+    pub fields: BTreeMap<String, String>,
+}
+
+/// The score of a single field.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FieldScore {
+    /// Name of the field.
+    pub name: String,
+    /// Weight of the field.
+    pub weight: f64,
+    /// Match score of the field, which is 0 when the field is present in only
+    /// one of the records.
+    pub score: u8,
+}
+
+/// An aggregate score of two records with a per-field breakdown.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AggregateScore {
+    /// The weighted average of the field scores (between 0 and 100).
+    pub score: u8,
+    /// Scores of the individual fields, sorted by field names.
+    pub fields: Vec<FieldScore>,
+}
+
+/// Computes fuzzy hashes of fields of a record.
+///
+/// When a field name appears multiple times, the last field wins. When a hash
+/// cannot be computed, it returns an error.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::fields::hash_fields;
+///
+/// let record = hash_fields(vec![
+///     ("subject", &b"Hello"[..]),
+///     ("body", &b"Hello there!"[..]),
+/// ])
+/// .unwrap();
+/// assert_eq!(record.fields["body"], "3:aNRn:aNRn");
+/// ```
+pub fn hash_fields<'a, I>(fields: I) -> Result<RecordHash>
+where
+    I: IntoIterator<Item = (&'a str, &'a [u8])>,
+{
+    let mut record = RecordHash::default();
+    for (name, data) in fields {
+        record.fields.insert(name.to_string(), hash(data)?);
+    }
+    Ok(record)
+}
+
+/// Compares two records field by field and combines the field scores into a
+/// weighted aggregate score.
+///
+/// All fields present in at least one of the records are compared. The weight
+/// of a field is taken from `weights`, and fields without a weight there have
+/// weight 1. A field present in only one of the records scores 0, so missing
+/// fields lower the aggregate score. Fields with zero weight are still
+/// reported in the breakdown but do not affect the aggregate score. When there
+/// are no fields with a positive weight, the aggregate score is 0.
+///
+/// When any of the hashes is invalid, it returns an error.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use ssdeep::fields::{compare_records, hash_fields};
+///
+/// let r1 = hash_fields(vec![("body", &b"Hello there!"[..]), ("x", &b"abc"[..])]).unwrap();
+/// let r2 = hash_fields(vec![("body", &b"Hello there!"[..])]).unwrap();
+/// let mut weights = BTreeMap::new();
+/// weights.insert("body".to_string(), 3.0);
+/// let aggregate = compare_records(&r1, &r2, &weights).unwrap();
+/// assert_eq!(aggregate.score, 75);
+/// assert_eq!(aggregate.fields.len(), 2);
+/// ```
+pub fn compare_records(
+    r1: &RecordHash,
+    r2: &RecordHash,
+    weights: &BTreeMap<String, f64>,
+) -> Result<AggregateScore> {
+    let mut names: Vec<_> = r1.fields.keys().chain(r2.fields.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    let mut fields = Vec::with_capacity(names.len());
+    let mut weighted_sum = 0.0;
+    let mut total_weight = 0.0;
+    for name in names {
+        let score = match (r1.fields.get(name), r2.fields.get(name)) {
+            (Some(h1), Some(h2)) => compare(h1, h2)?,
+            _ => 0,
+        };
+        let weight = weights.get(name).cloned().unwrap_or(1.0).max(0.0);
+        weighted_sum += weight * f64::from(score);
+        total_weight += weight;
+        fields.push(FieldScore {
+            name: name.clone(),
+            weight,
+            score,
+        });
+    }
+
+    let score = if total_weight > 0.0 {
+        (weighted_sum / total_weight).round() as u8
+    } else {
+        0
+    };
+    Ok(AggregateScore { score, fields })
+}
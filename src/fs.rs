@@ -0,0 +1,421 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Fuzzy hashing of files in directory trees.
+//!
+//! # Examples
+//!
+//! ```
+//! use ssdeep::fs::HashDirOptions;
+//!
+//! let options = HashDirOptions {
+//!     max_depth: Some(0),
+//!     include: vec!["*.txt".to_string()],
+//!     ..HashDirOptions::default()
+//! };
+//! for entry in ssdeep::fs::hash_dir("tests", &options).unwrap() {
+//!     let (path, hash) = entry.unwrap();
+//!     println!("{} {}", hash, path.display());
+//! }
+//! ```
+//!
+//! # Patterns
+//!
+//! The include and exclude patterns (as well as the patterns in `.gitignore`
+//! files) are glob patterns matched against paths relative to the root (or
+//! to the directory containing the `.gitignore` file), with `/` as the path
+//! separator:
+//!
+//! * `?` matches any character other than `/`,
+//! * `*` matches any sequence of characters other than `/`,
+//! * `**/` matches zero or more directories and `**` elsewhere matches any
+//!   sequence of characters,
+//! * `[...]` matches a character from the given set or ranges (e.g.
+//!   `[a-z0-9]`), `[!...]` or `[^...]` any other character,
+//! * `\` escapes the following character.
+//!
+//! As in `.gitignore` files, a pattern without a `/` (other than a trailing
+//! one) matches the name of an entry at any depth (e.g. `*.exe`), while a
+//! pattern containing a `/` is anchored to the root (e.g. `bin/*.exe` or
+//! `/build`). A pattern with a trailing `/` matches only directories.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::fs::DirEntry;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::vec;
+
+use super::glob::Glob;
+use super::hash_from_file;
+use super::FuzzyHash;
+use super::Phase;
+use super::Progress;
+use super::Result;
+
+/// Options for [`hash_dir()`](fn.hash_dir.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashDirOptions {
+    /// The maximal number of directory levels below the root to descend
+    /// into. `Some(0)` hashes only the files directly in the root, `None`
+    /// means no limit.
+    ///
+    /// Default: `None`.
+    pub max_depth: Option<usize>,
+    /// Whether hidden files and directories are included. An entry is
+    /// hidden when its name starts with a dot (or, on Windows, when it has
+    /// the hidden attribute). When hidden directories are not included, they
+    /// are not descended into.
+    ///
+    /// Default: `true`.
+    pub include_hidden: bool,
+    /// Patterns of files to be hashed (see the [module
+    /// documentation](index.html#patterns)). When non-empty, only files
+    /// matching at least one of the patterns are hashed. The patterns do not
+    /// restrict which directories are descended into.
+    ///
+    /// Default: empty (all files are hashed).
+    pub include: Vec<String>,
+    /// Patterns of files and directories to be skipped (see the [module
+    /// documentation](index.html#patterns)). Excluded directories are not
+    /// descended into. Exclusion takes precedence over inclusion.
+    ///
+    /// Default: empty.
+    pub exclude: Vec<String>,
+    /// Whether entries ignored by `.gitignore` files are skipped, as `git`
+    /// would skip them. The `.gitignore` file of each walked directory
+    /// applies to the entries below it, with the patterns of deeper files
+    /// and later patterns taking precedence, and a pattern starting with `!`
+    /// re-includes previously ignored entries. The `.git` directories are
+    /// skipped as well. Global and repository-specific exclude files are not
+    /// considered.
+    ///
+    /// Default: `false`.
+    pub respect_gitignore: bool,
+}
+
+impl Default for HashDirOptions {
+    fn default() -> Self {
+        HashDirOptions {
+            max_depth: None,
+            include_hidden: true,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            respect_gitignore: false,
+        }
+    }
+}
+
+/// Returns an iterator over the fuzzy hashes of files in the given directory
+/// tree.
+///
+/// The iterator walks the tree lazily, depth first, with the entries of each
+/// directory visited in the order of their names, so the files are always
+/// reported in the same order. Each file is hashed when it is reached. Only
+/// regular files are hashed; symbolic links are neither hashed nor followed.
+/// Entries are skipped according to the given options.
+///
+/// When the root directory (or its `.gitignore` file) cannot be read, it
+/// returns an error. When a file cannot be hashed or a subdirectory cannot be
+/// read, the iterator yields an error for it and continues with the next
+/// entry.
+pub fn hash_dir<P: AsRef<Path>>(root: P, options: &HashDirOptions) -> Result<HashDir> {
+    hash_dir_with_progress(root, options, no_progress as fn(&Progress))
+}
+
+/// Returns an iterator over the fuzzy hashes of files in the given directory
+/// tree, which reports the progress (in the [`Hashing`](../enum.Phase.html)
+/// phase) to the given callback.
+///
+/// The callback is called before each file is hashed, with the number of
+/// files processed so far and the path of the file, and once more after the
+/// walk has finished (without a path). As the tree is walked lazily, the
+/// total number of files is unknown.
+///
+/// See [`hash_dir()`](fn.hash_dir.html) for more details.
+pub fn hash_dir_with_progress<P, F>(
+    root: P,
+    options: &HashDirOptions,
+    progress: F,
+) -> Result<HashDir<F>>
+where
+    P: AsRef<Path>,
+    F: FnMut(&Progress),
+{
+    let mut hash_dir = HashDir {
+        max_depth: options.max_depth,
+        include_hidden: options.include_hidden,
+        include: options.include.iter().map(|p| Pattern::new(p)).collect(),
+        exclude: options.exclude.iter().map(|p| Pattern::new(p)).collect(),
+        respect_gitignore: options.respect_gitignore,
+        stack: Vec::new(),
+        progress,
+        processed: 0,
+        finished: false,
+    };
+    hash_dir.push_dir(root.as_ref(), 0, String::new())?;
+    Ok(hash_dir)
+}
+
+/// An iterator over the fuzzy hashes of files in a directory tree.
+///
+/// It is returned by [`hash_dir()`](fn.hash_dir.html) and
+/// [`hash_dir_with_progress()`](fn.hash_dir_with_progress.html).
+#[derive(Debug)]
+pub struct HashDir<F = fn(&Progress)> {
+    max_depth: Option<usize>,
+    include_hidden: bool,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    respect_gitignore: bool,
+    /// The directories being walked, from the root.
+    stack: Vec<WalkedDir>,
+    progress: F,
+    /// The number of files processed so far.
+    processed: u64,
+    /// Whether the final progress has been reported.
+    finished: bool,
+}
+
+/// A directory being walked.
+#[derive(Debug)]
+struct WalkedDir {
+    /// The depth below the root.
+    depth: usize,
+    /// The path relative to the root (empty for the root).
+    relative_path: String,
+    /// The remaining entries.
+    entries: vec::IntoIter<DirEntry>,
+    /// The patterns from the `.gitignore` file in the directory.
+    gitignore: Vec<Pattern>,
+}
+
+/// A glob pattern with `.gitignore` semantics.
+#[derive(Debug)]
+struct Pattern {
+    glob: Glob,
+    /// Whether the pattern is matched against whole relative paths (rather
+    /// than against names).
+    anchored: bool,
+    /// Whether the pattern matches only directories.
+    dirs_only: bool,
+    /// Whether the pattern re-includes matching entries (`!` in
+    /// `.gitignore` files).
+    negated: bool,
+}
+
+impl Pattern {
+    fn new(pattern: &str) -> Pattern {
+        let dirs_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let anchored = pattern.contains('/');
+        Pattern {
+            glob: Glob::new(pattern.trim_start_matches('/')),
+            anchored,
+            dirs_only,
+            negated: false,
+        }
+    }
+
+    fn parse_gitignore(content: &str) -> Vec<Pattern> {
+        content
+            .lines()
+            .map(|line| line.trim_end())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| match line.strip_prefix('!') {
+                Some(line) => Pattern {
+                    negated: true,
+                    ..Pattern::new(line)
+                },
+                None => Pattern::new(line),
+            })
+            .collect()
+    }
+
+    fn matches(&self, relative_path: &str, name: &str, is_dir: bool) -> bool {
+        if self.dirs_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            self.glob.matches(relative_path)
+        } else {
+            self.glob.matches(name)
+        }
+    }
+}
+
+impl<F: FnMut(&Progress)> Iterator for HashDir<F> {
+    type Item = Result<(PathBuf, FuzzyHash)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entry = match self.stack.last_mut() {
+                Some(dir) => dir.entries.next(),
+                None => {
+                    if !self.finished {
+                        self.finished = true;
+                        self.report(None);
+                    }
+                    return None;
+                }
+            };
+            let entry = match entry {
+                Some(entry) => entry,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            match self.visit(&entry) {
+                Ok(Some(hashed)) => return Some(Ok(hashed)),
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+impl<F: FnMut(&Progress)> HashDir<F> {
+    /// Hashes the given entry of the last walked directory if it is a file or
+    /// starts walking it if it is a directory.
+    fn visit(&mut self, entry: &DirEntry) -> Result<Option<(PathBuf, FuzzyHash)>> {
+        if !self.include_hidden && is_hidden(entry)? {
+            return Ok(None);
+        }
+        let file_type = entry.file_type()?;
+        let is_dir = file_type.is_dir();
+        if !is_dir && !file_type.is_file() {
+            return Ok(None);
+        }
+
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let (depth, relative_path) = {
+            let parent = self.stack.last().expect("a directory is being walked");
+            let relative_path = if parent.relative_path.is_empty() {
+                name.clone()
+            } else {
+                format!("{}/{}", parent.relative_path, name)
+            };
+            (parent.depth, relative_path)
+        };
+        let matches_any = |patterns: &[Pattern]| {
+            patterns
+                .iter()
+                .any(|p| p.matches(&relative_path, &name, is_dir))
+        };
+        if matches_any(&self.exclude) || self.is_gitignored(&relative_path, &name, is_dir) {
+            return Ok(None);
+        }
+
+        let path = entry.path();
+        if is_dir {
+            if self.max_depth.is_none_or(|max_depth| depth < max_depth) {
+                self.push_dir(&path, depth + 1, relative_path)?;
+            }
+            Ok(None)
+        } else if self.include.is_empty() || matches_any(&self.include) {
+            self.report(Some(path.clone()));
+            self.processed += 1;
+            let hash = hash_from_file(&path)?.parse()?;
+            Ok(Some((path, hash)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Starts walking the given directory.
+    fn push_dir(&mut self, path: &Path, depth: usize, relative_path: String) -> Result<()> {
+        let mut entries = fs::read_dir(path)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        let gitignore = if self.respect_gitignore {
+            match fs::read_to_string(path.join(".gitignore")) {
+                Ok(content) => Pattern::parse_gitignore(&content),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => return Err(e.into()),
+            }
+        } else {
+            Vec::new()
+        };
+        self.stack.push(WalkedDir {
+            depth,
+            relative_path,
+            entries: entries.into_iter(),
+            gitignore,
+        });
+        Ok(())
+    }
+
+    fn report(&mut self, current_path: Option<PathBuf>) {
+        (self.progress)(&Progress {
+            phase: Phase::Hashing,
+            done: self.processed,
+            total: None,
+            current_path,
+        });
+    }
+
+    fn is_gitignored(&self, relative_path: &str, name: &str, is_dir: bool) -> bool {
+        if !self.respect_gitignore {
+            return false;
+        }
+        if is_dir && name == ".git" {
+            return true;
+        }
+        for dir in self.stack.iter().rev() {
+            let path = if dir.relative_path.is_empty() {
+                relative_path
+            } else {
+                &relative_path[dir.relative_path.len() + 1..]
+            };
+            let last_match = dir
+                .gitignore
+                .iter()
+                .rev()
+                .find(|p| p.matches(path, name, is_dir));
+            if let Some(pattern) = last_match {
+                return !pattern.negated;
+            }
+        }
+        false
+    }
+}
+
+fn no_progress(_: &Progress) {}
+
+fn is_hidden(entry: &DirEntry) -> Result<bool> {
+    if starts_with_dot(&entry.file_name()) {
+        return Ok(true);
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+        Ok(entry.metadata()?.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+    }
+
+    #[cfg(not(windows))]
+    {
+        Ok(false)
+    }
+}
+
+fn starts_with_dot(name: &OsStr) -> bool {
+    name.to_string_lossy().starts_with('.')
+}
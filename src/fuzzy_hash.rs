@@ -0,0 +1,765 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! A parsed and validated fuzzy hash.
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::de;
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Deserializer;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+#[cfg(feature = "serde")]
+use serde::Serializer;
+
+#[cfg(feature = "native-compare")]
+use super::score::compare_parsed;
+use super::score::eliminate_sequences;
+use super::score::invalid_hash;
+#[cfg(feature = "native-compare")]
+use super::score::ParsedHash;
+use super::score::MIN_BLOCKSIZE;
+use super::score::ROLLING_WINDOW;
+use super::score::SPAMSUM_LENGTH;
+use super::sigfile;
+use super::Error;
+use super::Result;
+
+/// The characters of the parts of hashes, in the order of their 6b values.
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The size of the header of a compact hash (the exponent of the block size
+/// and the lengths of both parts).
+const COMPACT_HEADER_SIZE: usize = 3;
+
+/// A fuzzy hash in the `block_size:chunk:double_chunk` format, validated when
+/// it is parsed.
+///
+/// The block size has to be the minimal block size (3) multiplied by a power
+/// of two, and both parts have to consist of at most 64 base64 characters.
+/// An invalid hash is reported by
+/// [`Error::InvalidHash`](enum.Error.html#variant.InvalidHash) right when it
+/// is parsed, not later when it is compared.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::FuzzyHash;
+///
+/// let h: FuzzyHash = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C".parse().unwrap();
+/// assert_eq!(h.block_size(), 3);
+/// assert_eq!(h.chunk(), "AXGBicFlgVNhBGcL6wCrFQEv");
+/// assert_eq!(h.double_chunk(), "AXGHsNhxLsr2C");
+/// assert_eq!(h.to_string(), "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C");
+///
+/// let other: FuzzyHash = "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx".parse().unwrap();
+/// assert_eq!(ssdeep::compare(&h, &other), Ok(22));
+///
+/// assert!("3:AXGB:AX,file.txt".parse::<FuzzyHash>().is_err());
+/// ```
+///
+/// Hashes are equal when their block sizes and parts are equal, and they are
+/// ordered by their block sizes, then by their chunks, and then by their
+/// double chunks, so they can be used as keys in maps and sets and sorted
+/// into buckets by block sizes.
+///
+/// With the `serde` feature, it is serialized as a string and validated when
+/// it is deserialized.
+#[derive(Debug, Clone)]
+pub struct FuzzyHash {
+    hash: String,
+    block_size: u64,
+    chunk_start: usize,
+    double_chunk_start: usize,
+}
+
+impl FuzzyHash {
+    /// The default size of n-grams returned by [`ngrams()`](#method.ngrams).
+    ///
+    /// Parts of two hashes can have a non-zero match score only when they
+    /// have a common substring of this length (after sequences of more than
+    /// three identical characters are shortened to three characters), so two
+    /// such hashes share a 7-gram computed with the same block size.
+    pub const DEFAULT_NGRAM_SIZE: usize = ROLLING_WINDOW;
+
+    /// Returns the block size of the first part (the chunk).
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    /// Returns the block size of the second part (the double chunk), which is
+    /// double the block size of the chunk.
+    ///
+    /// It is `None` when the doubled block size does not fit into `u64`.
+    pub fn double_block_size(&self) -> Option<u64> {
+        self.block_size.checked_mul(2)
+    }
+
+    /// Returns the first part of the hash (the chunk), computed with the block
+    /// size.
+    pub fn chunk(&self) -> &str {
+        &self.hash[self.chunk_start..self.double_chunk_start - 1]
+    }
+
+    /// Returns the second part of the hash (the double chunk), computed with
+    /// double the block size.
+    pub fn double_chunk(&self) -> &str {
+        &self.hash[self.double_chunk_start..]
+    }
+
+    /// Parses a line of a signature file in the format used by the `ssdeep`
+    /// tool (`hash,"filename"`) and returns the hash and the unescaped
+    /// filename.
+    ///
+    /// The line is parsed by
+    /// [`sigfile::parse_line()`](sigfile/fn.parse_line.html), and the hash is
+    /// validated as when it is [parsed](#impl-FromStr-for-FuzzyHash). When the
+    /// line or the hash is invalid, it returns
+    /// [`Error::InvalidSignatureLine`](enum.Error.html#variant.InvalidSignatureLine).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ssdeep::FuzzyHash;
+    ///
+    /// let (hash, filename) = FuzzyHash::parse_csv_line(r#"3:aNRn:aNRn,"say \"hi\".txt""#).unwrap();
+    /// assert_eq!(hash.as_str(), "3:aNRn:aNRn");
+    /// assert_eq!(filename, "say \"hi\".txt");
+    /// ```
+    pub fn parse_csv_line(line: &str) -> Result<(FuzzyHash, String)> {
+        let (hash, filename) = sigfile::parse_line(line)?;
+        let hash = hash
+            .parse()
+            .map_err(|_| sigfile::invalid_line("invalid hash"))?;
+        Ok((hash, filename))
+    }
+
+    /// Returns an iterator over overlapping n-grams (substrings of `n`
+    /// characters) of the chunk and then of the double chunk, each with the
+    /// block size of its part.
+    ///
+    /// N-grams are the building block of inverted indexes for searching for
+    /// similar hashes: use
+    /// [`DEFAULT_NGRAM_SIZE`](#associatedconstant.DEFAULT_NGRAM_SIZE) and
+    /// index [`normalized()`](#method.normalized) hashes by their n-grams
+    /// together with their block sizes.
+    /// When `n` is zero or a part is shorter than `n` characters, the part has
+    /// no n-grams. The double chunk has no n-grams when its block size does
+    /// not fit into `u64`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ssdeep::FuzzyHash;
+    ///
+    /// let h: FuzzyHash = "3:aNRnB:aNRn".parse().unwrap();
+    /// let ngrams: Vec<_> = h.ngrams(4).collect();
+    /// assert_eq!(ngrams, [(3, "aNRn"), (3, "NRnB"), (6, "aNRn")]);
+    /// ```
+    pub fn ngrams(&self, n: usize) -> Ngrams<'_> {
+        Ngrams {
+            parts: [
+                Some((self.block_size, self.chunk())),
+                self.double_block_size().map(|bs| (bs, self.double_chunk())),
+            ],
+            part: 0,
+            pos: 0,
+            n,
+        }
+    }
+
+    /// Returns the terms for indexing the hash in and querying external
+    /// search engines (e.g. Elasticsearch or OpenSearch) or SQL databases.
+    ///
+    /// This follows the well-known scheme for storing ssdeep hashes at scale:
+    /// every hash is stored with its block size and with the unique 7-grams of
+    /// its [normalized](#method.normalized) chunk and double chunk (e.g. as
+    /// three fields of a document or as rows of an n-gram table). Similar
+    /// hashes are then found by looking up the stored hashes that match any of
+    /// the [clauses](struct.SearchTerms.html#method.clauses) of the query,
+    /// which cover the stored hashes with the same, double, and half the block
+    /// size. The found hashes have to be compared with the query by
+    /// [`compare()`](fn.compare.html) to get their scores, and every hash with
+    /// a non-zero score is found.
+    ///
+    /// A part shorter than 7 characters (after normalization) has no n-grams,
+    /// so a hash whose parts are both that short can only match identical
+    /// hashes, which have to be looked up by the whole normalized hash.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ssdeep::{ChunkField, FuzzyHash};
+    ///
+    /// let h: FuzzyHash = "6:AXGBicFlg:AXGHsNhh".parse().unwrap();
+    /// let terms = h.search_terms();
+    /// assert_eq!(terms.block_size, 6);
+    /// assert_eq!(terms.chunk_ngrams, ["AXGBicF", "XGBicFl", "GBicFlg"]);
+    /// assert_eq!(terms.double_chunk_ngrams, ["AXGHsNh", "XGHsNhh"]);
+    ///
+    /// let clauses = terms.clauses();
+    /// assert_eq!(clauses.len(), 4);
+    /// assert_eq!(clauses[0].block_size, 6);
+    /// assert_eq!(clauses[0].field, ChunkField::Chunk);
+    /// assert_eq!(clauses[0].ngrams, terms.chunk_ngrams);
+    /// ```
+    pub fn search_terms(&self) -> SearchTerms {
+        let normalized = self.normalized();
+        SearchTerms {
+            block_size: self.block_size,
+            chunk_ngrams: unique_ngrams(normalized.chunk()),
+            double_chunk_ngrams: unique_ngrams(normalized.double_chunk()),
+        }
+    }
+
+    /// Checks whether the hash can have a non-zero match score with the other
+    /// hash.
+    ///
+    /// It is the case only when the block sizes of the hashes are equal or
+    /// one of them is double the other one, so that the hashes have a part
+    /// with a common block size. Checking this is much cheaper than computing
+    /// the score, so it can be used to skip comparisons whose score is
+    /// guaranteed to be zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ssdeep::FuzzyHash;
+    ///
+    /// let h1: FuzzyHash = "3:aNRn:aNRn".parse().unwrap();
+    /// let h2: FuzzyHash = "6:aNRn:aNRn".parse().unwrap();
+    /// let h3: FuzzyHash = "12:aNRn:aNRn".parse().unwrap();
+    /// assert!(h1.comparable_with(&h2));
+    /// assert!(!h1.comparable_with(&h3));
+    /// ```
+    pub fn comparable_with(&self, other: &FuzzyHash) -> bool {
+        let (bs1, bs2) = (self.block_size, other.block_size);
+        bs1 == bs2
+            || self.double_block_size() == Some(bs2)
+            || other.double_block_size() == Some(bs1)
+    }
+
+    /// Returns the components by which hashes are compared.
+    fn key(&self) -> (u64, &str, &str) {
+        (self.block_size, self.chunk(), self.double_chunk())
+    }
+
+    /// Returns the hash as a string.
+    pub fn as_str(&self) -> &str {
+        &self.hash
+    }
+
+    /// Converts the hash into its compact binary encoding.
+    ///
+    /// See [`CompactFuzzyHash`](struct.CompactFuzzyHash.html) for more
+    /// details.
+    pub fn to_compact(&self) -> CompactFuzzyHash {
+        let chunk = self.chunk().as_bytes();
+        let double_chunk = self.double_chunk().as_bytes();
+        let mut bytes = vec![
+            (self.block_size / MIN_BLOCKSIZE).trailing_zeros() as u8,
+            chunk.len() as u8,
+            double_chunk.len() as u8,
+        ];
+        pack_chars(chunk.iter().chain(double_chunk), &mut bytes);
+        CompactFuzzyHash {
+            bytes: bytes.into_boxed_slice(),
+        }
+    }
+
+    /// Converts the compact binary encoding of a hash back into the hash.
+    ///
+    /// The block size of the resulting hash is always written without leading
+    /// zeros.
+    pub fn from_compact(compact: &CompactFuzzyHash) -> FuzzyHash {
+        let bytes = &compact.bytes;
+        let block_size = MIN_BLOCKSIZE << bytes[0];
+        let chunk_len = bytes[1] as usize;
+        let chars = unpack_chars(&bytes[COMPACT_HEADER_SIZE..], chunk_len + bytes[2] as usize);
+        FuzzyHash::from_valid_parts(block_size, &chars[..chunk_len], &chars[chunk_len..])
+    }
+
+    /// Returns the hash with sequences of more than three identical
+    /// characters in its parts shortened to three characters.
+    ///
+    /// The C library shortens the sequences in the same way before it
+    /// compares hashes, so the normalized hash consists of exactly the
+    /// characters that are compared (e.g. to be used in prefilters or
+    /// indexes). The match score of the normalized hash is the same as the
+    /// one of the original hash.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ssdeep::FuzzyHash;
+    ///
+    /// let h: FuzzyHash = "3:HRhhhhhhhhhhhhhn:Hhhhhh".parse().unwrap();
+    /// assert_eq!(h.normalized().as_str(), "3:HRhhhn:Hhhh");
+    /// ```
+    pub fn normalized(&self) -> FuzzyHash {
+        FuzzyHash::from_valid_parts(
+            self.block_size,
+            &eliminate_sequences(self.chunk().as_bytes()).unwrap(),
+            &eliminate_sequences(self.double_chunk().as_bytes()).unwrap(),
+        )
+    }
+
+    /// Prepares the hash for fast repeated comparisons.
+    ///
+    /// It is available only when the `native-compare` feature is enabled. See
+    /// [`PreparedHash`](struct.PreparedHash.html) for more details.
+    #[cfg(feature = "native-compare")]
+    pub fn prepare(&self) -> PreparedHash {
+        PreparedHash {
+            parsed: ParsedHash {
+                block_size: self.block_size,
+                parts: [
+                    eliminate_sequences(self.chunk().as_bytes()).unwrap(),
+                    eliminate_sequences(self.double_chunk().as_bytes()).unwrap(),
+                ],
+            },
+        }
+    }
+
+    /// Creates a hash from valid parts.
+    fn from_valid_parts(block_size: u64, chunk: &[u8], double_chunk: &[u8]) -> FuzzyHash {
+        let hash = format!(
+            "{}:{}:{}",
+            block_size,
+            String::from_utf8_lossy(chunk),
+            String::from_utf8_lossy(double_chunk)
+        );
+        FuzzyHash {
+            block_size,
+            chunk_start: hash.len() - chunk.len() - double_chunk.len() - 1,
+            double_chunk_start: hash.len() - double_chunk.len(),
+            hash,
+        }
+    }
+}
+
+/// A fuzzy hash prepared for fast repeated comparisons.
+///
+/// The hash is parsed and normalized (i.e. sequences of more than three
+/// identical characters in its parts are shortened) once, when it is
+/// prepared, and its parts are stored in fixed-size buffers. Comparing
+/// prepared hashes thus neither parses nor allocates anything, which makes it
+/// suitable for hot loops comparing one hash against many others. The scores
+/// are the same as the scores computed by [`compare()`](fn.compare.html).
+///
+/// It is available only when the `native-compare` feature is enabled.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::FuzzyHash;
+/// use ssdeep::PreparedHash;
+///
+/// let h1: FuzzyHash = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C".parse().unwrap();
+/// let h2: PreparedHash = "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx".parse().unwrap();
+/// assert_eq!(h1.prepare().compare(&h2), 22);
+/// ```
+#[cfg(feature = "native-compare")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PreparedHash {
+    parsed: ParsedHash,
+}
+
+#[cfg(feature = "native-compare")]
+impl PreparedHash {
+    /// Returns the block size of the hash.
+    pub fn block_size(&self) -> u64 {
+        self.parsed.block_size
+    }
+
+    /// Computes the match score between the hash and the given hash.
+    pub fn compare(&self, other: &PreparedHash) -> u8 {
+        compare_parsed(&self.parsed, &other.parsed)
+    }
+}
+
+#[cfg(feature = "native-compare")]
+impl FromStr for PreparedHash {
+    type Err = Error;
+
+    fn from_str(hash: &str) -> Result<PreparedHash> {
+        Ok(hash.parse::<FuzzyHash>()?.prepare())
+    }
+}
+
+#[cfg(feature = "native-compare")]
+impl<'a> From<&'a FuzzyHash> for PreparedHash {
+    fn from(hash: &'a FuzzyHash) -> PreparedHash {
+        hash.prepare()
+    }
+}
+
+/// An iterator over n-grams of a [`FuzzyHash`](struct.FuzzyHash.html),
+/// returned by [`FuzzyHash::ngrams()`](struct.FuzzyHash.html#method.ngrams).
+#[derive(Debug, Clone)]
+pub struct Ngrams<'a> {
+    parts: [Option<(u64, &'a str)>; 2],
+    part: usize,
+    pos: usize,
+    n: usize,
+}
+
+impl<'a> Iterator for Ngrams<'a> {
+    type Item = (u64, &'a str);
+
+    fn next(&mut self) -> Option<(u64, &'a str)> {
+        if self.n == 0 {
+            return None;
+        }
+        while self.part < self.parts.len() {
+            if let Some((block_size, part)) = self.parts[self.part] {
+                if self.pos + self.n <= part.len() {
+                    self.pos += 1;
+                    return Some((block_size, &part[self.pos - 1..self.pos - 1 + self.n]));
+                }
+            }
+            self.part += 1;
+            self.pos = 0;
+        }
+        None
+    }
+}
+
+/// Terms for indexing a [`FuzzyHash`](struct.FuzzyHash.html) in and
+/// querying external stores, returned by
+/// [`FuzzyHash::search_terms()`](struct.FuzzyHash.html#method.search_terms).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SearchTerms {
+    /// The block size of the hash (of its chunk).
+    pub block_size: u64,
+    /// Unique 7-grams of the normalized chunk, in the order of their first
+    /// occurrences.
+    pub chunk_ngrams: Vec<String>,
+    /// Unique 7-grams of the normalized double chunk, in the order of their
+    /// first occurrences.
+    pub double_chunk_ngrams: Vec<String>,
+}
+
+impl SearchTerms {
+    /// Returns the clauses of a query for stored hashes that can be similar to
+    /// the hash.
+    ///
+    /// A stored hash can be similar only when it matches at least one of the
+    /// clauses (i.e. the clauses are to be combined by "or"). The clauses
+    /// without n-grams are left out.
+    pub fn clauses(&self) -> Vec<SearchClause> {
+        let bs = self.block_size;
+        let mut clauses = vec![
+            SearchClause::new(bs, ChunkField::Chunk, &self.chunk_ngrams),
+            SearchClause::new(bs, ChunkField::DoubleChunk, &self.double_chunk_ngrams),
+        ];
+        if let Some(double_bs) = bs.checked_mul(2) {
+            clauses.push(SearchClause::new(
+                double_bs,
+                ChunkField::Chunk,
+                &self.double_chunk_ngrams,
+            ));
+        }
+        if bs.is_multiple_of(2) && bs / 2 >= MIN_BLOCKSIZE {
+            clauses.push(SearchClause::new(
+                bs / 2,
+                ChunkField::DoubleChunk,
+                &self.chunk_ngrams,
+            ));
+        }
+        clauses.retain(|clause| !clause.ngrams.is_empty());
+        clauses
+    }
+}
+
+/// A part of stored hashes, with n-grams in
+/// [`SearchTerms`](struct.SearchTerms.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ChunkField {
+    /// The first part (the chunk).
+    Chunk,
+    /// The second part (the double chunk).
+    DoubleChunk,
+}
+
+/// A clause of a query for stored hashes, returned by
+/// [`SearchTerms::clauses()`](struct.SearchTerms.html#method.clauses).
+///
+/// A stored hash matches the clause when its block size is the block size of
+/// the clause and the n-grams of its part given by the field contain any of
+/// the n-grams of the clause.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SearchClause {
+    /// The block size of the stored hashes.
+    pub block_size: u64,
+    /// The part of the stored hashes whose n-grams are matched.
+    pub field: ChunkField,
+    /// The n-grams, any of which has to be in the part.
+    pub ngrams: Vec<String>,
+}
+
+impl SearchClause {
+    fn new(block_size: u64, field: ChunkField, ngrams: &[String]) -> Self {
+        SearchClause {
+            block_size,
+            field,
+            ngrams: ngrams.to_vec(),
+        }
+    }
+}
+
+/// Returns the unique 7-grams of the given part, in the order of their first
+/// occurrences.
+fn unique_ngrams(part: &str) -> Vec<String> {
+    let mut ngrams: Vec<String> = Vec::new();
+    for i in 0..(part.len() + 1).saturating_sub(ROLLING_WINDOW) {
+        let ngram = &part[i..i + ROLLING_WINDOW];
+        if !ngrams.iter().any(|n| n == ngram) {
+            ngrams.push(ngram.to_string());
+        }
+    }
+    ngrams
+}
+
+/// A compact binary encoding of a [`FuzzyHash`](struct.FuzzyHash.html).
+///
+/// The block size is stored as a single byte (the exponent of the power of two
+/// by which the minimal block size is multiplied) and the characters of both
+/// parts are base64-decoded and packed into 6 bits each. A hash thus takes
+/// around half of the memory of its string form, which pays off when holding
+/// millions of hashes in memory (e.g. for clustering).
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::CompactFuzzyHash;
+/// use ssdeep::FuzzyHash;
+///
+/// let h: FuzzyHash = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C".parse().unwrap();
+/// let compact = h.to_compact();
+/// assert_eq!(compact.as_bytes().len(), 31);
+/// assert_eq!(FuzzyHash::from_compact(&compact), h);
+///
+/// let bytes = compact.as_bytes().to_vec();
+/// assert_eq!(CompactFuzzyHash::from_bytes(&bytes).unwrap(), compact);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CompactFuzzyHash {
+    bytes: Box<[u8]>,
+}
+
+impl CompactFuzzyHash {
+    /// Returns the encoded bytes (e.g. to store them).
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Creates a compact hash from bytes returned by
+    /// [`as_bytes()`](#method.as_bytes).
+    ///
+    /// When the bytes are not a valid encoding of a hash, it returns
+    /// [`Error::InvalidHash`](enum.Error.html#variant.InvalidHash).
+    pub fn from_bytes(bytes: &[u8]) -> Result<CompactFuzzyHash> {
+        if bytes.len() < COMPACT_HEADER_SIZE {
+            return Err(invalid_hash("missing header"));
+        }
+        if MIN_BLOCKSIZE
+            .checked_shl(bytes[0] as u32)
+            .map(|bs| bs >> bytes[0])
+            != Some(MIN_BLOCKSIZE)
+        {
+            return Err(invalid_hash("invalid block size"));
+        }
+        if bytes[1] as usize > SPAMSUM_LENGTH || bytes[2] as usize > SPAMSUM_LENGTH {
+            return Err(invalid_hash("too long part"));
+        }
+        let char_count = bytes[1] as usize + bytes[2] as usize;
+        if bytes.len() != COMPACT_HEADER_SIZE + packed_size(char_count) {
+            return Err(invalid_hash("invalid length"));
+        }
+        let padding_bits = packed_size(char_count) * 8 - char_count * 6;
+        if padding_bits > 0 && bytes[bytes.len() - 1] & ((1 << padding_bits) - 1) != 0 {
+            return Err(invalid_hash("non-zero padding"));
+        }
+        Ok(CompactFuzzyHash {
+            bytes: bytes.to_vec().into_boxed_slice(),
+        })
+    }
+}
+
+impl FromStr for FuzzyHash {
+    type Err = Error;
+
+    fn from_str(hash: &str) -> Result<FuzzyHash> {
+        let mut fields = hash.split(':');
+        let block_size = fields.next().unwrap();
+        let part1 = fields
+            .next()
+            .ok_or_else(|| invalid_hash("missing first part"))?;
+        let part2 = fields
+            .next()
+            .ok_or_else(|| invalid_hash("missing second part"))?;
+        if fields.next().is_some() {
+            return Err(invalid_hash("too many parts"));
+        }
+        if block_size.is_empty() || !block_size.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(invalid_hash("invalid block size"));
+        }
+        let block_size: u64 = block_size
+            .parse()
+            .map_err(|_| invalid_hash("invalid block size"))?;
+        if !block_size.is_multiple_of(MIN_BLOCKSIZE)
+            || !(block_size / MIN_BLOCKSIZE).is_power_of_two()
+        {
+            return Err(invalid_hash("invalid block size"));
+        }
+        validate_part(part1)?;
+        validate_part(part2)?;
+        Ok(FuzzyHash {
+            hash: hash.to_string(),
+            block_size,
+            chunk_start: hash.len() - part2.len() - part1.len() - 1,
+            double_chunk_start: hash.len() - part2.len(),
+        })
+    }
+}
+
+impl<'a> TryFrom<&'a str> for FuzzyHash {
+    type Error = Error;
+
+    fn try_from(hash: &'a str) -> Result<FuzzyHash> {
+        hash.parse()
+    }
+}
+
+impl PartialEq for FuzzyHash {
+    fn eq(&self, other: &FuzzyHash) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for FuzzyHash {}
+
+impl Hash for FuzzyHash {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+impl PartialOrd for FuzzyHash {
+    fn partial_cmp(&self, other: &FuzzyHash) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FuzzyHash {
+    fn cmp(&self, other: &FuzzyHash) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl fmt::Display for FuzzyHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.hash)
+    }
+}
+
+impl AsRef<str> for FuzzyHash {
+    fn as_ref(&self) -> &str {
+        &self.hash
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for FuzzyHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.hash)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for FuzzyHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let hash = String::deserialize(deserializer)?;
+        hash.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Returns the number of bytes needed to pack the given number of 6b values.
+fn packed_size(char_count: usize) -> usize {
+    (char_count * 6).div_ceil(8)
+}
+
+/// Packs the values of the given base64 characters into 6 bits each.
+fn pack_chars<'a, I: Iterator<Item = &'a u8>>(chars: I, bytes: &mut Vec<u8>) {
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    for &c in chars {
+        let value = BASE64_CHARS.iter().position(|&b| b == c).unwrap() as u32;
+        acc = (acc << 6) | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+        }
+    }
+    if bits > 0 {
+        bytes.push((acc << (8 - bits)) as u8);
+    }
+}
+
+/// Unpacks the given number of 6b values into base64 characters.
+fn unpack_chars(bytes: &[u8], char_count: usize) -> Vec<u8> {
+    let mut chars = Vec::with_capacity(char_count);
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+    let mut bytes = bytes.iter();
+    while chars.len() < char_count {
+        if bits < 6 {
+            acc = (acc << 8) | u32::from(*bytes.next().unwrap());
+            bits += 8;
+        }
+        bits -= 6;
+        chars.push(BASE64_CHARS[((acc >> bits) & 0x3f) as usize]);
+    }
+    chars
+}
+
+fn validate_part(part: &str) -> Result<()> {
+    if part.len() > SPAMSUM_LENGTH {
+        return Err(invalid_hash("too long part"));
+    }
+    if !part
+        .bytes()
+        .all(|c| c.is_ascii_alphanumeric() || c == b'+' || c == b'/')
+    {
+        return Err(invalid_hash("invalid character in part"));
+    }
+    Ok(())
+}
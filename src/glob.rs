@@ -0,0 +1,170 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Matching of paths against glob patterns.
+
+/// A token of a compiled glob pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// A literal character.
+    Literal(char),
+    /// `?`: any character other than `/`.
+    AnyChar,
+    /// `*`: any sequence of characters other than `/`.
+    AnyChars,
+    /// `**` (not followed by `/`): any sequence of characters.
+    AnyPath,
+    /// `**/`: an empty sequence or any sequence of characters ending with
+    /// `/` (i.e. zero or more directories).
+    AnyDirs,
+    /// `[...]`: any character (other than `/`) from the given ranges, or not
+    /// from them when negated (`[!...]` or `[^...]`).
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+/// A compiled glob pattern.
+///
+/// Paths are matched as a whole, with `/` as the path separator. A backslash
+/// escapes the following character and a `[` without a matching `]` is a
+/// literal character, so every pattern is valid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Glob {
+    tokens: Vec<Token>,
+}
+
+impl Glob {
+    /// Compiles the given pattern.
+    pub(crate) fn new(pattern: &str) -> Glob {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '\\' if i + 1 < chars.len() => {
+                    tokens.push(Token::Literal(chars[i + 1]));
+                    i += 2;
+                }
+                '?' => {
+                    tokens.push(Token::AnyChar);
+                    i += 1;
+                }
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    if chars.get(i + 2) == Some(&'/') {
+                        tokens.push(Token::AnyDirs);
+                        i += 3;
+                    } else {
+                        tokens.push(Token::AnyPath);
+                        i += 2;
+                    }
+                }
+                '*' => {
+                    tokens.push(Token::AnyChars);
+                    i += 1;
+                }
+                '[' => match parse_class(&chars[i + 1..]) {
+                    Some((token, len)) => {
+                        tokens.push(token);
+                        i += len + 1;
+                    }
+                    None => {
+                        tokens.push(Token::Literal('['));
+                        i += 1;
+                    }
+                },
+                c => {
+                    tokens.push(Token::Literal(c));
+                    i += 1;
+                }
+            }
+        }
+        Glob { tokens }
+    }
+
+    /// Checks whether the given path matches the pattern.
+    pub(crate) fn matches(&self, path: &str) -> bool {
+        let chars: Vec<char> = path.chars().collect();
+        matches_tokens(&self.tokens, &chars)
+    }
+}
+
+/// Parses a character class following a `[`, returning the class and the
+/// number of characters it spans (including the closing `]`).
+fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+    let mut i = 0;
+    let negated = matches!(chars.first(), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let mut c = *chars.get(i)?;
+        if c == ']' && !first {
+            return Some((Token::Class { negated, ranges }, i + 1));
+        }
+        if c == '\\' {
+            i += 1;
+            c = *chars.get(i)?;
+        }
+        first = false;
+        i += 1;
+        if chars.get(i) == Some(&'-') && chars.get(i + 1).is_some_and(|&end| end != ']') {
+            ranges.push((c, chars[i + 1]));
+            i += 2;
+        } else {
+            ranges.push((c, c));
+        }
+    }
+}
+
+fn matches_tokens(tokens: &[Token], path: &[char]) -> bool {
+    let token = match tokens.first() {
+        Some(token) => token,
+        None => return path.is_empty(),
+    };
+    let rest = &tokens[1..];
+    match *token {
+        Token::Literal(c) => path.first() == Some(&c) && matches_tokens(rest, &path[1..]),
+        Token::AnyChar => {
+            path.first().is_some_and(|&c| c != '/') && matches_tokens(rest, &path[1..])
+        }
+        Token::AnyChars => {
+            let max_len = path.iter().position(|&c| c == '/').unwrap_or(path.len());
+            (0..=max_len).any(|len| matches_tokens(rest, &path[len..]))
+        }
+        Token::AnyPath => (0..=path.len()).any(|len| matches_tokens(rest, &path[len..])),
+        Token::AnyDirs => {
+            matches_tokens(rest, path)
+                || (0..path.len())
+                    .filter(|&i| path[i] == '/')
+                    .any(|i| matches_tokens(rest, &path[i + 1..]))
+        }
+        Token::Class {
+            negated,
+            ref ranges,
+        } => match path.first() {
+            Some(&c) if c != '/' => {
+                let in_class = ranges.iter().any(|&(start, end)| start <= c && c <= end);
+                in_class != negated && matches_tokens(rest, &path[1..])
+            }
+            _ => false,
+        },
+    }
+}
@@ -0,0 +1,441 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Incremental computation of fuzzy hashes.
+
+#[cfg(not(feature = "rust-backend"))]
+use libc::c_char;
+use std::fmt;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::ops::BitOr;
+use std::ops::BitOrAssign;
+
+#[cfg(not(feature = "rust-backend"))]
+use super::create_buffer_for_result;
+#[cfg(not(feature = "rust-backend"))]
+use super::raw;
+#[cfg(not(feature = "rust-backend"))]
+use super::result_buffer_to_string;
+#[cfg(feature = "rust-backend")]
+use super::spamsum;
+use super::Error;
+use super::Result;
+
+/// Size of the buffer into which data are read before they are hashed.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Flags altering the fuzzy hash returned by
+/// [`FuzzyHasher::digest_with_flags()`](struct.FuzzyHasher.html#method.digest_with_flags).
+///
+/// Flags can be combined via `|`.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::DigestFlags;
+///
+/// let flags = DigestFlags::ELIMSEQ | DigestFlags::NOTRUNC;
+/// assert!(flags.contains(DigestFlags::NOTRUNC));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct DigestFlags(u32);
+
+impl DigestFlags {
+    /// Eliminate sequences of more than three identical characters from both
+    /// parts of the hash.
+    pub const ELIMSEQ: DigestFlags = DigestFlags(1);
+
+    /// Do not truncate the second part of the hash to 32 characters.
+    pub const NOTRUNC: DigestFlags = DigestFlags(2);
+
+    /// Returns flags with no flag set (the default hash).
+    pub fn empty() -> DigestFlags {
+        DigestFlags(0)
+    }
+
+    /// Returns the raw value of the flags, as passed into `fuzzy_digest()`.
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// Checks whether all the given flags are set.
+    pub fn contains(&self, other: DigestFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for DigestFlags {
+    type Output = DigestFlags;
+
+    fn bitor(self, other: DigestFlags) -> DigestFlags {
+        DigestFlags(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for DigestFlags {
+    fn bitor_assign(&mut self, other: DigestFlags) {
+        self.0 |= other.0;
+    }
+}
+
+/// Computes a fuzzy hash incrementally, from data fed in chunks.
+///
+/// It allows hashing data that arrive in chunks (e.g. from a network stream
+/// or a decompressor) without buffering all of them in memory. The fuzzy hash
+/// is the same as the one computed by [`hash()`](fn.hash.html) from all the
+/// data at once.
+///
+/// # Examples
+///
+/// ```
+/// let mut hasher = ssdeep::FuzzyHasher::new().unwrap();
+/// hasher.update(b"Hello ").unwrap();
+/// hasher.update(b"there!").unwrap();
+/// assert_eq!(hasher.digest().unwrap(), "3:aNRn:aNRn");
+/// ```
+///
+/// It also implements [`Write`](https://doc.rust-lang.org/std/io/trait.Write.html),
+/// so data can be copied into it from a reader:
+/// ```
+/// let mut reader = std::fs::File::open("tests/file.txt").unwrap();
+/// let mut hasher = ssdeep::FuzzyHasher::new().unwrap();
+/// std::io::copy(&mut reader, &mut hasher).unwrap();
+/// assert_eq!(hasher.digest(), ssdeep::hash_from_file("tests/file.txt"));
+/// ```
+///
+/// # Implementation details
+///
+/// Internally, it wraps the `fuzzy_new()`, `fuzzy_update()`, `fuzzy_digest()`,
+/// and `fuzzy_free()` functions from the underlying C library. The state of
+/// the C library is freed when the hasher is dropped.
+///
+/// With the `rust-backend` feature, the fuzzy hash is computed natively in
+/// Rust instead, with the same results (failures are reported as failures of
+/// the equivalent C functions).
+pub struct FuzzyHasher {
+    state: State,
+}
+
+impl FuzzyHasher {
+    /// Creates a new hasher.
+    ///
+    /// When the state of the underlying C library cannot be allocated, it
+    /// returns [`Error`](enum.Error.html).
+    pub fn new() -> Result<Self> {
+        Ok(FuzzyHasher {
+            state: new_state()?,
+        })
+    }
+
+    /// Sets the total length of the data that will be fed into the hasher.
+    ///
+    /// When the length is known in advance (e.g. the size of a file), the
+    /// underlying C library restricts the range of block sizes from the
+    /// start, which speeds up the hashing. The fuzzy hash is the same as
+    /// without the length being set.
+    ///
+    /// When the length is too large or a different length has already been
+    /// set, it returns [`Error`](enum.Error.html). When the total length of
+    /// the fed data differs from the set length, `digest()` returns an error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut hasher = ssdeep::FuzzyHasher::new().unwrap();
+    /// hasher.set_total_input_length(12).unwrap();
+    /// hasher.update(b"Hello there!").unwrap();
+    /// assert_eq!(hasher.digest().unwrap(), "3:aNRn:aNRn");
+    /// ```
+    pub fn set_total_input_length(&mut self, len: u64) -> Result<()> {
+        if !self.state.set_total_input_length(len) {
+            return Err(Error::CFunctionFailed {
+                name: "fuzzy_set_total_input_length".to_string(),
+                return_code: -1,
+            });
+        }
+        Ok(())
+    }
+
+    /// Feeds the given bytes into the hasher.
+    ///
+    /// When the underlying C function fails, it returns
+    /// [`Error`](enum.Error.html).
+    pub fn update(&mut self, buf: &[u8]) -> Result<()> {
+        if !self.state.update(buf) {
+            return Err(Error::CFunctionFailed {
+                name: "fuzzy_update".to_string(),
+                return_code: -1,
+            });
+        }
+        Ok(())
+    }
+
+    /// Reads all data from the reader, feeds them into the hasher, copies them
+    /// into the writer, and returns their size.
+    pub(crate) fn update_and_copy<R: Read, W: Write>(
+        &mut self,
+        reader: R,
+        writer: W,
+    ) -> Result<u64> {
+        self.update_and_copy_in_chunks(reader, writer, BUFFER_SIZE)
+    }
+
+    /// Like `update_and_copy()`, but reads the data in chunks of the given
+    /// size.
+    fn update_and_copy_in_chunks<R: Read, W: Write>(
+        &mut self,
+        mut reader: R,
+        mut writer: W,
+        chunk_size: usize,
+    ) -> Result<u64> {
+        let mut buf = vec![0; chunk_size];
+        let mut size = 0;
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => return Ok(size),
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            self.update(&buf[..n])?;
+            writer.write_all(&buf[..n])?;
+            size += n as u64;
+        }
+    }
+
+    /// Returns the fuzzy hash of all the bytes fed so far.
+    ///
+    /// The hasher is not reset, so more bytes can be fed into it afterwards.
+    /// When the underlying C function fails (e.g. when the total size of the
+    /// fed bytes is too large), it returns [`Error`](enum.Error.html).
+    pub fn digest(&self) -> Result<String> {
+        self.digest_with_flags(DigestFlags::empty())
+    }
+
+    /// Returns the fuzzy hash of all the bytes fed so far, altered by the
+    /// given flags.
+    ///
+    /// See [`digest()`](#method.digest) for more details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ssdeep::DigestFlags;
+    ///
+    /// let mut hasher = ssdeep::FuzzyHasher::new().unwrap();
+    /// hasher.update(&[4; 20]).unwrap();
+    /// assert_eq!(hasher.digest().unwrap(), "3:HRhhhhhhhhhhhhhn:H");
+    /// let h = hasher.digest_with_flags(DigestFlags::ELIMSEQ).unwrap();
+    /// assert_eq!(h, "3:HRhhhn:H");
+    /// ```
+    pub fn digest_with_flags(&self, flags: DigestFlags) -> Result<String> {
+        self.state.digest(flags)
+    }
+}
+
+impl Write for FuzzyHasher {
+    /// Feeds the given bytes into the hasher.
+    ///
+    /// All the bytes are always fed. A failure of the underlying C function
+    /// is reported as an error of kind `Other`.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf).map_err(io::Error::other)?;
+        Ok(buf.len())
+    }
+
+    /// Does nothing as the hasher does not buffer any data.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Clone for FuzzyHasher {
+    /// Creates a copy of the hasher (via `fuzzy_clone()` from the underlying C
+    /// library), which can be fed independently of the original hasher.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let mut hasher = ssdeep::FuzzyHasher::new().unwrap();
+    /// hasher.update(b"Hello ").unwrap();
+    /// let mut fork = hasher.clone();
+    /// fork.update(b"there!").unwrap();
+    /// assert_eq!(hasher.digest(), ssdeep::hash(b"Hello "));
+    /// assert_eq!(fork.digest().unwrap(), "3:aNRn:aNRn");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If the state of the underlying C library cannot be allocated.
+    fn clone(&self) -> Self {
+        FuzzyHasher {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for FuzzyHasher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FuzzyHasher").finish_non_exhaustive()
+    }
+}
+
+/// The state of the underlying C library.
+#[cfg(not(feature = "rust-backend"))]
+struct State {
+    ptr: *mut raw::fuzzy_state,
+}
+
+// The state of the C library is not tied to the thread that created it, so the
+// hasher can be moved into another thread.
+#[cfg(not(feature = "rust-backend"))]
+unsafe impl Send for State {}
+
+#[cfg(not(feature = "rust-backend"))]
+impl State {
+    fn set_total_input_length(&mut self, len: u64) -> bool {
+        unsafe { raw::fuzzy_set_total_input_length(self.ptr, len) == 0 }
+    }
+
+    fn update(&mut self, buf: &[u8]) -> bool {
+        unsafe { raw::fuzzy_update(self.ptr, buf.as_ptr(), buf.len()) == 0 }
+    }
+
+    fn digest(&self, flags: DigestFlags) -> Result<String> {
+        let mut result = create_buffer_for_result();
+        let rc =
+            unsafe { raw::fuzzy_digest(self.ptr, result.as_mut_ptr() as *mut c_char, flags.0) };
+        result_buffer_to_string("fuzzy_digest", result, rc)
+    }
+}
+
+#[cfg(not(feature = "rust-backend"))]
+impl Clone for State {
+    /// Creates a copy of the state via `fuzzy_clone()`.
+    ///
+    /// # Panics
+    ///
+    /// If the state cannot be allocated.
+    fn clone(&self) -> Self {
+        let ptr = unsafe { raw::fuzzy_clone(self.ptr) };
+        assert!(!ptr.is_null(), "fuzzy_clone() failed");
+        State { ptr }
+    }
+}
+
+#[cfg(not(feature = "rust-backend"))]
+impl Drop for State {
+    fn drop(&mut self) {
+        unsafe { raw::fuzzy_free(self.ptr) };
+    }
+}
+
+#[cfg(not(feature = "rust-backend"))]
+fn new_state() -> Result<State> {
+    let ptr = unsafe { raw::fuzzy_new() };
+    if ptr.is_null() {
+        return Err(Error::CFunctionFailed {
+            name: "fuzzy_new".to_string(),
+            return_code: 0,
+        });
+    }
+    Ok(State { ptr })
+}
+
+/// The state of the native implementation.
+#[cfg(feature = "rust-backend")]
+#[derive(Clone)]
+struct State(spamsum::State);
+
+#[cfg(feature = "rust-backend")]
+impl State {
+    fn set_total_input_length(&mut self, len: u64) -> bool {
+        self.0.set_total_input_length(len)
+    }
+
+    fn update(&mut self, buf: &[u8]) -> bool {
+        self.0.update(buf);
+        true
+    }
+
+    fn digest(&self, flags: DigestFlags) -> Result<String> {
+        let elimseq = flags.contains(DigestFlags::ELIMSEQ);
+        let notrunc = flags.contains(DigestFlags::NOTRUNC);
+        self.0
+            .digest(elimseq, notrunc)
+            .ok_or_else(|| Error::CFunctionFailed {
+                name: "fuzzy_digest".to_string(),
+                return_code: -1,
+            })
+    }
+}
+
+#[cfg(feature = "rust-backend")]
+fn new_state() -> Result<State> {
+    Ok(State(spamsum::State::new()))
+}
+
+/// Computes the fuzzy hash of all data from the given reader.
+///
+/// The data are read and hashed in chunks of 64 KiB, so pipes, sockets, or
+/// decompressed streams can be hashed without storing them in memory or in a
+/// temporary file. When reading fails, it returns
+/// [`Error::Io`](enum.Error.html#variant.Io).
+///
+/// # Examples
+///
+/// ```
+/// let file = std::fs::File::open("tests/file.txt").unwrap();
+/// let h = ssdeep::hash_from_reader(file).unwrap();
+/// assert_eq!(h, ssdeep::hash_from_file("tests/file.txt").unwrap());
+/// ```
+pub fn hash_from_reader<R: Read>(reader: R) -> Result<String> {
+    hash_from_reader_with_chunk_size(reader, BUFFER_SIZE)
+}
+
+/// Computes the fuzzy hash of all data from the given reader, which are read
+/// in chunks of the given size.
+///
+/// When `chunk_size` is zero, the default chunk size (64 KiB) is used. See
+/// [`hash_from_reader()`](fn.hash_from_reader.html) for more details.
+///
+/// # Examples
+///
+/// ```
+/// let h = ssdeep::hash_from_reader_with_chunk_size(&b"Hello there!"[..], 4).unwrap();
+/// assert_eq!(h, "3:aNRn:aNRn");
+/// ```
+pub fn hash_from_reader_with_chunk_size<R: Read>(reader: R, chunk_size: usize) -> Result<String> {
+    let chunk_size = if chunk_size == 0 {
+        BUFFER_SIZE
+    } else {
+        chunk_size
+    };
+    let mut hasher = FuzzyHasher::new()?;
+    hasher.update_and_copy_in_chunks(reader, io::sink(), chunk_size)?;
+    hasher.digest()
+}
+
+/// Reads all data from the reader, copies them into the writer, and returns
+/// their fuzzy hash.
+pub(crate) fn hash_and_copy<R: Read, W: Write>(reader: R, writer: W) -> Result<String> {
+    let mut hasher = FuzzyHasher::new()?;
+    hasher.update_and_copy(reader, writer)?;
+    hasher.digest()
+}
@@ -0,0 +1,68 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Fuzzy hashing of HTTP response bodies while they are being downloaded.
+//!
+//! This module is available only when the `http` feature is enabled. It
+//! integrates with the blocking client of the
+//! [reqwest](https://crates.io/crates/reqwest) crate. The crate is used without
+//! its default features, so if you need e.g. TLS support, enable the
+//! corresponding features of `reqwest` in your own `Cargo.toml`.
+
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+use reqwest::blocking::Response;
+
+use super::hasher::hash_and_copy;
+use super::Result;
+
+/// Downloads the body of the response and returns its fuzzy hash.
+///
+/// The body is hashed as it is being downloaded, so it is never stored in
+/// memory as a whole. The status code of the response is not checked; it is
+/// up to the caller to decide which responses to hash.
+///
+/// # Examples
+///
+/// ```no_run
+/// let response = reqwest::blocking::get("http://example.com/").unwrap();
+/// let h = ssdeep::http::hash_response(response).unwrap();
+/// ```
+pub fn hash_response(response: Response) -> Result<String> {
+    hash_and_copy(response, io::sink())
+}
+
+/// Downloads the body of the response into a file and returns its fuzzy hash.
+///
+/// The body is hashed and written into the file in a single pass. When the
+/// file already exists, it is overwritten.
+///
+/// # Examples
+///
+/// ```no_run
+/// let response = reqwest::blocking::get("http://example.com/").unwrap();
+/// let h = ssdeep::http::hash_response_to_file(response, "index.html").unwrap();
+/// ```
+pub fn hash_response_to_file<P: AsRef<Path>>(response: Response, file_path: P) -> Result<String> {
+    let mut file = File::create(file_path)?;
+    let h = hash_and_copy(response, &mut file)?;
+    file.flush()?;
+    Ok(h)
+}
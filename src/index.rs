@@ -0,0 +1,1147 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! An index of fuzzy hashes for finding similar hashes without comparing the
+//! query against all of them.
+//!
+//! Two hashes can have a non-zero match score only when they have parts with
+//! a common block size that share a substring of 7 characters (after
+//! sequences of more than three identical characters are shortened). The
+//! index therefore maps every 7-gram of the normalized parts of the stored
+//! hashes, together with the block size of the part, to the hashes containing
+//! it. A query is then compared only against the stored hashes that share at
+//! least one such 7-gram with it.
+//!
+//! The 7-grams are bucketed by block sizes. A query looks only into the
+//! buckets for the block sizes of its two parts, so hashes whose block size
+//! is not equal to, double, or half the block size of the query are never
+//! considered, and the 7-grams of a part are not looked up at all when no
+//! stored hash has a part with its block size.
+//!
+//! Optionally, every bucket can have a Bloom filter over its 7-grams (see
+//! [`FuzzyHashIndex::set_bloom_filters()`](struct.FuzzyHashIndex.html#method.set_bloom_filters)),
+//! so most 7-grams of a query that are not in the bucket are rejected
+//! without looking them up in the bucket. This makes queries that do not
+//! match any stored hash faster.
+//!
+//! File format
+//! -----------
+//!
+//! An index can be saved into a file and loaded from it (e.g. to build an
+//! index over millions of samples once and reuse it from many processes). All
+//! integers are stored in little endian:
+//!
+//! * The magic bytes `SSDEEPIX`, followed by the version of the format (`u32`,
+//!   currently 1).
+//! * The number of hashes (`u64`), followed by every hash as its length
+//!   (`u32`) and its UTF-8 bytes, ordered by their identifiers. A removed
+//!   hash is stored with length 0.
+//! * The number of 7-gram keys (`u64`), followed by every key as the block
+//!   size (`u64`), the 7 characters packed into `u64` (the first character in
+//!   the most significant used byte), the number of identifiers (`u32`), and
+//!   the identifiers (`u32` each, ascending).
+//! * The number of groups of hashes without 7-grams (`u64`), followed by every
+//!   group as the number of identifiers (`u32`) and the identifiers (`u32`
+//!   each, ascending) of the hashes with the same normalized hash.
+//!
+//! Files of other versions are rejected when they are loaded.
+//!
+//! Memory-mapped file format
+//! -------------------------
+//!
+//! An index can also be saved in a format that is queried directly in a
+//! memory-mapped file, without deserializing it (see
+//! [`FuzzyHashIndex::save_mapped()`](struct.FuzzyHashIndex.html#method.save_mapped)
+//! and [`MappedIndex`](struct.MappedIndex.html), which is available only when
+//! the `mmap` feature is enabled). Opening such an index takes constant time
+//! regardless of its size, and only the pages touched by queries are read.
+//! All integers are stored in little endian, and the sections follow each
+//! other without padding:
+//!
+//! * The magic bytes `SSDEEPMX`, followed by the version of the format (`u32`,
+//!   currently 1) and a reserved `u32` (zero).
+//! * The header with the number of hashes (including removed hashes), the
+//!   number of hashes that have not been removed, the number of 7-gram keys,
+//!   the number of groups of hashes without 7-grams, the total number of
+//!   identifiers, the length of the hash data, and the length of the
+//!   normalized hash data (all `u64`).
+//! * The offsets of hashes in the hash data (`u64` each), one more than the
+//!   number of hashes, so hash `i` spans from offset `i` to offset `i + 1`
+//!   (an empty span for a removed hash).
+//! * The 7-gram keys sorted by their block sizes and 7-grams, every key as
+//!   the block size, the packed 7-gram (as in the other format), the index of
+//!   its first identifier, and the number of its identifiers (all `u64`).
+//! * The groups of hashes without 7-grams sorted by the bytes of their
+//!   normalized hashes, every group as the offset and length of the
+//!   normalized hash in the normalized hash data, the index of its first
+//!   identifier, and the number of its identifiers (all `u64`).
+//! * The identifiers of all keys and groups (`u32` each, ascending within a
+//!   key or group).
+//! * The hash data (the UTF-8 bytes of all hashes).
+//! * The normalized hash data (the UTF-8 bytes of the normalized hashes of
+//!   all groups).
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+#[cfg(feature = "mmap")]
+use std::cmp::Ordering;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+
+use super::compare;
+use super::progress::INSERTIONS_PER_REPORT;
+use super::Error;
+use super::FuzzyHash;
+use super::Phase;
+use super::Progress;
+use super::Result;
+
+/// The magic bytes at the start of files with saved indexes.
+const MAGIC: &[u8; 8] = b"SSDEEPIX";
+
+/// The current version of the format of files with saved indexes.
+const VERSION: u32 = 1;
+
+/// The magic bytes at the start of files with memory-mappable indexes.
+const MAPPED_MAGIC: &[u8; 8] = b"SSDEEPMX";
+
+/// The current version of the format of files with memory-mappable indexes.
+const MAPPED_VERSION: u32 = 1;
+
+/// The size of the magic bytes, version, and header of memory-mappable
+/// indexes.
+#[cfg(feature = "mmap")]
+const MAPPED_HEADER_SIZE: usize = 16 + 7 * 8;
+
+/// The size of a key or group entry in memory-mappable indexes.
+#[cfg(feature = "mmap")]
+const MAPPED_ENTRY_SIZE: usize = 4 * 8;
+
+/// A match found in a [`FuzzyHashIndex`](struct.FuzzyHashIndex.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexMatch {
+    /// Identifier of the matched hash (returned when it was inserted).
+    pub id: usize,
+    /// The score between the query and the matched hash.
+    pub score: u8,
+}
+
+/// An n-gram inverted index of fuzzy hashes.
+///
+/// Hashes are bucketed by the block sizes of their parts and indexed by the
+/// 7-grams of the parts, so for a query, only the hashes that can have a
+/// non-zero score are compared with it. A search thus returns the same
+/// matches as comparing the query against all stored hashes, but it takes
+/// time proportional to the number of candidates instead of the number of
+/// stored hashes.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::index::FuzzyHashIndex;
+///
+/// let mut index = FuzzyHashIndex::new();
+/// let id = index.insert("3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C").unwrap();
+/// index.insert("3:u+N:u+N").unwrap();
+///
+/// let matches = index.search("3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx", 0).unwrap();
+/// assert_eq!(matches.len(), 1);
+/// assert_eq!(matches[0].id, id);
+/// assert_eq!(matches[0].score, 22);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FuzzyHashIndex {
+    /// Hashes by their identifiers (`None` for removed hashes).
+    hashes: Vec<Option<FuzzyHash>>,
+    /// The number of hashes that have not been removed.
+    count: usize,
+    /// Identifiers of hashes for packed 7-grams, bucketed by block sizes.
+    buckets: HashMap<u64, Bucket>,
+    /// Whether the buckets have Bloom filters.
+    bloom_filters: bool,
+    /// Identifiers of hashes without any 7-gram, which can match only a
+    /// query with the same normalized hash.
+    short: HashMap<FuzzyHash, Vec<u32>>,
+}
+
+impl FuzzyHashIndex {
+    /// Creates an empty index.
+    pub fn new() -> Self {
+        FuzzyHashIndex::default()
+    }
+
+    /// Returns the number of hashes in the index.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Checks whether the index is empty.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns the hash with the given identifier (`None` when there is no
+    /// such hash or it has been removed).
+    pub fn get(&self, id: usize) -> Option<&FuzzyHash> {
+        self.hashes.get(id).and_then(Option::as_ref)
+    }
+
+    /// Checks whether the index contains a hash equal to the given hash.
+    ///
+    /// When the hash is invalid, it returns
+    /// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash).
+    pub fn contains(&self, hash: &str) -> Result<bool> {
+        let parsed: FuzzyHash = hash.parse()?;
+        Ok(self
+            .candidates(hash)?
+            .into_iter()
+            .any(|id| self.get(id) == Some(&parsed)))
+    }
+
+    /// Adds a hash into the index and returns its identifier.
+    ///
+    /// Identifiers are assigned sequentially from zero and identifiers of
+    /// removed hashes are not reused. The index is updated in place, without
+    /// rebuilding it.
+    ///
+    /// When the hash is invalid, it returns
+    /// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash).
+    ///
+    /// # Panics
+    ///
+    /// If the index already contains `2^32` hashes.
+    pub fn insert(&mut self, hash: &str) -> Result<usize> {
+        let hash: FuzzyHash = hash.parse()?;
+        assert!(self.hashes.len() < u32::MAX as usize, "too many hashes");
+        let id = self.hashes.len() as u32;
+        let normalized = hash.normalized();
+        let mut has_ngrams = false;
+        let bloom_filters = self.bloom_filters;
+        for (block_size, ngram) in ngram_keys(&normalized) {
+            has_ngrams = true;
+            self.buckets
+                .entry(block_size)
+                .or_insert_with(|| Bucket::new(bloom_filters))
+                .insert(ngram, id);
+        }
+        if !has_ngrams {
+            self.short.entry(normalized).or_default().push(id);
+        }
+        self.hashes.push(Some(hash));
+        self.count += 1;
+        Ok(id as usize)
+    }
+
+    /// Adds the given hashes into the index and returns their identifiers (in
+    /// the order of the hashes).
+    ///
+    /// When a hash is invalid, it returns
+    /// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash). The
+    /// hashes preceding it stay in the index.
+    ///
+    /// # Panics
+    ///
+    /// If the index would contain more than `2^32` hashes.
+    pub fn insert_many<H: AsRef<str>>(&mut self, hashes: &[H]) -> Result<Vec<usize>> {
+        self.insert_many_with_progress(hashes, |_| {})
+    }
+
+    /// Adds the given hashes into the index, reporting the progress (in the
+    /// [`Indexing`](../enum.Phase.html) phase) to the given callback.
+    ///
+    /// See [`insert_many()`](#method.insert_many) for more details.
+    pub fn insert_many_with_progress<H, F>(
+        &mut self,
+        hashes: &[H],
+        mut progress: F,
+    ) -> Result<Vec<usize>>
+    where
+        H: AsRef<str>,
+        F: FnMut(&Progress),
+    {
+        let total = hashes.len() as u64;
+        let mut report = |done| {
+            progress(&Progress {
+                phase: Phase::Indexing,
+                done,
+                total: Some(total),
+                current_path: None,
+            })
+        };
+        let mut ids = Vec::with_capacity(hashes.len());
+        for (i, hash) in hashes.iter().enumerate() {
+            if i > 0 && (i as u64).is_multiple_of(INSERTIONS_PER_REPORT) {
+                report(i as u64);
+            }
+            ids.push(self.insert(hash.as_ref())?);
+        }
+        report(total);
+        Ok(ids)
+    }
+
+    /// Removes the hash with the given identifier from the index and returns
+    /// it (`None` when there is no such hash or it has already been removed).
+    ///
+    /// The index is updated in place, without rebuilding it.
+    pub fn remove(&mut self, id: usize) -> Option<FuzzyHash> {
+        let hash = self.hashes.get_mut(id)?.take()?;
+        self.count -= 1;
+        let id = id as u32;
+        let normalized = hash.normalized();
+        let mut has_ngrams = false;
+        for (block_size, ngram) in ngram_keys(&normalized) {
+            has_ngrams = true;
+            if let Some(bucket) = self.buckets.get_mut(&block_size) {
+                remove_id(&mut bucket.ngrams, &ngram, id);
+                if bucket.ngrams.is_empty() {
+                    self.buckets.remove(&block_size);
+                }
+            }
+        }
+        if !has_ngrams {
+            remove_id(&mut self.short, &normalized, id);
+        }
+        Some(hash)
+    }
+
+    /// Enables or disables Bloom filters of the buckets of 7-grams.
+    ///
+    /// A Bloom filter rejects most 7-grams of a query that are not in its
+    /// bucket without looking them up, which makes queries that do not match
+    /// any stored hash faster, at the cost of about 10 bits of memory per
+    /// stored 7-gram. The filters do not change the results of queries.
+    ///
+    /// The filters are not saved into files, so they have to be enabled again
+    /// after an index is loaded. By default, they are disabled.
+    pub fn set_bloom_filters(&mut self, enabled: bool) {
+        self.bloom_filters = enabled;
+        for bucket in self.buckets.values_mut() {
+            bucket.filter = if enabled {
+                Some(BloomFilter::for_ngrams(&bucket.ngrams))
+            } else {
+                None
+            };
+        }
+    }
+
+    /// Checks whether the buckets of 7-grams have Bloom filters.
+    pub fn has_bloom_filters(&self) -> bool {
+        self.bloom_filters
+    }
+
+    /// Returns identifiers of the hashes that can have a non-zero score with
+    /// the query (in ascending order).
+    ///
+    /// Only hashes with a block size equal to, double, or half the block size
+    /// of the query are returned.
+    ///
+    /// When the query is invalid, it returns
+    /// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash).
+    pub fn candidates(&self, query: &str) -> Result<Vec<usize>> {
+        let normalized = query.parse::<FuzzyHash>()?.normalized();
+        let mut ids: Vec<u32> = ngram_keys(&normalized)
+            .filter_map(|(block_size, ngram)| self.buckets.get(&block_size)?.get(ngram))
+            .flatten()
+            .cloned()
+            .collect();
+        if let Some(short) = self.short.get(&normalized) {
+            ids.extend(short);
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(candidates = ids.len(), "found candidates");
+        Ok(ids.into_iter().map(|id| id as usize).collect())
+    }
+
+    /// Returns the stored hashes whose score with the query is strictly
+    /// greater than the threshold, ordered by descending scores (and by
+    /// identifiers for equal scores).
+    ///
+    /// When the query is invalid, it returns
+    /// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash).
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(threshold))
+    )]
+    pub fn search(&self, query: &str, threshold: u8) -> Result<Vec<IndexMatch>> {
+        let mut matches = Vec::new();
+        for id in self.candidates(query)? {
+            let score = compare(query, self.hashes[id].as_ref().unwrap())?;
+            if score > threshold {
+                matches.push(IndexMatch { id, score });
+            }
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(matches = matches.len(), "scored candidates");
+        matches.sort_by(|m1, m2| m2.score.cmp(&m1.score).then(m1.id.cmp(&m2.id)));
+        Ok(matches)
+    }
+
+    /// Returns at most `k` stored hashes with the best scores with the probe
+    /// that are at least `min_score`, ordered by descending scores (and by
+    /// identifiers for equal scores).
+    ///
+    /// The matches are the first `k` matches of
+    /// [`search()`](#method.search) with the corresponding threshold, but
+    /// only the best `k` matches are kept while the candidates are scored,
+    /// so the full set of matches is neither stored nor sorted. Hashes with a
+    /// zero score are never returned (even when `min_score` is zero).
+    ///
+    /// When the probe is invalid, it returns
+    /// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash).
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(k, min_score))
+    )]
+    pub fn top_k(&self, probe: &str, k: usize, min_score: u8) -> Result<Vec<IndexMatch>> {
+        if k == 0 {
+            return Ok(Vec::new());
+        }
+        // The worst of the best matches found so far is on the top of the heap.
+        let mut best = BinaryHeap::with_capacity(k + 1);
+        for id in self.candidates(probe)? {
+            let score = compare(probe, self.hashes[id].as_ref().unwrap())?;
+            if score == 0 || score < min_score {
+                continue;
+            }
+            best.push((Reverse(score), id));
+            if best.len() > k {
+                best.pop();
+            }
+        }
+        Ok(best
+            .into_sorted_vec()
+            .into_iter()
+            .map(|(Reverse(score), id)| IndexMatch { id, score })
+            .collect())
+    }
+}
+
+impl FuzzyHashIndex {
+    /// Writes the index into the given writer in the format described in the
+    /// [module documentation](index.html#file-format).
+    ///
+    /// When writing fails, it returns
+    /// [`Error::Io`](../enum.Error.html#variant.Io).
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(self.hashes.len() as u64).to_le_bytes())?;
+        for hash in &self.hashes {
+            let hash = hash.as_ref().map_or("", |hash| hash.as_str());
+            writer.write_all(&(hash.len() as u32).to_le_bytes())?;
+            writer.write_all(hash.as_bytes())?;
+        }
+        let keys = self.sorted_keys();
+        writer.write_all(&(keys.len() as u64).to_le_bytes())?;
+        for (block_size, ngram, ids) in keys {
+            writer.write_all(&block_size.to_le_bytes())?;
+            writer.write_all(&ngram.to_le_bytes())?;
+            write_ids(&mut writer, ids)?;
+        }
+        let mut groups: Vec<_> = self.short.values().collect();
+        groups.sort_unstable();
+        writer.write_all(&(groups.len() as u64).to_le_bytes())?;
+        for ids in groups {
+            write_ids(&mut writer, ids)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads an index written via [`write()`](#method.write) from the given
+    /// reader.
+    ///
+    /// When reading fails or the data are not a valid index (including an
+    /// index saved in another version of the format), it returns
+    /// [`Error::Io`](../enum.Error.html#variant.Io).
+    pub fn read<R: Read>(mut reader: R) -> Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_index("not an index"));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(invalid_index(&format!("unsupported version {}", version)));
+        }
+        let mut index = FuzzyHashIndex::new();
+        let hash_count = read_count(&mut reader)?;
+        if hash_count > u32::MAX as usize {
+            return Err(invalid_index("too many hashes"));
+        }
+        for _ in 0..hash_count {
+            let len = read_u32(&mut reader)? as usize;
+            let mut bytes = Vec::new();
+            reader.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+            if bytes.len() != len {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            if bytes.is_empty() {
+                index.hashes.push(None);
+                continue;
+            }
+            let hash = String::from_utf8(bytes)
+                .ok()
+                .and_then(|hash| hash.parse().ok())
+                .ok_or_else(|| invalid_index("invalid hash"))?;
+            index.hashes.push(Some(hash));
+            index.count += 1;
+        }
+        let key_count = read_count(&mut reader)?;
+        for _ in 0..key_count {
+            let block_size = read_u64(&mut reader)?;
+            let ngram = read_u64(&mut reader)?;
+            let ids = read_ids(&mut reader, &index.hashes)?;
+            if ids.is_empty() {
+                return Err(invalid_index("empty key"));
+            }
+            let bucket = index
+                .buckets
+                .entry(block_size)
+                .or_insert_with(|| Bucket::new(false));
+            if bucket.ngrams.insert(ngram, ids).is_some() {
+                return Err(invalid_index("duplicate key"));
+            }
+        }
+        let group_count = read_count(&mut reader)?;
+        for _ in 0..group_count {
+            let ids = read_ids(&mut reader, &index.hashes)?;
+            let normalized = match ids.first() {
+                Some(&id) => index.hashes[id as usize].as_ref().unwrap().normalized(),
+                None => return Err(invalid_index("empty group")),
+            };
+            if index.short.insert(normalized, ids).is_some() {
+                return Err(invalid_index("duplicate group"));
+            }
+        }
+        if reader.read(&mut [0])? != 0 {
+            return Err(invalid_index("trailing data"));
+        }
+        Ok(index)
+    }
+
+    /// Writes the index into the given writer in the memory-mappable format
+    /// described in the
+    /// [module documentation](index.html#memory-mapped-file-format).
+    ///
+    /// When writing fails, it returns
+    /// [`Error::Io`](../enum.Error.html#variant.Io).
+    pub fn write_mapped<W: Write>(&self, mut writer: W) -> Result<()> {
+        let keys = self.sorted_keys();
+        let mut groups: Vec<_> = self
+            .short
+            .iter()
+            .map(|(normalized, ids)| (normalized.as_str(), ids))
+            .collect();
+        groups.sort_unstable();
+        let hashes: Vec<&str> = self
+            .hashes
+            .iter()
+            .map(|hash| hash.as_ref().map_or("", |hash| hash.as_str()))
+            .collect();
+        let id_lists: Vec<&Vec<u32>> = keys
+            .iter()
+            .map(|&(_, _, ids)| ids)
+            .chain(groups.iter().map(|&(_, ids)| ids))
+            .collect();
+
+        writer.write_all(MAPPED_MAGIC)?;
+        writer.write_all(&MAPPED_VERSION.to_le_bytes())?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        let header = [
+            hashes.len(),
+            self.count,
+            keys.len(),
+            groups.len(),
+            id_lists.iter().map(|ids| ids.len()).sum(),
+            hashes.iter().map(|hash| hash.len()).sum(),
+            groups.iter().map(|(normalized, _)| normalized.len()).sum(),
+        ];
+        write_u64s(&mut writer, &header)?;
+        let mut offset = 0;
+        write_u64s(&mut writer, &[offset])?;
+        for hash in &hashes {
+            offset += hash.len();
+            write_u64s(&mut writer, &[offset])?;
+        }
+        let mut ids_start = 0;
+        for &(block_size, ngram, ids) in &keys {
+            writer.write_all(&block_size.to_le_bytes())?;
+            writer.write_all(&ngram.to_le_bytes())?;
+            write_u64s(&mut writer, &[ids_start, ids.len()])?;
+            ids_start += ids.len();
+        }
+        let mut normalized_start = 0;
+        for &(normalized, ids) in &groups {
+            write_u64s(
+                &mut writer,
+                &[normalized_start, normalized.len(), ids_start, ids.len()],
+            )?;
+            normalized_start += normalized.len();
+            ids_start += ids.len();
+        }
+        for id in id_lists.into_iter().flatten() {
+            writer.write_all(&id.to_le_bytes())?;
+        }
+        for hash in &hashes {
+            writer.write_all(hash.as_bytes())?;
+        }
+        for (normalized, _) in &groups {
+            writer.write_all(normalized.as_bytes())?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Saves the index into a file at the given path in the memory-mappable
+    /// format, which can be opened via [`MappedIndex`](struct.MappedIndex.html).
+    ///
+    /// See [`write_mapped()`](#method.write_mapped) for more details.
+    pub fn save_mapped<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.write_mapped(BufWriter::new(File::create(path)?))
+    }
+
+    /// Returns the 7-gram keys with their identifiers, sorted so that equal
+    /// indexes produce equal files.
+    fn sorted_keys(&self) -> Vec<(u64, u64, &Vec<u32>)> {
+        let mut keys: Vec<_> = self
+            .buckets
+            .iter()
+            .flat_map(|(&block_size, bucket)| {
+                bucket
+                    .ngrams
+                    .iter()
+                    .map(move |(&ngram, ids)| (block_size, ngram, ids))
+            })
+            .collect();
+        keys.sort_unstable_by_key(|&(block_size, ngram, _)| (block_size, ngram));
+        keys
+    }
+
+    /// Saves the index into a file at the given path.
+    ///
+    /// See [`write()`](#method.write) for more details.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+
+    /// Loads an index from a file at the given path.
+    ///
+    /// See [`read()`](#method.read) for more details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ssdeep::index::FuzzyHashIndex;
+    ///
+    /// let mut index = FuzzyHashIndex::new();
+    /// index.insert("3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C").unwrap();
+    ///
+    /// let path = std::env::temp_dir().join("ssdeep-rs-doc-index.bin");
+    /// index.save(&path).unwrap();
+    /// let loaded = FuzzyHashIndex::load(&path).unwrap();
+    /// # std::fs::remove_file(&path).unwrap();
+    /// assert_eq!(loaded.len(), 1);
+    /// ```
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        FuzzyHashIndex::read(BufReader::new(File::open(path)?))
+    }
+}
+
+/// A read-only index in a memory-mapped file saved via
+/// [`FuzzyHashIndex::save_mapped()`](struct.FuzzyHashIndex.html#method.save_mapped).
+///
+/// The index is queried directly in the mapped file (see the
+/// [module documentation](index.html#memory-mapped-file-format)), so opening
+/// it is almost instant even for indexes of many gigabytes, and the operating
+/// system loads only the pages that are needed by queries. Queries return the
+/// same results as the queries of the saved
+/// [`FuzzyHashIndex`](struct.FuzzyHashIndex.html).
+///
+/// Only the header and the sizes of the sections are validated when the index
+/// is opened. When a query finds corrupted data, it returns
+/// [`Error::Io`](../enum.Error.html#variant.Io). The file must not be
+/// modified while it is mapped.
+///
+/// It is available only when the `mmap` feature is enabled.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::index::{FuzzyHashIndex, MappedIndex};
+///
+/// let mut index = FuzzyHashIndex::new();
+/// index.insert("3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C").unwrap();
+/// index.insert("3:u+N:u+N").unwrap();
+///
+/// let path = std::env::temp_dir().join("ssdeep-rs-doc-mapped-index.bin");
+/// index.save_mapped(&path).unwrap();
+/// let mapped = MappedIndex::open(&path).unwrap();
+/// let matches = mapped.search("3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx", 0).unwrap();
+/// assert_eq!(matches.len(), 1);
+/// assert_eq!(matches[0].score, 22);
+/// assert_eq!(mapped.get(1), Some("3:u+N:u+N"));
+/// # drop(mapped);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[cfg(feature = "mmap")]
+#[derive(Debug)]
+pub struct MappedIndex {
+    map: Mmap,
+    layout: MappedLayout,
+}
+
+#[cfg(feature = "mmap")]
+impl MappedIndex {
+    /// Opens the index in the file at the given path.
+    ///
+    /// When the file cannot be mapped or it is not a valid index (including
+    /// an index saved in another version of the format), it returns
+    /// [`Error::Io`](../enum.Error.html#variant.Io).
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path)?;
+        // The map is only read, and the file must not be modified while it is
+        // mapped (as documented above).
+        let map = unsafe { Mmap::map(&file)? };
+        let layout = MappedLayout::parse(&map)?;
+        Ok(MappedIndex { map, layout })
+    }
+
+    /// Returns the number of hashes in the index.
+    pub fn len(&self) -> usize {
+        self.layout.live_count
+    }
+
+    /// Checks whether the index is empty.
+    pub fn is_empty(&self) -> bool {
+        self.layout.live_count == 0
+    }
+
+    /// Returns the hash with the given identifier (`None` when there is no
+    /// such hash or it has been removed).
+    pub fn get(&self, id: usize) -> Option<&str> {
+        if id >= self.layout.hash_count {
+            return None;
+        }
+        let offsets = self.layout.hash_offsets + id * 8;
+        let start = usize::try_from(u64_at(&self.map, offsets)).ok()?;
+        let end = usize::try_from(u64_at(&self.map, offsets + 8)).ok()?;
+        let data = &self.map[self.layout.hash_data..self.layout.normalized_data];
+        let hash = data.get(start..end)?;
+        if hash.is_empty() {
+            return None;
+        }
+        std::str::from_utf8(hash).ok()
+    }
+
+    /// Returns identifiers of the hashes that can have a non-zero score with
+    /// the query (in ascending order).
+    ///
+    /// See
+    /// [`FuzzyHashIndex::candidates()`](struct.FuzzyHashIndex.html#method.candidates)
+    /// for more details.
+    pub fn candidates(&self, query: &str) -> Result<Vec<usize>> {
+        let normalized = query.parse::<FuzzyHash>()?.normalized();
+        let mut ids = Vec::new();
+        for key in ngram_keys(&normalized) {
+            if let Some(entry) = self.find_key(key) {
+                self.push_ids(entry, &mut ids)?;
+            }
+        }
+        if let Some(entry) = self.find_group(normalized.as_str())? {
+            self.push_ids(entry, &mut ids)?;
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(candidates = ids.len(), "found candidates");
+        Ok(ids)
+    }
+
+    /// Returns the stored hashes whose score with the query is strictly
+    /// greater than the threshold, ordered by descending scores (and by
+    /// identifiers for equal scores).
+    ///
+    /// When the query is invalid, it returns
+    /// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash).
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(threshold))
+    )]
+    pub fn search(&self, query: &str, threshold: u8) -> Result<Vec<IndexMatch>> {
+        let mut matches = Vec::new();
+        for id in self.candidates(query)? {
+            let hash = self
+                .get(id)
+                .ok_or_else(|| invalid_index("invalid identifier"))?;
+            let score = compare(query, hash)?;
+            if score > threshold {
+                matches.push(IndexMatch { id, score });
+            }
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(matches = matches.len(), "scored candidates");
+        matches.sort_by(|m1, m2| m2.score.cmp(&m1.score).then(m1.id.cmp(&m2.id)));
+        Ok(matches)
+    }
+
+    /// Returns the offset of the entry of the given 7-gram key (if any).
+    fn find_key(&self, key: (u64, u64)) -> Option<usize> {
+        let entry = |i: usize| self.layout.keys + i * MAPPED_ENTRY_SIZE;
+        let (mut low, mut high) = (0, self.layout.key_count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let mid_key = (
+                u64_at(&self.map, entry(mid)),
+                u64_at(&self.map, entry(mid) + 8),
+            );
+            match mid_key.cmp(&key) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Some(entry(mid)),
+            }
+        }
+        None
+    }
+
+    /// Returns the offset of the entry of the group of the given normalized
+    /// hash (if any).
+    fn find_group(&self, normalized: &str) -> Result<Option<usize>> {
+        let data = &self.map[self.layout.normalized_data..];
+        let entry = |i: usize| self.layout.groups + i * MAPPED_ENTRY_SIZE;
+        let (mut low, mut high) = (0, self.layout.group_count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let start = u64_at(&self.map, entry(mid));
+            let len = u64_at(&self.map, entry(mid) + 8);
+            let mid_normalized = usize::try_from(start)
+                .ok()
+                .zip(usize::try_from(len).ok())
+                .and_then(|(start, len)| data.get(start..start.checked_add(len)?))
+                .ok_or_else(|| invalid_index("invalid group"))?;
+            match mid_normalized.cmp(normalized.as_bytes()) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Ok(Some(entry(mid))),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Appends the identifiers of the key or group with the entry at the given
+    /// offset.
+    fn push_ids(&self, entry: usize, ids: &mut Vec<usize>) -> Result<()> {
+        let start = u64_at(&self.map, entry + 16);
+        let len = u64_at(&self.map, entry + 24);
+        match start.checked_add(len) {
+            Some(end) if end <= self.layout.id_count as u64 => {}
+            _ => return Err(invalid_index("invalid identifiers")),
+        }
+        let ids_data = &self.map[self.layout.ids + start as usize * 4..];
+        for id in ids_data[..len as usize * 4].chunks_exact(4) {
+            ids.push(u32::from_le_bytes([id[0], id[1], id[2], id[3]]) as usize);
+        }
+        Ok(())
+    }
+}
+
+/// Offsets of sections and counts of items in a memory-mapped index.
+#[cfg(feature = "mmap")]
+#[derive(Debug, Clone, Copy)]
+struct MappedLayout {
+    hash_count: usize,
+    live_count: usize,
+    key_count: usize,
+    group_count: usize,
+    id_count: usize,
+    hash_offsets: usize,
+    keys: usize,
+    groups: usize,
+    ids: usize,
+    hash_data: usize,
+    normalized_data: usize,
+}
+
+#[cfg(feature = "mmap")]
+impl MappedLayout {
+    /// Parses the header of an index and checks that the sections fit into
+    /// the data exactly.
+    fn parse(data: &[u8]) -> Result<MappedLayout> {
+        if data.len() < MAPPED_HEADER_SIZE || &data[..8] != MAPPED_MAGIC {
+            return Err(invalid_index("not a memory-mappable index"));
+        }
+        let version = u32::from_le_bytes([data[8], data[9], data[10], data[11]]);
+        if version != MAPPED_VERSION {
+            return Err(invalid_index(&format!("unsupported version {}", version)));
+        }
+        let mut header = [0; 7];
+        for (i, value) in header.iter_mut().enumerate() {
+            *value = usize::try_from(u64_at(data, 16 + i * 8))
+                .map_err(|_| invalid_index("too large count"))?;
+        }
+        let [hash_count, live_count, key_count, group_count, id_count, hash_data_len, normalized_data_len] =
+            header;
+        let too_large = || invalid_index("too large count");
+        let section = |start: usize, len: Option<usize>| {
+            len.and_then(|len| start.checked_add(len))
+                .ok_or_else(too_large)
+        };
+        let hash_offsets = MAPPED_HEADER_SIZE;
+        let keys = section(
+            hash_offsets,
+            hash_count.checked_add(1).and_then(|n| n.checked_mul(8)),
+        )?;
+        let groups = section(keys, key_count.checked_mul(MAPPED_ENTRY_SIZE))?;
+        let ids = section(groups, group_count.checked_mul(MAPPED_ENTRY_SIZE))?;
+        let hash_data = section(ids, id_count.checked_mul(4))?;
+        let normalized_data = section(hash_data, Some(hash_data_len))?;
+        let end = section(normalized_data, Some(normalized_data_len))?;
+        if end != data.len() {
+            return Err(invalid_index("invalid size"));
+        }
+        if live_count > hash_count {
+            return Err(invalid_index("invalid number of hashes"));
+        }
+        Ok(MappedLayout {
+            hash_count,
+            live_count,
+            key_count,
+            group_count,
+            id_count,
+            hash_offsets,
+            keys,
+            groups,
+            ids,
+            hash_data,
+            normalized_data,
+        })
+    }
+}
+
+/// A bucket of 7-grams of parts with the same block size.
+#[derive(Debug, Clone)]
+struct Bucket {
+    /// Identifiers of hashes for packed 7-grams.
+    ngrams: HashMap<u64, Vec<u32>>,
+    /// An optional Bloom filter over the packed 7-grams.
+    filter: Option<BloomFilter>,
+}
+
+impl Bucket {
+    fn new(bloom_filter: bool) -> Self {
+        Bucket {
+            ngrams: HashMap::new(),
+            filter: if bloom_filter {
+                Some(BloomFilter::with_capacity(0))
+            } else {
+                None
+            },
+        }
+    }
+
+    fn insert(&mut self, ngram: u64, id: u32) {
+        let ids = self.ngrams.entry(ngram).or_default();
+        if ids.last() != Some(&id) {
+            ids.push(id);
+        }
+        if let Some(filter) = &mut self.filter {
+            if self.ngrams.len() > filter.capacity {
+                // Keep the false positive rate low by rebuilding the filter
+                // with a larger capacity.
+                *filter = BloomFilter::for_ngrams(&self.ngrams);
+            } else {
+                filter.insert(ngram);
+            }
+        }
+    }
+
+    fn get(&self, ngram: u64) -> Option<&Vec<u32>> {
+        match &self.filter {
+            Some(filter) if !filter.may_contain(ngram) => None,
+            _ => self.ngrams.get(&ngram),
+        }
+    }
+}
+
+// The bits of Bloom filters depend on the order of insertions and removals,
+// so only the 7-grams are compared.
+impl PartialEq for Bucket {
+    fn eq(&self, other: &Bucket) -> bool {
+        self.ngrams == other.ngrams
+    }
+}
+
+impl Eq for Bucket {}
+
+/// The number of bits of a Bloom filter per 7-gram.
+const BLOOM_BITS_PER_NGRAM: usize = 10;
+
+/// The number of bits of a Bloom filter set for every 7-gram.
+const BLOOM_HASHES: u64 = 7;
+
+/// The minimal capacity of a Bloom filter.
+const BLOOM_MIN_CAPACITY: usize = 64;
+
+/// A Bloom filter over packed 7-grams.
+///
+/// Removed 7-grams stay in the filter, which only increases the false
+/// positive rate.
+#[derive(Debug, Clone)]
+struct BloomFilter {
+    bits: Vec<u64>,
+    /// The number of 7-grams the filter is sized for.
+    capacity: usize,
+}
+
+impl BloomFilter {
+    fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.max(BLOOM_MIN_CAPACITY);
+        BloomFilter {
+            bits: vec![0; (capacity * BLOOM_BITS_PER_NGRAM).div_ceil(64)],
+            capacity,
+        }
+    }
+
+    /// Creates a filter for the 7-grams, with room for as many more.
+    fn for_ngrams(ngrams: &HashMap<u64, Vec<u32>>) -> Self {
+        let mut filter = BloomFilter::with_capacity(2 * ngrams.len());
+        for &ngram in ngrams.keys() {
+            filter.insert(ngram);
+        }
+        filter
+    }
+
+    fn insert(&mut self, ngram: u64) {
+        for i in self.bit_indexes(ngram) {
+            self.bits[i / 64] |= 1 << (i % 64);
+        }
+    }
+
+    fn may_contain(&self, ngram: u64) -> bool {
+        self.bit_indexes(ngram)
+            .all(|i| self.bits[i / 64] & (1 << (i % 64)) != 0)
+    }
+
+    /// Returns indexes of the bits for the 7-gram (double hashing of a mixed
+    /// 7-gram).
+    fn bit_indexes(&self, ngram: u64) -> impl Iterator<Item = usize> {
+        let mut h = ngram;
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        h ^= h >> 31;
+        let (h1, h2) = (h & 0xffff_ffff, (h >> 32) | 1);
+        let len = (self.bits.len() * 64) as u64;
+        (0..BLOOM_HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+}
+
+fn write_ids<W: Write>(writer: &mut W, ids: &[u32]) -> Result<()> {
+    writer.write_all(&(ids.len() as u32).to_le_bytes())?;
+    for id in ids {
+        writer.write_all(&id.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_ids<R: Read>(reader: &mut R, hashes: &[Option<FuzzyHash>]) -> Result<Vec<u32>> {
+    let count = read_u32(reader)?;
+    let mut ids = Vec::new();
+    for _ in 0..count {
+        let id = read_u32(reader)?;
+        let is_live = hashes.get(id as usize).is_some_and(Option::is_some);
+        if !is_live || ids.last().is_some_and(|&last| last >= id) {
+            return Err(invalid_index("invalid identifier"));
+        }
+        ids.push(id);
+    }
+    Ok(ids)
+}
+
+fn write_u64s<W: Write>(writer: &mut W, values: &[usize]) -> Result<()> {
+    for &value in values {
+        writer.write_all(&(value as u64).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Returns the `u64` at the given offset, which has to be within the data.
+#[cfg(feature = "mmap")]
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_count<R: Read>(reader: &mut R) -> Result<usize> {
+    usize::try_from(read_u64(reader)?).map_err(|_| invalid_index("too large count"))
+}
+
+fn invalid_index(reason: &str) -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::InvalidData,
+        reason.to_string(),
+    ))
+}
+
+/// Removes the identifier from the list for the given key, and the list when
+/// it becomes empty.
+fn remove_id<K: std::hash::Hash + Eq>(lists: &mut HashMap<K, Vec<u32>>, key: &K, id: u32) {
+    if let Some(ids) = lists.get_mut(key) {
+        if let Ok(i) = ids.binary_search(&id) {
+            ids.remove(i);
+        }
+        if ids.is_empty() {
+            lists.remove(key);
+        }
+    }
+}
+
+/// Returns keys of the 7-grams of the given normalized hash.
+fn ngram_keys(hash: &FuzzyHash) -> impl Iterator<Item = (u64, u64)> + '_ {
+    hash.ngrams(FuzzyHash::DEFAULT_NGRAM_SIZE)
+        .map(|(block_size, ngram)| (block_size, pack_ngram(ngram)))
+}
+
+/// Packs a 7-gram of base64 characters into an integer.
+fn pack_ngram(ngram: &str) -> u64 {
+    ngram
+        .bytes()
+        .fold(0, |packed, c| (packed << 8) | u64::from(c))
+}
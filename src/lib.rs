@@ -43,6 +43,30 @@
 //! let h = ssdeep::hash_from_file("tests/file.txt").unwrap();
 //! ```
 //!
+//! To compute the fuzzy hash of all data from a reader (e.g. a pipe or a
+//! socket), use [`hash_from_reader()`](fn.hash_from_reader.html):
+//! ```
+//! let file = std::fs::File::open("tests/file.txt").unwrap();
+//! let h = ssdeep::hash_from_reader(file).unwrap();
+//! ```
+//!
+//! To hash a file that is already open, without reopening it by its path, use
+//! [`hash_from_file_handle()`](fn.hash_from_file_handle.html) (or
+//! [`hash_from_fd()`](fn.hash_from_fd.html) on Unix):
+//! ```
+//! let file = std::fs::File::open("tests/file.txt").unwrap();
+//! let h = ssdeep::hash_from_file_handle(&file).unwrap();
+//! ```
+//!
+//! To compute the fuzzy hash of data that arrive in chunks, use
+//! [`FuzzyHasher`](struct.FuzzyHasher.html):
+//! ```
+//! let mut hasher = ssdeep::FuzzyHasher::new().unwrap();
+//! hasher.update(b"Hello ").unwrap();
+//! hasher.update(b"there!").unwrap();
+//! let h = hasher.digest().unwrap();
+//! ```
+//!
 //! To compare two fuzzy hashes, use [`compare()`](fn.compare.html), which
 //! returns an integer between 0 (no match) and 100:
 //! ```
@@ -55,18 +79,268 @@
 //! Each of these functions returns a
 //! [`Result`](https://doc.rust-lang.org/std/result/enum.Result.html), where an
 //! error is returned when the underlying C function fails.
+//!
+//! Optional features
+//! -----------------
+//!
+//! * `archive`: Fuzzy hashing of members of (possibly nested) ZIP archives
+//!   (see the [`archive`](archive/index.html) module).
+//! * `c-backend` (enabled by default): Computation of fuzzy hashes via the
+//!   underlying C library, which is built from source and statically linked.
+//! * `cli`: The `ssdeep` command-line tool, whose `report` subcommand
+//!   renders the matches between files into a report (see the
+//!   [`report`](report/index.html) module) and whose `mutation` subcommand
+//!   reports the robustness of the fuzzy hash of a file against mutations
+//!   (see the [`mutation`](mutation/index.html) module).
+//! * `decompress`: Fuzzy hashing of gzip, zstd, xz, and bzip2 compressed data
+//!   after their transparent decompression (see the
+//!   [`decompress`](decompress/index.html) module).
+//! * `digest`: Computation of fuzzy hashes alongside cryptographic digests
+//!   from the [RustCrypto](https://github.com/RustCrypto/hashes) crates in a
+//!   single pass (see [`MultiHasher`](struct.MultiHasher.html)).
+//! * `fanotify`: On-access hashing and classification of files via the
+//!   fanotify API, available only on Linux (see the
+//!   [`fanotify`](fanotify/index.html) module).
+//! * `fast-scan`: Fast enumeration of files on NTFS volumes via the master
+//!   file table, available only on Windows (see the
+//!   [`fast_scan`](fast_scan/index.html) module). It enables `pipeline`.
+//! * `graph`: Conversion of similarities between hashes into graphs of
+//!   [petgraph](https://crates.io/crates/petgraph) (see
+//!   [`cluster::to_graph()`](cluster/fn.to_graph.html)).
+//! * `http`: Fuzzy hashing of HTTP response bodies while they are being
+//!   downloaded (see the [`http`](http/index.html) module).
+//! * `indicatif`: Progress bars of batch operations via
+//!   [indicatif](https://crates.io/crates/indicatif) (see the
+//!   [`progress_bar`](progress_bar/index.html) module).
+//! * `mail`: Fuzzy hashing of individual parts of email messages (see the
+//!   [`mail`](mail/index.html) module).
+//! * `misp`: Ingestion of ssdeep attributes from MISP (see the
+//!   [`misp`](misp/index.html) module).
+//! * `mmap`: Querying of indexes directly in memory-mapped files (see
+//!   [`index::MappedIndex`](index/struct.MappedIndex.html)).
+//! * `native-compare`: Computation of match scores in
+//!   [`compare()`](fn.compare.html) natively in Rust instead of via the
+//!   underlying C library, with the same results, and comparisons of
+//!   prepared hashes (see [`PreparedHash`](struct.PreparedHash.html)).
+//! * `office`: Fuzzy hashing of sub-streams of Microsoft Office documents (see
+//!   the [`office`](office/index.html) module).
+//! * `parallel`: Parallel computation of fuzzy hashes of many buffers (see
+//!   [`hash_many()`](fn.hash_many.html)) and of match scores between all
+//!   pairs of many hashes (see
+//!   [`matrix::similarity_matrix()`](matrix/fn.similarity_matrix.html)).
+//! * `pipeline`: Producer/consumer pipelines for hashing and matching many
+//!   files (see the [`pipeline`](pipeline/index.html) module).
+//! * `rust-backend`: Computation of fuzzy hashes natively in Rust instead of
+//!   via the underlying C library, with the same results. It enables
+//!   `native-compare`, so when `c-backend` is disabled, the C library is not
+//!   needed at all (e.g. when it cannot be built on the target platform).
+//! * `sandbox`: Hashing of untrusted data in resource-limited worker
+//!   processes, available only on Linux (see the
+//!   [`sandbox`](sandbox/index.html) module).
+//! * `sarif`: Output of classification results in the SARIF format (see the
+//!   [`sarif`](sarif/index.html) module).
+//! * `serde`: Serialization and deserialization of fuzzy hashes
+//!   ([`FuzzyHash`](struct.FuzzyHash.html), as strings), comparison results,
+//!   and report types via [serde](https://crates.io/crates/serde).
+//! * `sqlite`: A persistent store of labeled hashes with an n-gram index in a
+//!   SQLite database (see the [`sqlite`](sqlite/index.html) module).
+//! * `tracing`: Instrumentation via [tracing](https://crates.io/crates/tracing).
+//!   Hashing of buffers and files (with their sizes and paths), comparisons
+//!   of batches (with their sizes), and queries of indexes and stores (with
+//!   the numbers of candidates and matches) are recorded as spans and events
+//!   at the debug level, so subscribers can measure their durations.
+//! * `vt`: Enrichment of matches with metadata from VirusTotal (see the
+//!   [`vt`](vt/index.html) module).
+
+#[cfg(not(any(feature = "c-backend", feature = "rust-backend")))]
+compile_error!("either the `c-backend` or the `rust-backend` feature has to be enabled");
 
+#[cfg(feature = "decompress")]
+extern crate bzip2;
+#[cfg(feature = "office")]
+extern crate cfb;
+#[cfg(feature = "pipeline")]
+extern crate crossbeam_channel;
+#[cfg(feature = "digest")]
+extern crate digest;
+#[cfg(feature = "decompress")]
+extern crate flate2;
+#[cfg(feature = "indicatif")]
+extern crate indicatif;
 extern crate libc;
+#[cfg(feature = "c-backend")]
 extern crate libfuzzy_sys as raw;
+#[cfg(feature = "mail")]
+extern crate mailparse;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "graph")]
+extern crate petgraph;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+#[cfg(any(feature = "http", feature = "misp", feature = "vt"))]
+extern crate reqwest;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(any(feature = "misp", feature = "sarif", feature = "vt"))]
+#[cfg_attr(any(feature = "misp", feature = "sarif"), macro_use)]
+extern crate serde_json;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(windows)]
+extern crate windows_sys;
+#[cfg(feature = "decompress")]
+extern crate xz2;
+#[cfg(any(feature = "archive", feature = "office"))]
+extern crate zip;
+#[cfg(feature = "decompress")]
+extern crate zstd;
 
+#[cfg(not(feature = "rust-backend"))]
 use libc::c_char;
 use std::error;
+use std::ffi::CStr;
+#[cfg(not(feature = "rust-backend"))]
 use std::ffi::CString;
 use std::fmt;
+use std::io;
 use std::path::Path;
 
+#[cfg(windows)]
+pub mod ads;
+#[cfg(feature = "archive")]
+pub mod archive;
+mod batch;
+mod cache;
+mod cdc;
+mod chunk_map;
+pub mod classify;
+pub mod cluster;
+mod composite;
+#[cfg(feature = "decompress")]
+pub mod decompress;
+pub mod dedupe;
+mod diff;
+pub mod dot;
+pub mod export;
+#[cfg(all(target_os = "linux", feature = "fanotify"))]
+pub mod fanotify;
+#[cfg(all(windows, feature = "fast-scan"))]
+pub mod fast_scan;
+pub mod fields;
+pub mod fs;
+mod fuzzy_hash;
+mod glob;
+mod hasher;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod index;
+#[cfg(feature = "mail")]
+pub mod mail;
+pub mod manifest;
+mod many;
+mod match_set;
+pub mod matrix;
+#[cfg(feature = "misp")]
+pub mod misp;
+#[cfg(feature = "digest")]
+mod multi_hasher;
+pub mod mutation;
+#[cfg(feature = "office")]
+pub mod office;
+#[cfg(feature = "pipeline")]
+pub mod pipeline;
+mod progress;
+#[cfg(feature = "indicatif")]
+pub mod progress_bar;
+mod region;
+pub mod report;
+#[cfg(all(target_os = "linux", feature = "sandbox"))]
+pub mod sandbox;
+#[cfg(feature = "sarif")]
+pub mod sarif;
+mod score;
+mod shard;
+pub mod sigfile;
+#[cfg(feature = "rust-backend")]
+mod spamsum;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+mod text;
+mod tree;
+#[cfg(feature = "pipeline")]
+pub mod tuning;
+
+#[cfg(feature = "vt")]
+pub mod vt;
+mod weighted;
+
+pub use batch::compare_batch;
+pub use batch::compare_many;
+pub use batch::HashBatch;
+pub use cache::ComparisonCache;
+pub use cdc::compare_chunks;
+pub use cdc::hash_chunks;
+pub use cdc::Chunk;
+pub use cdc::ChunkingOptions;
+pub use chunk_map::hash_with_chunk_map;
+pub use chunk_map::ChunkMap;
+pub use composite::CompositeInput;
+pub use composite::CompositeScore;
+pub use composite::CompositeScorer;
+pub use diff::diff_signatures;
+pub use diff::DiffRun;
+pub use diff::SignatureDiff;
+pub use diff::TrackDiff;
+pub use fuzzy_hash::ChunkField;
+pub use fuzzy_hash::CompactFuzzyHash;
+pub use fuzzy_hash::FuzzyHash;
+pub use fuzzy_hash::Ngrams;
+#[cfg(feature = "native-compare")]
+pub use fuzzy_hash::PreparedHash;
+pub use fuzzy_hash::SearchClause;
+pub use fuzzy_hash::SearchTerms;
+pub use hasher::hash_from_reader;
+pub use hasher::hash_from_reader_with_chunk_size;
+pub use hasher::DigestFlags;
+pub use hasher::FuzzyHasher;
+pub use many::hash_files;
+pub use many::hash_files_with_progress;
+#[cfg(feature = "parallel")]
+pub use many::hash_many;
+pub use match_set::KnownMatch;
+pub use match_set::MatchSet;
+#[cfg(feature = "digest")]
+pub use multi_hasher::MultiDigest;
+#[cfg(feature = "digest")]
+pub use multi_hasher::MultiHasher;
+pub use progress::Phase;
+pub use progress::Progress;
+pub use region::hash_file_region;
+pub use region::hash_region;
+pub use shard::shards_for;
+pub use text::hash_text_normalized;
+pub use text::normalize_text;
+pub use text::NormalizationOptions;
+pub use tree::hash_dir_tree;
+pub use weighted::compare_weighted;
+pub use weighted::WeightedCompareOptions;
+
+/// The size of a buffer that can hold any fuzzy hash, including the
+/// terminating null byte (the same as `FUZZY_MAX_RESULT` from the underlying C
+/// library).
+pub const FUZZY_MAX_RESULT: usize = 2 * score::SPAMSUM_LENGTH + 20;
+
+#[cfg(feature = "c-backend")]
+const _: () = assert!(FUZZY_MAX_RESULT == raw::FUZZY_MAX_RESULT);
+
 /// An enum containing errors that the library might return.
-#[derive(Debug, PartialEq)]
+///
+/// Two [`Io`](#variant.Io) errors are considered equal when they are of the
+/// same [`kind`](https://doc.rust-lang.org/std/io/struct.Error.html#method.kind).
+#[derive(Debug)]
 pub enum Error {
     /// Error returned when a function from the underlying C library fails.
     CFunctionFailed {
@@ -75,9 +349,167 @@ pub enum Error {
         /// Return code of the function.
         return_code: i32,
     },
+    /// Error returned when an I/O operation fails.
+    Io(io::Error),
+    /// Error returned when an HTTP request fails or its response is invalid.
+    HttpRequestFailed {
+        /// Description of the problem.
+        reason: String,
+    },
+    /// Error returned when an operation of a database fails.
+    DatabaseFailed {
+        /// Description of the problem.
+        reason: String,
+    },
+    /// Error returned when processing of the input is stopped because it would
+    /// exceed a limit.
+    LimitExceeded {
+        /// Name of the limit.
+        name: String,
+        /// Value of the limit.
+        limit: u64,
+    },
+    /// Error returned when an archive cannot be parsed.
+    ArchiveParsingFailed {
+        /// Description of the problem.
+        reason: String,
+    },
+    /// Error returned when a document cannot be parsed.
+    DocumentParsingFailed {
+        /// Description of the problem.
+        reason: String,
+    },
+    /// Error returned when an email message cannot be parsed.
+    MailParsingFailed {
+        /// Description of the problem.
+        reason: String,
+    },
+    /// Error returned when a fuzzy hash is invalid.
+    InvalidHash {
+        /// Description of the problem.
+        reason: String,
+    },
+    /// Error returned when a filename cannot be written into a signature
+    /// file.
+    InvalidFilename {
+        /// Description of the problem.
+        reason: String,
+    },
+    /// Error returned when a line of a signature file cannot be parsed.
+    InvalidSignatureLine {
+        /// Description of the problem.
+        reason: String,
+    },
+    /// Error returned when a sandboxed worker process fails.
+    SandboxFailed {
+        /// Description of the problem.
+        reason: String,
+    },
+    /// Error returned when a string passed to the underlying C library (a
+    /// hash or a path) contains a null byte.
+    EmbeddedNul {
+        /// Position of the null byte in the string.
+        position: usize,
+    },
 }
 
-impl error::Error for Error {}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq for Error {
+    fn eq(&self, other: &Error) -> bool {
+        match (self, other) {
+            (
+                Error::CFunctionFailed { name, return_code },
+                Error::CFunctionFailed {
+                    name: other_name,
+                    return_code: other_return_code,
+                },
+            ) => name == other_name && return_code == other_return_code,
+            (
+                Error::HttpRequestFailed { reason },
+                Error::HttpRequestFailed {
+                    reason: other_reason,
+                },
+            ) => reason == other_reason,
+            (
+                Error::DatabaseFailed { reason },
+                Error::DatabaseFailed {
+                    reason: other_reason,
+                },
+            ) => reason == other_reason,
+            (Error::Io(e), Error::Io(other_e)) => e.kind() == other_e.kind(),
+            (
+                Error::LimitExceeded { name, limit },
+                Error::LimitExceeded {
+                    name: other_name,
+                    limit: other_limit,
+                },
+            ) => name == other_name && limit == other_limit,
+            (
+                Error::ArchiveParsingFailed { reason },
+                Error::ArchiveParsingFailed {
+                    reason: other_reason,
+                },
+            ) => reason == other_reason,
+            (
+                Error::DocumentParsingFailed { reason },
+                Error::DocumentParsingFailed {
+                    reason: other_reason,
+                },
+            ) => reason == other_reason,
+            (
+                Error::MailParsingFailed { reason },
+                Error::MailParsingFailed {
+                    reason: other_reason,
+                },
+            ) => reason == other_reason,
+            (
+                Error::InvalidHash { reason },
+                Error::InvalidHash {
+                    reason: other_reason,
+                },
+            ) => reason == other_reason,
+            (
+                Error::InvalidFilename { reason },
+                Error::InvalidFilename {
+                    reason: other_reason,
+                },
+            ) => reason == other_reason,
+            (
+                Error::InvalidSignatureLine { reason },
+                Error::InvalidSignatureLine {
+                    reason: other_reason,
+                },
+            ) => reason == other_reason,
+            (
+                Error::SandboxFailed { reason },
+                Error::SandboxFailed {
+                    reason: other_reason,
+                },
+            ) => reason == other_reason,
+            (
+                Error::EmbeddedNul { position },
+                Error::EmbeddedNul {
+                    position: other_position,
+                },
+            ) => position == other_position,
+            _ => false,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -89,6 +521,36 @@ impl std::fmt::Display for Error {
                     name, return_code
                 )
             }
+            Error::HttpRequestFailed { reason } => {
+                write!(f, "HTTP request failed: {}", reason)
+            }
+            Error::DatabaseFailed { reason } => {
+                write!(f, "database operation failed: {}", reason)
+            }
+            Error::Io(e) => write!(f, "I/O operation failed: {}", e),
+            Error::LimitExceeded { name, limit } => {
+                write!(f, "limit {} ({}) exceeded", name, limit)
+            }
+            Error::ArchiveParsingFailed { reason } => {
+                write!(f, "failed to parse archive: {}", reason)
+            }
+            Error::DocumentParsingFailed { reason } => {
+                write!(f, "failed to parse document: {}", reason)
+            }
+            Error::MailParsingFailed { reason } => {
+                write!(f, "failed to parse email message: {}", reason)
+            }
+            Error::InvalidHash { reason } => write!(f, "invalid fuzzy hash: {}", reason),
+            Error::InvalidFilename { reason } => write!(f, "invalid filename: {}", reason),
+            Error::InvalidSignatureLine { reason } => {
+                write!(f, "invalid signature line: {}", reason)
+            }
+            Error::SandboxFailed { reason } => {
+                write!(f, "sandboxed worker failed: {}", reason)
+            }
+            Error::EmbeddedNul { position } => {
+                write!(f, "null byte at position {}", position)
+            }
         }
     }
 }
@@ -139,27 +601,65 @@ pub type Result<T> = std::result::Result<T, Error>;
 ///
 /// ```
 ///
-/// # Panics
+/// When either of the hashes contains a null byte, it returns
+/// [`Error::EmbeddedNul`](enum.Error.html#variant.EmbeddedNul):
+/// ```
+/// assert_eq!(
+///     ssdeep::compare("3:tc:u", "3:tc\0:u"),
+///     Err(ssdeep::Error::EmbeddedNul { position: 4 })
+/// );
+/// ```
+/// Note that [`hash()`](fn.hash.html) never returns a hash with a null byte,
+/// so this may happen only if you handcrafted the hashes or obtained them from
+/// other sources.
 ///
-/// If either of the hashes contain a null byte. Note that
-/// [`hash()`](fn.hash.html) never returns a hash with a null byte, so this may
-/// happen only if you handcrafted the hashes or obtained them from other
-/// sources.
+/// Apart from strings, it also accepts parsed hashes
+/// ([`FuzzyHash`](struct.FuzzyHash.html)), whose validity is checked when they
+/// are parsed:
+/// ```
+/// use ssdeep::FuzzyHash;
+///
+/// let h1: FuzzyHash = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C".parse().unwrap();
+/// let h2: FuzzyHash = "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx".parse().unwrap();
+/// assert_eq!(ssdeep::compare(&h1, &h2), Ok(22));
+/// ```
 ///
 /// # Implementation details
 ///
 /// Internally, it calls the `fuzzy_compare()` function from the underlying C
 /// library. The return value `-1` is translated into
 /// [`Error`](enum.Error.html).
-pub fn compare(hash1: &str, hash2: &str) -> Result<u8> {
-    let h1 = str_to_cstring(hash1);
-    let h2 = str_to_cstring(hash2);
-    let score = unsafe {
-        raw::fuzzy_compare(
-            h1.as_bytes_with_nul().as_ptr() as *const c_char,
-            h2.as_bytes_with_nul().as_ptr() as *const c_char,
-        )
-    };
+///
+/// With the `native-compare` feature, the score is computed natively in Rust
+/// instead, without copying the hashes into C strings. The results (including
+/// errors) are the same as the results of `fuzzy_compare()`, including for
+/// block sizes that it parses leniently (e.g. with leading whitespace or a
+/// sign, like `strtoul()`).
+pub fn compare<H1: AsRef<str>, H2: AsRef<str>>(hash1: H1, hash2: H2) -> Result<u8> {
+    compare_impl(hash1.as_ref(), hash2.as_ref())
+}
+
+#[cfg(feature = "native-compare")]
+#[inline]
+fn compare_impl(hash1: &str, hash2: &str) -> Result<u8> {
+    check_no_nul(hash1)?;
+    check_no_nul(hash2)?;
+    score::compare(hash1, hash2).ok_or_else(|| Error::CFunctionFailed {
+        name: "fuzzy_compare".to_string(),
+        return_code: -1,
+    })
+}
+
+#[cfg(not(feature = "native-compare"))]
+fn compare_impl(hash1: &str, hash2: &str) -> Result<u8> {
+    let h1 = str_to_cstring(hash1)?;
+    let h2 = str_to_cstring(hash2)?;
+    compare_c_strings(&h1, &h2)
+}
+
+#[cfg(not(feature = "native-compare"))]
+fn compare_c_strings(hash1: &CStr, hash2: &CStr) -> Result<u8> {
+    let score = unsafe { raw::fuzzy_compare(hash1.as_ptr(), hash2.as_ptr()) };
     if score == -1 {
         Err(Error::CFunctionFailed {
             name: "fuzzy_compare".to_string(),
@@ -170,6 +670,48 @@ pub fn compare(hash1: &str, hash2: &str) -> Result<u8> {
     }
 }
 
+/// Computes the match score between two fuzzy hashes given as C strings.
+///
+/// It is the same as [`compare()`](fn.compare.html), but as the hashes are
+/// already null-terminated, they are passed to the underlying C library
+/// directly, without copying them into new C strings. This saves two
+/// allocations per comparison (e.g. for hashes read from a database or
+/// received over FFI).
+///
+/// # Examples
+///
+/// ```
+/// use std::ffi::CStr;
+///
+/// let h1 = CStr::from_bytes_with_nul(b"3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C\0").unwrap();
+/// let h2 = CStr::from_bytes_with_nul(b"3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx\0").unwrap();
+/// assert_eq!(ssdeep::compare_cstr(h1, h2), Ok(22));
+/// ```
+///
+/// # Implementation details
+///
+/// Internally, it calls the `fuzzy_compare()` function from the underlying C
+/// library. With the `native-compare` feature, the score is computed natively
+/// in Rust instead (as in [`compare()`](fn.compare.html)). Then, hashes that
+/// are not valid UTF-8 are rejected in the same way as malformed hashes.
+pub fn compare_cstr(hash1: &CStr, hash2: &CStr) -> Result<u8> {
+    #[cfg(feature = "native-compare")]
+    {
+        let compare_failed = |_| Error::CFunctionFailed {
+            name: "fuzzy_compare".to_string(),
+            return_code: -1,
+        };
+        let hash1 = hash1.to_str().map_err(compare_failed)?;
+        let hash2 = hash2.to_str().map_err(compare_failed)?;
+        compare_impl(hash1, hash2)
+    }
+
+    #[cfg(not(feature = "native-compare"))]
+    {
+        compare_c_strings(hash1, hash2)
+    }
+}
+
 /// Computes the fuzzy hash of bytes.
 ///
 /// Returns the fuzzy hash of the given bytes. When an error occurs, it returns
@@ -184,30 +726,93 @@ pub fn compare(hash1: &str, hash2: &str) -> Result<u8> {
 ///
 /// # Panics
 ///
-/// * If the length of the bytes is strictly greater than `2^32 - 1` bytes. The
-///   reason for this is that the corresponding function from the underlying C
-///   library accepts the length of the input buffer as an unsigned 32b
-///   integer.
-/// * If the function from the underyling C library provides a non-ASCII hash.
-///   This would be a bug in the C library.
+/// If the function from the underyling C library provides a non-ASCII hash.
+/// This would be a bug in the C library.
 ///
 /// # Implementation details
 ///
 /// Internally, it calls the `fuzzy_hash_buf()` function from the underlying C
 /// library. A non-zero return value is translated into
-/// [`Error`](enum.Error.html).
+/// [`Error`](enum.Error.html). As `fuzzy_hash_buf()` accepts the length of the
+/// input buffer as an unsigned 32b integer, bytes longer than `2^32 - 1` bytes
+/// are fed in chunks via `fuzzy_update()` and hashed via `fuzzy_digest()`
+/// instead.
+///
+/// With the `rust-backend` feature, the fuzzy hash is computed natively in
+/// Rust instead, with the same result.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(len = buf.len()))
+)]
 pub fn hash(buf: &[u8]) -> Result<String> {
-    assert!(buf.len() <= u32::max_value() as usize);
-
-    let mut result = create_buffer_for_result();
-    let rc = unsafe {
-        raw::fuzzy_hash_buf(
-            buf.as_ptr(),
-            buf.len() as u32,
-            result.as_mut_ptr() as *mut c_char,
-        )
-    };
-    result_buffer_to_string("fuzzy_hash_buf", result, rc)
+    #[cfg(not(feature = "rust-backend"))]
+    {
+        if buf.len() <= u32::MAX as usize {
+            let mut result = create_buffer_for_result();
+            let rc = unsafe {
+                raw::fuzzy_hash_buf(
+                    buf.as_ptr(),
+                    buf.len() as u32,
+                    result.as_mut_ptr() as *mut c_char,
+                )
+            };
+            return result_buffer_to_string("fuzzy_hash_buf", result, rc);
+        }
+    }
+
+    let mut hasher = FuzzyHasher::new()?;
+    hasher.set_total_input_length(buf.len() as u64)?;
+    for chunk in buf.chunks(u32::MAX as usize) {
+        hasher.update(chunk)?;
+    }
+    hasher.digest()
+}
+
+/// Computes the fuzzy hash of bytes into the given buffer.
+///
+/// It is the same as [`hash()`](fn.hash.html), but the hash is written into
+/// the given buffer (followed by a null byte, so the buffer can be passed to C
+/// code) instead of a newly allocated string, which is returned as a slice of
+/// the buffer. Reusing a buffer thus avoids an allocation per call.
+///
+/// # Examples
+///
+/// ```
+/// let mut out = [0; ssdeep::FUZZY_MAX_RESULT];
+/// let h = ssdeep::hash_into(b"Hello there!", &mut out).unwrap();
+/// assert_eq!(h, "3:aNRn:aNRn");
+/// ```
+///
+/// # Panics
+///
+/// If the function from the underyling C library provides a non-ASCII hash.
+/// This would be a bug in the C library.
+///
+/// # Implementation details
+///
+/// Internally, it calls the `fuzzy_hash_buf()` function from the underlying C
+/// library, which writes the hash directly into the buffer. With the
+/// `rust-backend` feature (or for inputs of `4 GiB` or more), the hash is
+/// computed as in [`hash()`](fn.hash.html) and copied into the buffer.
+pub fn hash_into<'a>(buf: &[u8], out: &'a mut [u8; FUZZY_MAX_RESULT]) -> Result<&'a str> {
+    #[cfg(not(feature = "rust-backend"))]
+    {
+        if buf.len() <= u32::MAX as usize {
+            let rc = unsafe {
+                raw::fuzzy_hash_buf(
+                    buf.as_ptr(),
+                    buf.len() as u32,
+                    out.as_mut_ptr() as *mut c_char,
+                )
+            };
+            return result_buffer_to_str("fuzzy_hash_buf", out, rc);
+        }
+    }
+
+    let hash = hash(buf)?;
+    out[..hash.len()].copy_from_slice(hash.as_bytes());
+    out[hash.len()] = 0;
+    Ok(std::str::from_utf8(&out[..hash.len()]).unwrap())
 }
 
 /// Computes the fuzzy hash of the contents of a file.
@@ -222,49 +827,125 @@ pub fn hash(buf: &[u8]) -> Result<String> {
 /// assert_eq!(h, "48:9MABzSwnjpDeSrLp8+nagE4f3ZMvcDT0MIhqy6Ic:9XMwnjdeSHS+n5ZfScX0MJ7");
 /// ```
 ///
+/// When the file cannot be opened or read (e.g. it does not exist or you lack
+/// permissions to read it), it returns [`Error::Io`](enum.Error.html#variant.Io)
+/// with the original [`io::Error`](https://doc.rust-lang.org/std/io/struct.Error.html),
+/// so you can inspect its [`kind()`](https://doc.rust-lang.org/std/io/struct.Error.html#method.kind).
+///
+/// Files larger than 4 GiB are supported. Any path that the operating system
+/// can represent can be hashed, including paths that are not valid UTF-8 (e.g.
+/// file names in a legacy encoding on Unix). On Windows, extended-length paths
+/// (`\\?\C:\...`), UNC paths (`\\server\share\...`), and paths longer than
+/// `MAX_PATH` are supported as well.
+///
 /// # Panics
 ///
-/// * If the path to the file cannot be converted into a string or it contains
-///   a null byte.
-/// * If the function from the underyling C library provides a non-ASCII hash.
-///   This would be a bug in the C library.
+/// If the function from the underyling C library provides a non-ASCII hash.
+/// This would be a bug in the C library.
 ///
 /// # Implementation details
 ///
-/// Internally, it calls the `fuzzy_hash_filename()` function from the
-/// underlying C library. A non-zero return value is translated into
-/// [`Error`](enum.Error.html).
+/// The file is opened by Rust and its contents are fed into the underlying C
+/// library in chunks (see [`FuzzyHasher`](struct.FuzzyHasher.html)). With the
+/// `rust-backend` feature, the contents are hashed natively in Rust.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(path = %file_path.as_ref().display()), err)
+)]
 pub fn hash_from_file<P: AsRef<Path>>(file_path: P) -> Result<String> {
-    let mut result = create_buffer_for_result();
-    let fp = path_as_cstring(file_path);
-    let rc = unsafe {
-        raw::fuzzy_hash_filename(
-            fp.as_bytes_with_nul().as_ptr() as *const c_char,
-            result.as_mut_ptr() as *mut c_char,
-        )
-    };
-    result_buffer_to_string("fuzzy_hash_filename", result, rc)
+    let file = std::fs::File::open(file_path)?;
+    hasher::hash_and_copy(file, io::sink())
+}
+
+/// Computes the fuzzy hash of the contents of an already opened file.
+///
+/// This allows hashing a file that was opened in a special way (e.g. with
+/// `O_NOFOLLOW` or `O_NOATIME`) or that was passed from a sandbox, without
+/// reopening it by its path, which would be prone to TOCTOU races. The contents
+/// are read from the current position of the file up to its end, so the
+/// position is at the end of the file afterwards. When reading fails, it
+/// returns [`Error::Io`](enum.Error.html#variant.Io).
+///
+/// # Examples
+///
+/// ```
+/// let file = std::fs::File::open("tests/file.txt").unwrap();
+/// let h = ssdeep::hash_from_file_handle(&file).unwrap();
+/// assert_eq!(h, ssdeep::hash_from_file("tests/file.txt").unwrap());
+/// ```
+pub fn hash_from_file_handle(file: &std::fs::File) -> Result<String> {
+    hasher::hash_and_copy(file, io::sink())
 }
 
-fn path_as_cstring<P: AsRef<Path>>(path: P) -> CString {
-    // We can unwrap() the result because if the path cannot be converted into
-    // a string, we panic, as documented in functions that call this function.
-    str_to_cstring(path.as_ref().to_str().unwrap())
+/// Computes the fuzzy hash of the contents of a file given by a borrowed file
+/// descriptor (Unix only).
+///
+/// It is the same as [`hash_from_file_handle()`](fn.hash_from_file_handle.html),
+/// but for callers that only hold a file descriptor. The descriptor is
+/// neither closed nor duplicated. To hash a raw descriptor, borrow it via
+/// [`BorrowedFd::borrow_raw()`](https://doc.rust-lang.org/std/os/fd/struct.BorrowedFd.html#method.borrow_raw).
+///
+/// # Examples
+///
+/// ```
+/// use std::os::fd::AsFd;
+///
+/// let file = std::fs::File::open("tests/file.txt").unwrap();
+/// let h = ssdeep::hash_from_fd(file.as_fd()).unwrap();
+/// assert_eq!(h, ssdeep::hash_from_file("tests/file.txt").unwrap());
+/// ```
+#[cfg(unix)]
+pub fn hash_from_fd(fd: std::os::fd::BorrowedFd<'_>) -> Result<String> {
+    use std::os::fd::AsRawFd;
+    use std::os::fd::FromRawFd;
+
+    // The file must not be dropped because that would close the descriptor,
+    // which is only borrowed.
+    let file = std::mem::ManuallyDrop::new(unsafe { std::fs::File::from_raw_fd(fd.as_raw_fd()) });
+    hash_from_file_handle(&file)
 }
 
-fn str_to_cstring(s: &str) -> CString {
-    // We can unwrap() the result because if there is a null byte, we panic, as
-    // documented in functions that call this function.
-    CString::new(s).unwrap()
+#[cfg(not(feature = "rust-backend"))]
+fn str_to_cstring(s: &str) -> Result<CString> {
+    CString::new(s).map_err(|e| Error::EmbeddedNul {
+        position: e.nul_position(),
+    })
 }
 
-fn create_buffer_for_result() -> Vec<u8> {
+#[cfg(feature = "native-compare")]
+fn check_no_nul(s: &str) -> Result<()> {
+    match s.bytes().position(|c| c == 0) {
+        Some(position) => Err(Error::EmbeddedNul { position }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(feature = "rust-backend"))]
+fn create_buffer_for_result() -> [u8; FUZZY_MAX_RESULT] {
     // From fuzzy.h: "The buffer into which the fuzzy hash is stored has to be
     // allocated to hold at least FUZZY_MAX_RESULT bytes."
-    Vec::with_capacity(raw::FUZZY_MAX_RESULT)
+    //
+    // The buffer is zeroed (rather than left uninitialized), so all of it can
+    // be safely read after the underlying C library has written the hash into
+    // it.
+    [0; FUZZY_MAX_RESULT]
+}
+
+#[cfg(not(feature = "rust-backend"))]
+fn result_buffer_to_string(
+    libfuzzy_func: &str,
+    result: [u8; FUZZY_MAX_RESULT],
+    rc: i32,
+) -> Result<String> {
+    result_buffer_to_str(libfuzzy_func, &result, rc).map(str::to_string)
 }
 
-fn result_buffer_to_string(libfuzzy_func: &str, mut result: Vec<u8>, rc: i32) -> Result<String> {
+#[cfg(not(feature = "rust-backend"))]
+fn result_buffer_to_str<'a>(
+    libfuzzy_func: &str,
+    result: &'a [u8; FUZZY_MAX_RESULT],
+    rc: i32,
+) -> Result<&'a str> {
     if rc != 0 {
         // The function from libfuzzy failed, so there is no result.
         return Err(Error::CFunctionFailed {
@@ -273,23 +954,12 @@ fn result_buffer_to_string(libfuzzy_func: &str, mut result: Vec<u8>, rc: i32) ->
         });
     }
 
-    // Since the resulting vector that holds the fuzzy hash was populated in
-    // the underlying C library, we have to adjust its length because at this
-    // point, the vector thinks that its length is zero. We do this by finding
-    // the first null byte.
-    unsafe {
-        let mut len = 0;
-        for i in 0..raw::FUZZY_MAX_RESULT {
-            if *result.get_unchecked(i) == 0 {
-                break;
-            }
-            len += 1;
-        }
-        result.set_len(len);
-    }
-
-    // The result should only be composed of ASCII characters, i.e. the result
-    // should be convertible to UTF-8. The presence of non-ASCII character
-    // would be a bug in libfuzzy, in which case we panic.
-    Ok(String::from_utf8(result).unwrap())
+    // The result should be a null-terminated string composed only of ASCII
+    // characters, i.e. convertible to UTF-8. Only the bytes of the buffer are
+    // searched for the null byte, so a missing one cannot make us read past
+    // the buffer. A missing null byte or the presence of a non-ASCII
+    // character would be a bug in libfuzzy, in which case we panic.
+    let result = CStr::from_bytes_until_nul(result)
+        .unwrap_or_else(|_| panic!("{}() returned a hash without a null byte", libfuzzy_func));
+    Ok(result.to_str().unwrap())
 }
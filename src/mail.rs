@@ -0,0 +1,147 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Fuzzy hashing of individual parts of email messages.
+//!
+//! This module is available only when the `mail` feature is enabled.
+
+use mailparse::{self, DispositionType, ParsedMail};
+
+use super::hash;
+use super::Error;
+use super::Result;
+
+/// Fuzzy hash of a single MIME part of an email message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MailPartHash {
+    /// Index of the message in the input (always zero for a single message).
+    pub message_index: usize,
+    /// Index of the part in the message (in depth-first order, counting also
+    /// multipart containers).
+    pub part_index: usize,
+    /// MIME type of the part (e.g. `text/plain` or `application/pdf`).
+    pub content_type: String,
+    /// Name of the attached file, if any.
+    pub filename: Option<String>,
+    /// Is the part an attachment (`Content-Disposition: attachment`)?
+    pub is_attachment: bool,
+    /// Length of the decoded contents of the part (in bytes).
+    pub size: usize,
+    /// Fuzzy hash of the decoded contents of the part.
+    pub hash: String,
+}
+
+/// Computes fuzzy hashes of all parts of an email message in the EML format.
+///
+/// Multipart containers are skipped; only parts carrying data are hashed. The
+/// data are hashed after their content transfer encoding (e.g. base64 or
+/// quoted-printable) is removed.
+///
+/// # Examples
+///
+/// ```
+/// let eml = b"Subject: Hello\r\nContent-Type: text/plain\r\n\r\nHello there!";
+/// let parts = ssdeep::mail::hash_eml(eml).unwrap();
+/// assert_eq!(parts.len(), 1);
+/// assert_eq!(parts[0].content_type, "text/plain");
+/// assert_eq!(parts[0].hash, "3:aNRn:aNRn");
+/// ```
+pub fn hash_eml(raw: &[u8]) -> Result<Vec<MailPartHash>> {
+    let mut result = Vec::new();
+    hash_message(0, raw, &mut result)?;
+    Ok(result)
+}
+
+/// Computes fuzzy hashes of all parts of all email messages in an mbox.
+///
+/// Messages are separated by lines starting with `From `. Lines quoted by the
+/// mboxrd convention (`>From `, `>>From `, etc.) are unquoted before the
+/// message is parsed. Otherwise, it behaves like
+/// [`hash_eml()`](fn.hash_eml.html).
+pub fn hash_mbox(raw: &[u8]) -> Result<Vec<MailPartHash>> {
+    let mut result = Vec::new();
+    for (i, message) in split_mbox(raw).iter().enumerate() {
+        hash_message(i, message, &mut result)?;
+    }
+    Ok(result)
+}
+
+fn hash_message(message_index: usize, raw: &[u8], result: &mut Vec<MailPartHash>) -> Result<()> {
+    let mail = mailparse::parse_mail(raw).map_err(to_error)?;
+    for (part_index, part) in mail.parts().enumerate() {
+        if !part.subparts.is_empty() || part.ctype.mimetype.starts_with("multipart/") {
+            continue;
+        }
+
+        let body = part.get_body_raw().map_err(to_error)?;
+        let disposition = part.get_content_disposition();
+        result.push(MailPartHash {
+            message_index,
+            part_index,
+            content_type: part.ctype.mimetype.clone(),
+            filename: part_filename(part),
+            is_attachment: disposition.disposition == DispositionType::Attachment,
+            size: body.len(),
+            hash: hash(&body)?,
+        });
+    }
+    Ok(())
+}
+
+fn part_filename(part: &ParsedMail) -> Option<String> {
+    part.get_content_disposition()
+        .params
+        .get("filename")
+        .or_else(|| part.ctype.params.get("name"))
+        .cloned()
+}
+
+fn split_mbox(raw: &[u8]) -> Vec<Vec<u8>> {
+    let mut messages = Vec::new();
+    let mut current: Option<Vec<u8>> = None;
+    for line in raw.split_inclusive(|&b| b == b'\n') {
+        if line.starts_with(b"From ") {
+            if let Some(message) = current.take() {
+                messages.push(message);
+            }
+            current = Some(Vec::new());
+            continue;
+        }
+
+        let message = match current {
+            Some(ref mut message) => message,
+            // Data before the first separator do not belong to any message.
+            None => continue,
+        };
+        let quotes = line.iter().take_while(|&&b| b == b'>').count();
+        if quotes > 0 && line[quotes..].starts_with(b"From ") {
+            message.extend_from_slice(&line[1..]);
+        } else {
+            message.extend_from_slice(line);
+        }
+    }
+    if let Some(message) = current {
+        messages.push(message);
+    }
+    messages
+}
+
+fn to_error(e: mailparse::MailParseError) -> Error {
+    Error::MailParsingFailed {
+        reason: e.to_string(),
+    }
+}
@@ -0,0 +1,151 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Comparison of two scans of the same files (e.g. for detection of drift or
+//! forensic baselining).
+//!
+//! A scan manifest is a [signature file](../sigfile/index.html) with the
+//! fuzzy hashes of the scanned files. The files of two manifests are paired
+//! by their filenames.
+//!
+//! # Examples
+//!
+//! ```
+//! use ssdeep::sigfile::SignatureFile;
+//!
+//! let mut baseline = SignatureFile::new();
+//! baseline.push("3:aNRn:aNRn", "hello.txt");
+//! baseline.push("3:aJn:aJn", "removed.txt");
+//! let mut current = SignatureFile::new();
+//! current.push("3:aNRn:aNRn", "hello.txt");
+//! current.push("3:aJn:aJn", "added.txt");
+//!
+//! let diff = ssdeep::manifest::diff(&baseline, &current).unwrap();
+//! assert_eq!(diff.added, ["added.txt"]);
+//! assert_eq!(diff.removed, ["removed.txt"]);
+//! assert_eq!(diff.identical, ["hello.txt"]);
+//! assert!(diff.modified.is_empty());
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use super::compare;
+use super::sigfile::SignatureFile;
+use super::Result;
+
+/// A file whose hash differs between two manifests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ModifiedFile {
+    /// Name of the file.
+    pub filename: String,
+    /// Hash of the file in the old manifest.
+    pub old_hash: String,
+    /// Hash of the file in the new manifest.
+    pub new_hash: String,
+    /// Match score between the hashes (0 when the file was rewritten
+    /// completely, up to 100 when it was modified only slightly).
+    pub score: u8,
+}
+
+/// Differences between two manifests.
+///
+/// All the lists are ordered by filenames.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ManifestDiff {
+    /// Files that are only in the new manifest.
+    pub added: Vec<String>,
+    /// Files that are only in the old manifest.
+    pub removed: Vec<String>,
+    /// Files with the same hash in both manifests.
+    pub identical: Vec<String>,
+    /// Files with different hashes in the manifests.
+    pub modified: Vec<ModifiedFile>,
+}
+
+impl ManifestDiff {
+    /// Returns the modified files whose score is strictly greater than the
+    /// given threshold (i.e. similar but modified files).
+    pub fn similar(&self, threshold: u8) -> impl Iterator<Item = &ModifiedFile> {
+        self.modified
+            .iter()
+            .filter(move |file| file.score > threshold)
+    }
+}
+
+/// Computes the differences between the given manifests.
+///
+/// When a filename appears in a manifest more than once, its last signature
+/// is used. Files are identical when their hashes are equal, which does not
+/// guarantee that their contents are equal (use a cryptographic hash for
+/// that).
+///
+/// When a hash of a modified file is invalid, it returns an error.
+pub fn diff(old: &SignatureFile, new: &SignatureFile) -> Result<ManifestDiff> {
+    let old_hashes = hashes_by_filename(old);
+    let new_hashes = hashes_by_filename(new);
+
+    let mut diff = ManifestDiff::default();
+    for (&filename, &old_hash) in &old_hashes {
+        match new_hashes.get(filename) {
+            None => diff.removed.push(filename.to_string()),
+            Some(&new_hash) if new_hash == old_hash => diff.identical.push(filename.to_string()),
+            Some(&new_hash) => diff.modified.push(ModifiedFile {
+                filename: filename.to_string(),
+                old_hash: old_hash.to_string(),
+                new_hash: new_hash.to_string(),
+                score: compare(old_hash, new_hash)?,
+            }),
+        }
+    }
+    diff.added = new_hashes
+        .keys()
+        .filter(|filename| !old_hashes.contains_key(*filename))
+        .map(|filename| filename.to_string())
+        .collect();
+    Ok(diff)
+}
+
+/// Computes the differences between the manifests in the given paths.
+///
+/// See [`diff()`](fn.diff.html) and
+/// [`SignatureFile::load()`](../sigfile/struct.SignatureFile.html#method.load)
+/// for more details.
+pub fn diff_files<P: AsRef<Path>, Q: AsRef<Path>>(
+    old_path: P,
+    new_path: Q,
+) -> Result<ManifestDiff> {
+    diff(
+        &SignatureFile::load(old_path)?,
+        &SignatureFile::load(new_path)?,
+    )
+}
+
+fn hashes_by_filename(manifest: &SignatureFile) -> BTreeMap<&str, &str> {
+    manifest
+        .signatures
+        .iter()
+        .map(|signature| (signature.filename.as_str(), signature.hash.as_str()))
+        .collect()
+}
@@ -0,0 +1,149 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Fuzzy hashing of many inputs at once.
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::path::Path;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::thread;
+
+#[cfg(feature = "parallel")]
+use super::hash;
+use super::hash_from_file;
+use super::Phase;
+use super::Progress;
+use super::Result;
+
+/// Computes the fuzzy hashes of the given buffers in parallel.
+///
+/// The buffers are hashed on the threads of the global rayon pool (i.e. on
+/// all cores by default), and the results are returned in the order of the
+/// buffers. The hashes are the same as the hashes computed by
+/// [`hash()`](fn.hash.html), which also produces the errors.
+///
+/// It is available only when the `parallel` feature is enabled.
+///
+/// # Examples
+///
+/// ```
+/// let bufs = [&b"Hello there!"[..], &b"Hello"[..]];
+/// let results = ssdeep::hash_many(&bufs);
+/// assert_eq!(results[0], ssdeep::hash(bufs[0]));
+/// assert_eq!(results[1], ssdeep::hash(bufs[1]));
+/// ```
+#[cfg(feature = "parallel")]
+pub fn hash_many<B: AsRef<[u8]> + Sync>(bufs: &[B]) -> Vec<Result<String>> {
+    bufs.par_iter().map(|buf| hash(buf.as_ref())).collect()
+}
+
+/// Computes the fuzzy hashes of the given files concurrently.
+///
+/// The files are hashed by `concurrency` worker threads (at least one), each
+/// of which hashes a single file at a time. Hence, at most `concurrency` files
+/// are open at any given moment, regardless of the number of paths. The
+/// results are returned in the order of the paths, one per path, so a file
+/// that cannot be hashed does not prevent the other files from being hashed.
+/// The hashes and errors are the same as those of
+/// [`hash_from_file()`](fn.hash_from_file.html).
+///
+/// The right concurrency depends on the storage: a few workers usually
+/// saturate a spinning disk, while an SSD or a network filesystem benefits
+/// from more workers than there are cores.
+///
+/// # Examples
+///
+/// ```
+/// let paths = ["tests/file.txt", "tests/non-existing-file.txt"];
+/// let results = ssdeep::hash_files(&paths, 4);
+/// assert_eq!(
+///     results[0],
+///     Ok("48:9MABzSwnjpDeSrLp8+nagE4f3ZMvcDT0MIhqy6Ic:9XMwnjdeSHS+n5ZfScX0MJ7".to_string())
+/// );
+/// assert!(results[1].is_err());
+/// ```
+pub fn hash_files<P: AsRef<Path> + Sync>(paths: &[P], concurrency: usize) -> Vec<Result<String>> {
+    hash_files_with_progress(paths, concurrency, |_| {})
+}
+
+/// Computes the fuzzy hashes of the given files concurrently, reporting the
+/// progress (in the [`Hashing`](enum.Phase.html) phase) to the given
+/// callback.
+///
+/// The callback is called after each file is hashed, with the number of
+/// files hashed so far and the path of the file. As the files are hashed
+/// concurrently, they are not necessarily reported in the order of the paths.
+/// The callback is never called by more than one worker at a time.
+///
+/// See [`hash_files()`](fn.hash_files.html) for more details.
+pub fn hash_files_with_progress<P, F>(
+    paths: &[P],
+    concurrency: usize,
+    progress: F,
+) -> Vec<Result<String>>
+where
+    P: AsRef<Path> + Sync,
+    F: FnMut(&Progress) + Send,
+{
+    let next_path = AtomicUsize::new(0);
+    let total = paths.len() as u64;
+    // The number of hashed files is kept together with the callback so that
+    // the reports are made in the order of their counts.
+    let progress = Mutex::new((0, progress));
+    let workers = concurrency.max(1).min(paths.len());
+    let worker_results: Vec<Vec<(usize, Result<String>)>> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let i = next_path.fetch_add(1, Ordering::Relaxed);
+                        if i >= paths.len() {
+                            return results;
+                        }
+                        results.push((i, hash_from_file(&paths[i])));
+                        let mut progress = progress.lock().unwrap_or_else(|e| e.into_inner());
+                        let (ref mut done, ref mut callback) = *progress;
+                        *done += 1;
+                        callback(&Progress {
+                            phase: Phase::Hashing,
+                            done: *done,
+                            total: Some(total),
+                            current_path: Some(paths[i].as_ref().to_path_buf()),
+                        });
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("a hashing worker panicked"))
+            .collect()
+    });
+
+    let mut results: Vec<Option<Result<String>>> = paths.iter().map(|_| None).collect();
+    for (i, result) in worker_results.into_iter().flatten() {
+        results[i] = Some(result);
+    }
+    results
+        .into_iter()
+        .map(|result| result.expect("every path is hashed by a worker"))
+        .collect()
+}
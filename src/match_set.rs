@@ -0,0 +1,165 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Matching of fuzzy hashes against a set of labeled known hashes.
+
+use std::path::Path;
+
+use super::hash;
+use super::hash_from_file;
+use super::sigfile::SignatureFile;
+use super::HashBatch;
+use super::Result;
+
+/// A known hash matched by [`MatchSet`](struct.MatchSet.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownMatch {
+    /// Label of the known hash (e.g. the name of the known file).
+    pub label: String,
+    /// The known hash.
+    pub hash: String,
+    /// The score between the matched hash and the known hash.
+    pub score: u8,
+}
+
+/// A set of labeled known hashes against which unknown hashes, bytes, or files
+/// are matched.
+///
+/// It is the library equivalent of matching files against a list of known
+/// hashes via `ssdeep -m`: all known hashes whose score is above the
+/// threshold are returned, in the order in which they were added. The scores
+/// are computed via a [`HashBatch`](struct.HashBatch.html), so matching does
+/// not call the underlying C library for every known hash.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::MatchSet;
+///
+/// let mut known = MatchSet::new();
+/// known
+///     .insert("dropper.exe", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C")
+///     .unwrap();
+/// known.insert("hello.txt", "3:aNRn:aNRn").unwrap();
+///
+/// let matches = known.match_hash("3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx").unwrap();
+/// assert_eq!(matches.len(), 1);
+/// assert_eq!(matches[0].label, "dropper.exe");
+/// assert_eq!(matches[0].score, 22);
+///
+/// let matches = known.match_buffer(b"Hello there!").unwrap();
+/// assert_eq!(matches[0].label, "hello.txt");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MatchSet {
+    /// Only known hashes whose score is strictly greater than the threshold
+    /// are matched.
+    ///
+    /// Default: 0 (as in the `ssdeep` tool).
+    pub threshold: u8,
+    labels: Vec<String>,
+    hashes: Vec<String>,
+    batch: HashBatch,
+}
+
+impl MatchSet {
+    /// Creates an empty set with the default threshold.
+    pub fn new() -> Self {
+        MatchSet::default()
+    }
+
+    /// Creates an empty set with the given threshold.
+    pub fn with_threshold(threshold: u8) -> Self {
+        MatchSet {
+            threshold,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a set from the signatures in the given signature file, labeled
+    /// by their filenames.
+    ///
+    /// When any of the hashes is invalid, it returns an error.
+    pub fn from_signature_file(sigfile: &SignatureFile) -> Result<Self> {
+        let mut set = MatchSet::new();
+        for signature in &sigfile.signatures {
+            set.insert(&signature.filename, &signature.hash)?;
+        }
+        Ok(set)
+    }
+
+    /// Loads a set from the signature file at the given path (e.g. one
+    /// written by `ssdeep`), with the known hashes labeled by their
+    /// filenames.
+    ///
+    /// When the file cannot be read or parsed, it returns an error.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        MatchSet::from_signature_file(&SignatureFile::load(path)?)
+    }
+
+    /// Returns the number of known hashes in the set.
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Checks whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Adds a known hash with the given label into the set.
+    ///
+    /// When the hash is invalid, it returns an error.
+    pub fn insert(&mut self, label: &str, hash: &str) -> Result<()> {
+        self.batch.push(hash)?;
+        self.labels.push(label.to_string());
+        self.hashes.push(hash.to_string());
+        Ok(())
+    }
+
+    /// Matches the given hash against the known hashes.
+    ///
+    /// When the hash is invalid, it returns an error.
+    pub fn match_hash(&self, hash: &str) -> Result<Vec<KnownMatch>> {
+        let scores = self.batch.scores(hash)?;
+        Ok(scores
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, score)| score > self.threshold)
+            .map(|(i, score)| KnownMatch {
+                label: self.labels[i].clone(),
+                hash: self.hashes[i].clone(),
+                score,
+            })
+            .collect())
+    }
+
+    /// Matches the fuzzy hash of the given bytes against the known hashes.
+    ///
+    /// When the bytes cannot be hashed, it returns an error.
+    pub fn match_buffer(&self, buf: &[u8]) -> Result<Vec<KnownMatch>> {
+        self.match_hash(&hash(buf)?)
+    }
+
+    /// Matches the fuzzy hash of the contents of the given file against the
+    /// known hashes.
+    ///
+    /// When the file cannot be hashed, it returns an error.
+    pub fn match_file<P: AsRef<Path>>(&self, file_path: P) -> Result<Vec<KnownMatch>> {
+        self.match_hash(&hash_from_file(file_path)?)
+    }
+}
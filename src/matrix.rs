@@ -0,0 +1,839 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Computation of match scores between all pairs of many fuzzy hashes (e.g.
+//! for clustering of samples) or between two sets of fuzzy hashes.
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+#[cfg(feature = "parallel")]
+use std::sync::Mutex;
+use std::vec;
+
+use super::score::eliminate_sequences;
+use super::score::BatchScorer;
+use super::score::ParsedHash;
+use super::Phase;
+use super::Progress;
+use super::Result;
+
+/// A pair of similar hashes found by
+/// [`similarity_matrix()`](fn.similarity_matrix.html) or
+/// [`pairs_above()`](fn.pairs_above.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimilarPair {
+    /// Index of the first hash of the pair.
+    pub i: usize,
+    /// Index of the second hash of the pair (always greater than `i`).
+    pub j: usize,
+    /// The score between the two hashes.
+    pub score: u8,
+}
+
+/// Computes the match scores between all pairs of the given hashes in
+/// parallel and returns the pairs whose score is strictly greater than the
+/// threshold.
+///
+/// Only the upper triangle of the matrix is computed and returned, i.e. every
+/// unordered pair is returned once, with `i < j`. The pairs are sorted by `i`
+/// and then by `j`. The scores are the same as the scores computed by
+/// [`compare()`](../fn.compare.html). Every hash is parsed only once and the
+/// rows of the matrix are scored on the threads of the global rayon pool.
+///
+/// When a hash is invalid, it returns
+/// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash).
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::matrix::similarity_matrix;
+///
+/// let pairs = similarity_matrix(
+///     &[
+///         "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+///         "3:u+N:u+N",
+///         "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx",
+///     ],
+///     0,
+/// )
+/// .unwrap();
+/// assert_eq!(pairs.len(), 1);
+/// assert_eq!((pairs[0].i, pairs[0].j, pairs[0].score), (0, 2, 22));
+/// ```
+#[cfg(feature = "parallel")]
+pub fn similarity_matrix<H: AsRef<str> + Sync>(
+    hashes: &[H],
+    threshold: u8,
+) -> Result<Vec<SimilarPair>> {
+    similarity_matrix_with_progress(hashes, threshold, |_| {})
+}
+
+/// Computes the match scores between all pairs of the given hashes in
+/// parallel, reporting the progress (in the
+/// [`Comparing`](../enum.Phase.html) phase) to the given callback.
+///
+/// The callback is called after each row of the matrix is scored, with the
+/// number of comparisons made so far. The callback is never called by more
+/// than one thread at a time.
+///
+/// See [`similarity_matrix()`](fn.similarity_matrix.html) for more details.
+#[cfg(feature = "parallel")]
+pub fn similarity_matrix_with_progress<H, F>(
+    hashes: &[H],
+    threshold: u8,
+    progress: F,
+) -> Result<Vec<SimilarPair>>
+where
+    H: AsRef<str> + Sync,
+    F: FnMut(&Progress) + Send,
+{
+    let parsed = hashes
+        .par_iter()
+        .map(|hash| ParsedHash::parse(hash.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+    let n = parsed.len() as u64;
+    let total = n * n.saturating_sub(1) / 2;
+    // The number of comparisons is kept together with the callback so that
+    // the reports are made in the order of their counts.
+    let progress = Mutex::new((0, progress));
+    Ok((0..parsed.len())
+        .into_par_iter()
+        .flat_map_iter(|i| {
+            let pairs = row_pairs(&parsed, i, threshold);
+            let mut progress = progress.lock().unwrap_or_else(|e| e.into_inner());
+            let (ref mut done, ref mut callback) = *progress;
+            *done += (parsed.len() - i - 1) as u64;
+            callback(&Progress {
+                phase: Phase::Comparing,
+                done: *done,
+                total: Some(total),
+                current_path: None,
+            });
+            pairs
+        })
+        .collect())
+}
+
+/// Returns an iterator over the pairs of the given hashes whose score is
+/// strictly greater than the threshold, which computes the scores lazily.
+///
+/// The pairs are the same and in the same order as the pairs returned by
+/// [`similarity_matrix()`](fn.similarity_matrix.html), but instead of
+/// computing all of them at once, the iterator scores one row of the matrix
+/// at a time, when the pairs of the previous row are consumed. Apart from the
+/// parsed hashes, it thus keeps only the pairs of a single row in memory, so
+/// the pairs can be streamed (e.g. to disk) with bounded memory. Every hash is
+/// parsed only once, when the iterator is created.
+///
+/// When a hash is invalid, it returns
+/// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash).
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::matrix::pairs_above;
+///
+/// let mut pairs = pairs_above(
+///     &[
+///         "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+///         "3:u+N:u+N",
+///         "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx",
+///     ],
+///     0,
+/// )
+/// .unwrap();
+/// let pair = pairs.next().unwrap();
+/// assert_eq!((pair.i, pair.j, pair.score), (0, 2, 22));
+/// assert_eq!(pairs.next(), None);
+/// ```
+pub fn pairs_above<H: AsRef<str>>(hashes: &[H], threshold: u8) -> Result<PairsAbove> {
+    pairs_above_with_progress(hashes, threshold, no_progress as fn(&Progress))
+}
+
+/// Returns an iterator over the pairs of the given hashes whose score is
+/// strictly greater than the threshold, reporting the progress (in the
+/// [`Comparing`](../enum.Phase.html) phase) to the given callback.
+///
+/// The callback is called after each row of the matrix is scored, with the
+/// number of comparisons made so far. As the rows are scored lazily, it is
+/// called while the pairs are being consumed.
+///
+/// See [`pairs_above()`](fn.pairs_above.html) for more details.
+pub fn pairs_above_with_progress<H, F>(
+    hashes: &[H],
+    threshold: u8,
+    progress: F,
+) -> Result<PairsAbove<F>>
+where
+    H: AsRef<str>,
+    F: FnMut(&Progress),
+{
+    let parsed = hashes
+        .iter()
+        .map(|hash| ParsedHash::parse(hash.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(PairsAbove {
+        parsed,
+        threshold,
+        next_row: 0,
+        row: Vec::new().into_iter(),
+        comparisons: 0,
+        progress,
+    })
+}
+
+/// An iterator over pairs of similar hashes, created by
+/// [`pairs_above()`](fn.pairs_above.html) or
+/// [`pairs_above_with_progress()`](fn.pairs_above_with_progress.html).
+#[derive(Debug, Clone)]
+pub struct PairsAbove<F = fn(&Progress)> {
+    parsed: Vec<ParsedHash>,
+    threshold: u8,
+    next_row: usize,
+    row: vec::IntoIter<SimilarPair>,
+    comparisons: u64,
+    progress: F,
+}
+
+impl<F: FnMut(&Progress)> Iterator for PairsAbove<F> {
+    type Item = SimilarPair;
+
+    fn next(&mut self) -> Option<SimilarPair> {
+        loop {
+            if let Some(pair) = self.row.next() {
+                return Some(pair);
+            }
+            if self.next_row >= self.parsed.len() {
+                return None;
+            }
+            self.row = row_pairs(&self.parsed, self.next_row, self.threshold).into_iter();
+            self.next_row += 1;
+
+            let n = self.parsed.len() as u64;
+            self.comparisons += n - self.next_row as u64;
+            (self.progress)(&Progress {
+                phase: Phase::Comparing,
+                done: self.comparisons,
+                total: Some(n * (n - 1) / 2),
+                current_path: None,
+            });
+        }
+    }
+}
+
+/// Statistics of the comparisons made when scoring pairs of hashes.
+///
+/// Every pair of positions in the input counts once in `pairs`, so
+/// `pairs == comparisons + self_pairs + cached_pairs`. The difference between
+/// `pairs` and `comparisons` is the work saved over comparing every pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ComparisonStats {
+    /// The number of ordered pairs of hashes that comparing every pair would
+    /// compare.
+    pub pairs: u64,
+    /// The number of pairs that were actually compared.
+    pub comparisons: u64,
+    /// The number of pairs of a hash with itself or with an identical hash,
+    /// which get a score of 100 without being compared.
+    pub self_pairs: u64,
+    /// The number of pairs whose score was reused from another pair that was
+    /// compared (e.g. from the symmetric pair).
+    pub cached_pairs: u64,
+}
+
+impl ComparisonStats {
+    /// Returns the statistics of scoring all pairs of `n` hashes by
+    /// [`similarity_matrix()`](fn.similarity_matrix.html) or
+    /// [`pairs_above()`](fn.pairs_above.html).
+    ///
+    /// Only the upper triangle of the matrix is compared: the pairs of every
+    /// hash with itself are skipped and the scores of the pairs below the
+    /// diagonal are the scores of their symmetric pairs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ssdeep::matrix::ComparisonStats;
+    ///
+    /// let stats = ComparisonStats::all_pairs(4);
+    /// assert_eq!(stats.pairs, 16);
+    /// assert_eq!(stats.comparisons, 6);
+    /// assert_eq!(stats.saved(), 10);
+    /// ```
+    pub fn all_pairs(n: u64) -> ComparisonStats {
+        let comparisons = n * n.saturating_sub(1) / 2;
+        ComparisonStats {
+            pairs: n * n,
+            comparisons,
+            self_pairs: n,
+            cached_pairs: comparisons,
+        }
+    }
+
+    /// Returns the number of pairs that were not compared.
+    pub fn saved(&self) -> u64 {
+        self.pairs - self.comparisons
+    }
+}
+
+/// A pair of similar hashes found by [`join_above()`](fn.join_above.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JoinPair {
+    /// Index of the hash in the left hashes.
+    pub left: usize,
+    /// Index of the hash in the right hashes.
+    pub right: usize,
+    /// The score between the two hashes.
+    pub score: u8,
+}
+
+/// The result of [`join_above()`](fn.join_above.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Join {
+    /// The pairs of similar hashes, sorted by `left` and then by `right`.
+    pub pairs: Vec<JoinPair>,
+    /// The statistics of the comparisons made to find the pairs.
+    pub stats: ComparisonStats,
+}
+
+/// Computes the match scores between every hash on the left and every hash on
+/// the right and returns the pairs whose score is strictly greater than the
+/// threshold.
+///
+/// Every distinct hash is parsed and compared only once, so overlapping
+/// inputs do not cost more comparisons than necessary. Pairs of identical
+/// hashes (ignoring e.g. the names of files after the hashes) get a score of
+/// 100 without being compared, and when both hashes of a pair are on both
+/// sides, the pair is compared only once and its score is reused for the
+/// symmetric pair. The savings are reported in the
+/// [statistics](struct.ComparisonStats.html) of the returned join. The scores
+/// are the same as the scores computed by [`compare()`](../fn.compare.html).
+///
+/// When a hash is invalid, it returns
+/// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash).
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::matrix::join_above;
+///
+/// let join = join_above(
+///     &[
+///         "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+///         "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx",
+///     ],
+///     &[
+///         "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx",
+///         "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+///     ],
+///     0,
+/// )
+/// .unwrap();
+/// let pairs: Vec<_> = join.pairs.iter().map(|p| (p.left, p.right, p.score)).collect();
+/// assert_eq!(pairs, [(0, 0, 22), (0, 1, 100), (1, 0, 100), (1, 1, 22)]);
+/// assert_eq!(join.stats.comparisons, 1);
+/// assert_eq!(join.stats.self_pairs, 2);
+/// assert_eq!(join.stats.cached_pairs, 1);
+/// ```
+pub fn join_above<L, R>(left: &[L], right: &[R], threshold: u8) -> Result<Join>
+where
+    L: AsRef<str>,
+    R: AsRef<str>,
+{
+    let mut distinct = Vec::new();
+    let mut ids = HashMap::new();
+    let mut intern = |hash: &str| -> Result<usize> {
+        let parsed = ParsedHash::parse(hash)?;
+        Ok(*ids.entry(parsed).or_insert_with(|| {
+            distinct.push(parsed);
+            distinct.len() - 1
+        }))
+    };
+    let left_ids = left
+        .iter()
+        .map(|hash| intern(hash.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+    let right_ids = right
+        .iter()
+        .map(|hash| intern(hash.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+    let left_positions = positions_of_ids(&left_ids, distinct.len());
+    let right_positions = positions_of_ids(&right_ids, distinct.len());
+    let right_distinct: Vec<_> = (0..distinct.len())
+        .filter(|&id| !right_positions[id].is_empty())
+        .collect();
+
+    let mut stats = ComparisonStats {
+        pairs: left.len() as u64 * right.len() as u64,
+        ..ComparisonStats::default()
+    };
+    // The scores of the pairs whose hashes are on both sides, by the pair of
+    // their ids in canonical order (the smaller id first), so that the
+    // symmetric pair reuses them.
+    let mut mirrored = HashMap::new();
+    let mut pairs = Vec::new();
+    for x in (0..distinct.len()).filter(|&id| !left_positions[id].is_empty()) {
+        let mut scores = vec![0; right_distinct.len()];
+        let mut scored = Vec::new();
+        let mut scorer = BatchScorer::new(&distinct[x]);
+        for (k, &y) in right_distinct.iter().enumerate() {
+            let key = (x.min(y), x.max(y));
+            if x == y {
+                scores[k] = 100;
+                stats.self_pairs += (left_positions[x].len() * right_positions[x].len()) as u64;
+            } else if let Some(&score) = mirrored.get(&key) {
+                scores[k] = score;
+            } else {
+                let hash = &distinct[y];
+                let parts = [&hash.parts[0][..], &hash.parts[1][..]];
+                scorer.push(k, hash.block_size, parts, &mut scores);
+                stats.comparisons += 1;
+                if !left_positions[y].is_empty() && !right_positions[x].is_empty() {
+                    scored.push((k, key));
+                }
+            }
+        }
+        scorer.finish(&mut scores);
+        for (k, key) in scored {
+            mirrored.insert(key, scores[k]);
+        }
+
+        for (k, &y) in right_distinct.iter().enumerate() {
+            if scores[k] <= threshold {
+                continue;
+            }
+            for &i in &left_positions[x] {
+                for &j in &right_positions[y] {
+                    pairs.push(JoinPair {
+                        left: i,
+                        right: j,
+                        score: scores[k],
+                    });
+                }
+            }
+        }
+    }
+    stats.cached_pairs = stats.pairs - stats.comparisons - stats.self_pairs;
+    pairs.sort_by_key(|pair| (pair.left, pair.right));
+    Ok(Join { pairs, stats })
+}
+
+/// Returns the positions of every id (of `count` ids) in the given ids.
+fn positions_of_ids(ids: &[usize], count: usize) -> Vec<Vec<usize>> {
+    let mut positions = vec![Vec::new(); count];
+    for (position, &id) in ids.iter().enumerate() {
+        positions[id].push(position);
+    }
+    positions
+}
+
+/// Options for
+/// [`pairs_above_out_of_core()`](fn.pairs_above_out_of_core.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutOfCoreOptions {
+    /// Directory in which the temporary files are stored (in a new
+    /// subdirectory, which is removed afterwards).
+    ///
+    /// Default: [`std::env::temp_dir()`].
+    pub temp_dir: PathBuf,
+    /// The maximal number of hashes in a tile. At most two tiles of parsed
+    /// hashes are kept in memory at a time.
+    ///
+    /// Default: 65536.
+    pub tile_size: usize,
+}
+
+impl Default for OutOfCoreOptions {
+    fn default() -> Self {
+        OutOfCoreOptions {
+            temp_dir: std::env::temp_dir(),
+            tile_size: 65536,
+        }
+    }
+}
+
+/// Passes the pairs of the given hashes whose score is strictly greater than
+/// the threshold to `on_pair`, keeping only a bounded number of hashes in
+/// memory.
+///
+/// This is meant for corpora that are too large for
+/// [`pairs_above()`](fn.pairs_above.html) (which keeps all the parsed hashes
+/// in memory), e.g. tens of millions of hashes. The hashes are parsed one by
+/// one and spilled into temporary files, one file per block size. As only
+/// hashes with equal block sizes or with block sizes differing by a factor of
+/// two can have a non-zero score, only the buckets of such block sizes are
+/// then compared, tile by tile, so at most two tiles of
+/// [`OutOfCoreOptions::tile_size`](struct.OutOfCoreOptions.html#structfield.tile_size)
+/// hashes are in memory at a time. The temporary files are removed
+/// afterwards (also on errors).
+///
+/// The indexes of the pairs are the positions of the hashes in `hashes`, with
+/// `i < j`, and the scores are the same as the scores computed by
+/// [`compare()`](../fn.compare.html). However, the pairs are not passed in
+/// any particular order. When `on_pair` returns an error, the comparison stops
+/// and the error is returned.
+///
+/// When a hash is invalid, it returns
+/// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash). When the
+/// temporary files cannot be written or read, it returns
+/// [`Error::Io`](../enum.Error.html#variant.Io).
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::matrix::{pairs_above_out_of_core, OutOfCoreOptions};
+///
+/// let hashes = [
+///     "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+///     "3:u+N:u+N",
+///     "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx",
+/// ];
+/// let mut pairs = Vec::new();
+/// pairs_above_out_of_core(&hashes, 0, &OutOfCoreOptions::default(), |pair| {
+///     pairs.push((pair.i, pair.j, pair.score));
+///     Ok(())
+/// })
+/// .unwrap();
+/// assert_eq!(pairs, vec![(0, 2, 22)]);
+/// ```
+pub fn pairs_above_out_of_core<I, H, F>(
+    hashes: I,
+    threshold: u8,
+    options: &OutOfCoreOptions,
+    on_pair: F,
+) -> Result<()>
+where
+    I: IntoIterator<Item = H>,
+    H: AsRef<str>,
+    F: FnMut(SimilarPair) -> Result<()>,
+{
+    pairs_above_out_of_core_with_progress(hashes, threshold, options, on_pair, |_| {})
+}
+
+/// Passes the pairs of the given hashes whose score is strictly greater than
+/// the threshold to `on_pair`, keeping only a bounded number of hashes in
+/// memory and reporting the progress (in the
+/// [`Comparing`](../enum.Phase.html) phase) to the given callback.
+///
+/// The callback is called after each pair of tiles is compared, with the
+/// number of comparisons made so far. The total number of comparisons is the
+/// number of pairs of hashes in the compared buckets, which is known once all
+/// the hashes are spilled.
+///
+/// See [`pairs_above_out_of_core()`](fn.pairs_above_out_of_core.html) for
+/// more details.
+pub fn pairs_above_out_of_core_with_progress<I, H, F, P>(
+    hashes: I,
+    threshold: u8,
+    options: &OutOfCoreOptions,
+    mut on_pair: F,
+    progress: P,
+) -> Result<()>
+where
+    I: IntoIterator<Item = H>,
+    H: AsRef<str>,
+    F: FnMut(SimilarPair) -> Result<()>,
+    P: FnMut(&Progress),
+{
+    let dir = TempDir::create(&options.temp_dir)?;
+    let mut buckets = BTreeMap::new();
+    let mut bucket_lens: BTreeMap<u64, u64> = BTreeMap::new();
+    for (i, hash) in hashes.into_iter().enumerate() {
+        let hash = ParsedHash::parse(hash.as_ref())?;
+        let writer = match buckets.entry(hash.block_size) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let file = File::create(dir.bucket_path(hash.block_size))?;
+                entry.insert(BufWriter::new(file))
+            }
+        };
+        write_spilled_hash(writer, i, &hash)?;
+        *bucket_lens.entry(hash.block_size).or_insert(0) += 1;
+    }
+    let block_sizes: Vec<u64> = buckets.keys().cloned().collect();
+    for (_, writer) in buckets {
+        writer.into_inner().map_err(|e| e.into_error())?;
+    }
+
+    let total = bucket_lens
+        .iter()
+        .map(|(&block_size, &len)| {
+            let double_len = block_size
+                .checked_mul(2)
+                .and_then(|double_block_size| bucket_lens.get(&double_block_size))
+                .cloned()
+                .unwrap_or(0);
+            len * (len - 1) / 2 + len * double_len
+        })
+        .sum();
+    let mut buckets = BucketComparison {
+        tile_size: options.tile_size.max(1),
+        threshold,
+        scores: Vec::new(),
+        comparisons: 0,
+        total,
+        progress,
+    };
+    for &block_size in &block_sizes {
+        let path = dir.bucket_path(block_size);
+        buckets.compare(&path, None, block_size, &mut on_pair)?;
+        let double_block_size = match block_size.checked_mul(2) {
+            Some(double_block_size) => double_block_size,
+            None => continue,
+        };
+        if block_sizes.binary_search(&double_block_size).is_ok() {
+            let other_path = dir.bucket_path(double_block_size);
+            buckets.compare(&path, Some(&other_path), block_size, &mut on_pair)?;
+        }
+    }
+    Ok(())
+}
+
+/// Unique names of temporary directories within the current process.
+static TEMP_DIR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary directory with spilled buckets, which is removed when dropped.
+struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    fn create(parent: &Path) -> Result<TempDir> {
+        loop {
+            let path = parent.join(format!(
+                "ssdeep-rs-matrix-{}-{}",
+                process::id(),
+                TEMP_DIR_COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(TempDir { path }),
+                Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn bucket_path(&self, block_size: u64) -> PathBuf {
+        self.path.join(format!("{}.bin", block_size))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Writes a hash with the given index into a bucket file (the block size is
+/// given by the bucket).
+fn write_spilled_hash<W: Write>(writer: &mut W, index: usize, hash: &ParsedHash) -> Result<()> {
+    writer.write_all(&(index as u64).to_le_bytes())?;
+    for part in &hash.parts {
+        writer.write_all(&[part.len() as u8])?;
+        writer.write_all(part)?;
+    }
+    Ok(())
+}
+
+/// Reads the next hash from a bucket file (`None` at the end of the file).
+fn read_spilled_hash<R: BufRead>(
+    reader: &mut R,
+    block_size: u64,
+) -> Result<Option<(usize, ParsedHash)>> {
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let mut index = [0; 8];
+    reader.read_exact(&mut index)?;
+    let mut parts = Vec::with_capacity(2);
+    for _ in 0..2 {
+        let mut len = [0; 1];
+        reader.read_exact(&mut len)?;
+        let mut part = vec![0; len[0] as usize];
+        reader.read_exact(&mut part)?;
+        parts.push(eliminate_sequences(&part)?);
+    }
+    let hash = ParsedHash {
+        block_size,
+        parts: [parts[0], parts[1]],
+    };
+    Ok(Some((u64::from_le_bytes(index) as usize, hash)))
+}
+
+/// Reads the next tile of hashes from a bucket file into `tile` (which is
+/// empty at the end of the file).
+fn read_tile<R: BufRead>(
+    reader: &mut R,
+    block_size: u64,
+    tile_size: usize,
+    tile: &mut Vec<(usize, ParsedHash)>,
+) -> Result<()> {
+    tile.clear();
+    while tile.len() < tile_size {
+        match read_spilled_hash(reader, block_size)? {
+            Some(hash) => tile.push(hash),
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+/// Comparison of spilled buckets tile by tile.
+struct BucketComparison<P> {
+    tile_size: usize,
+    threshold: u8,
+    scores: Vec<u8>,
+    comparisons: u64,
+    total: u64,
+    progress: P,
+}
+
+impl<P: FnMut(&Progress)> BucketComparison<P> {
+    /// Compares the hashes in the bucket with the given block size against
+    /// the hashes in the bucket with double the block size, or against each
+    /// other when `other_path` is `None`.
+    fn compare<F>(
+        &mut self,
+        path: &Path,
+        other_path: Option<&Path>,
+        block_size: u64,
+        on_pair: &mut F,
+    ) -> Result<()>
+    where
+        F: FnMut(SimilarPair) -> Result<()>,
+    {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut tile = Vec::new();
+        let mut other_tile = Vec::new();
+        loop {
+            read_tile(&mut reader, block_size, self.tile_size, &mut tile)?;
+            if tile.is_empty() {
+                return Ok(());
+            }
+            let (mut other_reader, other_block_size) = match other_path {
+                Some(other_path) => (BufReader::new(File::open(other_path)?), block_size * 2),
+                None => {
+                    self.compare_tiles(&tile, &tile, true, on_pair)?;
+                    // Only the following tiles of the bucket remain.
+                    let mut other_reader = BufReader::new(File::open(path)?);
+                    other_reader.seek(SeekFrom::Start(reader.stream_position()?))?;
+                    (other_reader, block_size)
+                }
+            };
+            loop {
+                read_tile(
+                    &mut other_reader,
+                    other_block_size,
+                    self.tile_size,
+                    &mut other_tile,
+                )?;
+                if other_tile.is_empty() {
+                    break;
+                }
+                self.compare_tiles(&tile, &other_tile, false, on_pair)?;
+            }
+        }
+    }
+
+    /// Compares the hashes of two tiles (only the hashes after each hash when
+    /// the tiles are the same).
+    fn compare_tiles<F>(
+        &mut self,
+        tile: &[(usize, ParsedHash)],
+        other_tile: &[(usize, ParsedHash)],
+        same: bool,
+        on_pair: &mut F,
+    ) -> Result<()>
+    where
+        F: FnMut(SimilarPair) -> Result<()>,
+    {
+        for (k, (i, query)) in tile.iter().enumerate() {
+            let others = if same {
+                &other_tile[k + 1..]
+            } else {
+                other_tile
+            };
+            self.scores.clear();
+            self.scores.resize(others.len(), 0);
+            let mut scorer = BatchScorer::new(query);
+            for (l, (_, hash)) in others.iter().enumerate() {
+                let parts = [&hash.parts[0][..], &hash.parts[1][..]];
+                scorer.push(l, hash.block_size, parts, &mut self.scores);
+            }
+            scorer.finish(&mut self.scores);
+            for ((j, _), &score) in others.iter().zip(&self.scores) {
+                if score > self.threshold {
+                    on_pair(SimilarPair {
+                        i: *i.min(j),
+                        j: *i.max(j),
+                        score,
+                    })?;
+                }
+            }
+            self.comparisons += others.len() as u64;
+        }
+        (self.progress)(&Progress {
+            phase: Phase::Comparing,
+            done: self.comparisons,
+            total: Some(self.total),
+            current_path: None,
+        });
+        Ok(())
+    }
+}
+
+fn no_progress(_: &Progress) {}
+
+/// Returns the pairs of the `i`-th hash with the hashes after it whose score
+/// is strictly greater than the threshold.
+fn row_pairs(parsed: &[ParsedHash], i: usize, threshold: u8) -> Vec<SimilarPair> {
+    let rest = &parsed[i + 1..];
+    let mut scores = vec![0; rest.len()];
+    let mut scorer = BatchScorer::new(&parsed[i]);
+    for (k, hash) in rest.iter().enumerate() {
+        let parts = [&hash.parts[0][..], &hash.parts[1][..]];
+        scorer.push(k, hash.block_size, parts, &mut scores);
+    }
+    scorer.finish(&mut scores);
+    scores
+        .into_iter()
+        .enumerate()
+        .filter(|&(_, score)| score > threshold)
+        .map(|(k, score)| SimilarPair {
+            i,
+            j: i + 1 + k,
+            score,
+        })
+        .collect()
+}
@@ -0,0 +1,256 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Ingestion of ssdeep attributes from [MISP](https://www.misp-project.org/).
+//!
+//! This module is available only when the `misp` feature is enabled. It
+//! allows pulling ssdeep attributes from a MISP instance together with their
+//! context (e.g. UUIDs of the attributes and their events), so that matches
+//! can be attributed back to MISP events (see
+//! [`index_attributes()`](fn.index_attributes.html)). The blocking client of the
+//! [reqwest](https://crates.io/crates/reqwest) crate is used without its
+//! default features, so if you need e.g. TLS support, enable the
+//! corresponding features of `reqwest` in your own `Cargo.toml`.
+
+use std::collections::HashMap;
+
+use reqwest::blocking::Client;
+use serde_json::Value;
+
+use super::index::FuzzyHashIndex;
+use super::Error;
+use super::Result;
+
+/// An ssdeep attribute from MISP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MispAttribute {
+    /// UUID of the attribute.
+    pub uuid: String,
+    /// UUID of the event to which the attribute belongs (if known).
+    pub event_uuid: Option<String>,
+    /// Description of the event to which the attribute belongs (if known).
+    pub event_info: Option<String>,
+    /// Category of the attribute (e.g. `Payload delivery`).
+    pub category: String,
+    /// Comment of the attribute.
+    pub comment: String,
+    /// Is the attribute marked to be used for intrusion detection?
+    pub to_ids: bool,
+    /// The fuzzy hash.
+    pub hash: String,
+}
+
+/// A client for pulling ssdeep attributes from a MISP instance.
+#[derive(Debug, Clone)]
+pub struct MispClient {
+    base_url: String,
+    api_key: String,
+    client: Client,
+}
+
+impl MispClient {
+    /// Creates a client for the MISP instance at the given URL (e.g.
+    /// `https://misp.example.com`), authenticating with the given API key.
+    pub fn new(base_url: &str, api_key: &str) -> Self {
+        MispClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            client: Client::new(),
+        }
+    }
+
+    /// Pulls all ssdeep attributes from the MISP instance.
+    ///
+    /// Internally, it uses the `/attributes/restSearch` endpoint. When the
+    /// request fails or the response cannot be parsed, it returns
+    /// [`Error::HttpRequestFailed`](../enum.Error.html#variant.HttpRequestFailed).
+    pub fn fetch_ssdeep_attributes(&self) -> Result<Vec<MispAttribute>> {
+        let body = json!({
+            "returnFormat": "json",
+            "type": "ssdeep",
+            "includeEventUuid": true,
+        });
+        let response = self
+            .client
+            .post(format!("{}/attributes/restSearch", self.base_url))
+            .header("Authorization", self.api_key.as_str())
+            .header("Accept", "application/json")
+            .header("Content-Type", "application/json")
+            .body(body.to_string())
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.bytes())
+            .map_err(request_failed)?;
+        parse_attributes(&response)
+    }
+
+    /// Pulls all ssdeep attributes from the MISP instance and adds their
+    /// hashes into the given index.
+    ///
+    /// Returns the attributes by the identifiers of their hashes in the index,
+    /// so matches can be attributed back to MISP attributes and events. See
+    /// [`fetch_ssdeep_attributes()`](#method.fetch_ssdeep_attributes) and
+    /// [`index_attributes()`](fn.index_attributes.html) for more details.
+    pub fn load_into(&self, index: &mut FuzzyHashIndex) -> Result<HashMap<usize, MispAttribute>> {
+        index_attributes(self.fetch_ssdeep_attributes()?, index)
+    }
+}
+
+/// Adds hashes of the given attributes into the index and returns the
+/// attributes by the identifiers of their hashes.
+///
+/// When a hash is invalid, it returns
+/// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash). The hashes
+/// of the preceding attributes stay in the index.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::index::FuzzyHashIndex;
+///
+/// let event = br#"{"Event": {"uuid": "e1", "Attribute": [
+///     {"uuid": "a1", "type": "ssdeep", "value": "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C"}
+/// ]}}"#;
+/// let attributes = ssdeep::misp::parse_attributes(event).unwrap();
+/// let mut index = FuzzyHashIndex::new();
+/// let by_id = ssdeep::misp::index_attributes(attributes, &mut index).unwrap();
+///
+/// let matches = index.search("3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx", 0).unwrap();
+/// assert_eq!(by_id[&matches[0].id].uuid, "a1");
+/// ```
+pub fn index_attributes(
+    attributes: Vec<MispAttribute>,
+    index: &mut FuzzyHashIndex,
+) -> Result<HashMap<usize, MispAttribute>> {
+    let mut by_id = HashMap::with_capacity(attributes.len());
+    for attribute in attributes {
+        let id = index.insert(&attribute.hash)?;
+        by_id.insert(id, attribute);
+    }
+    Ok(by_id)
+}
+
+/// Parses ssdeep attributes from a MISP JSON document.
+///
+/// Both responses of the `/attributes/restSearch` endpoint and event
+/// documents (e.g. events from MISP feeds or the `/events/view` endpoint)
+/// are supported. Attributes of other types than `ssdeep` are skipped.
+///
+/// # Examples
+///
+/// ```
+/// let event = br#"{"Event": {
+///     "uuid": "5e5f4f3e-0000-4000-8000-000000000000",
+///     "info": "Example event",
+///     "Attribute": [{
+///         "uuid": "5e5f4f3e-0000-4000-8000-000000000001",
+///         "type": "ssdeep",
+///         "category": "Payload delivery",
+///         "comment": "",
+///         "to_ids": true,
+///         "value": "3:aNRn:aNRn"
+///     }]
+/// }}"#;
+/// let attributes = ssdeep::misp::parse_attributes(event).unwrap();
+/// assert_eq!(attributes[0].hash, "3:aNRn:aNRn");
+/// assert_eq!(attributes[0].event_info, Some("Example event".to_string()));
+/// ```
+pub fn parse_attributes(json: &[u8]) -> Result<Vec<MispAttribute>> {
+    let document: Value = serde_json::from_slice(json).map_err(request_failed)?;
+    let mut attributes = Vec::new();
+    if let Some(event) = document.get("Event") {
+        collect_event_attributes(event, &mut attributes);
+    } else if let Some(response) = document.get("response") {
+        match response.get("Attribute") {
+            Some(Value::Array(items)) => {
+                for item in items {
+                    collect_attribute(item, item.get("Event"), &mut attributes);
+                }
+            }
+            _ => {
+                // A response of /events/restSearch contains a list of
+                // events.
+                if let Value::Array(events) = response {
+                    for event in events {
+                        collect_event_attributes(&event["Event"], &mut attributes);
+                    }
+                }
+            }
+        }
+    } else {
+        return Err(request_failed("unknown MISP document"));
+    }
+    Ok(attributes)
+}
+
+fn collect_event_attributes(event: &Value, attributes: &mut Vec<MispAttribute>) {
+    let mut items = Vec::new();
+    if let Some(Value::Array(event_attributes)) = event.get("Attribute") {
+        items.extend(event_attributes);
+    }
+    if let Some(Value::Array(objects)) = event.get("Object") {
+        for object in objects {
+            if let Some(Value::Array(object_attributes)) = object.get("Attribute") {
+                items.extend(object_attributes);
+            }
+        }
+    }
+    for item in items {
+        collect_attribute(item, Some(event), attributes);
+    }
+}
+
+fn collect_attribute(item: &Value, event: Option<&Value>, attributes: &mut Vec<MispAttribute>) {
+    if item["type"] != "ssdeep" {
+        return;
+    }
+    let hash = match item["value"].as_str() {
+        Some(hash) => hash.to_string(),
+        None => return,
+    };
+    let event_str = |key| {
+        event
+            .and_then(|e| e.get(key))
+            .and_then(Value::as_str)
+            .map(|s| s.to_string())
+    };
+    attributes.push(MispAttribute {
+        uuid: str_value(&item["uuid"]),
+        event_uuid: event_str("uuid"),
+        event_info: event_str("info"),
+        category: str_value(&item["category"]),
+        comment: str_value(&item["comment"]),
+        // Older versions of MISP represent booleans as strings or integers.
+        to_ids: match item["to_ids"] {
+            Value::Bool(b) => b,
+            Value::String(ref s) => s == "1" || s == "true",
+            Value::Number(ref n) => n.as_u64() == Some(1),
+            _ => false,
+        },
+        hash,
+    });
+}
+
+fn str_value(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+fn request_failed<E: ToString>(e: E) -> Error {
+    Error::HttpRequestFailed {
+        reason: e.to_string(),
+    }
+}
@@ -0,0 +1,186 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Computation of fuzzy hashes alongside cryptographic digests in a single
+//! pass over the data.
+
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+
+use digest::DynDigest;
+
+use super::FuzzyHasher;
+use super::Result;
+
+/// A hasher that feeds the same data into a fuzzy hasher and into any number
+/// of cryptographic digests (from the [RustCrypto](https://github.com/RustCrypto/hashes)
+/// crates, e.g. `sha2::Sha256` or `md5::Md5`).
+///
+/// Ingestion pipelines usually need both the fuzzy hash of a file and its
+/// cryptographic digests. The hasher computes all of them while reading the
+/// file once. It is available only when the `digest` feature is enabled.
+///
+/// # Examples
+///
+/// ```
+/// extern crate sha2;
+/// extern crate ssdeep;
+///
+/// use ssdeep::MultiHasher;
+///
+/// let mut hasher = MultiHasher::new().unwrap();
+/// hasher.add_digest("sha256", sha2::Sha256::default());
+/// hasher.update(b"Hello there!").unwrap();
+/// let digests = hasher.finish().unwrap();
+/// assert_eq!(digests.fuzzy_hash, "3:aNRn:aNRn");
+/// assert_eq!(
+///     digests.hex_digest("sha256").unwrap(),
+///     "89b8b8e486421463d7e0f5caf60fb9cb35ce169b76e657ab21fc4d1d6b093603"
+/// );
+/// ```
+pub struct MultiHasher {
+    fuzzy_hasher: FuzzyHasher,
+    digests: Vec<(String, Box<dyn DynDigest + Send>)>,
+}
+
+/// The fuzzy hash and cryptographic digests computed by
+/// [`MultiHasher`](struct.MultiHasher.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiDigest {
+    /// The fuzzy hash of the data.
+    pub fuzzy_hash: String,
+    /// Names and values of the digests of the data, in the order in which the
+    /// digests were added.
+    pub digests: Vec<(String, Vec<u8>)>,
+}
+
+impl MultiHasher {
+    /// Creates a hasher without any cryptographic digests.
+    pub fn new() -> Result<Self> {
+        Ok(MultiHasher {
+            fuzzy_hasher: FuzzyHasher::new()?,
+            digests: Vec::new(),
+        })
+    }
+
+    /// Adds a cryptographic digest under the given name.
+    ///
+    /// The digest is fed only the data fed into the hasher after it has been
+    /// added, so all digests should be added before the data.
+    pub fn add_digest<D: DynDigest + Send + 'static>(&mut self, name: &str, digest: D) {
+        self.digests.push((name.to_string(), Box::new(digest)));
+    }
+
+    /// Feeds the given bytes into the fuzzy hasher and all the digests.
+    ///
+    /// When the underlying C function fails, it returns
+    /// [`Error`](enum.Error.html).
+    pub fn update(&mut self, buf: &[u8]) -> Result<()> {
+        self.fuzzy_hasher.update(buf)?;
+        for (_, digest) in &mut self.digests {
+            digest.update(buf);
+        }
+        Ok(())
+    }
+
+    /// Reads all data from the given reader, feeds them into the fuzzy hasher
+    /// and all the digests, and returns their size.
+    ///
+    /// When reading fails, it returns
+    /// [`Error::Io`](enum.Error.html#variant.Io).
+    pub fn update_from_reader<R: Read>(&mut self, reader: R) -> Result<u64> {
+        let writer = DigestWriter {
+            digests: &mut self.digests,
+        };
+        self.fuzzy_hasher.update_and_copy(reader, writer)
+    }
+
+    /// Reads the contents of the given file, feeds them into the fuzzy hasher
+    /// and all the digests, and returns their size.
+    ///
+    /// See [`update_from_reader()`](#method.update_from_reader) for more
+    /// details.
+    pub fn update_from_file<P: AsRef<Path>>(&mut self, path: P) -> Result<u64> {
+        self.update_from_reader(File::open(path)?)
+    }
+
+    /// Returns the fuzzy hash and the digests of all the bytes fed into the
+    /// hasher.
+    ///
+    /// When the underlying C function fails (e.g. when the total size of the
+    /// fed bytes is too large), it returns [`Error`](enum.Error.html).
+    pub fn finish(self) -> Result<MultiDigest> {
+        Ok(MultiDigest {
+            fuzzy_hash: self.fuzzy_hasher.digest()?,
+            digests: self
+                .digests
+                .into_iter()
+                .map(|(name, digest)| (name, digest.finalize().into_vec()))
+                .collect(),
+        })
+    }
+}
+
+impl fmt::Debug for MultiHasher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<_> = self.digests.iter().map(|(name, _)| name).collect();
+        f.debug_struct("MultiHasher")
+            .field("fuzzy_hasher", &self.fuzzy_hasher)
+            .field("digests", &names)
+            .finish()
+    }
+}
+
+impl MultiDigest {
+    /// Returns the value of the digest with the given name (the first one when
+    /// there are more digests with the name).
+    pub fn digest(&self, name: &str) -> Option<&[u8]> {
+        self.digests
+            .iter()
+            .find(|&(digest_name, _)| digest_name == name)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /// Returns the value of the digest with the given name in lowercase
+    /// hexadecimal.
+    pub fn hex_digest(&self, name: &str) -> Option<String> {
+        self.digest(name)
+            .map(|value| value.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+/// A writer feeding the written data into digests.
+struct DigestWriter<'a> {
+    digests: &'a mut Vec<(String, Box<dyn DynDigest + Send>)>,
+}
+
+impl<'a> Write for DigestWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for (_, digest) in self.digests.iter_mut() {
+            digest.update(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
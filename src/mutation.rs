@@ -0,0 +1,214 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Experiments with the robustness of fuzzy hashes against mutations.
+//!
+//! This module generates controlled mutations of an input and reports the
+//! distribution of the scores between the input and its mutations for
+//! various mutation rates. This is useful when evaluating whether fuzzy
+//! hashing suits a new kind of data.
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use super::compare;
+use super::hash;
+use super::Result;
+
+/// A kind of mutation of an input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MutationKind {
+    /// Randomly selected bytes are changed to different values.
+    ByteFlip,
+    /// Random bytes are inserted at random positions.
+    Insertion,
+    /// Randomly selected pairs of blocks (of 512 bytes, or an eighth of the
+    /// input when it is shorter than 4 KiB) are swapped.
+    BlockSwap,
+    /// Bytes are removed from the end of the input.
+    Truncation,
+}
+
+/// All kinds of mutations.
+pub const ALL_MUTATION_KINDS: [MutationKind; 4] = [
+    MutationKind::ByteFlip,
+    MutationKind::Insertion,
+    MutationKind::BlockSwap,
+    MutationKind::Truncation,
+];
+
+/// Returns a mutation of the input.
+///
+/// The rate (between 0.0 and 1.0) is the fraction of the bytes of the input
+/// that are affected: flipped, inserted, moved by swaps, or removed. The
+/// mutation is pseudo-random but deterministic for the given seed.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::mutation::{mutate, MutationKind};
+///
+/// let mutated = mutate(b"Hello there!", MutationKind::Truncation, 0.5, 1);
+/// assert_eq!(mutated, b"Hello ");
+/// ```
+pub fn mutate(input: &[u8], kind: MutationKind, rate: f64, seed: u64) -> Vec<u8> {
+    let mut rng = SplitMix64(seed);
+    let count = (rate.clamp(0.0, 1.0) * input.len() as f64).round() as usize;
+    let mut output = input.to_vec();
+    match kind {
+        MutationKind::ByteFlip => {
+            for _ in 0..count {
+                let i = rng.below(output.len());
+                // XOR with a non-zero value always changes the byte.
+                output[i] ^= 1 + rng.below(255) as u8;
+            }
+        }
+        MutationKind::Insertion => {
+            for _ in 0..count {
+                let i = rng.below(output.len() + 1);
+                output.insert(i, rng.next() as u8);
+            }
+        }
+        MutationKind::BlockSwap => {
+            let block_size = if input.len() < 4096 {
+                (input.len() / 8).max(1)
+            } else {
+                512
+            };
+            let blocks = output.len() / block_size;
+            if blocks >= 2 {
+                for _ in 0..count / (2 * block_size) {
+                    let b1 = rng.below(blocks);
+                    let b2 = rng.below(blocks);
+                    if b1 == b2 {
+                        continue;
+                    }
+                    let (b1, b2) = (b1.min(b2), b1.max(b2));
+                    let (head, tail) = output.split_at_mut(b2 * block_size);
+                    head[b1 * block_size..(b1 + 1) * block_size]
+                        .swap_with_slice(&mut tail[..block_size]);
+                }
+            }
+        }
+        MutationKind::Truncation => {
+            output.truncate(input.len() - count);
+        }
+    }
+    output
+}
+
+/// Options for [`run_experiment()`](fn.run_experiment.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExperimentOptions {
+    /// Kinds of mutations to try. Default: all kinds.
+    pub kinds: Vec<MutationKind>,
+    /// Mutation rates to try. Default: 0.01, 0.05, 0.1, 0.25, and 0.5.
+    pub rates: Vec<f64>,
+    /// The number of mutations (with different seeds) per kind and rate.
+    /// Default: 10.
+    pub trials: u64,
+    /// The seed of the first trial. Default: 0.
+    pub seed: u64,
+}
+
+impl Default for ExperimentOptions {
+    fn default() -> Self {
+        ExperimentOptions {
+            kinds: ALL_MUTATION_KINDS.to_vec(),
+            rates: vec![0.01, 0.05, 0.1, 0.25, 0.5],
+            trials: 10,
+            seed: 0,
+        }
+    }
+}
+
+/// The distribution of the scores between an input and its mutations of the
+/// given kind and rate.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ScoreDistribution {
+    /// The kind of the mutations.
+    pub kind: MutationKind,
+    /// The rate of the mutations.
+    pub rate: f64,
+    /// The scores, one per trial.
+    pub scores: Vec<u8>,
+    /// The lowest score.
+    pub min: u8,
+    /// The highest score.
+    pub max: u8,
+    /// The mean of the scores.
+    pub mean: f64,
+}
+
+/// Mutates the input in all the combinations of kinds and rates from the
+/// options and reports the distributions of the scores between the input and
+/// its mutations.
+///
+/// The distributions are returned in the order of the kinds and, for each
+/// kind, in the order of the rates. The results are deterministic for the
+/// given options.
+///
+/// When the input cannot be hashed, it returns an error.
+pub fn run_experiment(input: &[u8], options: &ExperimentOptions) -> Result<Vec<ScoreDistribution>> {
+    let input_hash = hash(input)?;
+    let mut distributions = Vec::new();
+    for &kind in &options.kinds {
+        for &rate in &options.rates {
+            let mut scores = Vec::new();
+            for trial in 0..options.trials {
+                let mutated = mutate(input, kind, rate, options.seed.wrapping_add(trial));
+                scores.push(compare(&input_hash, &hash(&mutated)?)?);
+            }
+            let sum: u64 = scores.iter().map(|&s| u64::from(s)).sum();
+            distributions.push(ScoreDistribution {
+                kind,
+                rate,
+                min: scores.iter().cloned().min().unwrap_or(0),
+                max: scores.iter().cloned().max().unwrap_or(0),
+                mean: if scores.is_empty() {
+                    0.0
+                } else {
+                    sum as f64 / scores.len() as f64
+                },
+                scores,
+            });
+        }
+    }
+    Ok(distributions)
+}
+
+/// The SplitMix64 pseudo-random number generator.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number from `0..n` (`n` must be positive).
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
@@ -0,0 +1,156 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Fuzzy hashing of sub-streams of Microsoft Office documents.
+//!
+//! This module is available only when the `office` feature is enabled. A
+//! fuzzy hash of a whole document changes significantly even after trivial
+//! edits of its metadata (e.g. the last-saved time). Fuzzy hashes of the
+//! individual sub-streams of the document (e.g. the main text or VBA macros)
+//! are much more useful for finding related documents.
+//!
+//! Supported are both legacy OLE2 documents (`.doc`, `.xls`, `.ppt`) and
+//! Office Open XML documents (`.docx`, `.xlsm`, `.pptx`, etc.). Streams with
+//! document metadata (summary information in OLE2 documents and the
+//! `docProps` directory in OOXML documents) are skipped. OLE2 containers
+//! embedded in OOXML documents (e.g. `vbaProject.bin` or embedded objects) are
+//! hashed both as a whole and stream by stream.
+
+use std::fs::File;
+use std::io;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+
+use cfb::CompoundFile;
+use zip::ZipArchive;
+
+use super::hash;
+use super::hasher::hash_and_copy;
+use super::Error;
+use super::Result;
+
+/// Magic bytes of OLE2 (Compound File Binary) files.
+const OLE2_MAGIC: &[u8] = b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1";
+
+/// Magic bytes of ZIP files (OOXML documents are ZIP archives).
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Fuzzy hash of a single sub-stream of a document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamHash {
+    /// Path to the stream in the document, with components separated by `/`.
+    /// Streams of an embedded OLE2 container are prefixed with the path to the
+    /// container (e.g. `word/vbaProject.bin/VBA/Module1`).
+    pub path: String,
+    /// Length of the stream (in bytes).
+    pub size: u64,
+    /// Fuzzy hash of the stream.
+    pub hash: String,
+}
+
+/// Computes fuzzy hashes of sub-streams of the given document.
+///
+/// The format of the document is detected from its magic bytes. When the
+/// document is not an OLE2 or OOXML document, or when it cannot be parsed,
+/// [`Error::DocumentParsingFailed`](../enum.Error.html#variant.DocumentParsingFailed)
+/// is returned.
+pub fn hash_document<R: Read + Seek>(mut reader: R) -> Result<Vec<StreamHash>> {
+    let mut magic = Vec::with_capacity(OLE2_MAGIC.len());
+    (&mut reader)
+        .take(OLE2_MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    let mut result = Vec::new();
+    if magic.starts_with(OLE2_MAGIC) {
+        hash_ole2(reader, "", &mut result)?;
+    } else if magic.starts_with(ZIP_MAGIC) {
+        hash_ooxml(reader, &mut result)?;
+    } else {
+        return Err(parsing_failed("unsupported document format"));
+    }
+    Ok(result)
+}
+
+/// Computes fuzzy hashes of sub-streams of the given document file.
+///
+/// See [`hash_document()`](fn.hash_document.html) for more details.
+pub fn hash_document_file<P: AsRef<Path>>(file_path: P) -> Result<Vec<StreamHash>> {
+    hash_document(File::open(file_path)?)
+}
+
+fn hash_ole2<R: Read + Seek>(reader: R, prefix: &str, result: &mut Vec<StreamHash>) -> Result<()> {
+    let mut file = CompoundFile::open(reader).map_err(parsing_failed)?;
+    let streams: Vec<_> = file
+        .walk()
+        .filter(|entry| entry.is_stream() && !is_ole2_metadata_stream(entry.name()))
+        .map(|entry| (entry.path().to_path_buf(), entry.len()))
+        .collect();
+    for (stream_path, size) in streams {
+        let stream = file.open_stream(&stream_path).map_err(parsing_failed)?;
+        let path = stream_path
+            .iter()
+            .filter_map(|c| c.to_str())
+            .filter(|c| *c != "/")
+            .collect::<Vec<_>>()
+            .join("/");
+        result.push(StreamHash {
+            path: format!("{}{}", prefix, path),
+            size,
+            hash: hash_and_copy(stream, io::sink())?,
+        });
+    }
+    Ok(())
+}
+
+fn hash_ooxml<R: Read + Seek>(reader: R, result: &mut Vec<StreamHash>) -> Result<()> {
+    let mut archive = ZipArchive::new(reader).map_err(parsing_failed)?;
+    for i in 0..archive.len() {
+        let mut member = archive.by_index(i).map_err(parsing_failed)?;
+        if member.is_dir() || member.name().starts_with("docProps/") {
+            continue;
+        }
+
+        let path = member.name().to_string();
+        let mut data = Vec::with_capacity(member.size() as usize);
+        member.read_to_end(&mut data)?;
+        result.push(StreamHash {
+            path: path.clone(),
+            size: data.len() as u64,
+            hash: hash(&data)?,
+        });
+        if data.starts_with(OLE2_MAGIC) {
+            hash_ole2(Cursor::new(data), &format!("{}/", path), result)?;
+        }
+    }
+    Ok(())
+}
+
+fn is_ole2_metadata_stream(name: &str) -> bool {
+    // Property-set streams, such as "\x05SummaryInformation" and
+    // "\x05DocumentSummaryInformation", start with the character 0x05.
+    name.starts_with('\x05')
+}
+
+fn parsing_failed<E: ToString>(e: E) -> Error {
+    Error::DocumentParsingFailed {
+        reason: e.to_string(),
+    }
+}
@@ -0,0 +1,414 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Building blocks of producer/consumer pipelines for hashing and matching
+//! many files.
+//!
+//! This module is available only when the `pipeline` feature is enabled. Each
+//! stage runs in its own thread(s) and passes its results to the next stage
+//! through a bounded channel from the
+//! [crossbeam-channel](https://crates.io/crates/crossbeam-channel) crate.
+//! When a stage is slower than the previous one, the channel fills up and the
+//! previous stage waits (backpressure), so the memory usage stays bounded by
+//! the capacities of the channels. A stage finishes when all its input has
+//! been processed and the previous stage has finished.
+//!
+//! For services that receive jobs from outside (e.g. over the network), the
+//! module also provides [`IngestQueue`](struct.IngestQueue.html), a bounded
+//! job queue with a configurable policy for when it is full.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Arc;
+//! use ssdeep::pipeline::{hash_pool, match_hashes, read_files, sink};
+//! use ssdeep::HashBatch;
+//!
+//! let mut batch = HashBatch::new();
+//! batch.push("48:9MABzSwnjpDeSrLp8+nagE4f3ZMvcDT0MIhqy6Ic:9XMwnjdeSHS+n5ZfScX0MJ7").unwrap();
+//!
+//! let files = read_files(vec!["tests/file.txt".into()], 16);
+//! let hashes = hash_pool(files, 4, 16);
+//! let matches = match_hashes(hashes, Arc::new(batch), 50, 16);
+//! sink(matches, |item| {
+//!     let file_matches = item.result.unwrap();
+//!     assert_eq!(file_matches.matches, vec![(0, 100)]);
+//! });
+//! ```
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crossbeam_channel::bounded;
+use crossbeam_channel::Receiver;
+use crossbeam_channel::Sender;
+use crossbeam_channel::TrySendError;
+
+use super::hash;
+use super::HashBatch;
+use super::Phase;
+use super::Progress;
+use super::Result;
+
+/// An item passed between stages: the result of processing a file.
+#[derive(Debug)]
+pub struct Item<T> {
+    /// Path to the file.
+    pub path: PathBuf,
+    /// The result of processing the file (or the error from the first stage
+    /// that failed to process it).
+    pub result: Result<T>,
+}
+
+/// A hash of a file together with its matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMatches {
+    /// Fuzzy hash of the file.
+    pub hash: String,
+    /// Indexes of the matching hashes in the batch with their scores, sorted
+    /// by the indexes.
+    pub matches: Vec<(usize, u8)>,
+}
+
+/// Reads the given files in a new thread.
+///
+/// The contents of the files are sent to the returned channel, which can hold
+/// at most `capacity` files.
+pub fn read_files<I>(paths: I, capacity: usize) -> Receiver<Item<Vec<u8>>>
+where
+    I: IntoIterator<Item = PathBuf>,
+    I::IntoIter: Send + 'static,
+{
+    let (sender, receiver) = bounded(capacity);
+    let paths = paths.into_iter();
+    thread::spawn(move || {
+        for path in paths {
+            let result = fs::read(&path).map_err(Into::into);
+            if sender.send(Item { path, result }).is_err() {
+                // The next stage has been dropped.
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Computes fuzzy hashes of the received data in a pool of `threads` threads.
+///
+/// The hashes are sent to the returned channel, which can hold at most
+/// `capacity` hashes. The order of the hashes may differ from the order of
+/// the received data.
+///
+/// # Panics
+///
+/// When `threads` is zero.
+pub fn hash_pool(
+    input: Receiver<Item<Vec<u8>>>,
+    threads: usize,
+    capacity: usize,
+) -> Receiver<Item<String>> {
+    assert!(threads > 0, "the number of threads has to be positive");
+
+    let (sender, receiver) = bounded(capacity);
+    for _ in 0..threads {
+        let input = input.clone();
+        let sender = sender.clone();
+        thread::spawn(move || {
+            for item in input {
+                let result = item.result.and_then(|data| hash(&data));
+                if sender
+                    .send(Item {
+                        path: item.path,
+                        result,
+                    })
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+    }
+    receiver
+}
+
+/// Matches the received hashes against the hashes in the given batch in a new
+/// thread.
+///
+/// Hashes from the batch whose score is at least `min_score` are considered
+/// to be matches. The results are sent to the returned channel, which can
+/// hold at most `capacity` results.
+pub fn match_hashes(
+    input: Receiver<Item<String>>,
+    batch: Arc<HashBatch>,
+    min_score: u8,
+    capacity: usize,
+) -> Receiver<Item<FileMatches>> {
+    let (sender, receiver) = bounded(capacity);
+    thread::spawn(move || {
+        for item in input {
+            let result = item.result.and_then(|hash| {
+                let matches = batch
+                    .scores(&hash)?
+                    .into_iter()
+                    .enumerate()
+                    .filter(|&(_, score)| score > 0 && score >= min_score)
+                    .collect();
+                Ok(FileMatches { hash, matches })
+            });
+            if sender
+                .send(Item {
+                    path: item.path,
+                    result,
+                })
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Reports the progress of the previous stage to the given callback in a new
+/// thread.
+///
+/// All received items are passed unchanged to the returned channel, which can
+/// hold at most `capacity` items. After each item, the callback is called
+/// with the number of items that have passed through so far in the given
+/// phase (e.g. [`Phase::Hashing`](../enum.Phase.html) after
+/// [`hash_pool()`](fn.hash_pool.html)) and with the path of the item. `total`
+/// is the total number of items (if known).
+pub fn report_progress<T, F>(
+    input: Receiver<Item<T>>,
+    phase: Phase,
+    total: Option<u64>,
+    mut progress: F,
+    capacity: usize,
+) -> Receiver<Item<T>>
+where
+    T: Send + 'static,
+    F: FnMut(&Progress) + Send + 'static,
+{
+    let (sender, receiver) = bounded(capacity);
+    thread::spawn(move || {
+        for (done, item) in (1..).zip(input) {
+            progress(&Progress {
+                phase,
+                done,
+                total,
+                current_path: Some(item.path.clone()),
+            });
+            if sender.send(item).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Passes all received items to the given function in the current thread.
+///
+/// It returns after all previous stages have finished.
+pub fn sink<T, F>(input: Receiver<Item<T>>, mut f: F)
+where
+    F: FnMut(Item<T>),
+{
+    for item in input {
+        f(item);
+    }
+}
+
+/// What to do with a job when an [`IngestQueue`](struct.IngestQueue.html) is
+/// full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Park the submitting thread until there is room in the queue.
+    Park,
+    /// Park the submitting thread for at most the given time, and shed the job
+    /// when there is still no room in the queue.
+    ParkWithTimeout(Duration),
+    /// Shed the submitted job.
+    ShedNewest,
+    /// Shed the oldest job in the queue to make room for the submitted job.
+    ShedOldest,
+}
+
+/// A bounded queue of jobs (e.g. data to hash or hashes to match) with
+/// admission control for services that embed the matcher.
+///
+/// Jobs are submitted by producers and taken by consumers (e.g. a pool of
+/// workers). When the queue is full, the
+/// [`OverflowPolicy`](enum.OverflowPolicy.html) determines whether the
+/// producer waits or a job is shed. The queue can be cloned to be shared
+/// between threads; all clones refer to the same queue.
+///
+/// To report the queue depth (e.g. into a metrics system), set a depth hook
+/// via [`with_depth_hook()`](#method.with_depth_hook). It is called with the
+/// current depth after every submission and after every taken job.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::pipeline::{IngestQueue, OverflowPolicy};
+///
+/// let queue = IngestQueue::new(1, OverflowPolicy::ShedNewest);
+/// assert_eq!(queue.submit("job1"), None);
+/// assert_eq!(queue.submit("job2"), Some("job2"));
+/// assert_eq!(queue.depth(), 1);
+/// assert_eq!(queue.take(), Some("job1"));
+/// ```
+pub struct IngestQueue<J> {
+    sender: Sender<J>,
+    receiver: Receiver<J>,
+    policy: OverflowPolicy,
+    depth_hook: Option<Arc<dyn Fn(usize) + Send + Sync>>,
+    /// Serializes shedding of the oldest job and sending of the submitted job
+    /// between producers.
+    shed_lock: Arc<Mutex<()>>,
+}
+
+impl<J> IngestQueue<J> {
+    /// Creates a queue that can hold at most `capacity` jobs.
+    ///
+    /// # Panics
+    ///
+    /// When `capacity` is zero.
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        assert!(capacity > 0, "the capacity has to be positive");
+
+        let (sender, receiver) = bounded(capacity);
+        IngestQueue {
+            sender,
+            receiver,
+            policy,
+            depth_hook: None,
+            shed_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Sets a function to be called with the current depth of the queue after
+    /// every submission and after every taken job.
+    pub fn with_depth_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.depth_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Submits a job into the queue.
+    ///
+    /// It returns the job that has been shed (either the submitted job or the
+    /// oldest job in the queue, based on the policy), or `None` when no job
+    /// has been shed.
+    pub fn submit(&self, job: J) -> Option<J> {
+        let shed = match self.policy {
+            OverflowPolicy::Park => self.sender.send(job).err().map(|e| e.into_inner()),
+            OverflowPolicy::ParkWithTimeout(timeout) => self
+                .sender
+                .send_timeout(job, timeout)
+                .err()
+                .map(|e| e.into_inner()),
+            OverflowPolicy::ShedNewest => self.sender.try_send(job).err().map(|e| e.into_inner()),
+            OverflowPolicy::ShedOldest => {
+                // Without the lock, another producer could fill the room made
+                // by shedding before the job is sent.
+                let _guard = self.shed_lock.lock().unwrap_or_else(|e| e.into_inner());
+                match self.sender.try_send(job) {
+                    Ok(()) => None,
+                    Err(TrySendError::Full(job)) => {
+                        // A consumer may take the oldest job in the meantime,
+                        // in which case nothing is shed. Consumers only make
+                        // room, so the job can be sent either way.
+                        let shed = self.receiver.try_recv().ok();
+                        match self.sender.try_send(job) {
+                            Ok(()) => shed,
+                            Err(e) => Some(e.into_inner()),
+                        }
+                    }
+                    Err(TrySendError::Disconnected(job)) => Some(job),
+                }
+            }
+        };
+        self.report_depth();
+        shed
+    }
+
+    /// Takes the oldest job from the queue, parking the current thread until
+    /// there is a job.
+    ///
+    /// Since the queue holds both ends of its channel, it returns `None` only
+    /// in the unlikely case of the channel being disconnected.
+    pub fn take(&self) -> Option<J> {
+        let job = self.receiver.recv().ok();
+        self.report_depth();
+        job
+    }
+
+    /// Takes the oldest job from the queue, or returns `None` when the queue
+    /// is empty.
+    pub fn try_take(&self) -> Option<J> {
+        let job = self.receiver.try_recv().ok();
+        if job.is_some() {
+            self.report_depth();
+        }
+        job
+    }
+
+    /// Takes the oldest job from the queue, parking the current thread for at
+    /// most the given time, or returns `None` when there is still no job.
+    pub fn take_timeout(&self, timeout: Duration) -> Option<J> {
+        let job = self.receiver.recv_timeout(timeout).ok();
+        if job.is_some() {
+            self.report_depth();
+        }
+        job
+    }
+
+    /// Returns the number of jobs in the queue.
+    pub fn depth(&self) -> usize {
+        self.receiver.len()
+    }
+
+    /// Returns the maximal number of jobs in the queue.
+    pub fn capacity(&self) -> usize {
+        self.receiver.capacity().unwrap_or(usize::MAX)
+    }
+
+    fn report_depth(&self) {
+        if let Some(hook) = &self.depth_hook {
+            hook(self.depth());
+        }
+    }
+}
+
+// Implemented manually because deriving would require the jobs to be clonable.
+impl<J> Clone for IngestQueue<J> {
+    fn clone(&self) -> Self {
+        IngestQueue {
+            sender: self.sender.clone(),
+            receiver: self.receiver.clone(),
+            policy: self.policy,
+            depth_hook: self.depth_hook.clone(),
+            shed_lock: self.shed_lock.clone(),
+        }
+    }
+}
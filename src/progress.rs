@@ -0,0 +1,62 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Progress reporting of batch operations.
+
+use std::path::PathBuf;
+
+/// A phase of a batch operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// Computing fuzzy hashes of files (or other inputs).
+    Hashing,
+    /// Comparing fuzzy hashes.
+    Comparing,
+    /// Grouping compared fuzzy hashes into clusters.
+    Clustering,
+    /// Inserting fuzzy hashes into an index.
+    Indexing,
+    /// Summarizing a cluster (e.g. selecting its representatives).
+    Summarizing,
+}
+
+/// A progress report of a batch operation.
+///
+/// Batch operations accepting a progress callback call it with a report
+/// periodically during each phase and once at its end (with `done` equal to
+/// `total` when the total is known).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress {
+    /// The current phase.
+    pub phase: Phase,
+    /// The number of items (files, comparisons) processed so far in the
+    /// current phase.
+    pub done: u64,
+    /// The total number of items to be processed in the current phase (if
+    /// known).
+    pub total: Option<u64>,
+    /// The path of the file that is being (or has just been) processed, when
+    /// the items are files.
+    pub current_path: Option<PathBuf>,
+}
+
+/// The number of comparisons between two consecutive progress reports.
+pub(crate) const COMPARISONS_PER_REPORT: u64 = 1024;
+
+/// The number of insertions (e.g. into an index) between two consecutive
+/// progress reports.
+pub(crate) const INSERTIONS_PER_REPORT: u64 = 1024;
@@ -0,0 +1,135 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Progress bars for batch operations.
+//!
+//! This module is available only when the `indicatif` feature is enabled. It
+//! provides ready-made reporters that draw the [`Progress`](../struct.Progress.html)
+//! reported by batch operations with progress bars from the
+//! [indicatif](https://crates.io/crates/indicatif) crate.
+//!
+//! # Examples
+//!
+//! ```
+//! use ssdeep::fs::hash_dir_with_progress;
+//! use ssdeep::fs::HashDirOptions;
+//! use ssdeep::progress_bar::ProgressBarReporter;
+//!
+//! let reporter = ProgressBarReporter::for_scan();
+//! let count = hash_dir_with_progress("tests", &HashDirOptions::default(), reporter.callback())
+//!     .unwrap()
+//!     .count();
+//! reporter.bar().finish();
+//! assert_eq!(reporter.bar().position(), count as u64);
+//! ```
+
+use std::time::Duration;
+
+use indicatif::ProgressBar;
+use indicatif::ProgressStyle;
+
+use super::Phase;
+use super::Progress;
+
+/// The template of progress bars of scans.
+const SCAN_TEMPLATE: &str = "{spinner} {prefix} {pos} files [{elapsed_precise}] {wide_msg}";
+
+/// The template of progress bars of matrix jobs.
+const MATRIX_TEMPLATE: &str =
+    "{prefix} [{bar:40}] {percent}% ({human_pos}/{human_len}) [{elapsed_precise}, ETA {eta}]";
+
+/// A reporter of progress to a progress bar.
+///
+/// The reporter sets the position of the bar to the number of processed
+/// items, its length to the total number of items (when known), its prefix
+/// to the name of the current phase, and its message to the path of the
+/// current file (when reported). When the number of processed items reaches
+/// the total, the bar is finished.
+///
+/// Cloning the reporter gives a reporter of the same bar.
+#[derive(Debug, Clone)]
+pub struct ProgressBarReporter {
+    bar: ProgressBar,
+}
+
+impl ProgressBarReporter {
+    /// Creates a reporter of the given progress bar.
+    pub fn new(bar: ProgressBar) -> Self {
+        ProgressBarReporter { bar }
+    }
+
+    /// Creates a reporter of a new spinner, which shows the number of
+    /// processed files and the current path (e.g. for
+    /// [`fs::hash_dir_with_progress()`](../fs/fn.hash_dir_with_progress.html)).
+    pub fn for_scan() -> Self {
+        let bar = ProgressBar::new_spinner().with_style(style(SCAN_TEMPLATE));
+        bar.enable_steady_tick(Duration::from_millis(100));
+        ProgressBarReporter::new(bar)
+    }
+
+    /// Creates a reporter of a new bar, which shows the number of processed
+    /// items out of the total with an estimate of the remaining time (e.g.
+    /// for
+    /// [`matrix::similarity_matrix_with_progress()`](../matrix/fn.similarity_matrix_with_progress.html)).
+    pub fn for_matrix() -> Self {
+        let bar = ProgressBar::new(0).with_style(style(MATRIX_TEMPLATE).progress_chars("=> "));
+        ProgressBarReporter::new(bar)
+    }
+
+    /// Returns the reported progress bar (e.g. to finish or clear it).
+    pub fn bar(&self) -> &ProgressBar {
+        &self.bar
+    }
+
+    /// Reports the given progress to the bar.
+    pub fn report(&self, progress: &Progress) {
+        self.bar.set_prefix(phase_name(progress.phase));
+        if let Some(total) = progress.total {
+            if self.bar.length() != Some(total) {
+                self.bar.set_length(total);
+            }
+        }
+        self.bar.set_position(progress.done);
+        if let Some(ref path) = progress.current_path {
+            self.bar.set_message(path.display().to_string());
+        }
+        if progress.total == Some(progress.done) {
+            self.bar.finish();
+        }
+    }
+
+    /// Returns a progress callback for batch operations that reports the
+    /// progress to the bar.
+    pub fn callback(&self) -> impl FnMut(&Progress) + Send + 'static {
+        let reporter = self.clone();
+        move |progress| reporter.report(progress)
+    }
+}
+
+fn style(template: &str) -> ProgressStyle {
+    ProgressStyle::with_template(template).expect("the template is valid")
+}
+
+fn phase_name(phase: Phase) -> &'static str {
+    match phase {
+        Phase::Hashing => "Hashing",
+        Phase::Comparing => "Comparing",
+        Phase::Clustering => "Clustering",
+        Phase::Indexing => "Indexing",
+        Phase::Summarizing => "Summarizing",
+    }
+}
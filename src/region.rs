@@ -0,0 +1,66 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Fuzzy hashing of byte ranges of files.
+
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+
+use super::hasher::FuzzyHasher;
+use super::Error;
+use super::Result;
+
+/// Computes the fuzzy hash of a byte range of a file.
+///
+/// The range starts at `offset` and is `len` bytes long. Only the range is
+/// read from the file (in chunks), so it is useful for hashing embedded
+/// payloads, partition images within disk dumps, or appended overlays at
+/// known offsets.
+///
+/// When the file cannot be read or the range is beyond its end, it returns an
+/// error.
+///
+/// # Examples
+///
+/// ```
+/// let h = ssdeep::hash_file_region("tests/file.txt", 0, 1738).unwrap();
+/// assert_eq!(h, ssdeep::hash_from_file("tests/file.txt").unwrap());
+/// ```
+pub fn hash_file_region<P: AsRef<Path>>(file_path: P, offset: u64, len: u64) -> Result<String> {
+    hash_region(File::open(file_path)?, offset, len)
+}
+
+/// Computes the fuzzy hash of a byte range of data from the given reader.
+///
+/// See [`hash_file_region()`](fn.hash_file_region.html) for more details.
+pub fn hash_region<R: Read + Seek>(mut reader: R, offset: u64, len: u64) -> Result<String> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut hasher = FuzzyHasher::new()?;
+    hasher.set_total_input_length(len)?;
+    let size = hasher.update_and_copy(reader.take(len), io::sink())?;
+    if size < len {
+        return Err(Error::Io(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the range is beyond the end of the input",
+        )));
+    }
+    hasher.digest()
+}
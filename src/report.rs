@@ -0,0 +1,363 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Rendering of scan results, matches, and cluster summaries into reports.
+//!
+//! Reports are rendered either into a self-contained HTML document (with
+//! inline styles and sortable tables) or into Markdown.
+
+use std::fmt::Write;
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use super::classify::Classification;
+use super::classify::ScanResult;
+use super::diff_signatures;
+use super::export::ClusterSummary;
+use super::DiffRun;
+
+/// A match between two samples.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Match {
+    /// Name of the first sample (e.g. its path).
+    pub name1: String,
+    /// Fuzzy hash of the first sample.
+    pub hash1: String,
+    /// Name of the second sample.
+    pub name2: String,
+    /// Fuzzy hash of the second sample.
+    pub hash2: String,
+    /// The match score between the samples.
+    pub score: u8,
+}
+
+/// A report of scan results, matches, and cluster summaries.
+///
+/// Sections without any items are left out of the rendered report. Matches
+/// are rendered from the highest score to the lowest one, together with the
+/// diffs of the signatures (see
+/// [`diff_signatures()`](../fn.diff_signatures.html)).
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::report::{Match, Report};
+///
+/// let mut report = Report::new("Nightly scan");
+/// report.matches.push(Match {
+///     name1: "a.exe".to_string(),
+///     hash1: "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C".to_string(),
+///     name2: "b.exe".to_string(),
+///     hash2: "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx".to_string(),
+///     score: 22,
+/// });
+/// assert!(report.to_html().starts_with("<!DOCTYPE html>"));
+/// assert!(report.to_markdown().contains("| a.exe | b.exe | 22 |"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Report {
+    /// Title of the report.
+    pub title: String,
+    /// Classified scanned files.
+    pub scan_results: Vec<ScanResult>,
+    /// Matches between samples.
+    pub matches: Vec<Match>,
+    /// Summaries of clusters of related samples.
+    pub clusters: Vec<ClusterSummary>,
+}
+
+impl Report {
+    /// Creates an empty report with the given title.
+    pub fn new(title: &str) -> Self {
+        Report {
+            title: title.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Renders the report into a self-contained HTML document.
+    ///
+    /// Tables can be sorted by clicking on their headers.
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        // Writing into a String cannot fail, so we can ignore the results.
+        let _ = writeln!(html, "<!DOCTYPE html>");
+        let _ = writeln!(html, "<html>\n<head>\n<meta charset=\"utf-8\">");
+        let _ = writeln!(html, "<title>{}</title>", html_escape(&self.title));
+        let _ = writeln!(html, "<style>{}</style>", HTML_STYLE);
+        let _ = writeln!(html, "</head>\n<body>");
+        let _ = writeln!(html, "<h1>{}</h1>", html_escape(&self.title));
+
+        if !self.scan_results.is_empty() {
+            let _ = writeln!(html, "<h2>Scan results</h2>");
+            let _ = writeln!(html, "<table class=\"sortable\">");
+            let _ = writeln!(
+                html,
+                "<tr><th>Path</th><th>Hash</th><th>Class</th><th>Score</th><th>Reference</th></tr>"
+            );
+            for result in &self.scan_results {
+                let (class, score, reference) = classification_columns(&result.classification);
+                let _ = writeln!(
+                    html,
+                    "<tr><td>{}</td><td><code>{}</code></td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    html_escape(&result.path),
+                    html_escape(&result.hash),
+                    class,
+                    score,
+                    html_escape(&reference)
+                );
+            }
+            let _ = writeln!(html, "</table>");
+        }
+
+        if !self.matches.is_empty() {
+            let _ = writeln!(html, "<h2>Matches</h2>");
+            let _ = writeln!(html, "<table class=\"sortable\">");
+            let _ = writeln!(
+                html,
+                "<tr><th>Sample</th><th>Matching sample</th><th>Score</th><th>Signature diff</th></tr>"
+            );
+            for m in self.sorted_matches() {
+                let diff = match diff_signatures(&m.hash1, &m.hash2) {
+                    Ok(diff) => diff.to_html(),
+                    Err(_) => "invalid hash".to_string(),
+                };
+                let _ = writeln!(
+                    html,
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    html_escape(&m.name1),
+                    html_escape(&m.name2),
+                    m.score,
+                    diff.trim_end()
+                );
+            }
+            let _ = writeln!(html, "</table>");
+        }
+
+        for (i, cluster) in self.clusters.iter().enumerate() {
+            let _ = writeln!(html, "<h2>Cluster {}</h2>", i + 1);
+            let _ = writeln!(
+                html,
+                "<p>{} sample(s), minimal score {}</p>",
+                cluster.sample_count, cluster.min_score
+            );
+            let _ = writeln!(html, "<table class=\"sortable\">");
+            let _ = writeln!(html, "<tr><th>Representative</th><th>Hash</th></tr>");
+            for sample in &cluster.representatives {
+                let _ = writeln!(
+                    html,
+                    "<tr><td>{}</td><td><code>{}</code></td></tr>",
+                    html_escape(&sample.name),
+                    html_escape(&sample.hash)
+                );
+            }
+            let _ = writeln!(html, "</table>");
+            if !cluster.common_metadata.is_empty() {
+                let _ = writeln!(html, "<ul>");
+                for (key, value) in &cluster.common_metadata {
+                    let _ = writeln!(
+                        html,
+                        "<li>{}: {}</li>",
+                        html_escape(key),
+                        html_escape(value)
+                    );
+                }
+                let _ = writeln!(html, "</ul>");
+            }
+        }
+
+        let _ = writeln!(html, "<script>{}</script>", HTML_SCRIPT);
+        let _ = writeln!(html, "</body>\n</html>");
+        html
+    }
+
+    /// Renders the report into Markdown.
+    ///
+    /// In signature diffs, characters present only in the first hash are
+    /// rendered as `[-...-]` and characters present only in the second hash
+    /// as `{+...+}`.
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::new();
+        let _ = writeln!(md, "# {}", self.title);
+
+        if !self.scan_results.is_empty() {
+            let _ = writeln!(md, "\n## Scan results\n");
+            let _ = writeln!(md, "| Path | Hash | Class | Score | Reference |");
+            let _ = writeln!(md, "| --- | --- | --- | ---: | --- |");
+            for result in &self.scan_results {
+                let (class, score, reference) = classification_columns(&result.classification);
+                let _ = writeln!(
+                    md,
+                    "| {} | `{}` | {} | {} | {} |",
+                    markdown_escape(&result.path),
+                    result.hash,
+                    class,
+                    score,
+                    markdown_escape(&reference)
+                );
+            }
+        }
+
+        if !self.matches.is_empty() {
+            let _ = writeln!(md, "\n## Matches\n");
+            let _ = writeln!(md, "| Sample | Matching sample | Score | Signature diff |");
+            let _ = writeln!(md, "| --- | --- | ---: | --- |");
+            for m in self.sorted_matches() {
+                let _ = writeln!(
+                    md,
+                    "| {} | {} | {} | {} |",
+                    markdown_escape(&m.name1),
+                    markdown_escape(&m.name2),
+                    m.score,
+                    markdown_diff(&m.hash1, &m.hash2)
+                );
+            }
+        }
+
+        for (i, cluster) in self.clusters.iter().enumerate() {
+            let _ = writeln!(md, "\n## Cluster {}\n", i + 1);
+            let _ = writeln!(
+                md,
+                "{} sample(s), minimal score {}\n",
+                cluster.sample_count, cluster.min_score
+            );
+            let _ = writeln!(md, "| Representative | Hash |");
+            let _ = writeln!(md, "| --- | --- |");
+            for sample in &cluster.representatives {
+                let _ = writeln!(
+                    md,
+                    "| {} | `{}` |",
+                    markdown_escape(&sample.name),
+                    sample.hash
+                );
+            }
+            if !cluster.common_metadata.is_empty() {
+                let _ = writeln!(md);
+                for (key, value) in &cluster.common_metadata {
+                    let _ = writeln!(md, "* {}: {}", markdown_escape(key), markdown_escape(value));
+                }
+            }
+        }
+        md
+    }
+
+    fn sorted_matches(&self) -> Vec<&Match> {
+        let mut matches: Vec<_> = self.matches.iter().collect();
+        matches.sort_by_key(|m| std::cmp::Reverse(m.score));
+        matches
+    }
+}
+
+/// Styles of the HTML report.
+const HTML_STYLE: &str = "body { font-family: sans-serif; margin: 2em; } \
+    table { border-collapse: collapse; margin-bottom: 1em; } \
+    th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; } \
+    th { background: #eee; cursor: pointer; } \
+    code, .ssdeep-diff { font-family: monospace; } \
+    del { background: #fdd; text-decoration: none; } \
+    ins { background: #dfd; text-decoration: none; }";
+
+/// A script making the tables of the HTML report sortable by their columns.
+const HTML_SCRIPT: &str = "document.querySelectorAll('table.sortable th').forEach(function (th) {\
+    th.addEventListener('click', function () {\
+    var table = th.closest('table');\
+    var index = Array.prototype.indexOf.call(th.parentNode.children, th);\
+    var rows = Array.prototype.slice.call(table.rows, 1);\
+    var asc = th.dataset.order !== 'asc';\
+    th.dataset.order = asc ? 'asc' : 'desc';\
+    rows.sort(function (a, b) {\
+    var x = a.cells[index].textContent, y = b.cells[index].textContent;\
+    var c = (isNaN(x) || isNaN(y)) ? x.localeCompare(y) : x - y;\
+    return asc ? c : -c;\
+    });\
+    rows.forEach(function (row) { table.appendChild(row); });\
+    });\
+    });";
+
+/// Returns the class, score, and reference name of a classification.
+fn classification_columns(classification: &Classification) -> (&'static str, String, String) {
+    match *classification {
+        Classification::KnownBad {
+            ref reference,
+            score,
+        } => ("known-bad", score.to_string(), reference.name.clone()),
+        Classification::KnownGood {
+            ref reference,
+            score,
+        } => ("known-good", score.to_string(), reference.name.clone()),
+        Classification::SimilarToBad {
+            ref reference,
+            score,
+        } => ("similar-to-bad", score.to_string(), reference.name.clone()),
+        Classification::Unknown => ("unknown", String::new(), String::new()),
+    }
+}
+
+fn markdown_diff(hash1: &str, hash2: &str) -> String {
+    let diff = match diff_signatures(hash1, hash2) {
+        Ok(diff) => diff,
+        Err(_) => return "invalid hash".to_string(),
+    };
+    let tracks: Vec<_> = diff
+        .tracks
+        .iter()
+        .map(|track| {
+            let mut s = format!("`{}:", track.block_size);
+            for run in &track.runs {
+                match *run {
+                    DiffRun::Common(ref r) => s.push_str(r),
+                    DiffRun::Removed(ref r) => {
+                        let _ = write!(s, "[-{}-]", r);
+                    }
+                    DiffRun::Added(ref r) => {
+                        let _ = write!(s, "{{+{}+}}", r);
+                    }
+                }
+            }
+            s.push('`');
+            s
+        })
+        .collect();
+    tracks.join("<br>")
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn markdown_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '|' | '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' | '\r' => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,344 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Hashing of untrusted data in separate, resource-limited processes.
+//!
+//! This module is available only on Linux when the `sandbox` feature is
+//! enabled. The data are parsed, decompressed, and hashed in a worker process
+//! instead of in the calling process, so a malformed input or a
+//! decompression bomb can at most bring down the worker. The worker limits
+//! its memory and CPU time via rlimits and, on x86-64 and AArch64, restricts
+//! itself via a seccomp filter to the system calls needed for reading its
+//! input, allocating memory, and writing its output. In particular, it cannot
+//! open files, create processes, or access the network.
+//!
+//! The worker is the `ssdeep-sandbox-worker` binary of this crate (built when
+//! the `sandbox` feature is enabled). Alternatively, any program whose `main()`
+//! calls [`run_worker()`](fn.run_worker.html) can be used as the worker.
+//!
+//! The protocol between the caller and the worker is simple: the operation
+//! and limits are passed as command-line arguments, the input is written into
+//! the standard input of the worker, and the worker writes either `ok
+//! <hash>` or `error <message>` into its standard output.
+
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+
+#[cfg(feature = "decompress")]
+use super::decompress::hash_decompressed;
+use super::hash;
+use super::Error;
+use super::Result;
+
+/// An operation performed by the worker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Computing the fuzzy hash of the input (see
+    /// [`hash()`](../fn.hash.html)).
+    Hash,
+    /// Computing the fuzzy hash of the decompressed input (see
+    /// [`decompress::hash_decompressed()`](../decompress/fn.hash_decompressed.html)).
+    /// Available only when the `decompress` feature is enabled.
+    #[cfg(feature = "decompress")]
+    HashDecompressed,
+}
+
+impl Operation {
+    fn name(self) -> &'static str {
+        match self {
+            Operation::Hash => "hash",
+            #[cfg(feature = "decompress")]
+            Operation::HashDecompressed => "hash-decompressed",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Operation> {
+        match name {
+            "hash" => Some(Operation::Hash),
+            #[cfg(feature = "decompress")]
+            "hash-decompressed" => Some(Operation::HashDecompressed),
+            _ => None,
+        }
+    }
+}
+
+/// Options of sandboxed hashing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxOptions {
+    /// Path to the worker executable. Default: `ssdeep-sandbox-worker`
+    /// (searched for in `PATH`).
+    pub worker: PathBuf,
+    /// The maximal size of the address space of the worker in bytes (it has
+    /// to hold the whole input). Default: 1 GiB.
+    pub memory_limit: u64,
+    /// The maximal CPU time of the worker in seconds. Default: 60.
+    pub cpu_time_limit: u64,
+}
+
+impl Default for SandboxOptions {
+    fn default() -> Self {
+        SandboxOptions {
+            worker: PathBuf::from("ssdeep-sandbox-worker"),
+            memory_limit: 1024 * 1024 * 1024,
+            cpu_time_limit: 60,
+        }
+    }
+}
+
+/// Performs the operation on the given bytes in a new worker process.
+///
+/// When the worker cannot be started, fails (e.g. because the input is
+/// invalid), or is killed (e.g. because it exceeded a limit), it returns an
+/// error.
+pub fn hash_in_sandbox(
+    buf: &[u8],
+    operation: Operation,
+    options: &SandboxOptions,
+) -> Result<String> {
+    run_in_sandbox(buf, operation, options)
+}
+
+/// Performs the operation on the contents of the given file in a new worker
+/// process.
+///
+/// The file is read by the calling process and its contents are sent to the
+/// worker, which cannot open any files. See
+/// [`hash_in_sandbox()`](fn.hash_in_sandbox.html) for more details.
+pub fn hash_file_in_sandbox<P: AsRef<Path>>(
+    file_path: P,
+    operation: Operation,
+    options: &SandboxOptions,
+) -> Result<String> {
+    run_in_sandbox(File::open(file_path)?, operation, options)
+}
+
+fn run_in_sandbox<R: Read>(
+    mut input: R,
+    operation: Operation,
+    options: &SandboxOptions,
+) -> Result<String> {
+    let mut worker = Command::new(&options.worker)
+        .arg(operation.name())
+        .arg(options.memory_limit.to_string())
+        .arg(options.cpu_time_limit.to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // The worker reads the whole input before it writes anything, so the
+    // input can be written before the output is read. When the worker dies
+    // while reading, the write fails, and the reason is obtained from its
+    // exit status below.
+    let write_result = match worker.stdin.take() {
+        Some(mut stdin) => io::copy(&mut input, &mut stdin).map(|_| ()),
+        None => Ok(()),
+    };
+    let mut output = String::new();
+    if let Some(mut stdout) = worker.stdout.take() {
+        stdout.read_to_string(&mut output)?;
+    }
+    let status = worker.wait()?;
+    match write_result {
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(e) => return Err(e.into()),
+        Ok(()) => {}
+    }
+
+    if let Some(signal) = status.signal() {
+        return Err(sandbox_failed(&format!("killed by signal {}", signal)));
+    }
+    let output = output.trim_end();
+    if let Some(hash) = output.strip_prefix("ok ") {
+        if status.success() {
+            return Ok(hash.to_string());
+        }
+    }
+    if let Some(message) = output.strip_prefix("error ") {
+        return Err(sandbox_failed(message));
+    }
+    Err(sandbox_failed(&format!("exited with {}", status)))
+}
+
+/// Runs the worker: performs the operation given by command-line arguments on
+/// the standard input and writes the result into the standard output.
+///
+/// It is meant to be called from `main()` of the worker executable, which
+/// should exit with the returned exit code.
+pub fn run_worker() -> i32 {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let parsed = match args.as_slice() {
+        [operation, memory_limit, cpu_time_limit] => (
+            Operation::from_name(operation),
+            memory_limit.parse().ok(),
+            cpu_time_limit.parse().ok(),
+        ),
+        _ => (None, None, None),
+    };
+    let (operation, memory_limit, cpu_time_limit) = match parsed {
+        (Some(operation), Some(memory_limit), Some(cpu_time_limit)) => {
+            (operation, memory_limit, cpu_time_limit)
+        }
+        _ => {
+            return respond(Err(
+                "usage: OPERATION MEMORY_LIMIT CPU_TIME_LIMIT".to_string()
+            ))
+        }
+    };
+    if let Err(e) = restrict(memory_limit, cpu_time_limit) {
+        return respond(Err(format!("cannot restrict the worker: {}", e)));
+    }
+
+    let mut input = Vec::new();
+    if let Err(e) = io::stdin().lock().read_to_end(&mut input) {
+        return respond(Err(e.to_string()));
+    }
+    let result = match operation {
+        Operation::Hash => hash(&input),
+        #[cfg(feature = "decompress")]
+        Operation::HashDecompressed => hash_decompressed(io::Cursor::new(input)),
+    };
+    respond(result.map_err(|e| e.to_string()))
+}
+
+fn respond(result: std::result::Result<String, String>) -> i32 {
+    let (line, code) = match result {
+        Ok(hash) => (format!("ok {}\n", hash), 0),
+        Err(message) => (format!("error {}\n", message.replace('\n', " ")), 1),
+    };
+    let mut stdout = io::stdout().lock();
+    if stdout.write_all(line.as_bytes()).is_err() || stdout.flush().is_err() {
+        return 2;
+    }
+    code
+}
+
+/// Sets the resource limits of the current process and installs the seccomp
+/// filter.
+fn restrict(memory_limit: u64, cpu_time_limit: u64) -> io::Result<()> {
+    let limits = [
+        (libc::RLIMIT_AS, memory_limit),
+        (libc::RLIMIT_CPU, cpu_time_limit),
+        (libc::RLIMIT_NPROC, 0),
+    ];
+    for &(resource, limit) in &limits {
+        let rlimit = libc::rlimit {
+            rlim_cur: limit as libc::rlim_t,
+            rlim_max: limit as libc::rlim_t,
+        };
+        if unsafe { libc::setrlimit(resource, &rlimit) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    install_seccomp_filter()
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn install_seccomp_filter() -> io::Result<()> {
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xc000_00b7;
+    // Offsets of the fields of struct seccomp_data.
+    const NR_OFFSET: u32 = 0;
+    const ARCH_OFFSET: u32 = 4;
+
+    let allowed = [
+        libc::SYS_read,
+        libc::SYS_write,
+        libc::SYS_close,
+        libc::SYS_brk,
+        libc::SYS_mmap,
+        libc::SYS_munmap,
+        libc::SYS_mremap,
+        libc::SYS_madvise,
+        libc::SYS_futex,
+        libc::SYS_sigaltstack,
+        libc::SYS_rt_sigreturn,
+        libc::SYS_exit,
+        libc::SYS_exit_group,
+    ];
+    let load = |offset| bpf_stmt((libc::BPF_LD | libc::BPF_W | libc::BPF_ABS) as u16, offset);
+    let ret = |value| bpf_stmt((libc::BPF_RET | libc::BPF_K) as u16, value);
+    let jump_if_equal = |value, jt, jf| libc::sock_filter {
+        code: (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16,
+        jt,
+        jf,
+        k: value,
+    };
+
+    let mut filter = vec![
+        load(ARCH_OFFSET),
+        jump_if_equal(AUDIT_ARCH, 1, 0),
+        ret(libc::SECCOMP_RET_KILL_PROCESS),
+        load(NR_OFFSET),
+    ];
+    for &nr in &allowed {
+        filter.push(jump_if_equal(nr as u32, 0, 1));
+        filter.push(ret(libc::SECCOMP_RET_ALLOW));
+    }
+    filter.push(ret(libc::SECCOMP_RET_KILL_PROCESS));
+
+    let program = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_mut_ptr(),
+    };
+    unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if libc::prctl(
+            libc::PR_SET_SECCOMP,
+            libc::SECCOMP_MODE_FILTER,
+            &program as *const libc::sock_fprog,
+        ) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn install_seccomp_filter() -> io::Result<()> {
+    // The numbers of system calls differ between architectures, so the filter
+    // is provided only for the most common ones.
+    Ok(())
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+fn bpf_stmt(code: u16, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn sandbox_failed(reason: &str) -> Error {
+    Error::SandboxFailed {
+        reason: reason.to_string(),
+    }
+}
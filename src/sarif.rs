@@ -0,0 +1,160 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Output of classification results in the [SARIF
+//! 2.1.0](https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html)
+//! format.
+//!
+//! This module is available only when the `sarif` feature is enabled. SARIF
+//! is consumed by code-scanning UIs (e.g. GitHub code scanning), so CI checks
+//! like "no file in this artifact resembles a known-bad sample" can surface
+//! their findings there.
+
+use serde_json::Value;
+
+use super::classify::Classification;
+use super::classify::Reference;
+pub use super::classify::ScanResult;
+
+/// ID of the rule for known-bad files.
+pub const KNOWN_BAD_RULE_ID: &str = "known-bad";
+
+/// ID of the rule for files that are similar to a known-bad file.
+pub const SIMILAR_TO_BAD_RULE_ID: &str = "similar-to-bad";
+
+/// Converts scan results into a SARIF log (a JSON document).
+///
+/// Known-bad files are reported as errors and files similar to a known-bad
+/// file as warnings. Known-good and unknown files are not findings, so they
+/// are not reported. The scores, hashes, and matching references are stored
+/// in the `properties` of each result.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::classify::{Classification, Reference};
+/// use ssdeep::sarif::{to_sarif, ScanResult};
+///
+/// let results = vec![ScanResult {
+///     path: "bin/tool.exe".to_string(),
+///     hash: "3:aNRn:aNRn".to_string(),
+///     classification: Classification::KnownBad {
+///         reference: Reference {
+///             name: "dropper".to_string(),
+///             hash: "3:aNRn:aNRn".to_string(),
+///         },
+///         score: 100,
+///     },
+/// }];
+/// let sarif = to_sarif(&results);
+/// assert!(sarif.contains("\"ruleId\": \"known-bad\""));
+/// ```
+pub fn to_sarif(results: &[ScanResult]) -> String {
+    let results: Vec<_> = results.iter().filter_map(sarif_result).collect();
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "ssdeep-rs",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/s3rvac/ssdeep-rs",
+                    "rules": [
+                        {
+                            "id": KNOWN_BAD_RULE_ID,
+                            "shortDescription": {
+                                "text": "File matches a known-bad sample."
+                            },
+                            "defaultConfiguration": {"level": "error"}
+                        },
+                        {
+                            "id": SIMILAR_TO_BAD_RULE_ID,
+                            "shortDescription": {
+                                "text": "File is similar to a known-bad sample."
+                            },
+                            "defaultConfiguration": {"level": "warning"}
+                        }
+                    ]
+                }
+            },
+            "results": results
+        }]
+    });
+    serde_json::to_string_pretty(&log).unwrap_or_default()
+}
+
+fn sarif_result(result: &ScanResult) -> Option<Value> {
+    let (rule_id, level, reference, score, description) = match &result.classification {
+        Classification::KnownBad { reference, score } => (
+            KNOWN_BAD_RULE_ID,
+            "error",
+            reference,
+            score,
+            "matches known-bad sample",
+        ),
+        Classification::SimilarToBad { reference, score } => (
+            SIMILAR_TO_BAD_RULE_ID,
+            "warning",
+            reference,
+            score,
+            "is similar to known-bad sample",
+        ),
+        Classification::KnownGood { .. } | Classification::Unknown => return None,
+    };
+    Some(json!({
+        "ruleId": rule_id,
+        "level": level,
+        "message": {
+            "text": message(&result.path, description, reference, *score)
+        },
+        "locations": [{
+            "physicalLocation": {
+                "artifactLocation": {"uri": path_to_uri(&result.path)}
+            }
+        }],
+        "properties": {
+            "score": score,
+            "hash": result.hash,
+            "reference": reference.name,
+            "referenceHash": reference.hash
+        }
+    }))
+}
+
+fn message(path: &str, description: &str, reference: &Reference, score: u8) -> String {
+    format!(
+        "{} {} {} (score {}).",
+        path, description, reference.name, score
+    )
+}
+
+/// Converts a path into a relative URI reference (with forward slashes and
+/// percent-encoded special characters).
+fn path_to_uri(path: &str) -> String {
+    let mut uri = String::with_capacity(path.len());
+    for b in path.bytes() {
+        match b {
+            b'\\' => uri.push('/'),
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(b as char)
+            }
+            b => uri.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    uri
+}
@@ -0,0 +1,589 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! A native implementation of the scoring of fuzzy hashes.
+//!
+//! It follows `fuzzy_compare()` from the underlying C library, so the scores
+//! of the individual parts are the same as the scores computed by the C
+//! library.
+//!
+//! Hashes are parsed into fixed-size buffers, so a comparison does not
+//! allocate any memory on the heap.
+
+use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::ops::Deref;
+
+use super::Error;
+use super::Result;
+
+/// The maximal length of a part of a hash.
+pub(crate) const SPAMSUM_LENGTH: usize = 64;
+
+/// The length of the common substring needed for a part to match.
+pub(crate) const ROLLING_WINDOW: usize = 7;
+
+/// The minimal block size.
+pub(crate) const MIN_BLOCKSIZE: u64 = 3;
+
+/// A part of a parsed fuzzy hash, stored in a fixed-size buffer.
+#[derive(Clone, Copy)]
+pub(crate) struct Part {
+    bytes: [u8; SPAMSUM_LENGTH],
+    len: usize,
+}
+
+impl Deref for Part {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl PartialEq for Part {
+    fn eq(&self, other: &Part) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Part {}
+
+impl Hash for Part {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state);
+    }
+}
+
+impl fmt::Debug for Part {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", String::from_utf8_lossy(self))
+    }
+}
+
+/// A parsed fuzzy hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct ParsedHash {
+    /// The block size of the first part (the second part has double the block
+    /// size).
+    pub block_size: u64,
+    /// The two parts of the hash with sequences of more than three identical
+    /// characters shortened to three characters.
+    pub parts: [Part; 2],
+}
+
+impl ParsedHash {
+    /// Parses the given hash.
+    ///
+    /// The format is `block_size:part1:part2`, optionally followed by a comma
+    /// and arbitrary data (e.g. the name of the file), which is ignored.
+    pub fn parse(hash: &str) -> Result<ParsedHash> {
+        let (block_size, rest) = split_block_size(hash)?;
+        let block_size = block_size
+            .parse()
+            .map_err(|_| invalid_hash("invalid block size"))?;
+        ParsedHash::from_parts(block_size, rest)
+    }
+
+    /// Parses the parts of a hash (`part1:part2`, optionally followed by a
+    /// comma and arbitrary data) with the given block size.
+    fn from_parts(block_size: u64, parts: &str) -> Result<ParsedHash> {
+        let (part1, part2) = match parts.find(':') {
+            Some(i) => (&parts[..i], &parts[i + 1..]),
+            None => return Err(invalid_hash("missing second part")),
+        };
+        let part2 = match part2.find(',') {
+            Some(i) => &part2[..i],
+            None => part2,
+        };
+        Ok(ParsedHash {
+            block_size,
+            parts: [
+                eliminate_sequences(part1.as_bytes())?,
+                eliminate_sequences(part2.as_bytes())?,
+            ],
+        })
+    }
+}
+
+/// Splits the given hash into its block size (a non-empty sequence of digits)
+/// and the rest of the hash after the colon.
+fn split_block_size(hash: &str) -> Result<(&str, &str)> {
+    let (block_size, rest) = match hash.find(':') {
+        Some(i) => (&hash[..i], &hash[i + 1..]),
+        None => return Err(invalid_hash("missing block size")),
+    };
+    if block_size.is_empty() || !block_size.bytes().all(|c| c.is_ascii_digit()) {
+        return Err(invalid_hash("invalid block size"));
+    }
+    Ok((block_size, rest))
+}
+
+/// Computes the score of the two hashes in the same way as `fuzzy_compare()`
+/// from the underlying C library (`None` when `fuzzy_compare()` fails, i.e.
+/// when either of the hashes is malformed).
+#[cfg(feature = "native-compare")]
+pub(crate) fn compare(hash1: &str, hash2: &str) -> Option<u8> {
+    let bs1 = c_block_size(hash1)?;
+    let bs2 = c_block_size(hash2)?;
+    // Like fuzzy_compare(), return zero without checking the parts when the
+    // block sizes are not comparable.
+    if bs1 != bs2 && bs1.checked_mul(2) != Some(bs2) && (bs1 % 2 == 1 || bs1 / 2 != bs2) {
+        return Some(0);
+    }
+    // Like fuzzy_compare(), the parts start after the first colon, even when
+    // there is something else than the block size before it.
+    let rest1 = &hash1[hash1.find(':')? + 1..];
+    let rest2 = &hash2[hash2.find(':')? + 1..];
+    let h1 = ParsedHash::from_parts(bs1, rest1).ok()?;
+    let h2 = ParsedHash::from_parts(bs2, rest2).ok()?;
+    Some(compare_parsed(&h1, &h2))
+}
+
+/// Parses the block size at the start of the given hash in the same way as
+/// `sscanf("%lu")` (i.e. `strtoul()`), which is used by `fuzzy_compare()`.
+///
+/// Leading whitespace and a sign are accepted, a negative block size wraps
+/// around, and a block size that is too large saturates. Anything after the
+/// digits is ignored. It returns `None` when there are no digits.
+#[cfg(feature = "native-compare")]
+fn c_block_size(hash: &str) -> Option<u64> {
+    let hash = hash.trim_start_matches([' ', '\t', '\n', '\x0b', '\x0c', '\r']);
+    let (negative, hash) = match hash.as_bytes().first() {
+        Some(b'-') => (true, &hash[1..]),
+        Some(b'+') => (false, &hash[1..]),
+        _ => (false, hash),
+    };
+    let digits = hash.bytes().take_while(u8::is_ascii_digit).count();
+    if digits == 0 {
+        return None;
+    }
+    // The digits can fail to parse only when they overflow.
+    let block_size = match hash[..digits].parse::<u64>() {
+        Ok(block_size) if negative => block_size.wrapping_neg(),
+        Ok(block_size) => block_size,
+        Err(_) => u64::MAX,
+    };
+    Some(block_size)
+}
+
+/// Computes the score of the two parsed hashes as the maximum of the scores
+/// of the parts with a common block size (or 100 for identical hashes).
+#[cfg(feature = "native-compare")]
+pub(crate) fn compare_parsed(h1: &ParsedHash, h2: &ParsedHash) -> u8 {
+    if h1 == h2 {
+        return 100;
+    }
+    common_tracks(h1, h2)
+        .iter()
+        .filter_map(|track| track.as_ref().map(Track::score))
+        .max()
+        .unwrap_or(0)
+}
+
+/// A pair of parts of two hashes with a common block size.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Track<'a> {
+    /// The part of the first hash.
+    pub part1: &'a [u8],
+    /// The part of the second hash.
+    pub part2: &'a [u8],
+    /// The common block size.
+    pub block_size: u64,
+}
+
+impl Track<'_> {
+    /// Checks whether both parts are long enough to be compared.
+    pub fn is_comparable(&self) -> bool {
+        self.part1.len() >= ROLLING_WINDOW && self.part2.len() >= ROLLING_WINDOW
+    }
+
+    /// Computes the score of the parts.
+    pub fn score(&self) -> u8 {
+        score_parts(self.part1, self.part2, self.block_size)
+    }
+}
+
+/// Returns pairs of parts of the two hashes with a common block size.
+///
+/// The first item is the pair with the smaller block size, and the second
+/// item is the pair with the larger block size. An item is `None` when the
+/// hashes do not have parts with such a block size in common.
+pub(crate) fn common_tracks<'a>(h1: &'a ParsedHash, h2: &'a ParsedHash) -> [Option<Track<'a>>; 2] {
+    let track = |part1: &'a [u8], part2: &'a [u8], block_size| {
+        Some(Track {
+            part1,
+            part2,
+            block_size,
+        })
+    };
+    let (bs1, bs2) = (h1.block_size, h2.block_size);
+    if bs1 == bs2 {
+        [
+            track(&h1.parts[0], &h2.parts[0], bs1),
+            bs1.checked_mul(2)
+                .and_then(|double_bs| track(&h1.parts[1], &h2.parts[1], double_bs)),
+        ]
+    } else if bs1.checked_mul(2) == Some(bs2) {
+        [None, track(&h2.parts[0], &h1.parts[1], bs2)]
+    } else if bs1 % 2 == 0 && bs1 / 2 == bs2 {
+        [None, track(&h1.parts[0], &h2.parts[1], bs1)]
+    } else {
+        [None, None]
+    }
+}
+
+/// Computes the score of two parts with the given block size.
+fn score_parts(s1: &[u8], s2: &[u8], block_size: u64) -> u8 {
+    PositionArray::new(s1).score(s2, block_size)
+}
+
+/// A bit-parallel representation of a part: for every character, the bits
+/// for the positions at which the character occurs in the part are set.
+///
+/// It allows computing the score of one part against many other parts without
+/// processing the part again for each of them.
+#[derive(Clone)]
+pub(crate) struct PositionArray {
+    positions: [u64; 256],
+    len: usize,
+}
+
+impl PositionArray {
+    /// Creates a position array for the given part (which cannot be longer
+    /// than `SPAMSUM_LENGTH`).
+    pub fn new(part: &[u8]) -> Self {
+        debug_assert!(part.len() <= SPAMSUM_LENGTH);
+        let mut positions = [0; 256];
+        for (i, &c) in part.iter().enumerate() {
+            positions[c as usize] |= 1 << i;
+        }
+        PositionArray {
+            positions,
+            len: part.len(),
+        }
+    }
+
+    /// Computes the score of the part against the given part with the given
+    /// block size.
+    pub fn score(&self, other: &[u8], block_size: u64) -> u8 {
+        if !self.may_score(other) {
+            return 0;
+        }
+        self.score_from_distance(self.edit_distance(other), other.len(), block_size)
+    }
+
+    /// Checks whether the score against the given part can be non-zero, i.e.
+    /// whether both parts are long enough and have a common substring.
+    fn may_score(&self, other: &[u8]) -> bool {
+        self.len >= ROLLING_WINDOW
+            && other.len() >= ROLLING_WINDOW
+            && self.has_common_substring(other)
+    }
+
+    /// Computes the score from the edit distance against a part of the given
+    /// length with the given block size.
+    fn score_from_distance(&self, distance: usize, other_len: usize, block_size: u64) -> u8 {
+        // Scale the edit distance by the lengths of the parts and rescale it
+        // to the 0-100 scale, with 100 being the best match.
+        let scaled = (distance * SPAMSUM_LENGTH) / (self.len + other_len);
+        let score = 100 - (100 * scaled) / SPAMSUM_LENGTH;
+
+        // When the block size is small, do not exaggerate the match size.
+        let min_len = self.len.min(other_len) as u64;
+        if block_size < (99 + ROLLING_WINDOW as u64) / ROLLING_WINDOW as u64 * MIN_BLOCKSIZE {
+            (score as u64).min(block_size / MIN_BLOCKSIZE * min_len) as u8
+        } else {
+            score as u8
+        }
+    }
+
+    /// Checks whether the parts have a common substring of length
+    /// `ROLLING_WINDOW` (a bit-parallel search with Boyer-Moore-like skipping
+    /// over reversed parts).
+    fn has_common_substring(&self, other: &[u8]) -> bool {
+        let len = other.len();
+        let mut r = ROLLING_WINDOW - 1;
+        while r < len {
+            let l = r - (ROLLING_WINDOW - 1);
+            let mut i = len - 1 - r;
+            let mut d = self.positions[other[i] as usize];
+            while d != 0 {
+                r -= 1;
+                i += 1;
+                d = (d << 1) & self.positions[other[i] as usize];
+                if r == l && d != 0 {
+                    return true;
+                }
+            }
+            r += ROLLING_WINDOW;
+        }
+        false
+    }
+
+    /// Computes the edit distance of the parts, where insertions and removals
+    /// cost 1 and replacements cost 2 (a bit-parallel computation over the
+    /// positions of characters).
+    fn edit_distance(&self, other: &[u8]) -> usize {
+        let msb = 1 << (self.len - 1);
+        let mut cur = self.len;
+        let mut pv: u64 = !0;
+        let mut nv: u64 = 0;
+        for &c in other {
+            let mt = self.positions[c as usize];
+            let zd = ((mt & pv).wrapping_add(pv) ^ pv) | mt | nv;
+            let nh = pv & zd;
+            if nh & msb != 0 {
+                cur -= 1;
+            }
+            let x = nv | !(pv | zd) | (pv & !mt & 1);
+            let y = pv.wrapping_sub(nh) >> 1;
+            let ph = x.wrapping_add(y) ^ y;
+            if ph & msb != 0 {
+                cur += 1;
+            }
+            let x = (ph << 1) | 1;
+            nv = x & zd;
+            pv = (nh << 1) | !(x | zd) | (x & pv.wrapping_sub(nh));
+        }
+        cur
+    }
+
+    /// Computes the edit distances of the part against up to `LANES` other
+    /// parts at once (the distances for empty parts are meaningless).
+    fn edit_distances(&self, others: &[&[u8]; LANES]) -> [usize; LANES] {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return unsafe { self.edit_distances_avx2(others) };
+            }
+        }
+        let mut distances = [0; LANES];
+        for (distance, other) in distances.iter_mut().zip(others) {
+            if !other.is_empty() {
+                *distance = self.edit_distance(other);
+            }
+        }
+        distances
+    }
+
+    /// Computes the edit distances like `edit_distance()`, but in parallel
+    /// for all the parts, each in one 64-bit lane of AVX2 registers. A lane
+    /// is not updated after the end of its part.
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx2")]
+    unsafe fn edit_distances_avx2(&self, others: &[&[u8]; LANES]) -> [usize; LANES] {
+        use std::arch::x86_64::*;
+
+        let lens = others.map(|other| other.len() as i64);
+        let max_len = others.iter().map(|other| other.len()).max().unwrap_or(0);
+        let all = _mm256_set1_epi64x(-1);
+        let one = _mm256_set1_epi64x(1);
+        let msb = _mm256_set1_epi64x(1 << (self.len - 1));
+        let lens = _mm256_set_epi64x(lens[3], lens[2], lens[1], lens[0]);
+        let mut cur = _mm256_set1_epi64x(self.len as i64);
+        let mut pv = all;
+        let mut nv = _mm256_setzero_si256();
+        for i in 0..max_len {
+            let mut mt = [0; LANES];
+            for (mt, other) in mt.iter_mut().zip(others) {
+                if let Some(&c) = other.get(i) {
+                    *mt = self.positions[c as usize] as i64;
+                }
+            }
+            let mt = _mm256_set_epi64x(mt[3], mt[2], mt[1], mt[0]);
+            let active = _mm256_cmpgt_epi64(lens, _mm256_set1_epi64x(i as i64));
+
+            let zd = _mm256_or_si256(
+                _mm256_or_si256(
+                    _mm256_xor_si256(_mm256_add_epi64(_mm256_and_si256(mt, pv), pv), pv),
+                    mt,
+                ),
+                nv,
+            );
+            let nh = _mm256_and_si256(pv, zd);
+            let x = _mm256_or_si256(
+                _mm256_or_si256(nv, _mm256_andnot_si256(_mm256_or_si256(pv, zd), all)),
+                _mm256_and_si256(_mm256_andnot_si256(mt, pv), one),
+            );
+            let pv_minus_nh = _mm256_sub_epi64(pv, nh);
+            let y = _mm256_srli_epi64::<1>(pv_minus_nh);
+            let ph = _mm256_xor_si256(_mm256_add_epi64(x, y), y);
+            // The comparisons yield -1 for set bits, so adding them decreases
+            // the distance and subtracting them increases it.
+            let dec = _mm256_cmpeq_epi64(_mm256_and_si256(nh, msb), msb);
+            let inc = _mm256_cmpeq_epi64(_mm256_and_si256(ph, msb), msb);
+            cur = _mm256_add_epi64(cur, _mm256_and_si256(dec, active));
+            cur = _mm256_sub_epi64(cur, _mm256_and_si256(inc, active));
+            let x = _mm256_or_si256(_mm256_slli_epi64::<1>(ph), one);
+            let new_nv = _mm256_and_si256(x, zd);
+            let new_pv = _mm256_or_si256(
+                _mm256_or_si256(
+                    _mm256_slli_epi64::<1>(nh),
+                    _mm256_andnot_si256(_mm256_or_si256(x, zd), all),
+                ),
+                _mm256_and_si256(x, pv_minus_nh),
+            );
+            nv = _mm256_blendv_epi8(nv, new_nv, active);
+            pv = _mm256_blendv_epi8(pv, new_pv, active);
+        }
+        let mut distances = [0i64; LANES];
+        _mm256_storeu_si256(distances.as_mut_ptr() as *mut __m256i, cur);
+        distances.map(|distance| distance as usize)
+    }
+}
+
+/// The number of parts whose edit distances against a part are computed at
+/// once.
+const LANES: usize = 4;
+
+/// A pending computation of the score of a part of a query.
+#[derive(Clone, Copy)]
+struct PendingTrack<'a> {
+    /// The index of the score.
+    index: usize,
+    part: &'a [u8],
+    block_size: u64,
+}
+
+const NO_TRACK: PendingTrack<'static> = PendingTrack {
+    index: 0,
+    part: &[],
+    block_size: 0,
+};
+
+/// Scores a query against many hashes, computing the edit distances of the
+/// parts of the query against several parts at once (via AVX2 when it is
+/// available).
+///
+/// Scores are stored into a slice of scores once the edit distances are
+/// computed, so all the scores are complete only after `finish()`.
+pub(crate) struct BatchScorer<'a> {
+    query: ParsedHash,
+    positions: [PositionArray; 2],
+    pending: [[PendingTrack<'a>; LANES]; 2],
+    pending_len: [usize; 2],
+}
+
+impl<'a> BatchScorer<'a> {
+    pub fn new(query: &ParsedHash) -> Self {
+        BatchScorer {
+            query: *query,
+            positions: [
+                PositionArray::new(&query.parts[0]),
+                PositionArray::new(&query.parts[1]),
+            ],
+            pending: [[NO_TRACK; LANES]; 2],
+            pending_len: [0; 2],
+        }
+    }
+
+    /// Schedules the scoring of the query against the hash with the given
+    /// block size and parts, whose score is stored into `scores[index]`
+    /// (which has to be zero).
+    pub fn push(&mut self, index: usize, block_size: u64, parts: [&'a [u8]; 2], scores: &mut [u8]) {
+        let bs = self.query.block_size;
+        if block_size == bs {
+            if parts[0] == &self.query.parts[0][..] && parts[1] == &self.query.parts[1][..] {
+                scores[index] = 100;
+                return;
+            }
+            self.push_track(0, index, parts[0], bs, scores);
+            if let Some(double_bs) = bs.checked_mul(2) {
+                self.push_track(1, index, parts[1], double_bs, scores);
+            }
+        } else if bs.checked_mul(2) == Some(block_size) {
+            self.push_track(1, index, parts[0], block_size, scores);
+        } else if bs.is_multiple_of(2) && bs / 2 == block_size {
+            self.push_track(0, index, parts[1], bs, scores);
+        }
+    }
+
+    /// Stores all the pending scores.
+    pub fn finish(&mut self, scores: &mut [u8]) {
+        self.flush(0, scores);
+        self.flush(1, scores);
+    }
+
+    fn push_track(
+        &mut self,
+        query_part: usize,
+        index: usize,
+        part: &'a [u8],
+        block_size: u64,
+        scores: &mut [u8],
+    ) {
+        if !self.positions[query_part].may_score(part) {
+            return;
+        }
+        let len = &mut self.pending_len[query_part];
+        self.pending[query_part][*len] = PendingTrack {
+            index,
+            part,
+            block_size,
+        };
+        *len += 1;
+        if *len == LANES {
+            self.flush(query_part, scores);
+        }
+    }
+
+    fn flush(&mut self, query_part: usize, scores: &mut [u8]) {
+        let len = self.pending_len[query_part];
+        if len == 0 {
+            return;
+        }
+        let tracks = &mut self.pending[query_part];
+        for track in &mut tracks[len..] {
+            *track = NO_TRACK;
+        }
+        let positions = &self.positions[query_part];
+        let distances = positions.edit_distances(&tracks.map(|track| track.part));
+        for (track, &distance) in tracks[..len].iter().zip(&distances) {
+            let score = positions.score_from_distance(distance, track.part.len(), track.block_size);
+            scores[track.index] = scores[track.index].max(score);
+        }
+        self.pending_len[query_part] = 0;
+    }
+}
+
+/// Shortens sequences of more than three identical characters to three
+/// characters, which contain very little information.
+pub(crate) fn eliminate_sequences(part: &[u8]) -> Result<Part> {
+    let mut result = Part {
+        bytes: [0; SPAMSUM_LENGTH],
+        len: 0,
+    };
+    for (i, &c) in part.iter().enumerate() {
+        if i >= 3 && part[i - 1] == c && part[i - 2] == c && part[i - 3] == c {
+            continue;
+        }
+        if result.len == SPAMSUM_LENGTH {
+            return Err(invalid_hash("too long part"));
+        }
+        result.bytes[result.len] = c;
+        result.len += 1;
+    }
+    Ok(result)
+}
+
+pub(crate) fn invalid_hash(reason: &str) -> Error {
+    Error::InvalidHash {
+        reason: reason.to_string(),
+    }
+}
@@ -0,0 +1,100 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Deterministic sharding of fuzzy hashes for distributed processing.
+
+use super::score::ParsedHash;
+use super::score::ROLLING_WINDOW;
+use super::Result;
+
+/// Returns the shards (between `0` and `n_shards - 1`) to which the given
+/// fuzzy hash has to be sent so that it can be compared with every hash that
+/// it can possibly match.
+///
+/// Every part of the hash is split into n-grams of seven characters, and each
+/// n-gram, together with the block size of the part, determines one shard. A
+/// hash is thus assigned to one or more shards (and to a single shard
+/// determined by the whole hash when none of its parts is long enough to have
+/// an n-gram).
+///
+/// The guarantee is as follows: when [`compare()`](fn.compare.html) returns a
+/// non-zero score for two hashes, the shards returned for them have at least
+/// one shard in common. This holds because a non-zero score requires the
+/// hashes to be identical or to have a common substring of seven characters
+/// in parts with the same block size. To report every matching pair only
+/// once, a worker can report a pair only when its shard is the smallest shard
+/// common to both hashes.
+///
+/// The assignment depends only on the hash and `n_shards`, so it is the same
+/// on all machines and in all versions of this crate with the same major
+/// version.
+///
+/// When the hash is invalid, it returns an error.
+///
+/// # Panics
+///
+/// When `n_shards` is zero.
+///
+/// # Examples
+///
+/// ```
+/// let shards = ssdeep::shards_for("3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C", 16).unwrap();
+/// assert!(!shards.is_empty());
+/// assert!(shards.iter().all(|&shard| shard < 16));
+/// ```
+pub fn shards_for(hash: &str, n_shards: usize) -> Result<Vec<usize>> {
+    assert!(n_shards > 0, "the number of shards has to be positive");
+
+    let hash = ParsedHash::parse(hash)?;
+    let block_sizes = [Some(hash.block_size), hash.block_size.checked_mul(2)];
+    let mut shards = Vec::new();
+    for (part, block_size) in hash.parts.iter().zip(&block_sizes) {
+        let block_size = match block_size {
+            Some(block_size) => *block_size,
+            None => continue,
+        };
+        for ngram in part.windows(ROLLING_WINDOW) {
+            let key = fnv1a(fnv1a(FNV_OFFSET_BASIS, &block_size.to_le_bytes()), ngram);
+            shards.push((key % n_shards as u64) as usize);
+        }
+    }
+    if shards.is_empty() {
+        let mut key = fnv1a(FNV_OFFSET_BASIS, &hash.block_size.to_le_bytes());
+        for part in &hash.parts {
+            key = fnv1a(key, part);
+            key = fnv1a(key, b":");
+        }
+        shards.push((key % n_shards as u64) as usize);
+    }
+    shards.sort_unstable();
+    shards.dedup();
+    Ok(shards)
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Computes the 64-bit FNV-1a hash of the given bytes (which is stable,
+/// unlike the hashers from the standard library).
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &b in bytes {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
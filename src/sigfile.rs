@@ -0,0 +1,281 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Lines of signature files in the format used by the `ssdeep` tool.
+//!
+//! A signature file starts with a [header](constant.HEADER.html), followed by
+//! one line per file in the form `hash,"filename"`, for example:
+//!
+//! ```text
+//! ssdeep,1.1--blocksize:hash:hash,filename
+//! 3:aNRn:aNRn,"/home/user/hello.txt"
+//! ```
+//!
+//! Quotation marks in filenames are escaped with a backslash (`\"`). No other
+//! characters are escaped (in particular, backslashes are not, so Windows
+//! paths are written as they are). Commas need no escaping as the filename is
+//! quoted. Filenames with newlines cannot be represented in this format, so
+//! they are rejected by the writing functions.
+//!
+//! Whole signature files are read and written via
+//! [`SignatureFile`](struct.SignatureFile.html).
+
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+
+use super::score::ParsedHash;
+use super::Error;
+use super::Result;
+
+/// The header of signature files written by the current version of the
+/// `ssdeep` tool.
+pub const HEADER: &str = "ssdeep,1.1--blocksize:hash:hash,filename";
+
+/// The header of signature files written by older versions of the `ssdeep`
+/// tool.
+pub const HEADER_V1_0: &str = "ssdeep,1.0--blocksize:hash:hash,filename";
+
+/// Escapes the given filename so that it can be put between quotation marks
+/// in a signature file.
+///
+/// When the filename contains a newline or carriage return, it returns
+/// [`Error::InvalidFilename`](../enum.Error.html#variant.InvalidFilename).
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::sigfile::escape_filename;
+///
+/// assert_eq!(escape_filename(r#"say "hi", bye"#).unwrap(), r#"say \"hi\", bye"#);
+/// ```
+pub fn escape_filename(filename: &str) -> Result<String> {
+    if filename.contains(['\n', '\r']) {
+        return Err(Error::InvalidFilename {
+            reason: "filenames with newlines cannot be represented".to_string(),
+        });
+    }
+    Ok(filename.replace('"', "\\\""))
+}
+
+/// Unescapes a filename that was escaped via
+/// [`escape_filename()`](fn.escape_filename.html).
+///
+/// A quotation mark that is not escaped makes the filename ambiguous (the
+/// `ssdeep` tool never writes such filenames), so it returns
+/// [`Error::InvalidSignatureLine`](../enum.Error.html#variant.InvalidSignatureLine)
+/// instead of guessing where the filename ends.
+pub fn unescape_filename(escaped: &str) -> Result<String> {
+    let mut filename = String::with_capacity(escaped.len());
+    let mut chars = escaped.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'"') => {
+                chars.next();
+                filename.push('"');
+            }
+            '"' => return Err(invalid_line("unescaped quotation mark in filename")),
+            '\n' | '\r' => return Err(invalid_line("newline in filename")),
+            c => filename.push(c),
+        }
+    }
+    Ok(filename)
+}
+
+/// Formats a line of a signature file (without the trailing newline) for the
+/// given hash and filename.
+///
+/// When the filename contains a newline or carriage return, it returns
+/// [`Error::InvalidFilename`](../enum.Error.html#variant.InvalidFilename).
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::sigfile::format_line;
+///
+/// let line = format_line("3:aNRn:aNRn", "hello, \"world\".txt").unwrap();
+/// assert_eq!(line, r#"3:aNRn:aNRn,"hello, \"world\".txt""#);
+/// ```
+pub fn format_line(hash: &str, filename: &str) -> Result<String> {
+    Ok(format!("{},\"{}\"", hash, escape_filename(filename)?))
+}
+
+/// Parses a line of a signature file and returns the hash and the unescaped
+/// filename.
+///
+/// The parsing is strict: the line has to consist of a valid hash, a comma,
+/// and a filename between quotation marks, with all quotation marks inside
+/// the filename escaped. Otherwise, it returns
+/// [`Error::InvalidSignatureLine`](../enum.Error.html#variant.InvalidSignatureLine).
+/// A trailing newline (`\n` or `\r\n`) is ignored.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::sigfile::parse_line;
+///
+/// let (hash, filename) = parse_line(r#"3:aNRn:aNRn,"hello, \"world\".txt""#).unwrap();
+/// assert_eq!(hash, "3:aNRn:aNRn");
+/// assert_eq!(filename, "hello, \"world\".txt");
+///
+/// assert!(parse_line(r#"3:aNRn:aNRn,"a"b""#).is_err());
+/// ```
+pub fn parse_line(line: &str) -> Result<(String, String)> {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let (hash, quoted) = match line.find(',') {
+        Some(i) => (&line[..i], &line[i + 1..]),
+        None => return Err(invalid_line("missing filename")),
+    };
+    if hash.contains('"') {
+        return Err(invalid_line("quotation mark in hash"));
+    }
+    ParsedHash::parse(hash).map_err(|_| invalid_line("invalid hash"))?;
+    if quoted.len() < 2 || !quoted.starts_with('"') || !quoted.ends_with('"') {
+        return Err(invalid_line("filename is not between quotation marks"));
+    }
+    let filename = unescape_filename(&quoted[1..quoted.len() - 1])?;
+    Ok((hash.to_string(), filename))
+}
+
+/// A signature in a signature file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    /// Fuzzy hash of the file.
+    pub hash: String,
+    /// Name of the file (unescaped).
+    pub filename: String,
+}
+
+/// A signature file in the format used by the `ssdeep` tool.
+///
+/// The signatures are kept in the order in which they were read or added, and
+/// filenames are escaped and unescaped in the same way as by the `ssdeep`
+/// tool, so files written by the tool can be read and files written by this
+/// type can be used by the tool (e.g. via `ssdeep -m`).
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::sigfile::SignatureFile;
+///
+/// let mut sigfile = SignatureFile::new();
+/// sigfile.push("3:aNRn:aNRn", "say \"hi\".txt");
+/// let mut written = Vec::new();
+/// sigfile.write(&mut written).unwrap();
+/// assert_eq!(
+///     String::from_utf8(written.clone()).unwrap(),
+///     "ssdeep,1.1--blocksize:hash:hash,filename\n3:aNRn:aNRn,\"say \\\"hi\\\".txt\"\n"
+/// );
+/// assert_eq!(SignatureFile::read(&written[..]).unwrap(), sigfile);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SignatureFile {
+    /// Signatures in the file.
+    pub signatures: Vec<Signature>,
+}
+
+impl SignatureFile {
+    /// Creates an empty signature file.
+    pub fn new() -> Self {
+        SignatureFile::default()
+    }
+
+    /// Appends a signature with the given hash and filename.
+    pub fn push(&mut self, hash: &str, filename: &str) {
+        self.signatures.push(Signature {
+            hash: hash.to_string(),
+            filename: filename.to_string(),
+        });
+    }
+
+    /// Reads a signature file from the given reader.
+    ///
+    /// The file has to start with [`HEADER`](constant.HEADER.html) or
+    /// [`HEADER_V1_0`](constant.HEADER_V1_0.html), followed by lines parsed
+    /// via [`parse_line()`](fn.parse_line.html). When the header or any of the
+    /// lines is invalid, it returns
+    /// [`Error::InvalidSignatureLine`](../enum.Error.html#variant.InvalidSignatureLine)
+    /// with the number of the line in the reason. When reading fails, it
+    /// returns [`Error::Io`](../enum.Error.html#variant.Io).
+    pub fn read<R: BufRead>(reader: R) -> Result<Self> {
+        let mut lines = reader.lines();
+        match lines.next() {
+            Some(line) => {
+                let line = line?;
+                if line != HEADER && line != HEADER_V1_0 {
+                    return Err(invalid_line("line 1: invalid header"));
+                }
+            }
+            None => return Err(invalid_line("missing header")),
+        }
+        let mut sigfile = SignatureFile::new();
+        for (i, line) in lines.enumerate() {
+            let (hash, filename) = parse_line(&line?).map_err(|e| match e {
+                Error::InvalidSignatureLine { reason } => {
+                    invalid_line(&format!("line {}: {}", i + 2, reason))
+                }
+                e => e,
+            })?;
+            sigfile.signatures.push(Signature { hash, filename });
+        }
+        Ok(sigfile)
+    }
+
+    /// Reads a signature file from the given path.
+    ///
+    /// See [`read()`](#method.read) for more details.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        SignatureFile::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Writes the signature file (the current header and one line per
+    /// signature, each terminated by a newline) into the given writer.
+    ///
+    /// When a filename cannot be written, it returns
+    /// [`Error::InvalidFilename`](../enum.Error.html#variant.InvalidFilename),
+    /// and when writing fails, it returns
+    /// [`Error::Io`](../enum.Error.html#variant.Io).
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "{}", HEADER)?;
+        for signature in &self.signatures {
+            writeln!(
+                writer,
+                "{}",
+                format_line(&signature.hash, &signature.filename)?
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the signature file into the given path.
+    ///
+    /// See [`write()`](#method.write) for more details.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.write(BufWriter::new(File::create(path)?))
+    }
+}
+
+pub(crate) fn invalid_line(reason: &str) -> Error {
+    Error::InvalidSignatureLine {
+        reason: reason.to_string(),
+    }
+}
@@ -0,0 +1,374 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! A native implementation of the computation of fuzzy hashes.
+//!
+//! It follows `fuzzy_update()` and `fuzzy_digest()` from the underlying C
+//! library (the rolling hash, which determines the ends of pieces of the
+//! input, and the FNV-based hashes of the pieces for block sizes from 3 to
+//! `3 * 2^30`), so the fuzzy hashes are identical to the hashes computed by
+//! the C library.
+
+use super::score::MIN_BLOCKSIZE;
+use super::score::ROLLING_WINDOW;
+use super::score::SPAMSUM_LENGTH;
+
+/// The initial value of the FNV-based hashes of pieces.
+const HASH_INIT: u8 = 0x27;
+
+/// The number of block sizes for which hashes are computed.
+const NUM_BLOCKHASHES: usize = 31;
+
+/// The maximal total size of the input.
+const TOTAL_SIZE_MAX: u64 = (MIN_BLOCKSIZE << (NUM_BLOCKHASHES - 1)) * SPAMSUM_LENGTH as u64;
+
+/// The characters of hashes of pieces.
+const B64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Precomputed 6-bit FNV hashes: `SUM_TABLE[h][c]` is the hash `h` updated
+/// by the lower 6 bits of the byte `c`.
+const SUM_TABLE: [[u8; 64]; 64] = sum_table();
+
+const fn sum_table() -> [[u8; 64]; 64] {
+    let mut table = [[0; 64]; 64];
+    let mut h = 0;
+    while h < 64 {
+        let mut c = 0;
+        while c < 64 {
+            table[h][c] = (((h as u32).wrapping_mul(0x0100_0193) ^ c as u32) & 0x3f) as u8;
+            c += 1;
+        }
+        h += 1;
+    }
+    table
+}
+
+fn sum_hash(c: u8, h: u8) -> u8 {
+    SUM_TABLE[h as usize][(c & 0x3f) as usize]
+}
+
+fn block_size(index: usize) -> u64 {
+    MIN_BLOCKSIZE << index
+}
+
+/// A rolling hash of the last `ROLLING_WINDOW` bytes, based on the Adler
+/// checksum.
+#[derive(Debug, Clone, Copy, Default)]
+struct RollState {
+    window: [u8; ROLLING_WINDOW],
+    /// The sum of the bytes in the window.
+    h1: u32,
+    /// The sum of the bytes in the window multiplied by their positions.
+    h2: u32,
+    /// A shift/xor-based hash, needed for large block sizes.
+    h3: u32,
+    n: usize,
+}
+
+impl RollState {
+    fn update(&mut self, c: u8) {
+        let c32 = u32::from(c);
+        self.h2 = self
+            .h2
+            .wrapping_sub(self.h1)
+            .wrapping_add(ROLLING_WINDOW as u32 * c32);
+        self.h1 = self
+            .h1
+            .wrapping_add(c32)
+            .wrapping_sub(u32::from(self.window[self.n]));
+        self.window[self.n] = c;
+        self.n = (self.n + 1) % ROLLING_WINDOW;
+        self.h3 = (self.h3 << 5) ^ c32;
+    }
+
+    fn sum(&self) -> u32 {
+        self.h1.wrapping_add(self.h2).wrapping_add(self.h3)
+    }
+}
+
+/// The state of the hash for a single block size.
+#[derive(Debug, Clone, Copy)]
+struct BlockHash {
+    /// The characters of the hash, followed by a zero byte when there is room
+    /// for more characters.
+    digest: [u8; SPAMSUM_LENGTH],
+    /// The number of characters of the hash.
+    dindex: usize,
+    /// The last character of the hash truncated to `SPAMSUM_LENGTH / 2`
+    /// characters.
+    halfdigest: u8,
+    /// The hash of the current piece.
+    h: u8,
+    /// The hash of the current piece that is not reset after the hash has
+    /// `SPAMSUM_LENGTH / 2` characters.
+    halfh: u8,
+}
+
+impl BlockHash {
+    fn new(h: u8, halfh: u8) -> Self {
+        BlockHash {
+            digest: [0; SPAMSUM_LENGTH],
+            dindex: 0,
+            halfdigest: 0,
+            h,
+            halfh,
+        }
+    }
+}
+
+/// The state of the computation of a fuzzy hash.
+#[derive(Debug, Clone)]
+pub(crate) struct State {
+    total_size: u64,
+    fixed_size: Option<u64>,
+    reduce_border: u64,
+    bh_start: usize,
+    bh_end: usize,
+    bh_end_limit: usize,
+    rollmask: u32,
+    bh: [BlockHash; NUM_BLOCKHASHES],
+    roll: RollState,
+    /// The hash of the current piece for the block size after the largest
+    /// one, once it is needed.
+    lasth: Option<u8>,
+}
+
+impl State {
+    pub fn new() -> Self {
+        let mut bh = [BlockHash::new(0, 0); NUM_BLOCKHASHES];
+        bh[0] = BlockHash::new(HASH_INIT, HASH_INIT);
+        State {
+            total_size: 0,
+            fixed_size: None,
+            reduce_border: MIN_BLOCKSIZE * SPAMSUM_LENGTH as u64,
+            bh_start: 0,
+            bh_end: 1,
+            bh_end_limit: NUM_BLOCKHASHES - 1,
+            rollmask: 0,
+            bh,
+            roll: RollState::default(),
+            lasth: None,
+        }
+    }
+
+    /// Sets the total length of the input (`false` when it is too large or
+    /// another length has already been set).
+    pub fn set_total_input_length(&mut self, len: u64) -> bool {
+        if len > TOTAL_SIZE_MAX || self.fixed_size.is_some_and(|fixed| fixed != len) {
+            return false;
+        }
+        self.fixed_size = Some(len);
+        let mut bi = 0;
+        while block_size(bi) * (SPAMSUM_LENGTH as u64) < len {
+            bi += 1;
+            if bi == NUM_BLOCKHASHES - 2 {
+                break;
+            }
+        }
+        self.bh_end_limit = bi + 1;
+        true
+    }
+
+    pub fn update(&mut self, buf: &[u8]) {
+        let len = buf.len() as u64;
+        if len > TOTAL_SIZE_MAX || TOTAL_SIZE_MAX - len < self.total_size {
+            self.total_size = TOTAL_SIZE_MAX + 1;
+        } else {
+            self.total_size += len;
+        }
+        for &c in buf {
+            self.step(c);
+        }
+    }
+
+    /// Returns the fuzzy hash (`None` when the input is too large or its
+    /// length differs from the set length).
+    pub fn digest(&self, elimseq: bool, notrunc: bool) -> Option<String> {
+        if self.total_size > TOTAL_SIZE_MAX
+            || self
+                .fixed_size
+                .is_some_and(|fixed| fixed != self.total_size)
+        {
+            return None;
+        }
+
+        // Guess the block size from the size of the input and adapt it to the
+        // actual lengths of the hashes.
+        let mut bi = self.bh_start;
+        while block_size(bi) * (SPAMSUM_LENGTH as u64) < self.total_size {
+            bi += 1;
+        }
+        bi = bi.min(self.bh_end - 1);
+        while bi > self.bh_start && self.bh[bi].dindex < SPAMSUM_LENGTH / 2 {
+            bi -= 1;
+        }
+
+        let h = self.roll.sum();
+        let mut result = format!("{}:", block_size(bi)).into_bytes();
+        let mut part = Part::new(&mut result, elimseq);
+        let bh = &self.bh[bi];
+        part.extend(&bh.digest[..bh.dindex]);
+        if h != 0 {
+            part.push(B64[bh.h as usize]);
+        } else if bh.digest[bh.dindex] != 0 {
+            part.push(bh.digest[bh.dindex]);
+        }
+        result.push(b':');
+
+        let mut part = Part::new(&mut result, elimseq);
+        if bi < self.bh_end - 1 {
+            let bh = &self.bh[bi + 1];
+            let len = if notrunc {
+                bh.dindex
+            } else {
+                bh.dindex.min(SPAMSUM_LENGTH / 2 - 1)
+            };
+            part.extend(&bh.digest[..len]);
+            if h != 0 {
+                part.push(B64[if notrunc { bh.h } else { bh.halfh } as usize]);
+            } else {
+                let c = if notrunc {
+                    bh.digest[bh.dindex]
+                } else {
+                    bh.halfdigest
+                };
+                if c != 0 {
+                    part.push(c);
+                }
+            }
+        } else if h != 0 {
+            let last = match self.lasth {
+                Some(lasth) if bi != 0 => lasth,
+                _ => bh.h,
+            };
+            part.push(B64[last as usize]);
+        }
+        Some(result.into_iter().map(char::from).collect())
+    }
+
+    fn step(&mut self, c: u8) {
+        // Update the rolling hash and the hashes of the current pieces. When
+        // the rolling hash hits a reset point for a block size, the hash of
+        // the piece is appended to the hash for the block size.
+        self.roll.update(c);
+        let horg = self.roll.sum().wrapping_add(1);
+        let mut h = horg / MIN_BLOCKSIZE as u32;
+        for bh in &mut self.bh[self.bh_start..self.bh_end] {
+            bh.h = sum_hash(c, bh.h);
+            bh.halfh = sum_hash(c, bh.halfh);
+        }
+        if let Some(lasth) = &mut self.lasth {
+            *lasth = sum_hash(c, *lasth);
+        }
+
+        if horg == 0 || h & self.rollmask != 0 || !horg.is_multiple_of(MIN_BLOCKSIZE as u32) {
+            return;
+        }
+        h >>= self.bh_start;
+        let mut i = self.bh_start;
+        loop {
+            if self.bh[i].dindex == 0 {
+                self.try_fork_blockhash();
+            }
+            let bh = &mut self.bh[i];
+            bh.digest[bh.dindex] = B64[bh.h as usize];
+            bh.halfdigest = B64[bh.halfh as usize];
+            if bh.dindex < SPAMSUM_LENGTH - 1 {
+                // Reset the hash of the piece only when there is room for
+                // more characters, so the last pieces of the input are
+                // combined into one.
+                bh.dindex += 1;
+                bh.digest[bh.dindex] = 0;
+                bh.h = HASH_INIT;
+                if bh.dindex < SPAMSUM_LENGTH / 2 {
+                    bh.halfh = HASH_INIT;
+                    bh.halfdigest = 0;
+                }
+            } else {
+                self.try_reduce_blockhash();
+            }
+            if h & 1 != 0 {
+                break;
+            }
+            h >>= 1;
+            i += 1;
+            if i >= self.bh_end {
+                break;
+            }
+        }
+    }
+
+    /// Starts computing the hash for the next block size.
+    fn try_fork_blockhash(&mut self) {
+        let last = self.bh[self.bh_end - 1];
+        if self.bh_end <= self.bh_end_limit {
+            self.bh[self.bh_end] = BlockHash::new(last.h, last.halfh);
+            self.bh_end += 1;
+        } else if self.bh_end == NUM_BLOCKHASHES && self.lasth.is_none() {
+            self.lasth = Some(last.h);
+        }
+    }
+
+    /// Stops computing the hash for the smallest block size when it is clear
+    /// that it will not be used.
+    fn try_reduce_blockhash(&mut self) {
+        if self.bh_end - self.bh_start < 2 {
+            return;
+        }
+        if self.reduce_border >= self.fixed_size.unwrap_or(self.total_size) {
+            return;
+        }
+        if self.bh[self.bh_start + 1].dindex < SPAMSUM_LENGTH / 2 {
+            return;
+        }
+        self.bh_start += 1;
+        self.reduce_border *= 2;
+        self.rollmask = self.rollmask * 2 + 1;
+    }
+}
+
+/// A part of a fuzzy hash that is being appended to the hash, optionally
+/// with sequences of more than three identical characters eliminated.
+struct Part<'a> {
+    result: &'a mut Vec<u8>,
+    start: usize,
+    elimseq: bool,
+}
+
+impl<'a> Part<'a> {
+    fn new(result: &'a mut Vec<u8>, elimseq: bool) -> Self {
+        let start = result.len();
+        Part {
+            result,
+            start,
+            elimseq,
+        }
+    }
+
+    fn push(&mut self, c: u8) {
+        let len = self.result.len();
+        if !self.elimseq || len - self.start < 3 || self.result[len - 3..].iter().any(|&p| p != c) {
+            self.result.push(c);
+        }
+    }
+
+    fn extend(&mut self, chars: &[u8]) {
+        for &c in chars {
+            self.push(c);
+        }
+    }
+}
@@ -0,0 +1,283 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! A persistent store of labeled fuzzy hashes in a
+//! [SQLite](https://www.sqlite.org/) database.
+//!
+//! This module is available only when the `sqlite` feature is enabled. The
+//! database is accessed via the [rusqlite](https://crates.io/crates/rusqlite)
+//! crate, with SQLite bundled, so no system library is needed.
+//!
+//! # Schema
+//!
+//! Hashes are stored in the `hashes` table (label, hash, and normalized hash)
+//! and the 7-grams of their normalized parts together with the block sizes of
+//! the parts are stored in the `ngrams` table, which is an inverted index of
+//! the hashes, as in [`FuzzyHashIndex`](../index/struct.FuzzyHashIndex.html).
+//! Block sizes are stored as 64-bit signed integers with the same bits as the
+//! unsigned block sizes. The tables are created when a database is opened
+//! (unless they already exist).
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use rusqlite::params;
+use rusqlite::Connection;
+use rusqlite::OptionalExtension;
+
+use super::compare;
+use super::Error;
+use super::FuzzyHash;
+use super::Result;
+
+/// The statements creating the tables and indexes of the store.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS hashes (
+        id INTEGER PRIMARY KEY,
+        label TEXT NOT NULL UNIQUE,
+        hash TEXT NOT NULL,
+        normalized TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS hashes_normalized ON hashes (normalized);
+    CREATE TABLE IF NOT EXISTS ngrams (
+        block_size INTEGER NOT NULL,
+        ngram TEXT NOT NULL,
+        hash_id INTEGER NOT NULL REFERENCES hashes (id) ON DELETE CASCADE,
+        PRIMARY KEY (block_size, ngram, hash_id)
+    ) WITHOUT ROWID;
+    CREATE INDEX IF NOT EXISTS ngrams_hash_id ON ngrams (hash_id);
+";
+
+/// A match found in a [`SqliteHashStore`](struct.SqliteHashStore.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreMatch {
+    /// Label of the matched hash.
+    pub label: String,
+    /// The matched hash.
+    pub hash: String,
+    /// The score between the query and the matched hash.
+    pub score: u8,
+}
+
+/// A persistent store of fuzzy hashes identified by unique labels (e.g. names
+/// or SHA-256 hashes of files), with an n-gram index for searching for similar
+/// hashes.
+///
+/// When a database operation fails, the methods return
+/// [`Error::DatabaseFailed`](../enum.Error.html#variant.DatabaseFailed).
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::sqlite::SqliteHashStore;
+///
+/// let mut store = SqliteHashStore::open_in_memory().unwrap();
+/// store
+///     .upsert("dropper.exe", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C")
+///     .unwrap();
+/// store.upsert("hello.txt", "3:aNRn:aNRn").unwrap();
+///
+/// let matches = store
+///     .search("3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx", 0)
+///     .unwrap();
+/// assert_eq!(matches.len(), 1);
+/// assert_eq!(matches[0].label, "dropper.exe");
+/// assert_eq!(matches[0].score, 22);
+/// ```
+#[derive(Debug)]
+pub struct SqliteHashStore {
+    connection: Connection,
+}
+
+impl SqliteHashStore {
+    /// Opens the store in the given SQLite file (which is created when it
+    /// does not exist).
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        SqliteHashStore::with_connection(Connection::open(path).map_err(database_failed)?)
+    }
+
+    /// Opens a new store in memory (e.g. for tests).
+    pub fn open_in_memory() -> Result<Self> {
+        SqliteHashStore::with_connection(Connection::open_in_memory().map_err(database_failed)?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self> {
+        connection
+            .execute_batch("PRAGMA foreign_keys = ON;")
+            .map_err(database_failed)?;
+        connection.execute_batch(SCHEMA).map_err(database_failed)?;
+        Ok(SqliteHashStore { connection })
+    }
+
+    /// Returns the number of stored hashes.
+    pub fn len(&self) -> Result<usize> {
+        let len: i64 = self
+            .connection
+            .query_row("SELECT COUNT(*) FROM hashes", [], |row| row.get(0))
+            .map_err(database_failed)?;
+        Ok(len as usize)
+    }
+
+    /// Checks whether the store contains no hashes.
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Stores the hash with the given label, replacing the hash previously
+    /// stored with the label (if any).
+    ///
+    /// When the hash is invalid, it returns
+    /// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash).
+    pub fn upsert(&mut self, label: &str, hash: &str) -> Result<()> {
+        self.upsert_many(vec![(label, hash)])
+    }
+
+    /// Stores the given hashes with their labels (`(label, hash)` pairs) in a
+    /// single transaction, replacing the hashes previously stored with the
+    /// labels.
+    ///
+    /// This is much faster than storing the hashes one by one via
+    /// [`upsert()`](#method.upsert). When any of the hashes is invalid, it
+    /// returns [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash)
+    /// and none of the hashes is stored.
+    pub fn upsert_many<'a, I>(&mut self, hashes: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        let transaction = self.connection.transaction().map_err(database_failed)?;
+        {
+            let mut upsert_hash = transaction
+                .prepare_cached(
+                    "INSERT INTO hashes (label, hash, normalized) VALUES (?1, ?2, ?3)
+                     ON CONFLICT (label) DO UPDATE
+                     SET hash = excluded.hash, normalized = excluded.normalized
+                     RETURNING id",
+                )
+                .map_err(database_failed)?;
+            let mut delete_ngrams = transaction
+                .prepare_cached("DELETE FROM ngrams WHERE hash_id = ?1")
+                .map_err(database_failed)?;
+            let mut insert_ngram = transaction
+                .prepare_cached(
+                    "INSERT OR IGNORE INTO ngrams (block_size, ngram, hash_id) VALUES (?1, ?2, ?3)",
+                )
+                .map_err(database_failed)?;
+            for (label, hash) in hashes {
+                let parsed: FuzzyHash = hash.parse()?;
+                let normalized = parsed.normalized();
+                let id: i64 = upsert_hash
+                    .query_row(params![label, hash, normalized.as_str()], |row| row.get(0))
+                    .map_err(database_failed)?;
+                delete_ngrams.execute([id]).map_err(database_failed)?;
+                for (block_size, ngram) in normalized.ngrams(FuzzyHash::DEFAULT_NGRAM_SIZE) {
+                    insert_ngram
+                        .execute(params![block_size as i64, ngram, id])
+                        .map_err(database_failed)?;
+                }
+            }
+        }
+        transaction.commit().map_err(database_failed)
+    }
+
+    /// Returns the hash stored with the given label (if any).
+    pub fn get(&self, label: &str) -> Result<Option<String>> {
+        self.connection
+            .query_row("SELECT hash FROM hashes WHERE label = ?1", [label], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(database_failed)
+    }
+
+    /// Removes the hash stored with the given label and returns whether it
+    /// was stored.
+    pub fn remove(&mut self, label: &str) -> Result<bool> {
+        let removed = self
+            .connection
+            .execute("DELETE FROM hashes WHERE label = ?1", [label])
+            .map_err(database_failed)?;
+        Ok(removed > 0)
+    }
+
+    /// Returns the stored hashes whose score with the query is strictly
+    /// greater than the threshold, ordered by descending scores (and by
+    /// labels for equal scores).
+    ///
+    /// Only the stored hashes that share a 7-gram with the query (or whose
+    /// normalized hash is the same) are compared with it, which returns the
+    /// same matches as comparing the query against all stored hashes.
+    ///
+    /// When the query is invalid, it returns
+    /// [`Error::InvalidHash`](../enum.Error.html#variant.InvalidHash).
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(threshold))
+    )]
+    pub fn search(&self, query: &str, threshold: u8) -> Result<Vec<StoreMatch>> {
+        let normalized = query.parse::<FuzzyHash>()?.normalized();
+        let mut candidates = BTreeMap::new();
+        {
+            let mut add_candidates = |sql: &str, params: &[&dyn rusqlite::ToSql]| -> Result<()> {
+                let mut statement = self
+                    .connection
+                    .prepare_cached(sql)
+                    .map_err(database_failed)?;
+                let rows = statement
+                    .query_map(params, |row| Ok((row.get(0)?, row.get(1)?)))
+                    .map_err(database_failed)?;
+                for row in rows {
+                    let (label, hash): (String, String) = row.map_err(database_failed)?;
+                    candidates.insert(label, hash);
+                }
+                Ok(())
+            };
+            for (block_size, ngram) in normalized.ngrams(FuzzyHash::DEFAULT_NGRAM_SIZE) {
+                add_candidates(
+                    "SELECT h.label, h.hash FROM ngrams n JOIN hashes h ON h.id = n.hash_id
+                     WHERE n.block_size = ?1 AND n.ngram = ?2",
+                    params![block_size as i64, ngram],
+                )?;
+            }
+            add_candidates(
+                "SELECT label, hash FROM hashes WHERE normalized = ?1",
+                params![normalized.as_str()],
+            )?;
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(candidates = candidates.len(), "found candidates");
+        let mut matches = Vec::new();
+        for (label, hash) in candidates {
+            let score = compare(query, &hash)?;
+            if score > threshold {
+                matches.push(StoreMatch { label, hash, score });
+            }
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(matches = matches.len(), "scored candidates");
+        // The candidates are sorted by labels and the sort is stable.
+        matches.sort_by_key(|m| Reverse(m.score));
+        Ok(matches)
+    }
+}
+
+fn database_failed(e: rusqlite::Error) -> Error {
+    Error::DatabaseFailed {
+        reason: e.to_string(),
+    }
+}
@@ -0,0 +1,117 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use super::hash;
+use super::Result;
+
+/// Options controlling how text is normalized before it is hashed.
+///
+/// By default, all normalizations are enabled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizationOptions {
+    /// Convert the text to lowercase.
+    pub lowercase: bool,
+    /// Replace each run of whitespace with a single space and remove
+    /// leading and trailing whitespace.
+    pub collapse_whitespace: bool,
+    /// Remove HTML tags (`<...>`) and comments (`<!-- ... -->`).
+    pub strip_html_tags: bool,
+}
+
+impl Default for NormalizationOptions {
+    fn default() -> Self {
+        NormalizationOptions {
+            lowercase: true,
+            collapse_whitespace: true,
+            strip_html_tags: true,
+        }
+    }
+}
+
+/// Normalizes text according to the given options.
+///
+/// HTML tags are stripped first, then the text is converted to lowercase, and
+/// finally, whitespace is collapsed.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::NormalizationOptions;
+///
+/// let text = ssdeep::normalize_text(
+///     "<p>Hello   <b>THERE</b>!</p>\n",
+///     &NormalizationOptions::default(),
+/// );
+/// assert_eq!(text, "hello there!");
+/// ```
+pub fn normalize_text(text: &str, options: &NormalizationOptions) -> String {
+    let mut text = if options.strip_html_tags {
+        strip_html_tags(text)
+    } else {
+        text.to_string()
+    };
+    if options.lowercase {
+        text = text.to_lowercase();
+    }
+    if options.collapse_whitespace {
+        text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    }
+    text
+}
+
+/// Computes the fuzzy hash of normalized text.
+///
+/// The text is normalized via [`normalize_text()`](fn.normalize_text.html)
+/// and then hashed via [`hash()`](fn.hash.html). This is useful when trivially
+/// mutated texts (e.g. spam or plagiarized documents) should still match.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::NormalizationOptions;
+///
+/// let options = NormalizationOptions::default();
+/// let h1 = ssdeep::hash_text_normalized("Hello there!", &options).unwrap();
+/// let h2 = ssdeep::hash_text_normalized("<i>HELLO</i>\tthere!", &options).unwrap();
+/// assert_eq!(h1, h2);
+/// ```
+pub fn hash_text_normalized(text: &str, options: &NormalizationOptions) -> Result<String> {
+    hash(normalize_text(text, options).as_bytes())
+}
+
+fn strip_html_tags(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        result.push_str(&rest[..start]);
+        let end_marker = if rest[start..].starts_with("<!--") {
+            "-->"
+        } else {
+            ">"
+        };
+        match rest[start..].find(end_marker) {
+            Some(end) => rest = &rest[start + end + end_marker.len()..],
+            None => {
+                // An unterminated tag is not a tag, so keep it.
+                result.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
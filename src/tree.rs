@@ -0,0 +1,117 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Fuzzy hashing of whole directory trees.
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use super::hasher::FuzzyHasher;
+use super::Result;
+
+/// The kind of an entry in a directory tree.
+enum EntryKind {
+    Dir,
+    File { size: u64 },
+    Symlink { target: PathBuf },
+}
+
+/// An entry in a directory tree.
+struct Entry {
+    /// Components of the path relative to the root of the tree.
+    components: Vec<String>,
+    path: PathBuf,
+    kind: EntryKind,
+}
+
+/// Computes the fuzzy hash of a directory tree.
+///
+/// The hash is computed over a canonical serialization of the tree, so the
+/// same tree always has the same hash (regardless of the order in which the
+/// file system lists the entries, the location of the tree, or timestamps),
+/// and similar trees (e.g. two versions of an extracted package or container
+/// layers) have similar hashes. The entries are serialized in the order of
+/// their relative paths (compared component by component), with `/` as the
+/// path separator:
+///
+/// * a directory as `D <path>\n`,
+/// * a file as `F <path>\n<size>\n` followed by its contents,
+/// * a symbolic link as `L <path>\n<target>\n` (links are not followed).
+///
+/// Paths that are not valid Unicode are serialized lossily.
+///
+/// When the path is not a directory or the tree cannot be read, it returns an
+/// error.
+///
+/// # Panics
+///
+/// If the underlying C library returns an invalid hash.
+pub fn hash_dir_tree<P: AsRef<Path>>(dir_path: P) -> Result<String> {
+    let mut entries = Vec::new();
+    collect_entries(dir_path.as_ref(), &[], &mut entries)?;
+    entries.sort_by(|e1, e2| e1.components.cmp(&e2.components));
+
+    let mut hasher = FuzzyHasher::new()?;
+    for entry in &entries {
+        let path = entry.components.join("/");
+        match entry.kind {
+            EntryKind::Dir => {
+                hasher.update(format!("D {}\n", path).as_bytes())?;
+            }
+            EntryKind::File { size } => {
+                hasher.update(format!("F {}\n{}\n", path, size).as_bytes())?;
+                hasher.update_and_copy(File::open(&entry.path)?, io::sink())?;
+            }
+            EntryKind::Symlink { ref target } => {
+                let target = target.to_string_lossy().replace('\\', "/");
+                hasher.update(format!("L {}\n{}\n", path, target).as_bytes())?;
+            }
+        }
+    }
+    hasher.digest()
+}
+
+fn collect_entries(dir_path: &Path, components: &[String], entries: &mut Vec<Entry>) -> Result<()> {
+    for dir_entry in fs::read_dir(dir_path)? {
+        let dir_entry = dir_entry?;
+        let mut entry_components = components.to_vec();
+        entry_components.push(dir_entry.file_name().to_string_lossy().into_owned());
+        let path = dir_entry.path();
+        let file_type = dir_entry.file_type()?;
+        let kind = if file_type.is_symlink() {
+            EntryKind::Symlink {
+                target: fs::read_link(&path)?,
+            }
+        } else if file_type.is_dir() {
+            collect_entries(&path, &entry_components, entries)?;
+            EntryKind::Dir
+        } else {
+            EntryKind::File {
+                size: dir_entry.metadata()?.len(),
+            }
+        };
+        entries.push(Entry {
+            components: entry_components,
+            path,
+            kind,
+        });
+    }
+    Ok(())
+}
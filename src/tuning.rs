@@ -0,0 +1,234 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Calibration of the parameters of pipelines to the host.
+//!
+//! This module is available only when the `pipeline` feature is enabled. The
+//! default parameters of [`pipeline`](../pipeline/index.html) stages suit
+//! neither laptops nor servers with many cores, so
+//! [`calibrate()`](fn.calibrate.html) measures the throughput of the disk and
+//! the hashing speed of a core and derives the number of hashing threads and
+//! the capacity of channels (the read-ahead depth) from them. The resulting
+//! [`TuningProfile`](struct.TuningProfile.html) can be saved into a file and
+//! loaded later, so the calibration does not have to be repeated.
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use super::hash;
+use super::Error;
+use super::Result;
+
+/// The maximal number of bytes read when measuring the throughput of the
+/// disk.
+const MAX_DISK_SAMPLE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Size of the data hashed when measuring the hashing speed.
+const HASH_SAMPLE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Size of the buffer into which files are read when measuring the
+/// throughput of the disk.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Parameters of pipelines calibrated to the host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TuningProfile {
+    /// The measured throughput of the disk in bytes per second.
+    pub disk_throughput: f64,
+    /// The measured hashing speed of a single core in bytes per second.
+    pub hash_rate: f64,
+    /// The number of available cores.
+    pub cores: usize,
+    /// The number of threads for
+    /// [`hash_pool()`](../pipeline/fn.hash_pool.html).
+    pub threads: usize,
+    /// The capacity of the channels between stages (the read-ahead depth).
+    pub capacity: usize,
+}
+
+/// Measures the throughput of the disk (by reading the given file or files in
+/// the given directory) and the hashing speed, and picks the parameters of
+/// pipelines based on them.
+///
+/// The number of threads is the number of cores needed for hashing to keep up
+/// with the disk (but at least one and at most the number of available
+/// cores), and the capacity of channels is twice the number of threads, so
+/// that every thread has data to hash while the next data are being read.
+/// Note that the measured throughput of the disk may be affected by caching
+/// of the files by the operating system.
+///
+/// When the files cannot be read or hashed, it returns an error.
+pub fn calibrate<P: AsRef<Path>>(sample_path: P) -> Result<TuningProfile> {
+    let disk_throughput = measure_disk_throughput(sample_path.as_ref())?;
+    let hash_rate = measure_hash_rate()?;
+    let cores = thread::available_parallelism().map_or(1, |n| n.get());
+    Ok(TuningProfile::from_measurements(
+        disk_throughput,
+        hash_rate,
+        cores,
+    ))
+}
+
+impl TuningProfile {
+    /// Creates a profile with parameters picked based on the given
+    /// measurements (see [`calibrate()`](fn.calibrate.html)).
+    pub fn from_measurements(disk_throughput: f64, hash_rate: f64, cores: usize) -> Self {
+        let cores = cores.max(1);
+        let needed = if hash_rate > 0.0 {
+            (disk_throughput / hash_rate).ceil()
+        } else {
+            cores as f64
+        };
+        let threads = (needed as usize).clamp(1, cores);
+        TuningProfile {
+            disk_throughput,
+            hash_rate,
+            cores,
+            threads,
+            capacity: 2 * threads,
+        }
+    }
+
+    /// Saves the profile into the given file.
+    ///
+    /// When the file cannot be written, it returns an error.
+    pub fn save<P: AsRef<Path>>(&self, file_path: P) -> Result<()> {
+        fs::write(file_path, self.to_string())?;
+        Ok(())
+    }
+
+    /// Loads a profile from the given file (see
+    /// [`parse()`](#method.parse)).
+    ///
+    /// When the file cannot be read or parsed, it returns an error.
+    pub fn load<P: AsRef<Path>>(file_path: P) -> Result<TuningProfile> {
+        TuningProfile::parse(&fs::read_to_string(file_path)?)
+    }
+
+    /// Parses a profile in the format produced by its `Display`
+    /// implementation: one `key = value` pair per line.
+    ///
+    /// When a value is missing or invalid, it returns an error.
+    pub fn parse(s: &str) -> Result<TuningProfile> {
+        let value = |key: &str| {
+            s.lines()
+                .filter_map(|line| {
+                    let mut parts = line.splitn(2, '=');
+                    match (parts.next(), parts.next()) {
+                        (Some(k), Some(v)) if k.trim() == key => Some(v.trim()),
+                        _ => None,
+                    }
+                })
+                .next()
+                .ok_or_else(|| invalid_profile(&format!("missing {}", key)))
+        };
+        let float = |key: &str| {
+            value(key)?
+                .parse::<f64>()
+                .map_err(|_| invalid_profile(&format!("invalid {}", key)))
+        };
+        let int = |key: &str| {
+            value(key)?
+                .parse::<usize>()
+                .map_err(|_| invalid_profile(&format!("invalid {}", key)))
+        };
+        Ok(TuningProfile {
+            disk_throughput: float("disk_throughput")?,
+            hash_rate: float("hash_rate")?,
+            cores: int("cores")?,
+            threads: int("threads")?,
+            capacity: int("capacity")?,
+        })
+    }
+}
+
+impl std::fmt::Display for TuningProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "disk_throughput = {}", self.disk_throughput)?;
+        writeln!(f, "hash_rate = {}", self.hash_rate)?;
+        writeln!(f, "cores = {}", self.cores)?;
+        writeln!(f, "threads = {}", self.threads)?;
+        writeln!(f, "capacity = {}", self.capacity)
+    }
+}
+
+fn measure_disk_throughput(path: &Path) -> Result<f64> {
+    let files = if path.is_dir() {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                files.push(entry.path());
+            }
+        }
+        files.sort();
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let start = Instant::now();
+    let mut read = 0;
+    let mut buf = vec![0; BUFFER_SIZE];
+    for file_path in files {
+        if read >= MAX_DISK_SAMPLE_SIZE {
+            break;
+        }
+        let mut file = File::open(file_path)?.take(MAX_DISK_SAMPLE_SIZE - read);
+        loop {
+            match file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => read += n as u64,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+    Ok(rate(read, start.elapsed()))
+}
+
+fn measure_hash_rate() -> Result<f64> {
+    // Pseudo-random data, so that the hashing is not unrealistically fast.
+    let mut state: u32 = 1;
+    let data: Vec<u8> = (0..HASH_SAMPLE_SIZE)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        })
+        .collect();
+    let start = Instant::now();
+    hash(&data)?;
+    Ok(rate(data.len() as u64, start.elapsed()))
+}
+
+fn rate(bytes: u64, elapsed: Duration) -> f64 {
+    // Avoid division by zero for extremely fast (or empty) measurements.
+    bytes as f64 / elapsed.as_secs_f64().max(1e-9)
+}
+
+fn invalid_profile(reason: &str) -> Error {
+    Error::Io(io::Error::new(
+        io::ErrorKind::InvalidData,
+        reason.to_string(),
+    ))
+}
@@ -0,0 +1,160 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Enrichment of matches with metadata from
+//! [VirusTotal](https://www.virustotal.com/).
+//!
+//! This module is available only when the `vt` feature is enabled. It uses
+//! the VirusTotal API v3 (an API key with access to VirusTotal Intelligence
+//! search is needed) to find samples with the same or a similar fuzzy hash.
+//! The blocking client of the [reqwest](https://crates.io/crates/reqwest)
+//! crate is used without its default features, so to be able to connect to
+//! VirusTotal, enable a TLS feature of `reqwest` (e.g. `rustls-tls`) in your
+//! own `Cargo.toml`.
+
+use reqwest::blocking::Client;
+use serde_json::Value;
+
+use super::compare;
+use super::Error;
+use super::Result;
+
+/// The default URL of the VirusTotal API.
+const DEFAULT_BASE_URL: &str = "https://www.virustotal.com/api/v3";
+
+/// A sample found on VirusTotal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VtSample {
+    /// SHA-256 of the sample.
+    pub sha256: String,
+    /// The most interesting name of the sample (if any).
+    pub meaningful_name: Option<String>,
+    /// Description of the type of the sample (e.g. `Win32 EXE`).
+    pub type_description: Option<String>,
+    /// Size of the sample (in bytes).
+    pub size: Option<u64>,
+    /// The number of engines that detected the sample as malicious in its last
+    /// analysis.
+    pub malicious_detections: Option<u64>,
+    /// Fuzzy hash of the sample.
+    pub ssdeep: Option<String>,
+    /// Match score between the queried fuzzy hash and the fuzzy hash of the
+    /// sample (when the sample has a valid fuzzy hash).
+    pub score: Option<u8>,
+}
+
+/// A match record enriched with samples found on VirusTotal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnrichedMatch<M> {
+    /// The original match record.
+    pub record: M,
+    /// Samples found on VirusTotal, the most similar ones first.
+    pub vt_samples: Vec<VtSample>,
+}
+
+/// A client for querying VirusTotal.
+#[derive(Debug, Clone)]
+pub struct VtClient {
+    base_url: String,
+    api_key: String,
+    client: Client,
+}
+
+impl VtClient {
+    /// Creates a client authenticating with the given API key.
+    pub fn new(api_key: &str) -> Self {
+        VtClient::with_base_url(api_key, DEFAULT_BASE_URL)
+    }
+
+    /// Creates a client for the VirusTotal API at the given URL (e.g. when it
+    /// is accessed through a proxy).
+    pub fn with_base_url(api_key: &str, base_url: &str) -> Self {
+        VtClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            client: Client::new(),
+        }
+    }
+
+    /// Finds samples whose fuzzy hash is the same as or similar to the given
+    /// fuzzy hash.
+    ///
+    /// At most `limit` samples are returned, sorted by their match score
+    /// (the most similar ones first). When the request fails or the response
+    /// cannot be parsed, it returns
+    /// [`Error::HttpRequestFailed`](../enum.Error.html#variant.HttpRequestFailed).
+    pub fn similar_samples(&self, hash: &str, limit: usize) -> Result<Vec<VtSample>> {
+        let query = format!("ssdeep:\"{}\"", hash);
+        let limit = limit.to_string();
+        let response = self
+            .client
+            .get(format!("{}/intelligence/search", self.base_url))
+            .query(&[("query", query.as_str()), ("limit", limit.as_str())])
+            .header("x-apikey", self.api_key.as_str())
+            .header("Accept", "application/json")
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.bytes())
+            .map_err(request_failed)?;
+        parse_samples(hash, &response)
+    }
+
+    /// Enriches a match record with samples whose fuzzy hash is the same as or
+    /// similar to the given fuzzy hash (typically the hash of the matched
+    /// sample).
+    ///
+    /// See [`similar_samples()`](#method.similar_samples) for more details.
+    pub fn enrich<M>(&self, record: M, hash: &str, limit: usize) -> Result<EnrichedMatch<M>> {
+        Ok(EnrichedMatch {
+            record,
+            vt_samples: self.similar_samples(hash, limit)?,
+        })
+    }
+}
+
+fn parse_samples(hash: &str, json: &[u8]) -> Result<Vec<VtSample>> {
+    let document: Value = serde_json::from_slice(json).map_err(request_failed)?;
+    let items = match document.get("data") {
+        Some(Value::Array(items)) => items,
+        _ => return Err(request_failed("missing data in VirusTotal response")),
+    };
+    let mut samples: Vec<_> = items
+        .iter()
+        .map(|item| {
+            let attributes = &item["attributes"];
+            let str_attribute = |key| attributes[key].as_str().map(|s| s.to_string());
+            let ssdeep = str_attribute("ssdeep");
+            VtSample {
+                sha256: item["id"].as_str().unwrap_or_default().to_string(),
+                meaningful_name: str_attribute("meaningful_name"),
+                type_description: str_attribute("type_description"),
+                size: attributes["size"].as_u64(),
+                malicious_detections: attributes["last_analysis_stats"]["malicious"].as_u64(),
+                score: ssdeep.as_ref().and_then(|s| compare(hash, s).ok()),
+                ssdeep,
+            }
+        })
+        .collect();
+    samples.sort_by_key(|s| std::cmp::Reverse(s.score));
+    Ok(samples)
+}
+
+fn request_failed<E: ToString>(e: E) -> Error {
+    Error::HttpRequestFailed {
+        reason: e.to_string(),
+    }
+}
@@ -0,0 +1,118 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Comparison of fuzzy hashes with weighted block-size tracks.
+
+use super::score::common_tracks;
+use super::score::ParsedHash;
+use super::Result;
+
+/// Options for [`compare_weighted()`](fn.compare_weighted.html).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WeightedCompareOptions {
+    /// Weight of the score of the parts with the smaller common block size.
+    /// Default: 1.0.
+    pub block_size_weight: f64,
+    /// Weight of the score of the parts with the larger common block size.
+    /// Default: 1.0.
+    pub double_block_size_weight: f64,
+    /// Sizes of the two inputs whose hashes are compared (if known). Default:
+    /// `None`.
+    pub input_sizes: Option<(u64, u64)>,
+    /// How much the score is lowered when the inputs have different sizes
+    /// (between 0.0 and 1.0). The score is multiplied by `1 - size_penalty *
+    /// (1 - smaller_size / larger_size)`, so 0.0 disables the penalty and 1.0
+    /// scales the score by the ratio of the sizes. It is used only when
+    /// `input_sizes` are set. Default: 0.5.
+    pub size_penalty: f64,
+}
+
+impl Default for WeightedCompareOptions {
+    fn default() -> Self {
+        WeightedCompareOptions {
+            block_size_weight: 1.0,
+            double_block_size_weight: 1.0,
+            input_sizes: None,
+            size_penalty: 0.5,
+        }
+    }
+}
+
+/// Computes the match score between two fuzzy hashes by weighting the scores
+/// of their block-size tracks.
+///
+/// In contrast to [`compare()`](fn.compare.html), which returns the maximum
+/// of the scores of the parts with a common block size, this function returns
+/// their weighted average. Parts that are too short to be compared (shorter
+/// than seven characters) are left out of the average, so that a short part
+/// of a small input does not lower the score. When the sizes of the inputs
+/// are known, the score is also lowered based on how much the sizes differ
+/// (see [`WeightedCompareOptions`](struct.WeightedCompareOptions.html)).
+///
+/// Identical hashes of inputs with identical sizes always have score 100, and
+/// hashes without a common block size always have score 0.
+///
+/// When any of the hashes is invalid, it returns an error.
+///
+/// # Examples
+///
+/// ```
+/// use ssdeep::{compare_weighted, WeightedCompareOptions};
+///
+/// let h1 = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C";
+/// let h2 = "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx";
+/// let opts = WeightedCompareOptions {
+///     input_sizes: Some((100, 200)),
+///     ..Default::default()
+/// };
+/// assert_eq!(compare_weighted(h1, h2, &opts), Ok(8));
+/// ```
+pub fn compare_weighted(hash1: &str, hash2: &str, opts: &WeightedCompareOptions) -> Result<u8> {
+    let h1 = ParsedHash::parse(hash1)?;
+    let h2 = ParsedHash::parse(hash2)?;
+
+    let score = if h1 == h2 {
+        100.0
+    } else {
+        let weights = [opts.block_size_weight, opts.double_block_size_weight];
+        let mut weighted_sum = 0.0;
+        let mut total_weight = 0.0;
+        for (track, weight) in common_tracks(&h1, &h2).iter().zip(&weights) {
+            let track = match track {
+                Some(track) if track.is_comparable() => track,
+                _ => continue,
+            };
+            let weight = weight.max(0.0);
+            weighted_sum += weight * f64::from(track.score());
+            total_weight += weight;
+        }
+        if total_weight > 0.0 {
+            weighted_sum / total_weight
+        } else {
+            0.0
+        }
+    };
+
+    let size_factor = match opts.input_sizes {
+        Some((size1, size2)) if size1 != size2 => {
+            let ratio = size1.min(size2) as f64 / size1.max(size2) as f64;
+            1.0 - opts.size_penalty.clamp(0.0, 1.0) * (1.0 - ratio)
+        }
+        _ => 1.0,
+    };
+    Ok((score * size_factor).round() as u8)
+}
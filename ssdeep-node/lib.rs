@@ -0,0 +1,197 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Node.js bindings for the
+//! [ssdeep-rs](https://github.com/s3rvac/ssdeep-rs) crate, built via
+//! [napi-rs](https://napi.rs/). See its description for more information.
+
+extern crate napi;
+#[macro_use]
+extern crate napi_derive;
+extern crate ssdeep;
+
+use napi::bindgen_prelude::Buffer;
+use napi::Error;
+use napi::Result;
+
+fn to_napi_error(e: ssdeep::Error) -> Error {
+    Error::from_reason(e.to_string())
+}
+
+fn to_score(score: u32) -> u8 {
+    // Scores are between 0 and 100, so larger values behave as 100.
+    score.min(100) as u8
+}
+
+/// Computes the fuzzy hash of the given buffer.
+#[napi]
+pub fn hash(buf: Buffer) -> Result<String> {
+    ssdeep::hash(&buf).map_err(to_napi_error)
+}
+
+/// Computes the fuzzy hash of the contents of the given file.
+#[napi]
+pub fn hash_from_file(file_path: String) -> Result<String> {
+    ssdeep::hash_from_file(file_path).map_err(to_napi_error)
+}
+
+/// Computes the match score between two fuzzy hashes (between 0 and 100).
+#[napi]
+pub fn compare(hash1: String, hash2: String) -> Result<u32> {
+    ssdeep::compare(&hash1, &hash2)
+        .map(u32::from)
+        .map_err(to_napi_error)
+}
+
+/// A batch of fuzzy hashes against which a query can be scored at once.
+#[napi]
+#[derive(Default)]
+pub struct HashBatch {
+    inner: ssdeep::HashBatch,
+}
+
+#[napi]
+impl HashBatch {
+    /// Creates an empty batch.
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        HashBatch::default()
+    }
+
+    /// Returns the number of hashes in the batch.
+    #[napi(getter)]
+    pub fn length(&self) -> u32 {
+        self.inner.len() as u32
+    }
+
+    /// Adds a hash into the batch and returns its index.
+    #[napi]
+    pub fn push(&mut self, hash: String) -> Result<u32> {
+        self.inner
+            .push(&hash)
+            .map(|i| i as u32)
+            .map_err(to_napi_error)
+    }
+
+    /// Returns the scores of the query against all hashes in the batch (in the
+    /// order in which they were added).
+    #[napi]
+    pub fn scores(&self, query: String) -> Result<Vec<u32>> {
+        self.inner
+            .scores(&query)
+            .map(|scores| scores.into_iter().map(u32::from).collect())
+            .map_err(to_napi_error)
+    }
+}
+
+/// A match found in a [`FuzzyHashIndex`](struct.FuzzyHashIndex.html).
+#[napi(object)]
+pub struct IndexMatch {
+    /// Identifier of the matched hash (returned when it was inserted).
+    pub id: u32,
+    /// The score between the query and the matched hash.
+    pub score: u32,
+}
+
+impl From<ssdeep::index::IndexMatch> for IndexMatch {
+    fn from(m: ssdeep::index::IndexMatch) -> Self {
+        IndexMatch {
+            id: m.id as u32,
+            score: u32::from(m.score),
+        }
+    }
+}
+
+/// An n-gram inverted index of fuzzy hashes, which finds similar hashes
+/// without comparing the query against all stored hashes.
+#[napi]
+#[derive(Default)]
+pub struct FuzzyHashIndex {
+    inner: ssdeep::index::FuzzyHashIndex,
+}
+
+#[napi]
+impl FuzzyHashIndex {
+    /// Creates an empty index.
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        FuzzyHashIndex::default()
+    }
+
+    /// Loads an index from a file at the given path.
+    #[napi(factory)]
+    pub fn load(path: String) -> Result<Self> {
+        ssdeep::index::FuzzyHashIndex::load(path)
+            .map(|inner| FuzzyHashIndex { inner })
+            .map_err(to_napi_error)
+    }
+
+    /// Saves the index into a file at the given path.
+    #[napi]
+    pub fn save(&self, path: String) -> Result<()> {
+        self.inner.save(path).map_err(to_napi_error)
+    }
+
+    /// Returns the number of hashes in the index.
+    #[napi(getter)]
+    pub fn length(&self) -> u32 {
+        self.inner.len() as u32
+    }
+
+    /// Returns the hash with the given identifier (`null` when there is no
+    /// such hash or it has been removed).
+    #[napi]
+    pub fn get(&self, id: u32) -> Option<String> {
+        self.inner.get(id as usize).map(|hash| hash.to_string())
+    }
+
+    /// Adds a hash into the index and returns its identifier.
+    #[napi]
+    pub fn insert(&mut self, hash: String) -> Result<u32> {
+        self.inner
+            .insert(&hash)
+            .map(|id| id as u32)
+            .map_err(to_napi_error)
+    }
+
+    /// Removes the hash with the given identifier from the index and returns
+    /// it (`null` when there is no such hash or it has already been removed).
+    #[napi]
+    pub fn remove(&mut self, id: u32) -> Option<String> {
+        self.inner.remove(id as usize).map(|hash| hash.to_string())
+    }
+
+    /// Returns the stored hashes whose score with the query is strictly
+    /// greater than the threshold, ordered by descending scores.
+    #[napi]
+    pub fn search(&self, query: String, threshold: u32) -> Result<Vec<IndexMatch>> {
+        self.inner
+            .search(&query, to_score(threshold))
+            .map(|matches| matches.into_iter().map(IndexMatch::from).collect())
+            .map_err(to_napi_error)
+    }
+
+    /// Returns at most `k` stored hashes with the best scores with the probe
+    /// that are at least `minScore`, ordered by descending scores.
+    #[napi]
+    pub fn top_k(&self, probe: String, k: u32, min_score: u32) -> Result<Vec<IndexMatch>> {
+        self.inner
+            .top_k(&probe, k as usize, to_score(min_score))
+            .map(|matches| matches.into_iter().map(IndexMatch::from).collect())
+            .map_err(to_napi_error)
+    }
+}
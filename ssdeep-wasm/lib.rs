@@ -0,0 +1,82 @@
+// ssdeep-rs: A Rust wrapper for ssdeep.
+//
+// Copyright (c) 2016 Petr Zemek <s3rvac@petrzemek.net>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! WebAssembly bindings for the
+//! [ssdeep-rs](https://github.com/s3rvac/ssdeep-rs) crate, built via
+//! [wasm-bindgen](https://rustwasm.github.io/wasm-bindgen/). See its
+//! description for more information.
+//!
+//! The crate is built with the `rust-backend` feature, so the hashes are
+//! computed natively in Rust and no C library has to be compiled for wasm32.
+
+extern crate ssdeep;
+extern crate wasm_bindgen;
+
+use wasm_bindgen::prelude::*;
+
+fn to_js_error(e: ssdeep::Error) -> JsError {
+    JsError::new(&e.to_string())
+}
+
+/// Computes the fuzzy hash of the given bytes.
+#[wasm_bindgen]
+pub fn hash(buf: &[u8]) -> Result<String, JsError> {
+    ssdeep::hash(buf).map_err(to_js_error)
+}
+
+/// Computes the match score between two fuzzy hashes (between 0 and 100).
+#[wasm_bindgen]
+pub fn compare(hash1: &str, hash2: &str) -> Result<u8, JsError> {
+    ssdeep::compare(hash1, hash2).map_err(to_js_error)
+}
+
+/// A hasher computing the fuzzy hash of data fed into it in chunks (e.g. of a
+/// file read in slices or a stream).
+#[wasm_bindgen]
+pub struct FuzzyHasher {
+    inner: ssdeep::FuzzyHasher,
+}
+
+#[wasm_bindgen]
+impl FuzzyHasher {
+    /// Creates a new hasher.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<FuzzyHasher, JsError> {
+        ssdeep::FuzzyHasher::new()
+            .map(|inner| FuzzyHasher { inner })
+            .map_err(to_js_error)
+    }
+
+    /// Sets the total length of the data that will be fed into the hasher,
+    /// which speeds up the hashing when it is known in advance.
+    #[wasm_bindgen(js_name = setTotalInputLength)]
+    pub fn set_total_input_length(&mut self, len: f64) -> Result<(), JsError> {
+        self.inner
+            .set_total_input_length(len as u64)
+            .map_err(to_js_error)
+    }
+
+    /// Feeds the given bytes into the hasher.
+    pub fn update(&mut self, buf: &[u8]) -> Result<(), JsError> {
+        self.inner.update(buf).map_err(to_js_error)
+    }
+
+    /// Returns the fuzzy hash of all the bytes fed so far.
+    pub fn digest(&self) -> Result<String, JsError> {
+        self.inner.digest().map_err(to_js_error)
+    }
+}
@@ -15,12 +15,99 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
+#[cfg(feature = "decompress")]
+extern crate bzip2;
+#[cfg(feature = "office")]
+extern crate cfb;
+#[cfg(feature = "decompress")]
+extern crate flate2;
+#[cfg(feature = "indicatif")]
+extern crate indicatif;
+#[cfg(all(feature = "c-backend", feature = "native-compare"))]
+extern crate libfuzzy_sys;
+#[cfg(feature = "digest")]
+extern crate md5;
+#[cfg(feature = "graph")]
+extern crate petgraph;
+#[cfg(any(feature = "http", feature = "misp", feature = "vt"))]
+extern crate reqwest;
+#[cfg(any(feature = "sarif", feature = "serde"))]
+extern crate serde_json;
+#[cfg(feature = "digest")]
+extern crate sha2;
 extern crate ssdeep;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "decompress")]
+extern crate xz2;
+#[cfg(any(feature = "archive", feature = "office"))]
+extern crate zip;
+#[cfg(feature = "decompress")]
+extern crate zstd;
 
 use ssdeep::compare;
+use ssdeep::compare_batch;
+use ssdeep::compare_chunks;
+use ssdeep::compare_many;
+use ssdeep::compare_weighted;
+use ssdeep::diff_signatures;
 use ssdeep::hash;
+use ssdeep::hash_chunks;
+use ssdeep::hash_dir_tree;
+use ssdeep::hash_file_region;
+#[cfg(unix)]
+use ssdeep::hash_from_fd;
 use ssdeep::hash_from_file;
+use ssdeep::hash_from_file_handle;
+use ssdeep::hash_from_reader;
+use ssdeep::hash_from_reader_with_chunk_size;
+use ssdeep::hash_text_normalized;
+use ssdeep::hash_with_chunk_map;
+use ssdeep::normalize_text;
+use ssdeep::shards_for;
+use ssdeep::ChunkingOptions;
+use ssdeep::CompactFuzzyHash;
+use ssdeep::CompositeInput;
+use ssdeep::CompositeScorer;
+use ssdeep::DiffRun;
+use ssdeep::DigestFlags;
 use ssdeep::Error;
+use ssdeep::FuzzyHash;
+use ssdeep::FuzzyHasher;
+use ssdeep::HashBatch;
+use ssdeep::MatchSet;
+use ssdeep::NormalizationOptions;
+use ssdeep::Phase;
+use ssdeep::Progress;
+use ssdeep::WeightedCompareOptions;
+
+// An allocator counting allocations made by the current thread, so that tests
+// can check that an operation does not allocate.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+}
+
+unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|a| a.set(a.get() + 1));
+        std::alloc::System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        std::alloc::System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations_in<F: FnOnce()>(f: F) -> u64 {
+    let before = ALLOCATIONS.with(|a| a.get());
+    f();
+    ALLOCATIONS.with(|a| a.get()) - before
+}
 
 //
 // compare()
@@ -60,6 +147,13 @@ fn compare_returns_error_when_hash_is_invalid() {
     );
 }
 
+#[test]
+fn compare_returns_error_when_hash_contains_null_byte() {
+    let h1 = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C";
+    let h2 = "3:AXGB\0icFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx";
+    assert_eq!(compare(h1, h2), Err(Error::EmbeddedNul { position: 6 }));
+}
+
 #[test]
 fn compare_accepts_hashes_as_str() {
     let h1 = "3:OWR:OWR";
@@ -74,6 +168,596 @@ fn compare_accepts_hashes_as_string() {
     assert_eq!(compare(&h1, &h2), Ok(100));
 }
 
+#[test]
+fn compare_accepts_hashes_as_owned_string() {
+    let h1 = "3:OWR:OWR".to_string();
+    let h2 = "3:OWR:OWR".to_string();
+    assert_eq!(compare(h1, h2), Ok(100));
+}
+
+#[test]
+fn compare_accepts_parsed_hashes() {
+    let h1: FuzzyHash = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C".parse().unwrap();
+    let h2: FuzzyHash = "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx".parse().unwrap();
+    assert_eq!(compare(&h1, &h2), Ok(22));
+    assert_eq!(
+        compare(&h1, "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx"),
+        Ok(22)
+    );
+}
+
+#[test]
+fn compare_accepts_block_sizes_with_leading_whitespace_or_sign() {
+    assert_eq!(compare(" 3:aNRn:aNRn", "3:aNRn:aNRn"), Ok(100));
+    assert_eq!(compare("+3:aNRn:aNRn", "3:aNRn:aNRn"), Ok(100));
+}
+
+#[cfg(all(feature = "c-backend", feature = "native-compare"))]
+fn c_compare(hash1: &str, hash2: &str) -> i32 {
+    let h1 = std::ffi::CString::new(hash1).unwrap();
+    let h2 = std::ffi::CString::new(hash2).unwrap();
+    unsafe { libfuzzy_sys::fuzzy_compare(h1.as_ptr(), h2.as_ptr()) }
+}
+
+#[test]
+#[cfg(all(feature = "c-backend", feature = "native-compare"))]
+fn native_compare_returns_same_results_as_c_library() {
+    let mut hashes = index_test_hashes();
+    hashes.extend(
+        [
+            "3:aNRn:aNRn,\"file\"",
+            "3:ABCDEFGHHHHHHHHHHIJK:ABCDEFG",
+            "6:ABCDEFGHHHHIJK:ABCDEFGHHHHHHHHIJK",
+            "12:aNRn:aNRn",
+            "12:aNRn",
+            "3:aNRn",
+            "XYZ",
+            ":aNRn:aNRn",
+            "3:",
+            "18446744073709551615:ABCDEFGHIJ:ABCDEFGHIJ",
+            "184467440737095516150:aNRn:aNRn",
+            " 3:aNRn:aNRn",
+            "+3:aNRn:aNRn",
+            "\t\n6:aNRn:aNRn",
+            "-3:aNRn:aNRn",
+            "-18446744073709551613:aNRn:aNRn",
+            "- 3:aNRn:aNRn",
+            "+:aNRn:aNRn",
+            "3x:aNRn:aNRn",
+            "3",
+            "6",
+        ]
+        .iter()
+        .map(|h| h.to_string()),
+    );
+    for h1 in &hashes {
+        for h2 in &hashes {
+            let expected = c_compare(h1, h2);
+            match compare(h1, h2) {
+                Ok(score) => assert_eq!(i32::from(score), expected, "{} {}", h1, h2),
+                Err(_) => assert_eq!(expected, -1, "{} {}", h1, h2),
+            }
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "native-compare")]
+fn compare_does_not_allocate() {
+    let h1 = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C";
+    let h2 = "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx";
+    let allocations = allocations_in(|| {
+        for _ in 0..1000 {
+            compare(h1, h2).unwrap();
+        }
+    });
+    assert_eq!(allocations, 0);
+}
+
+//
+// compare_cstr()
+//
+
+#[test]
+fn compare_cstr_returns_same_scores_as_compare() {
+    let hashes = index_test_hashes();
+    let c_hashes: Vec<_> = hashes
+        .iter()
+        .map(|h| std::ffi::CString::new(h.as_str()).unwrap())
+        .collect();
+    for (h1, c1) in hashes.iter().zip(&c_hashes) {
+        for (h2, c2) in hashes.iter().zip(&c_hashes) {
+            assert_eq!(ssdeep::compare_cstr(c1, c2), compare(h1, h2));
+        }
+    }
+}
+
+#[test]
+fn compare_cstr_returns_error_when_hash_is_malformed() {
+    let h1 = std::ffi::CString::new("3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C").unwrap();
+    let h2 = std::ffi::CString::new("XYZ").unwrap();
+    assert_eq!(
+        ssdeep::compare_cstr(&h1, &h2),
+        Err(Error::CFunctionFailed {
+            name: "fuzzy_compare".to_string(),
+            return_code: -1,
+        })
+    );
+}
+
+//
+// compare_weighted()
+//
+
+#[test]
+fn compare_weighted_returns_one_hundred_score_when_hashes_are_equal() {
+    let h = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C";
+    assert_eq!(compare_weighted(h, h, &Default::default()), Ok(100));
+}
+
+#[test]
+fn compare_weighted_returns_weighted_average_of_track_scores() {
+    let h1 = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C";
+    let h2 = "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx";
+    let only_first = WeightedCompareOptions {
+        double_block_size_weight: 0.0,
+        ..Default::default()
+    };
+    let only_second = WeightedCompareOptions {
+        block_size_weight: 0.0,
+        ..Default::default()
+    };
+    let s1 = compare_weighted(h1, h2, &only_first).unwrap();
+    let s2 = compare_weighted(h1, h2, &only_second).unwrap();
+    assert_eq!(compare(h1, h2).unwrap(), s1.max(s2));
+    assert_eq!(
+        compare_weighted(h1, h2, &Default::default()),
+        Ok(((f64::from(s1) + f64::from(s2)) / 2.0).round() as u8)
+    );
+}
+
+#[test]
+fn compare_weighted_ignores_tracks_that_are_too_short_to_compare() {
+    let h1 = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXG";
+    let h2 = "3:AXGBicFlIHBGcL6wCrFQEv:AXGH";
+    assert_eq!(
+        compare_weighted(h1, h2, &Default::default()),
+        Ok(compare(h1, h2).unwrap())
+    );
+}
+
+#[test]
+fn compare_weighted_does_not_allocate() {
+    let h1 = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C";
+    let h2 = "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx";
+    let opts = WeightedCompareOptions::default();
+    let allocations = allocations_in(|| {
+        for _ in 0..1000 {
+            compare_weighted(h1, h2, &opts).unwrap();
+        }
+    });
+    assert_eq!(allocations, 0);
+}
+
+#[test]
+fn compare_weighted_lowers_score_when_input_sizes_differ() {
+    let h = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C";
+    let opts = WeightedCompareOptions {
+        input_sizes: Some((100, 400)),
+        ..Default::default()
+    };
+    // 100 * (1 - 0.5 * (1 - 100 / 400))
+    assert_eq!(compare_weighted(h, h, &opts), Ok(63));
+}
+
+#[test]
+fn compare_weighted_returns_zero_when_block_sizes_are_incompatible() {
+    let h1 = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C";
+    let h2 = "12:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C";
+    assert_eq!(compare_weighted(h1, h2, &Default::default()), Ok(0));
+}
+
+#[test]
+fn compare_weighted_returns_error_when_hash_is_invalid() {
+    assert_eq!(
+        compare_weighted("XYZ", "3:tc:u", &Default::default()),
+        Err(Error::InvalidHash {
+            reason: "missing block size".to_string(),
+        })
+    );
+}
+
+//
+// CompositeScorer
+//
+
+#[test]
+fn composite_scorer_lowers_confidence_when_sizes_differ() {
+    let input = |size| CompositeInput {
+        hash: "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+        size,
+        digest: None,
+    };
+    let scorer = CompositeScorer::default();
+    let same_size = scorer.score(&input(100), &input(100)).unwrap();
+    let different_size = scorer.score(&input(100), &input(10_000)).unwrap();
+    assert_eq!(same_size.confidence, 100);
+    assert_eq!(different_size.ssdeep_score, 100);
+    assert_eq!(different_size.size_ratio, 0.01);
+    assert_eq!(different_size.confidence, 70);
+}
+
+#[test]
+fn composite_scorer_returns_zero_confidence_for_unrelated_inputs_of_equal_sizes() {
+    let input = |hash| CompositeInput {
+        hash,
+        size: 12,
+        digest: None,
+    };
+    let score = CompositeScorer::default()
+        .score(&input("3:aNRn:aNRn"), &input("3:u+N:u+N"))
+        .unwrap();
+    assert_eq!(score.ssdeep_score, 0);
+    assert_eq!(score.size_ratio, 1.0);
+    assert_eq!(score.confidence, 0);
+}
+
+#[test]
+fn composite_scorer_returns_full_confidence_when_digests_are_equal() {
+    let input = |hash, digest| CompositeInput {
+        hash,
+        size: 12,
+        digest: Some(digest),
+    };
+    let score = CompositeScorer::default()
+        .score(&input("3:aNRn:aNRn", "ABCD"), &input("3:u+N:u+N", "abcd"))
+        .unwrap();
+    assert_eq!(score.ssdeep_score, 0);
+    assert_eq!(score.digest_match, Some(true));
+    assert_eq!(score.confidence, 100);
+}
+
+#[test]
+fn composite_scorer_returns_error_when_hash_is_invalid() {
+    let input = CompositeInput {
+        hash: "XYZ",
+        size: 0,
+        digest: None,
+    };
+    assert!(CompositeScorer::default().score(&input, &input).is_err());
+}
+
+//
+// diff_signatures()
+//
+
+#[test]
+fn diff_signatures_returns_runs_of_common_and_differing_characters() {
+    let diff = diff_signatures("3:abcdefg:xyz", "3:abXdefgh:xyz").unwrap();
+    assert_eq!(diff.tracks.len(), 2);
+    assert_eq!(diff.tracks[0].block_size, 3);
+    assert_eq!(
+        diff.tracks[0].runs,
+        vec![
+            DiffRun::Common("ab".to_string()),
+            DiffRun::Removed("c".to_string()),
+            DiffRun::Added("X".to_string()),
+            DiffRun::Common("defg".to_string()),
+            DiffRun::Added("h".to_string()),
+        ]
+    );
+    assert_eq!(diff.tracks[1].block_size, 6);
+    assert_eq!(
+        diff.tracks[1].runs,
+        vec![DiffRun::Common("xyz".to_string())]
+    );
+}
+
+#[test]
+fn diff_signatures_aligns_parts_with_common_block_size() {
+    let diff = diff_signatures("3:abc:def", "6:deg:ghi").unwrap();
+    assert_eq!(diff.tracks.len(), 1);
+    assert_eq!(diff.tracks[0].block_size, 6);
+    assert_eq!(diff.tracks[0].runs[0], DiffRun::Common("de".to_string()));
+}
+
+#[test]
+fn diff_signatures_returns_no_tracks_when_block_sizes_are_incompatible() {
+    let diff = diff_signatures("3:abc:def", "24:abc:def").unwrap();
+    assert!(diff.tracks.is_empty());
+}
+
+#[test]
+fn signature_diff_renders_aligned_lines_and_html() {
+    let diff = diff_signatures("3:abc:x", "3:aXc:x").unwrap();
+    assert_eq!(
+        diff.to_ansi(),
+        "3:a\x1b[31mb\x1b[0m c\n  a \x1b[32mX\x1b[0mc\n6:x\n  x\n"
+    );
+    assert_eq!(
+        diff.to_html(),
+        "<div class=\"ssdeep-diff\"><span class=\"block-size\">3</span>:a<del>b</del><ins>X</ins>c</div>\n\
+         <div class=\"ssdeep-diff\"><span class=\"block-size\">6</span>:x</div>\n"
+    );
+}
+
+#[test]
+fn diff_signatures_returns_error_when_hash_is_invalid() {
+    assert!(diff_signatures("XYZ", "3:abc:def").is_err());
+}
+
+//
+// HashBatch::scores()
+//
+
+#[test]
+fn hash_batch_scores_returns_same_scores_as_compare() {
+    let hashes = [
+        "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+        "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx",
+        "6:AXGHsNhxLsr2C:AXGH",
+        "3:u+N:u+N",
+        "12:AXGHsNhxLsr2C:AXGH",
+    ];
+    let mut batch = HashBatch::new();
+    for h in &hashes {
+        batch.push(h).unwrap();
+    }
+    assert_eq!(batch.len(), hashes.len());
+    for query in &hashes {
+        let expected: Vec<_> = hashes.iter().map(|h| compare(query, h).unwrap()).collect();
+        assert_eq!(batch.scores(query).unwrap(), expected);
+    }
+}
+
+#[test]
+fn hash_batch_scores_with_progress_reports_comparisons() {
+    let mut batch = HashBatch::new();
+    for _ in 0..2000 {
+        batch.push("3:u+N:u+N").unwrap();
+    }
+    let mut reports = Vec::new();
+    let scores = batch
+        .scores_with_progress("3:u+N:u+N", |progress| reports.push(progress.clone()))
+        .unwrap();
+    assert_eq!(scores.len(), 2000);
+    assert_eq!(
+        reports,
+        vec![
+            Progress {
+                phase: Phase::Comparing,
+                done: 1024,
+                total: Some(2000),
+                current_path: None,
+            },
+            Progress {
+                phase: Phase::Comparing,
+                done: 2000,
+                total: Some(2000),
+                current_path: None,
+            },
+        ]
+    );
+}
+
+#[test]
+fn hash_batch_scores_allocates_only_returned_scores() {
+    let mut batch = HashBatch::new();
+    for _ in 0..1000 {
+        batch
+            .push("3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C")
+            .unwrap();
+    }
+    let allocations = allocations_in(|| {
+        batch
+            .scores("3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx")
+            .unwrap();
+    });
+    assert_eq!(allocations, 1);
+}
+
+#[test]
+fn hash_batch_scores_returns_empty_vector_when_batch_is_empty() {
+    let batch = HashBatch::new();
+    assert!(batch.is_empty());
+    assert_eq!(batch.scores("3:u+N:u+N").unwrap(), Vec::<u8>::new());
+}
+
+#[test]
+fn hash_batch_scores_returns_same_scores_as_compare_for_many_hashes() {
+    let hashes = index_test_hashes();
+    let mut batch = HashBatch::new();
+    for h in &hashes {
+        batch.push(h).unwrap();
+    }
+    for query in &hashes {
+        let expected: Vec<_> = hashes.iter().map(|h| compare(query, h).unwrap()).collect();
+        assert_eq!(batch.scores(query).unwrap(), expected);
+    }
+}
+
+#[test]
+fn hash_batch_push_returns_error_when_hash_is_invalid() {
+    let mut batch = HashBatch::new();
+    assert!(matches!(batch.push("XYZ"), Err(Error::InvalidHash { .. })));
+    assert!(batch.is_empty());
+}
+
+//
+// compare_batch()
+//
+
+#[test]
+fn compare_batch_returns_same_scores_as_compare() {
+    let hashes = index_test_hashes();
+    for query in &hashes {
+        let expected: Vec<_> = hashes.iter().map(|h| compare(query, h).unwrap()).collect();
+        assert_eq!(compare_batch(query, &hashes).unwrap(), expected);
+    }
+}
+
+#[test]
+fn compare_batch_returns_same_scores_as_compare_for_more_hashes_than_block() {
+    let query = "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx";
+    let hashes: Vec<&str> = (0..2500)
+        .map(|i| match i % 3 {
+            0 => "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+            1 => "6:AXGHsNhxLsr2C:AXGH",
+            _ => "3:u+N:u+N",
+        })
+        .collect();
+    let scores = compare_batch(query, &hashes).unwrap();
+    assert_eq!(scores.len(), hashes.len());
+    for (h, score) in hashes.iter().zip(scores) {
+        assert_eq!(score, compare(query, h).unwrap());
+    }
+}
+
+#[test]
+fn compare_batch_returns_empty_vector_when_there_are_no_hashes() {
+    let hashes: [&str; 0] = [];
+    assert_eq!(
+        compare_batch("3:u+N:u+N", &hashes).unwrap(),
+        Vec::<u8>::new()
+    );
+}
+
+#[test]
+fn compare_batch_returns_error_when_hash_is_invalid() {
+    assert!(matches!(
+        compare_batch("XYZ", &["3:u+N:u+N"]),
+        Err(Error::InvalidHash { .. })
+    ));
+    assert!(matches!(
+        compare_batch("3:u+N:u+N", &["3:u+N:u+N", "XYZ"]),
+        Err(Error::InvalidHash { .. })
+    ));
+}
+
+//
+// compare_many()
+//
+
+#[test]
+fn compare_many_returns_same_scores_as_compare() {
+    let hashes = index_test_hashes();
+    for query in hashes.iter().take(10) {
+        let expected: Vec<_> = hashes.iter().map(|h| compare(query, h)).collect();
+        let results = compare_many(query, &hashes).unwrap();
+        assert_eq!(results, expected);
+    }
+}
+
+#[test]
+fn compare_many_returns_error_only_for_invalid_candidates() {
+    let probe = "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx";
+    let candidates: Vec<Vec<u8>> = vec![
+        b"3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C".to_vec(),
+        b"XYZ".to_vec(),
+        b"3:u+N:u+\xff".to_vec(),
+        b"3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx".to_vec(),
+    ];
+    let results = compare_many(probe, &candidates).unwrap();
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[0], Ok(22));
+    assert!(matches!(results[1], Err(Error::InvalidHash { .. })));
+    assert!(matches!(results[2], Err(Error::InvalidHash { .. })));
+    assert_eq!(results[3], Ok(100));
+}
+
+#[test]
+fn compare_many_returns_error_when_probe_is_invalid() {
+    assert!(matches!(
+        compare_many("XYZ", &["3:u+N:u+N"]),
+        Err(Error::InvalidHash { .. })
+    ));
+}
+
+//
+// ComparisonCache
+//
+
+#[test]
+fn comparison_cache_returns_same_scores_as_compare() {
+    let hashes = index_test_hashes();
+    let mut cache = ssdeep::ComparisonCache::new(100);
+    for _ in 0..2 {
+        for h1 in &hashes[..12] {
+            for h2 in &hashes[..12] {
+                assert_eq!(cache.compare(h1, h2).unwrap(), compare(h1, h2).unwrap());
+            }
+        }
+    }
+    assert_eq!(cache.len(), 12 * 13 / 2);
+    assert_eq!(cache.misses(), 12 * 13 / 2);
+    assert_eq!(cache.hits(), 2 * 12 * 12 - 12 * 13 / 2);
+}
+
+#[test]
+fn comparison_cache_evicts_least_recently_used_scores() {
+    let mut cache = ssdeep::ComparisonCache::new(2);
+    let (a, b, c) = ("3:aNRn:aNRn", "3:u+N:u+N", "3:aJn:aJn");
+    cache.compare(a, a).unwrap();
+    cache.compare(b, b).unwrap();
+    cache.compare(a, a).unwrap();
+    cache.compare(c, c).unwrap();
+    assert_eq!((cache.len(), cache.hits(), cache.misses()), (2, 1, 3));
+    cache.compare(a, a).unwrap();
+    assert_eq!((cache.hits(), cache.misses()), (2, 3));
+    cache.compare(b, b).unwrap();
+    assert_eq!((cache.hits(), cache.misses()), (2, 4));
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn comparison_cache_does_not_cache_errors_or_with_zero_capacity() {
+    let mut cache = ssdeep::ComparisonCache::new(0);
+    assert_eq!(cache.compare("3:aNRn:aNRn", "3:aNRn:aNRn"), Ok(100));
+    assert!(cache.is_empty());
+    let mut cache = ssdeep::ComparisonCache::new(10);
+    assert!(cache.compare("3:aNRn:aNRn", "invalid").is_err());
+    assert!(cache.is_empty());
+}
+
+//
+// hash_into()
+//
+
+#[test]
+fn hash_into_returns_same_hash_as_hash() {
+    let data = pseudo_random_bytes(100_000);
+    let mut out = [0xff; ssdeep::FUZZY_MAX_RESULT];
+    for len in [0, 5, 1_000, 100_000] {
+        let expected = hash(&data[..len]).unwrap();
+        let h = ssdeep::hash_into(&data[..len], &mut out)
+            .unwrap()
+            .to_string();
+        assert_eq!(h, expected);
+        assert_eq!(out[h.len()], 0);
+    }
+}
+
+//
+// hash_many()
+//
+
+#[test]
+#[cfg(feature = "parallel")]
+fn hash_many_returns_same_hashes_as_hash_in_input_order() {
+    let data = pseudo_random_bytes(200_000);
+    let bufs: Vec<&[u8]> = (0..50)
+        .map(|i| &data[i * 1_000..i * 1_000 + 10_000 + i * 2_000])
+        .collect();
+    let results = ssdeep::hash_many(&bufs);
+    assert_eq!(results.len(), bufs.len());
+    for (buf, result) in bufs.iter().zip(results) {
+        assert_eq!(result, hash(buf));
+    }
+    let no_bufs: [&[u8]; 0] = [];
+    assert!(ssdeep::hash_many(&no_bufs).is_empty());
+}
+
 //
 // hash()
 //
@@ -96,6 +780,69 @@ fn hash_accepts_string_as_bytes() {
     assert_eq!(h, "3:aNRn:aNRn");
 }
 
+#[cfg(all(feature = "c-backend", feature = "rust-backend"))]
+fn c_hash(buf: &[u8], fixed_length: bool, flags: u32) -> String {
+    unsafe {
+        let state = libfuzzy_sys::fuzzy_new();
+        if fixed_length {
+            libfuzzy_sys::fuzzy_set_total_input_length(state, buf.len() as u64);
+        }
+        libfuzzy_sys::fuzzy_update(state, buf.as_ptr(), buf.len());
+        let mut result = vec![0u8; libfuzzy_sys::FUZZY_MAX_RESULT];
+        assert_eq!(
+            libfuzzy_sys::fuzzy_digest(state, result.as_mut_ptr() as *mut _, flags),
+            0
+        );
+        libfuzzy_sys::fuzzy_free(state);
+        let len = result.iter().position(|&c| c == 0).unwrap();
+        String::from_utf8(result[..len].to_vec()).unwrap()
+    }
+}
+
+#[test]
+#[cfg(all(feature = "c-backend", feature = "rust-backend"))]
+fn rust_backend_returns_same_hashes_as_c_library() {
+    use ssdeep::DigestFlags;
+
+    let random = pseudo_random_bytes(1_000_000);
+    let mut inputs: Vec<Vec<u8>> = vec![
+        Vec::new(),
+        b"Hello there!".to_vec(),
+        vec![4; 20],
+        vec![0; 100_000],
+        random.iter().map(|&c| c % 3).collect(),
+        random.clone(),
+    ];
+    for len in [1, 7, 100, 191, 192, 193, 1_000, 4_096, 50_000, 123_457] {
+        inputs.push(random[..len].to_vec());
+    }
+    for input in &inputs {
+        for &fixed_length in &[false, true] {
+            let mut hasher = ssdeep::FuzzyHasher::new().unwrap();
+            if fixed_length {
+                hasher.set_total_input_length(input.len() as u64).unwrap();
+            }
+            hasher.update(input).unwrap();
+            for flags in [
+                DigestFlags::empty(),
+                DigestFlags::ELIMSEQ,
+                DigestFlags::NOTRUNC,
+                DigestFlags::ELIMSEQ | DigestFlags::NOTRUNC,
+            ] {
+                assert_eq!(
+                    hasher.digest_with_flags(flags).unwrap(),
+                    c_hash(input, fixed_length, flags.bits()),
+                    "length {}, fixed length {}, flags {}",
+                    input.len(),
+                    fixed_length,
+                    flags.bits()
+                );
+            }
+        }
+        assert_eq!(hash(input).unwrap(), c_hash(input, true, 0));
+    }
+}
+
 //
 // hash_from_file()
 //
@@ -108,3 +855,4667 @@ fn hash_from_file_returns_correct_hash() {
         "48:9MABzSwnjpDeSrLp8+nagE4f3ZMvcDT0MIhqy6Ic:9XMwnjdeSHS+n5ZfScX0MJ7",
     );
 }
+
+#[test]
+fn hash_from_file_returns_io_error_when_path_contains_null_byte() {
+    match hash_from_file("tests/\0file.txt") {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput),
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+fn hash_from_file_returns_io_error_when_file_does_not_exist() {
+    match hash_from_file("tests/no-such-file.txt") {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn hash_from_file_supports_paths_that_are_not_valid_utf8() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let file_path = std::env::temp_dir().join(OsStr::from_bytes(b"ssdeep-rs-\xff\xfe.txt"));
+    std::fs::write(&file_path, b"Hello there!").unwrap();
+    let h = hash_from_file(&file_path);
+    std::fs::remove_file(&file_path).unwrap();
+    assert_eq!(h.unwrap(), hash(b"Hello there!").unwrap());
+}
+
+#[test]
+#[cfg(windows)]
+fn hash_from_file_supports_paths_longer_than_max_path() {
+    let root = std::env::temp_dir().join("ssdeep-rs-long-path");
+    let mut dir = root.clone();
+    for _ in 0..10 {
+        dir.push("a".repeat(30));
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    let file_path = dir.join("file.txt");
+    std::fs::write(&file_path, b"Hello there!").unwrap();
+    let h = hash_from_file(&file_path);
+    std::fs::remove_dir_all(&root).unwrap();
+    assert!(file_path.as_os_str().len() > 260);
+    assert_eq!(h, Ok("3:aNRn:aNRn".to_string()));
+}
+
+//
+// hash_from_file_handle()
+//
+
+#[test]
+fn hash_from_file_handle_returns_correct_hash() {
+    let file = std::fs::File::open("tests/file.txt").unwrap();
+    let h = hash_from_file_handle(&file).unwrap();
+    assert_eq!(h, hash_from_file("tests/file.txt").unwrap());
+}
+
+#[test]
+fn hash_from_file_handle_hashes_data_from_current_position() {
+    use std::io::Seek;
+
+    let mut file = std::fs::File::open("tests/file.txt").unwrap();
+    file.seek(std::io::SeekFrom::Start(10)).unwrap();
+    let h = hash_from_file_handle(&file).unwrap();
+    let data = std::fs::read("tests/file.txt").unwrap();
+    assert_eq!(h, hash(&data[10..]).unwrap());
+}
+
+//
+// hash_from_fd()
+//
+
+#[test]
+#[cfg(unix)]
+fn hash_from_fd_returns_correct_hash_and_keeps_fd_open() {
+    use std::io::Seek;
+    use std::os::fd::AsFd;
+
+    let mut file = std::fs::File::open("tests/file.txt").unwrap();
+    let h = hash_from_fd(file.as_fd()).unwrap();
+    assert_eq!(h, hash_from_file("tests/file.txt").unwrap());
+    // The descriptor is still usable after hashing.
+    file.rewind().unwrap();
+    assert_eq!(hash_from_fd(file.as_fd()).unwrap(), h);
+}
+
+//
+// hash_files()
+//
+
+#[test]
+fn hash_files_returns_result_for_each_file_in_input_order() {
+    let dir = std::env::temp_dir().join("ssdeep-rs-hash-files");
+    std::fs::create_dir_all(&dir).unwrap();
+    let data = pseudo_random_bytes(100_000);
+    let mut paths = Vec::new();
+    for i in 0..20 {
+        let path = dir.join(format!("file{}", i));
+        std::fs::write(&path, &data[..5_000 + i * 4_000]).unwrap();
+        paths.push(path);
+    }
+    paths.insert(7, dir.join("non-existing-file"));
+
+    let results = ssdeep::hash_files(&paths, 3);
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(results.len(), paths.len());
+    for (i, result) in results.into_iter().enumerate() {
+        match i {
+            7 => assert!(result.is_err()),
+            _ => {
+                let size = 5_000 + if i < 7 { i } else { i - 1 } * 4_000;
+                assert_eq!(result, hash(&data[..size]));
+            }
+        }
+    }
+}
+
+#[test]
+fn hash_files_uses_single_worker_when_concurrency_is_zero() {
+    let results = ssdeep::hash_files(&["tests/file.txt"], 0);
+    assert_eq!(results, vec![hash_from_file("tests/file.txt")]);
+    let no_paths: [&str; 0] = [];
+    assert!(ssdeep::hash_files(&no_paths, 0).is_empty());
+}
+
+#[test]
+fn hash_files_with_progress_reports_each_hashed_file() {
+    let paths = [
+        "tests/file.txt",
+        "tests/non-existing-file.txt",
+        "tests/file.txt",
+    ];
+    let mut reports = Vec::new();
+    let results =
+        ssdeep::hash_files_with_progress(&paths, 2, |progress| reports.push(progress.clone()));
+    assert_eq!(results.len(), 3);
+    let done: Vec<_> = reports.iter().map(|r| r.done).collect();
+    assert_eq!(done, [1, 2, 3]);
+    assert!(reports
+        .iter()
+        .all(|r| r.phase == Phase::Hashing && r.total == Some(3)));
+    let mut reported_paths: Vec<_> = reports
+        .iter()
+        .map(|r| r.current_path.clone().unwrap())
+        .collect();
+    reported_paths.sort();
+    assert_eq!(
+        reported_paths,
+        [
+            std::path::PathBuf::from("tests/file.txt"),
+            "tests/file.txt".into(),
+            "tests/non-existing-file.txt".into(),
+        ]
+    );
+}
+
+//
+// hash_from_reader()
+//
+
+#[test]
+fn hash_from_reader_returns_same_hash_as_hash_from_file() {
+    let file = std::fs::File::open("tests/file.txt").unwrap();
+    assert_eq!(hash_from_reader(file), hash_from_file("tests/file.txt"));
+}
+
+#[test]
+fn hash_from_reader_with_chunk_size_returns_same_hash_for_all_chunk_sizes() {
+    let data = pseudo_random_bytes(100_000);
+    for &chunk_size in &[0, 1, 7, 4096, 1 << 20] {
+        let h = hash_from_reader_with_chunk_size(&data[..], chunk_size);
+        assert_eq!(h, hash(&data), "chunk size {}", chunk_size);
+    }
+}
+
+#[test]
+fn hash_from_reader_returns_error_when_reading_fails() {
+    struct FailingReader;
+
+    impl std::io::Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("failure"))
+        }
+    }
+
+    match hash_from_reader(FailingReader) {
+        Err(Error::Io(e)) => assert_eq!(e.to_string(), "failure"),
+        r => panic!("unexpected result: {:?}", r),
+    }
+}
+
+//
+// FuzzyHash
+//
+
+#[test]
+fn fuzzy_hash_parses_valid_hash() {
+    let h: FuzzyHash = "48:9MABzSwnjpDeSrLp8+nagE4f3ZMvcDT0MIhqy6Ic:9XMwnjdeSHS+n5ZfScX0MJ7"
+        .parse()
+        .unwrap();
+    assert_eq!(h.block_size(), 48);
+    assert_eq!(h.double_block_size(), Some(96));
+    assert_eq!(h.chunk(), "9MABzSwnjpDeSrLp8+nagE4f3ZMvcDT0MIhqy6Ic");
+    assert_eq!(h.double_chunk(), "9XMwnjdeSHS+n5ZfScX0MJ7");
+}
+
+#[test]
+fn fuzzy_hash_parses_hash_with_empty_parts() {
+    let h: FuzzyHash = "3::".parse().unwrap();
+    assert_eq!(h.block_size(), 3);
+    assert_eq!(h.chunk(), "");
+    assert_eq!(h.double_chunk(), "");
+}
+
+#[test]
+fn fuzzy_hash_double_block_size_returns_none_when_it_does_not_fit_into_u64() {
+    let h: FuzzyHash = "13835058055282163712:aNRn:aNRn".parse().unwrap();
+    assert_eq!(h.block_size(), 3 << 62);
+    assert_eq!(h.double_block_size(), None);
+}
+
+#[test]
+fn fuzzy_hash_comparable_with_returns_true_only_for_equal_double_or_half_block_sizes() {
+    let h = |bs: u64| format!("{}:aNRn:aNRn", bs).parse::<FuzzyHash>().unwrap();
+    assert!(h(48).comparable_with(&h(48)));
+    assert!(h(48).comparable_with(&h(96)));
+    assert!(h(48).comparable_with(&h(24)));
+    assert!(!h(48).comparable_with(&h(192)));
+    assert!(!h(48).comparable_with(&h(12)));
+    assert!(h(3 << 62).comparable_with(&h(3 << 61)));
+}
+
+#[test]
+fn fuzzy_hash_comparable_with_agrees_with_compare() {
+    let hashes: Vec<FuzzyHash> = (0..8)
+        .map(|i| {
+            hash(&pseudo_random_bytes(1000 << i))
+                .unwrap()
+                .parse()
+                .unwrap()
+        })
+        .collect();
+    for h1 in &hashes {
+        for h2 in &hashes {
+            if !h1.comparable_with(h2) {
+                assert_eq!(compare(h1, h2), Ok(0), "{} {}", h1, h2);
+            }
+        }
+    }
+}
+
+#[test]
+fn fuzzy_hash_ngrams_returns_ngrams_of_both_parts_with_their_block_sizes() {
+    let h: FuzzyHash = "48:9MABzSwnjp:9XMwnjde".parse().unwrap();
+    let ngrams: Vec<_> = h.ngrams(FuzzyHash::DEFAULT_NGRAM_SIZE).collect();
+    assert_eq!(
+        ngrams,
+        [
+            (48, "9MABzSw"),
+            (48, "MABzSwn"),
+            (48, "ABzSwnj"),
+            (48, "BzSwnjp"),
+            (96, "9XMwnjd"),
+            (96, "XMwnjde"),
+        ]
+    );
+}
+
+#[test]
+fn fuzzy_hash_ngrams_skips_parts_shorter_than_n() {
+    let h: FuzzyHash = "3:aNRnBn:aN".parse().unwrap();
+    assert_eq!(h.ngrams(3).count(), 4);
+    assert_eq!(h.ngrams(7).count(), 0);
+    assert_eq!(h.ngrams(0).count(), 0);
+}
+
+#[test]
+fn fuzzy_hash_ngrams_skips_double_chunk_when_its_block_size_does_not_fit_into_u64() {
+    let h: FuzzyHash = "13835058055282163712:aNRn:aNRn".parse().unwrap();
+    assert_eq!(h.ngrams(4).collect::<Vec<_>>(), [(3 << 62, "aNRn")]);
+}
+
+#[test]
+fn fuzzy_hash_search_terms_find_all_hashes_with_nonzero_score() {
+    use ssdeep::{ChunkField, SearchTerms};
+
+    let hashes: Vec<FuzzyHash> = index_test_hashes()
+        .iter()
+        .map(|h| h.parse().unwrap())
+        .collect();
+    let stored: Vec<SearchTerms> = hashes.iter().map(|h| h.search_terms()).collect();
+    for query in &hashes {
+        let clauses = query.search_terms().clauses();
+        let found: Vec<usize> = (0..stored.len())
+            .filter(|&i| {
+                clauses.iter().any(|clause| {
+                    let ngrams = match clause.field {
+                        ChunkField::Chunk => &stored[i].chunk_ngrams,
+                        ChunkField::DoubleChunk => &stored[i].double_chunk_ngrams,
+                    };
+                    stored[i].block_size == clause.block_size
+                        && clause.ngrams.iter().any(|n| ngrams.contains(n))
+                })
+            })
+            .collect();
+        for (i, h) in hashes.iter().enumerate() {
+            let score = compare(query, h).unwrap();
+            if score > 0 && query.normalized() != h.normalized() {
+                assert!(found.contains(&i), "{} not found for {}", h, query);
+            }
+            if found.contains(&i) {
+                assert!(query.comparable_with(h));
+            }
+        }
+    }
+}
+
+#[test]
+fn fuzzy_hash_search_terms_are_unique_ngrams_of_normalized_parts() {
+    let h: FuzzyHash = "3:ABCDEFGABCDEFGhhhhhhh:AB".parse().unwrap();
+    let terms = h.search_terms();
+    assert_eq!(terms.block_size, 3);
+    assert_eq!(
+        terms.chunk_ngrams,
+        [
+            "ABCDEFG", "BCDEFGA", "CDEFGAB", "DEFGABC", "EFGABCD", "FGABCDE", "GABCDEF", "BCDEFGh",
+            "CDEFGhh", "DEFGhhh"
+        ]
+    );
+    assert!(terms.double_chunk_ngrams.is_empty());
+    let clauses = terms.clauses();
+    let block_sizes: Vec<u64> = clauses.iter().map(|c| c.block_size).collect();
+    assert_eq!(block_sizes, [3]);
+}
+
+#[test]
+fn fuzzy_hashes_with_nonzero_score_share_ngram() {
+    let hashes: Vec<FuzzyHash> = (0..20)
+        .map(|i| {
+            let mut data = pseudo_random_bytes(20_000);
+            data[i * 1000] ^= 0xff;
+            data.truncate(20_000 - i * 500);
+            hash(&data).unwrap().parse().unwrap()
+        })
+        .collect();
+    for h1 in &hashes {
+        for h2 in &hashes {
+            if compare(h1, h2).unwrap() > 0 {
+                let (h1, h2) = (h1.normalized(), h2.normalized());
+                let ngrams: Vec<_> = h1.ngrams(FuzzyHash::DEFAULT_NGRAM_SIZE).collect();
+                assert!(h2
+                    .ngrams(FuzzyHash::DEFAULT_NGRAM_SIZE)
+                    .any(|ngram| ngrams.contains(&ngram)));
+            }
+        }
+    }
+}
+
+#[test]
+fn fuzzy_hash_normalized_shortens_sequences_of_identical_characters() {
+    let h: FuzzyHash = "6:aaaaaaBBBB:cccdddd".parse().unwrap();
+    assert_eq!(h.normalized().as_str(), "6:aaaBBB:cccddd");
+    assert_eq!(h.normalized().block_size(), 6);
+    assert_eq!(h.normalized().double_chunk(), "cccddd");
+}
+
+#[test]
+fn fuzzy_hash_normalized_returns_same_hash_when_there_are_no_long_sequences() {
+    let h: FuzzyHash = "3:aNRn:aNRn".parse().unwrap();
+    assert_eq!(h.normalized(), h);
+}
+
+#[test]
+fn fuzzy_hash_normalized_does_not_change_score() {
+    let h1: FuzzyHash = "3:HRhhhhhhhhhhhhhn:H".parse().unwrap();
+    let h2: FuzzyHash = "3:HRhhhhhhn:H".parse().unwrap();
+    assert_eq!(compare(h1.normalized(), &h2), compare(&h1, &h2));
+    assert_eq!(compare(h1.normalized(), h2.normalized()), compare(&h1, &h2));
+}
+
+#[test]
+fn fuzzy_hash_parse_csv_line_returns_hash_and_unescaped_filename() {
+    let line = "48:9MABzSwnjpDeSrLp8+nagE4f3ZMvcDT0MIhqy6Ic:9XMwnjdeSHS+n5ZfScX0MJ7,\"C:\\say \\\"hi\\\".txt\"\r\n";
+    let (hash, filename) = FuzzyHash::parse_csv_line(line).unwrap();
+    assert_eq!(
+        hash,
+        hash_from_file("tests/file.txt").unwrap().parse().unwrap()
+    );
+    assert_eq!(filename, "C:\\say \"hi\".txt");
+}
+
+#[test]
+fn fuzzy_hash_parse_csv_line_returns_error_when_line_is_invalid() {
+    let cases = [
+        ("3:aNRn:aNRn", "missing filename"),
+        (
+            "3:aNRn:aNRn,file.txt",
+            "filename is not between quotation marks",
+        ),
+        ("4:aNRn:aNRn,\"file.txt\"", "invalid hash"),
+        ("3:aN-n:aNRn,\"file.txt\"", "invalid hash"),
+    ];
+    for &(line, reason) in &cases {
+        assert_eq!(
+            FuzzyHash::parse_csv_line(line),
+            Err(Error::InvalidSignatureLine {
+                reason: reason.to_string()
+            }),
+            "line {:?}",
+            line
+        );
+    }
+}
+
+#[test]
+fn fuzzy_hash_is_displayed_as_original_hash() {
+    let h: FuzzyHash = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C".parse().unwrap();
+    assert_eq!(h.to_string(), "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C");
+    assert_eq!(h.as_str(), "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C");
+}
+
+#[test]
+fn fuzzy_hashes_are_equal_when_block_sizes_and_parts_are_equal() {
+    let h = |s: &str| s.parse::<FuzzyHash>().unwrap();
+    assert_eq!(h("3:aNRn:aNRn"), h("3:aNRn:aNRn"));
+    assert_eq!(h("3:aNRn:aNRn"), h("03:aNRn:aNRn"));
+    assert_ne!(h("3:aNRn:aNRn"), h("6:aNRn:aNRn"));
+    assert_ne!(h("3:aNRn:aNRn"), h("3:aNRn:aNR"));
+}
+
+#[test]
+fn fuzzy_hashes_can_be_used_as_keys_in_hash_sets() {
+    let mut set = std::collections::HashSet::new();
+    set.insert("3:aNRn:aNRn".parse::<FuzzyHash>().unwrap());
+    set.insert("03:aNRn:aNRn".parse::<FuzzyHash>().unwrap());
+    set.insert("6:aNRn:aNRn".parse::<FuzzyHash>().unwrap());
+    assert_eq!(set.len(), 2);
+}
+
+#[test]
+fn fuzzy_hashes_are_sorted_by_block_size_then_chunk_then_double_chunk() {
+    let mut hashes: Vec<FuzzyHash> = ["12:a:b", "3:b:a", "6:a:a", "3:a:b", "3:a:a"]
+        .iter()
+        .map(|h| h.parse().unwrap())
+        .collect();
+    hashes.sort();
+    let sorted: Vec<String> = hashes.iter().map(|h| h.to_string()).collect();
+    assert_eq!(sorted, ["3:a:a", "3:a:b", "3:b:a", "6:a:a", "12:a:b"]);
+    let set: std::collections::BTreeSet<FuzzyHash> = hashes.into_iter().collect();
+    assert_eq!(set.len(), 5);
+}
+
+#[test]
+fn fuzzy_hash_can_be_created_via_try_from() {
+    use std::convert::TryFrom;
+
+    let h = FuzzyHash::try_from("3:aNRn:aNRn").unwrap();
+    assert_eq!(h, "3:aNRn:aNRn".parse().unwrap());
+    assert!(FuzzyHash::try_from("3:aNRn").is_err());
+}
+
+#[test]
+fn fuzzy_hash_parses_hash_returned_by_hash() {
+    let h = hash(&pseudo_random_bytes(100_000)).unwrap();
+    assert_eq!(h.parse::<FuzzyHash>().unwrap().as_str(), h);
+}
+
+#[test]
+fn fuzzy_hash_returns_error_when_hash_is_invalid() {
+    let cases = [
+        ("", "missing first part"),
+        ("3", "missing first part"),
+        ("3:aNRn", "missing second part"),
+        ("3:aNRn:aNRn:aNRn", "too many parts"),
+        (":aNRn:aNRn", "invalid block size"),
+        ("x:aNRn:aNRn", "invalid block size"),
+        ("+3:aNRn:aNRn", "invalid block size"),
+        ("99999999999999999999999:aNRn:aNRn", "invalid block size"),
+        ("0:aNRn:aNRn", "invalid block size"),
+        ("4:aNRn:aNRn", "invalid block size"),
+        ("9:aNRn:aNRn", "invalid block size"),
+        ("3:aN-n:aNRn", "invalid character in part"),
+        ("3:aNRn:aNRn,\"file.txt\"", "invalid character in part"),
+        ("3:aNRn:aN Rn", "invalid character in part"),
+        (&format!("3:{}:aNRn", "a".repeat(65)), "too long part"),
+    ];
+    for &(hash, reason) in &cases {
+        assert_eq!(
+            hash.parse::<FuzzyHash>(),
+            Err(Error::InvalidHash {
+                reason: reason.to_string()
+            }),
+            "hash {:?}",
+            hash
+        );
+    }
+}
+
+//
+// CompactFuzzyHash
+//
+
+#[test]
+fn compact_fuzzy_hash_round_trips() {
+    let mut hashes = vec![
+        "3::".to_string(),
+        "3:aNRn:aNRn".to_string(),
+        "13835058055282163712:+/09azAZ:/".to_string(),
+    ];
+    for i in 0..10 {
+        hashes.push(hash(&pseudo_random_bytes(1000 << i)).unwrap());
+    }
+    for h in &hashes {
+        let h: FuzzyHash = h.parse().unwrap();
+        let compact = h.to_compact();
+        assert_eq!(FuzzyHash::from_compact(&compact), h);
+        assert_eq!(
+            CompactFuzzyHash::from_bytes(compact.as_bytes()),
+            Ok(compact)
+        );
+    }
+}
+
+#[test]
+fn compact_fuzzy_hash_takes_around_half_of_memory_of_hash() {
+    let h = hash(&pseudo_random_bytes(100_000)).unwrap();
+    let compact = h.parse::<FuzzyHash>().unwrap().to_compact();
+    assert!(compact.as_bytes().len() * 10 < h.len() * 8);
+}
+
+#[test]
+fn compact_fuzzy_hash_from_compact_writes_block_size_without_leading_zeros() {
+    let h: FuzzyHash = "003:aNRn:aNRn".parse().unwrap();
+    assert_eq!(
+        FuzzyHash::from_compact(&h.to_compact()).as_str(),
+        "3:aNRn:aNRn"
+    );
+}
+
+#[test]
+fn compact_fuzzy_hash_from_bytes_returns_error_when_bytes_are_invalid() {
+    let valid = "3:aNRn:aNR".parse::<FuzzyHash>().unwrap().to_compact();
+    let mut invalid_padding = valid.as_bytes().to_vec();
+    *invalid_padding.last_mut().unwrap() |= 1;
+    let mut too_long = valid.as_bytes().to_vec();
+    too_long.push(0);
+    let cases: Vec<(&[u8], &str)> = vec![
+        (&[0, 0], "missing header"),
+        (&[63, 0, 0], "invalid block size"),
+        (&[64, 0, 0], "invalid block size"),
+        (&[0, 65, 0], "too long part"),
+        (&valid.as_bytes()[..4], "invalid length"),
+        (&too_long, "invalid length"),
+        (&invalid_padding, "non-zero padding"),
+    ];
+    for (bytes, reason) in cases {
+        assert_eq!(
+            CompactFuzzyHash::from_bytes(bytes),
+            Err(Error::InvalidHash {
+                reason: reason.to_string()
+            }),
+            "bytes {:?}",
+            bytes
+        );
+    }
+}
+
+//
+// MultiHasher
+//
+
+#[test]
+#[cfg(feature = "digest")]
+fn multi_hasher_computes_fuzzy_hash_and_digests_of_file_in_single_pass() {
+    use sha2::Digest;
+    use ssdeep::MultiHasher;
+
+    let data = std::fs::read("tests/file.txt").unwrap();
+    let mut hasher = MultiHasher::new().unwrap();
+    hasher.add_digest("md5", md5::Md5::new());
+    hasher.add_digest("sha256", sha2::Sha256::new());
+    let size = hasher.update_from_file("tests/file.txt").unwrap();
+    let digests = hasher.finish().unwrap();
+    assert_eq!(size, data.len() as u64);
+    assert_eq!(
+        digests.fuzzy_hash,
+        hash_from_file("tests/file.txt").unwrap()
+    );
+    assert_eq!(digests.digest("md5").unwrap(), &md5::Md5::digest(&data)[..]);
+    assert_eq!(
+        digests.digest("sha256").unwrap(),
+        &sha2::Sha256::digest(&data)[..]
+    );
+    assert_eq!(digests.digest("sha1"), None);
+    let names: Vec<_> = digests
+        .digests
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(names, ["md5", "sha256"]);
+}
+
+#[test]
+#[cfg(feature = "digest")]
+fn multi_hasher_returns_same_results_for_buffers_and_readers() {
+    use ssdeep::MultiHasher;
+
+    let data = pseudo_random_bytes(100_000);
+    let mut hasher1 = MultiHasher::new().unwrap();
+    hasher1.add_digest("sha256", sha2::Sha256::default());
+    for chunk in data.chunks(999) {
+        hasher1.update(chunk).unwrap();
+    }
+    let mut hasher2 = MultiHasher::new().unwrap();
+    hasher2.add_digest("sha256", sha2::Sha256::default());
+    hasher2.update_from_reader(&data[..]).unwrap();
+    let digests = hasher1.finish().unwrap();
+    assert_eq!(digests, hasher2.finish().unwrap());
+    assert_eq!(digests.fuzzy_hash, hash(&data).unwrap());
+    assert_eq!(digests.hex_digest("sha256").unwrap().len(), 64);
+}
+
+//
+// PreparedHash
+//
+
+#[test]
+#[cfg(feature = "native-compare")]
+fn prepared_hash_compare_returns_same_scores_as_compare() {
+    use ssdeep::PreparedHash;
+
+    let mut hashes = index_test_hashes();
+    hashes.push("3:HRhhhhhhhhhhhhhn:Hhhhhh".to_string());
+    hashes.push("3:HRhhhn:Hhhh".to_string());
+    let prepared: Vec<PreparedHash> = hashes.iter().map(|h| h.parse().unwrap()).collect();
+    for (h1, p1) in hashes.iter().zip(&prepared) {
+        for (h2, p2) in hashes.iter().zip(&prepared) {
+            assert_eq!(p1.compare(p2), compare(h1, h2).unwrap(), "{} {}", h1, h2);
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "native-compare")]
+fn prepared_hash_can_be_created_from_fuzzy_hash() {
+    use ssdeep::PreparedHash;
+
+    let h: FuzzyHash = "6:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C".parse().unwrap();
+    assert_eq!(PreparedHash::from(&h), h.prepare());
+    assert_eq!(h.prepare().block_size(), 6);
+    assert!("invalid".parse::<PreparedHash>().is_err());
+}
+
+#[test]
+#[cfg(feature = "native-compare")]
+fn prepared_hash_compare_does_not_allocate() {
+    use ssdeep::PreparedHash;
+
+    let p1: PreparedHash = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C".parse().unwrap();
+    let p2: PreparedHash = "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx".parse().unwrap();
+    let allocations = allocations_in(|| {
+        for _ in 0..1000 {
+            p1.compare(&p2);
+        }
+    });
+    assert_eq!(allocations, 0);
+}
+
+//
+// FuzzyHasher
+//
+
+fn pseudo_random_bytes(len: usize) -> Vec<u8> {
+    let mut state: u32 = 1;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+#[test]
+fn fuzzy_hasher_returns_same_hash_as_hash_when_data_are_fed_in_chunks() {
+    let data = pseudo_random_bytes(100_000);
+    let mut hasher = FuzzyHasher::new().unwrap();
+    for chunk in data.chunks(777) {
+        hasher.update(chunk).unwrap();
+    }
+    assert_eq!(hasher.digest(), hash(&data));
+}
+
+#[test]
+fn fuzzy_hasher_returns_hash_of_empty_data_when_nothing_is_fed() {
+    let hasher = FuzzyHasher::new().unwrap();
+    assert_eq!(hasher.digest().unwrap(), "3::");
+}
+
+#[test]
+fn fuzzy_hasher_can_be_fed_after_digest() {
+    let mut hasher = FuzzyHasher::new().unwrap();
+    hasher.update(b"Hello ").unwrap();
+    assert_eq!(hasher.digest(), hash(b"Hello "));
+    hasher.update(b"there!").unwrap();
+    assert_eq!(hasher.digest().unwrap(), "3:aNRn:aNRn");
+}
+
+#[test]
+fn fuzzy_hasher_can_be_written_into() {
+    use std::io::Write;
+
+    let data = pseudo_random_bytes(100_000);
+    let mut hasher = FuzzyHasher::new().unwrap();
+    std::io::copy(&mut &data[..], &mut hasher).unwrap();
+    hasher.write_all(b"Hello there!").unwrap();
+    hasher.flush().unwrap();
+    let mut expected = data.clone();
+    expected.extend_from_slice(b"Hello there!");
+    assert_eq!(hasher.digest(), hash(&expected));
+}
+
+#[test]
+fn fuzzy_hasher_can_be_wrapped_in_buf_writer() {
+    use std::io::Write;
+
+    let data = pseudo_random_bytes(100_000);
+    let mut writer = std::io::BufWriter::new(FuzzyHasher::new().unwrap());
+    for chunk in data.chunks(13) {
+        writer.write_all(chunk).unwrap();
+    }
+    let hasher = writer.into_inner().unwrap();
+    assert_eq!(hasher.digest(), hash(&data));
+}
+
+#[test]
+fn fuzzy_hasher_digest_with_flags_eliminates_sequences_when_requested() {
+    let mut hasher = FuzzyHasher::new().unwrap();
+    hasher.update(&[4; 20]).unwrap();
+    let h = hasher.digest_with_flags(DigestFlags::ELIMSEQ).unwrap();
+    assert_eq!(h, "3:HRhhhn:H");
+}
+
+#[test]
+fn fuzzy_hasher_digest_with_flags_does_not_truncate_second_part_when_requested() {
+    let data: Vec<u8> = [48, 157].iter().cycle().take(66).cloned().collect();
+    let mut hasher = FuzzyHasher::new().unwrap();
+    hasher.update(&data).unwrap();
+    let h = hasher.digest().unwrap();
+    assert_eq!(
+        h,
+        "3:m4V4VYYYYYYYYYYYYYYYYYYYYYYYYYYYYY4:m4SVYYYYYYYYYYYYYYYYYYYYYYYYYYYw"
+    );
+    let h = hasher.digest_with_flags(DigestFlags::NOTRUNC).unwrap();
+    assert_eq!(
+        h,
+        "3:m4V4VYYYYYYYYYYYYYYYYYYYYYYYYYYYYY4:m4SVYYYYYYYYYYYYYYYYYYYYYYYYYYYYY4"
+    );
+}
+
+#[test]
+fn fuzzy_hasher_digest_with_empty_flags_returns_same_hash_as_digest() {
+    let mut hasher = FuzzyHasher::new().unwrap();
+    hasher.update(&[4; 20]).unwrap();
+    assert_eq!(
+        hasher.digest_with_flags(DigestFlags::empty()),
+        hasher.digest()
+    );
+}
+
+#[test]
+fn fuzzy_hasher_returns_same_hash_when_total_input_length_is_set() {
+    for &len in &[0, 100, 10_000, 1_000_000] {
+        let data = pseudo_random_bytes(len);
+        let mut hasher = FuzzyHasher::new().unwrap();
+        hasher.set_total_input_length(len as u64).unwrap();
+        for chunk in data.chunks(4096) {
+            hasher.update(chunk).unwrap();
+        }
+        assert_eq!(hasher.digest(), hash(&data), "length {}", len);
+    }
+}
+
+#[test]
+fn fuzzy_hasher_set_total_input_length_returns_error_when_other_length_is_set() {
+    let mut hasher = FuzzyHasher::new().unwrap();
+    hasher.set_total_input_length(10).unwrap();
+    assert!(hasher.set_total_input_length(10).is_ok());
+    assert!(hasher.set_total_input_length(20).is_err());
+}
+
+#[test]
+fn fuzzy_hasher_digest_returns_error_when_fed_length_differs_from_set_length() {
+    let mut hasher = FuzzyHasher::new().unwrap();
+    hasher.set_total_input_length(100).unwrap();
+    hasher.update(b"Hello there!").unwrap();
+    assert!(hasher.digest().is_err());
+}
+
+#[test]
+fn fuzzy_hasher_clone_can_be_fed_independently_of_original() {
+    let data = pseudo_random_bytes(100_000);
+    let (prefix, rest) = data.split_at(40_000);
+    let mut hasher = FuzzyHasher::new().unwrap();
+    hasher.update(prefix).unwrap();
+    let prefix_hasher = hasher.clone();
+    hasher.update(rest).unwrap();
+    assert_eq!(prefix_hasher.digest(), hash(prefix));
+    assert_eq!(hasher.digest(), hash(&data));
+}
+
+#[test]
+fn fuzzy_hasher_clone_keeps_set_total_input_length() {
+    let mut hasher = FuzzyHasher::new().unwrap();
+    hasher.set_total_input_length(100).unwrap();
+    let mut clone = hasher.clone();
+    assert!(clone.set_total_input_length(20).is_err());
+}
+
+#[test]
+fn digest_flags_can_be_combined() {
+    let mut flags = DigestFlags::ELIMSEQ;
+    flags |= DigestFlags::NOTRUNC;
+    assert_eq!(flags, DigestFlags::ELIMSEQ | DigestFlags::NOTRUNC);
+    assert!(flags.contains(DigestFlags::ELIMSEQ));
+    assert!(flags.contains(DigestFlags::NOTRUNC));
+    assert!(!DigestFlags::ELIMSEQ.contains(DigestFlags::NOTRUNC));
+    assert_eq!(flags.bits(), 0x3);
+    assert_eq!(DigestFlags::default(), DigestFlags::empty());
+}
+
+//
+// hash_file_region()
+//
+
+#[test]
+fn hash_file_region_returns_hash_of_byte_range() {
+    let data = std::fs::read("tests/file.txt").unwrap();
+    let h = hash_file_region("tests/file.txt", 100, 1000);
+    assert_eq!(h, hash(&data[100..1100]));
+}
+
+#[test]
+fn hash_region_returns_hash_of_byte_range_of_reader() {
+    let data = std::fs::read("tests/file.txt").unwrap();
+    let h = ssdeep::hash_region(std::io::Cursor::new(&data), 1000, 0);
+    assert_eq!(h, hash(b""));
+}
+
+#[test]
+fn hash_file_region_returns_error_when_range_is_beyond_end_of_file() {
+    let h = hash_file_region("tests/file.txt", 1000, 1000);
+    assert!(matches!(h, Err(Error::Io(_))));
+}
+
+//
+// hash_dir_tree()
+//
+
+fn create_tree(root: &std::path::Path, files: &[(&str, &[u8])]) {
+    let _ = std::fs::remove_dir_all(root);
+    for (path, content) in files {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+}
+
+#[test]
+fn hash_dir_tree_returns_same_hash_for_same_trees() {
+    let data = std::fs::read("tests/file.txt").unwrap();
+    let root1 = std::env::temp_dir().join("ssdeep-rs-tree1");
+    let root2 = std::env::temp_dir().join("ssdeep-rs-tree2");
+    create_tree(&root1, &[("a/b.txt", b"Hello there!"), ("c.txt", &data)]);
+    create_tree(&root2, &[("c.txt", &data), ("a/b.txt", b"Hello there!")]);
+    let h1 = hash_dir_tree(&root1);
+    let h2 = hash_dir_tree(&root2);
+    std::fs::remove_dir_all(&root1).unwrap();
+    std::fs::remove_dir_all(&root2).unwrap();
+    assert!(h1.is_ok());
+    assert_eq!(h1, h2);
+}
+
+#[test]
+fn hash_dir_tree_returns_similar_hash_for_similar_trees() {
+    let data = std::fs::read("tests/file.txt").unwrap();
+    let root1 = std::env::temp_dir().join("ssdeep-rs-tree3");
+    let root2 = std::env::temp_dir().join("ssdeep-rs-tree4");
+    create_tree(&root1, &[("a/b.txt", b"Hello there!"), ("c.txt", &data)]);
+    create_tree(&root2, &[("a/b.txt", b"Hello!"), ("c.txt", &data)]);
+    let h1 = hash_dir_tree(&root1).unwrap();
+    let h2 = hash_dir_tree(&root2).unwrap();
+    std::fs::remove_dir_all(&root1).unwrap();
+    std::fs::remove_dir_all(&root2).unwrap();
+    assert_ne!(h1, h2);
+    assert!(compare(&h1, &h2).unwrap() > 50);
+}
+
+#[test]
+fn hash_dir_tree_returns_error_when_path_is_not_directory() {
+    assert!(hash_dir_tree("tests/file.txt").is_err());
+}
+
+//
+// fs::hash_dir()
+//
+
+fn hash_dir_relative_paths(
+    root: &std::path::Path,
+    options: &ssdeep::fs::HashDirOptions,
+) -> Vec<String> {
+    ssdeep::fs::hash_dir(root, options)
+        .unwrap()
+        .map(|entry| {
+            let (path, _) = entry.unwrap();
+            let path = path
+                .strip_prefix(root)
+                .unwrap()
+                .to_string_lossy()
+                .into_owned();
+            path.replace('\\', "/")
+        })
+        .collect()
+}
+
+#[test]
+fn hash_dir_returns_hashes_of_all_files_in_name_order() {
+    use ssdeep::fs::hash_dir;
+    use ssdeep::fs::HashDirOptions;
+
+    let data = std::fs::read("tests/file.txt").unwrap();
+    let root = std::env::temp_dir().join("ssdeep-rs-hash-dir1");
+    create_tree(
+        &root,
+        &[
+            ("c.txt", &data),
+            ("a/b/d.txt", b"Hello there!"),
+            ("a/a.txt", b"Hello"),
+        ],
+    );
+    let entries: Vec<_> = hash_dir(&root, &HashDirOptions::default())
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    std::fs::remove_dir_all(&root).unwrap();
+    let expected_paths = [
+        root.join("a/a.txt"),
+        root.join("a/b/d.txt"),
+        root.join("c.txt"),
+    ];
+    let paths: Vec<_> = entries.iter().map(|(path, _)| path.clone()).collect();
+    assert_eq!(paths, expected_paths);
+    assert_eq!(
+        entries[2].1,
+        hash(&data).unwrap().parse::<FuzzyHash>().unwrap()
+    );
+}
+
+#[test]
+fn hash_dir_does_not_descend_below_max_depth() {
+    use ssdeep::fs::HashDirOptions;
+
+    let root = std::env::temp_dir().join("ssdeep-rs-hash-dir2");
+    create_tree(
+        &root,
+        &[
+            ("a.txt", b"Hello"),
+            ("b/c.txt", b"Hello"),
+            ("b/d/e.txt", b"Hello"),
+        ],
+    );
+    let depth = |max_depth| HashDirOptions {
+        max_depth,
+        ..HashDirOptions::default()
+    };
+    let paths0 = hash_dir_relative_paths(&root, &depth(Some(0)));
+    let paths1 = hash_dir_relative_paths(&root, &depth(Some(1)));
+    let paths = hash_dir_relative_paths(&root, &depth(None));
+    std::fs::remove_dir_all(&root).unwrap();
+    assert_eq!(paths0, ["a.txt"]);
+    assert_eq!(paths1, ["a.txt", "b/c.txt"]);
+    assert_eq!(paths, ["a.txt", "b/c.txt", "b/d/e.txt"]);
+}
+
+#[test]
+fn hash_dir_skips_hidden_files_and_directories_when_requested() {
+    use ssdeep::fs::HashDirOptions;
+
+    let root = std::env::temp_dir().join("ssdeep-rs-hash-dir3");
+    create_tree(
+        &root,
+        &[
+            (".a.txt", b"Hello"),
+            (".b/c.txt", b"Hello"),
+            ("d.txt", b"Hello"),
+        ],
+    );
+    let all_paths = hash_dir_relative_paths(&root, &HashDirOptions::default());
+    let visible_paths = hash_dir_relative_paths(
+        &root,
+        &HashDirOptions {
+            include_hidden: false,
+            ..HashDirOptions::default()
+        },
+    );
+    std::fs::remove_dir_all(&root).unwrap();
+    assert_eq!(all_paths, [".a.txt", ".b/c.txt", "d.txt"]);
+    assert_eq!(visible_paths, ["d.txt"]);
+}
+
+#[test]
+#[cfg(unix)]
+fn hash_dir_does_not_follow_symbolic_links() {
+    use ssdeep::fs::HashDirOptions;
+
+    let root = std::env::temp_dir().join("ssdeep-rs-hash-dir4");
+    create_tree(&root, &[("a/b.txt", b"Hello")]);
+    std::os::unix::fs::symlink(root.join("a"), root.join("c")).unwrap();
+    std::os::unix::fs::symlink(root.join("a/b.txt"), root.join("d.txt")).unwrap();
+    let paths = hash_dir_relative_paths(&root, &HashDirOptions::default());
+    std::fs::remove_dir_all(&root).unwrap();
+    assert_eq!(paths, ["a/b.txt"]);
+}
+
+#[test]
+fn hash_dir_hashes_only_included_files_that_are_not_excluded() {
+    use ssdeep::fs::HashDirOptions;
+
+    let root = std::env::temp_dir().join("ssdeep-rs-hash-dir5");
+    create_tree(
+        &root,
+        &[
+            ("a.exe", b"Hello"),
+            ("a.txt", b"Hello"),
+            ("bin/b.exe", b"Hello"),
+            ("bin/c.dll", b"Hello"),
+            ("bin/x/d.exe", b"Hello"),
+            ("build/e.exe", b"Hello"),
+            ("f1.exe", b"Hello"),
+            ("fa.exe", b"Hello"),
+        ],
+    );
+    let paths = |include: &[&str], exclude: &[&str]| {
+        let options = HashDirOptions {
+            include: include.iter().map(|p| p.to_string()).collect(),
+            exclude: exclude.iter().map(|p| p.to_string()).collect(),
+            ..HashDirOptions::default()
+        };
+        hash_dir_relative_paths(&root, &options)
+    };
+    let exe_paths = paths(&["*.exe", "*.dll"], &["build/", "f[0-9].exe"]);
+    let bin_paths = paths(&["bin/*.exe"], &[]);
+    let deep_paths = paths(&["**/x/*"], &[]);
+    let not_bin_paths = paths(&["*.exe"], &["/bin"]);
+    std::fs::remove_dir_all(&root).unwrap();
+    assert_eq!(
+        exe_paths,
+        ["a.exe", "bin/b.exe", "bin/c.dll", "bin/x/d.exe", "fa.exe"]
+    );
+    assert_eq!(bin_paths, ["bin/b.exe"]);
+    assert_eq!(deep_paths, ["bin/x/d.exe"]);
+    assert_eq!(not_bin_paths, ["a.exe", "build/e.exe", "f1.exe", "fa.exe"]);
+}
+
+#[test]
+fn hash_dir_skips_files_ignored_by_gitignore_when_requested() {
+    use ssdeep::fs::HashDirOptions;
+
+    let root = std::env::temp_dir().join("ssdeep-rs-hash-dir6");
+    create_tree(
+        &root,
+        &[
+            (".git/config", b"Hello"),
+            (".gitignore", b"# Comment\n*.log\n!keep.log\ntarget/\n"),
+            ("a.log", b"Hello"),
+            ("keep.log", b"Hello"),
+            ("src/.gitignore", b"*.tmp\n/keep.log\n"),
+            ("src/b.tmp", b"Hello"),
+            ("src/keep.log", b"Hello"),
+            ("src/main.rs", b"Hello"),
+            ("target/c.rs", b"Hello"),
+        ],
+    );
+    let all_paths = hash_dir_relative_paths(&root, &HashDirOptions::default());
+    let paths = hash_dir_relative_paths(
+        &root,
+        &HashDirOptions {
+            respect_gitignore: true,
+            ..HashDirOptions::default()
+        },
+    );
+    std::fs::remove_dir_all(&root).unwrap();
+    assert_eq!(all_paths.len(), 9);
+    assert_eq!(
+        paths,
+        [".gitignore", "keep.log", "src/.gitignore", "src/main.rs"]
+    );
+}
+
+#[test]
+fn hash_dir_with_progress_reports_each_file_before_hashing_it() {
+    use ssdeep::fs::HashDirOptions;
+
+    let root = std::env::temp_dir().join("ssdeep-rs-hash-dir7");
+    create_tree(&root, &[("a.txt", b"Hello"), ("b/c.txt", b"Hello")]);
+    let mut reports = Vec::new();
+    let count = ssdeep::fs::hash_dir_with_progress(&root, &HashDirOptions::default(), |p| {
+        reports.push(p.clone())
+    })
+    .unwrap()
+    .count();
+    std::fs::remove_dir_all(&root).unwrap();
+    assert_eq!(count, 2);
+    let expected_paths = [Some(root.join("a.txt")), Some(root.join("b/c.txt")), None];
+    assert_eq!(
+        reports,
+        (0..)
+            .zip(expected_paths)
+            .map(|(done, current_path)| Progress {
+                phase: Phase::Hashing,
+                done,
+                total: None,
+                current_path,
+            })
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn hash_dir_returns_error_when_root_is_not_directory() {
+    let options = ssdeep::fs::HashDirOptions::default();
+    assert!(ssdeep::fs::hash_dir("tests/file.txt", &options).is_err());
+}
+
+//
+// hash_with_chunk_map()
+//
+
+#[test]
+fn hash_with_chunk_map_returns_same_hash_as_hash() {
+    let data = std::fs::read("tests/file.txt").unwrap();
+    let (h, _) = hash_with_chunk_map(&data).unwrap();
+    assert_eq!(h, hash(&data).unwrap());
+}
+
+#[test]
+fn hash_with_chunk_map_returns_range_for_each_hash_character() {
+    let data = std::fs::read("tests/file.txt").unwrap();
+    let (h, map) = hash_with_chunk_map(&data).unwrap();
+    let parts: Vec<_> = h.split(':').collect();
+    assert_eq!(map.block_size.to_string(), parts[0]);
+    assert_eq!(map.chunks.len(), parts[1].len());
+    assert_eq!(map.double_chunks.len(), parts[2].len());
+}
+
+#[test]
+fn hash_with_chunk_map_returns_contiguous_ranges_covering_whole_input() {
+    let data = std::fs::read("tests/file.txt").unwrap();
+    let (_, map) = hash_with_chunk_map(&data).unwrap();
+    for ranges in &[map.chunks, map.double_chunks] {
+        assert_eq!(ranges[0].start, 0);
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+        }
+        assert_eq!(ranges[ranges.len() - 1].end, data.len() as u64);
+    }
+}
+
+#[test]
+fn hash_with_chunk_map_returns_empty_map_for_empty_input() {
+    let (h, map) = hash_with_chunk_map(b"").unwrap();
+    assert_eq!(h, "3::");
+    assert!(map.chunks.is_empty());
+    assert!(map.double_chunks.is_empty());
+}
+
+//
+// hash_chunks()
+//
+
+#[test]
+fn hash_chunks_returns_contiguous_chunks_within_size_limits() {
+    let data = pseudo_random_bytes(1024 * 1024);
+    let options = ChunkingOptions::default();
+    let chunks = hash_chunks(&data, &options).unwrap();
+    assert!(chunks.len() > 1);
+    let mut offset = 0;
+    for (i, chunk) in chunks.iter().enumerate() {
+        assert_eq!(chunk.offset, offset);
+        assert!(chunk.size as usize <= options.max_size);
+        if i + 1 < chunks.len() {
+            assert!(chunk.size as usize > options.min_size);
+        }
+        let range = chunk.offset as usize..(chunk.offset + chunk.size) as usize;
+        assert_eq!(chunk.hash, hash(&data[range]).unwrap());
+        offset += chunk.size;
+    }
+    assert_eq!(offset, data.len() as u64);
+}
+
+#[test]
+fn hash_chunks_returns_same_chunks_after_insertion_at_start() {
+    let data = pseudo_random_bytes(1024 * 1024);
+    let mut shifted = b"inserted bytes".to_vec();
+    shifted.extend_from_slice(&data);
+    let options = ChunkingOptions::default();
+    let chunks = hash_chunks(&data, &options).unwrap();
+    let shifted_chunks = hash_chunks(&shifted, &options).unwrap();
+    let same = chunks[1..]
+        .iter()
+        .filter(|c| shifted_chunks.iter().any(|s| s.hash == c.hash))
+        .count();
+    assert_eq!(same, chunks.len() - 1);
+    assert!(compare_chunks(&chunks, &shifted_chunks).unwrap() >= 90);
+}
+
+#[test]
+fn hash_chunks_returns_no_chunks_for_empty_input() {
+    let chunks = hash_chunks(b"", &ChunkingOptions::default()).unwrap();
+    assert!(chunks.is_empty());
+    assert_eq!(compare_chunks(&chunks, &chunks), Ok(100));
+}
+
+#[test]
+fn compare_chunks_returns_zero_when_one_list_is_empty() {
+    let chunks = hash_chunks(b"Hello there!", &ChunkingOptions::default()).unwrap();
+    assert_eq!(compare_chunks(&chunks, &[]), Ok(0));
+}
+
+//
+// shards_for()
+//
+
+#[test]
+fn shards_for_returns_common_shard_for_matching_hashes() {
+    let h1 = "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C";
+    let h2 = "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx";
+    assert!(compare(h1, h2).unwrap() > 0);
+    for n_shards in 1..64 {
+        let shards1 = shards_for(h1, n_shards).unwrap();
+        let shards2 = shards_for(h2, n_shards).unwrap();
+        assert!(shards1.iter().any(|shard| shards2.contains(shard)));
+    }
+}
+
+#[test]
+fn shards_for_returns_sorted_unique_shards_within_range() {
+    let shards = shards_for("3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C", 8).unwrap();
+    assert!(shards.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(shards.iter().all(|&shard| shard < 8));
+}
+
+#[test]
+fn shards_for_returns_single_shard_when_hash_has_no_ngrams() {
+    assert_eq!(shards_for("3:u+N:u+N", 8).unwrap().len(), 1);
+    assert_eq!(
+        shards_for("3:u+N:u+N", 8).unwrap(),
+        shards_for("3:u+N:u+N", 8).unwrap()
+    );
+}
+
+#[test]
+fn shards_for_returns_error_when_hash_is_invalid() {
+    assert!(matches!(
+        shards_for("XYZ", 8),
+        Err(Error::InvalidHash { .. })
+    ));
+}
+
+//
+// conformance with the ssdeep executable
+//
+
+// Returns the ssdeep executable to check the conformance against (either from
+// the SSDEEP_EXECUTABLE environment variable or from PATH), or None when it is
+// not available.
+fn ssdeep_executable() -> Option<String> {
+    let executable = std::env::var("SSDEEP_EXECUTABLE").unwrap_or_else(|_| "ssdeep".to_string());
+    let output = std::process::Command::new(&executable).arg("-V").output();
+    match output {
+        Ok(ref output) if output.status.success() => Some(executable),
+        _ => None,
+    }
+}
+
+#[test]
+fn hashes_and_scores_are_same_as_from_ssdeep_executable() {
+    let executable = match ssdeep_executable() {
+        Some(executable) => executable,
+        None => return,
+    };
+    let dir = std::env::temp_dir().join("ssdeep-rs-conformance");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut inputs = vec![
+        b"Hello there!".to_vec(),
+        std::fs::read("tests/file.txt").unwrap(),
+    ];
+    for &size in &[1000, 5000, 64 * 1024, 1024 * 1024] {
+        let data = pseudo_random_bytes(size);
+        let mut mutated = data.clone();
+        for i in (0..mutated.len()).step_by(997) {
+            mutated[i] ^= 0xff;
+        }
+        mutated.truncate(size * 9 / 10);
+        inputs.push(data);
+        inputs.push(mutated);
+    }
+    let names: Vec<_> = (0..inputs.len()).map(|i| format!("input{}", i)).collect();
+    for (name, input) in names.iter().zip(&inputs) {
+        std::fs::write(dir.join(name), input).unwrap();
+    }
+    let run = |args: &[&str]| {
+        let output = std::process::Command::new(&executable)
+            .args(args)
+            .args(&names)
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let hash_output = run(&["-b"]);
+    let match_output = run(&["-a", "-p", "-b"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // Signatures.
+    let mut hashes = std::collections::HashMap::new();
+    for line in hash_output.lines().skip(1) {
+        let (h, name) = ssdeep::sigfile::parse_line(line).unwrap();
+        hashes.insert(name, h);
+    }
+    assert_eq!(hashes.len(), inputs.len());
+    let mut batch = HashBatch::new();
+    for (name, input) in names.iter().zip(&inputs) {
+        assert_eq!(hash(input).unwrap(), hashes[name], "hash of {}", name);
+        batch.push(&hashes[name]).unwrap();
+    }
+
+    // Scores (of both the C library and the native scoring of HashBatch).
+    let mut checked = 0;
+    for line in match_output.lines().filter(|line| !line.is_empty()) {
+        // Format: "NAME1 matches NAME2 (SCORE)"
+        let parts: Vec<_> = line.split(' ').collect();
+        assert_eq!(parts.len(), 4, "unexpected line: {}", line);
+        let (name1, name2) = (parts[0], parts[2]);
+        let score: u8 = parts[3]
+            .trim_matches(|c| c == '(' || c == ')')
+            .parse()
+            .unwrap();
+        assert_eq!(compare(&hashes[name1], &hashes[name2]).unwrap(), score);
+        let index2 = names.iter().position(|name| name == name2).unwrap();
+        assert_eq!(batch.scores(&hashes[name1]).unwrap()[index2], score);
+        checked += 1;
+    }
+    assert_eq!(checked, inputs.len() * (inputs.len() - 1));
+}
+
+//
+// sigfile
+//
+
+#[test]
+fn sigfile_format_line_and_parse_line_round_trip_tricky_filenames() {
+    let filenames = [
+        "plain.txt",
+        "with, comma.txt",
+        "with \"quotes\".txt",
+        "C:\\dir\\",
+        "back\\\"slash",
+        "\"",
+        "",
+    ];
+    for filename in &filenames {
+        let line = ssdeep::sigfile::format_line("3:aNRn:aNRn", filename).unwrap();
+        assert_eq!(
+            ssdeep::sigfile::parse_line(&line).unwrap(),
+            ("3:aNRn:aNRn".to_string(), filename.to_string())
+        );
+    }
+}
+
+#[test]
+fn sigfile_format_line_escapes_quotes_like_ssdeep_tool() {
+    assert_eq!(
+        ssdeep::sigfile::format_line("3:aNRn:aNRn", "a\"b,c").unwrap(),
+        "3:aNRn:aNRn,\"a\\\"b,c\""
+    );
+}
+
+#[test]
+fn sigfile_format_line_returns_error_when_filename_contains_newline() {
+    assert!(matches!(
+        ssdeep::sigfile::format_line("3:aNRn:aNRn", "a\nb"),
+        Err(Error::InvalidFilename { .. })
+    ));
+}
+
+#[test]
+fn sigfile_parse_line_ignores_trailing_newline() {
+    assert_eq!(
+        ssdeep::sigfile::parse_line("3:aNRn:aNRn,\"a.txt\"\r\n").unwrap(),
+        ("3:aNRn:aNRn".to_string(), "a.txt".to_string())
+    );
+}
+
+#[test]
+fn sigfile_parse_line_rejects_ambiguous_lines() {
+    let lines = [
+        "3:aNRn:aNRn",
+        "3:aNRn:aNRn,a.txt",
+        "3:aNRn:aNRn,\"a.txt",
+        "3:aNRn:aNRn,\"a\"b.txt\"",
+        "3:aNRn:aNRn,\"a.txt\" ",
+        "3:aN\"Rn:aNRn,\"a.txt\"",
+        "XYZ,\"a.txt\"",
+        "3:aNRn:aNRn,\"",
+    ];
+    for line in &lines {
+        assert!(
+            matches!(
+                ssdeep::sigfile::parse_line(line),
+                Err(Error::InvalidSignatureLine { .. })
+            ),
+            "{}",
+            line
+        );
+    }
+}
+
+#[test]
+fn sigfile_signature_file_round_trips_and_preserves_order() {
+    use ssdeep::sigfile::SignatureFile;
+
+    let mut sigfile = SignatureFile::new();
+    sigfile.push("3:aNRn:aNRn", "z.txt");
+    sigfile.push(
+        "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+        "with, \"quotes\".txt",
+    );
+    sigfile.push("3:aNRn:aNRn", "a.txt");
+    let path = std::env::temp_dir().join("ssdeep-rs-sigfile.txt");
+    sigfile.save(&path).unwrap();
+    let loaded = SignatureFile::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.unwrap(), sigfile);
+}
+
+#[test]
+fn sigfile_signature_file_reads_file_with_old_header() {
+    use ssdeep::sigfile::SignatureFile;
+
+    let contents = "ssdeep,1.0--blocksize:hash:hash,filename\r\n3:aNRn:aNRn,\"a.txt\"\r\n";
+    let sigfile = SignatureFile::read(contents.as_bytes()).unwrap();
+    assert_eq!(sigfile.signatures.len(), 1);
+    assert_eq!(sigfile.signatures[0].filename, "a.txt");
+}
+
+#[test]
+fn sigfile_signature_file_read_returns_error_with_line_number() {
+    use ssdeep::sigfile::SignatureFile;
+
+    let cases = [
+        ("", "missing header"),
+        ("ssdeep,2.0\n", "line 1: invalid header"),
+        (
+            "ssdeep,1.1--blocksize:hash:hash,filename\n3:aNRn:aNRn,\"a.txt\"\nXYZ,\"b.txt\"\n",
+            "line 3: invalid hash",
+        ),
+    ];
+    for &(contents, reason) in &cases {
+        assert_eq!(
+            SignatureFile::read(contents.as_bytes()),
+            Err(Error::InvalidSignatureLine {
+                reason: reason.to_string()
+            })
+        );
+    }
+}
+
+#[test]
+fn sigfile_signature_file_write_returns_error_when_filename_contains_newline() {
+    use ssdeep::sigfile::SignatureFile;
+
+    let mut sigfile = SignatureFile::new();
+    sigfile.push("3:aNRn:aNRn", "a\nb.txt");
+    assert!(matches!(
+        sigfile.write(Vec::new()),
+        Err(Error::InvalidFilename { .. })
+    ));
+}
+
+#[test]
+fn sigfile_signature_file_is_compatible_with_ssdeep_executable() {
+    use ssdeep::sigfile::SignatureFile;
+
+    let executable = match ssdeep_executable() {
+        Some(executable) => executable,
+        None => return,
+    };
+
+    // Read a file written by the executable.
+    let output = std::process::Command::new(&executable)
+        .args(["-ls", "tests/file.txt"])
+        .output()
+        .unwrap();
+    let sigfile = SignatureFile::read(&output.stdout[..]).unwrap();
+    assert_eq!(sigfile.signatures.len(), 1);
+    assert_eq!(
+        sigfile.signatures[0].hash,
+        hash_from_file("tests/file.txt").unwrap()
+    );
+    assert_eq!(sigfile.signatures[0].filename, "tests/file.txt");
+
+    // Let the executable match against a file written by us.
+    let mut known = SignatureFile::new();
+    known.push(&sigfile.signatures[0].hash, "known \"file\".txt");
+    let path = std::env::temp_dir().join("ssdeep-rs-sigfile-known.txt");
+    known.save(&path).unwrap();
+    let output = std::process::Command::new(&executable)
+        .arg("-sm")
+        .arg(&path)
+        .arg("tests/file.txt")
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let output = String::from_utf8(output.stdout).unwrap();
+    assert!(output.contains(":known \"file\".txt (100)"), "{}", output);
+}
+
+//
+// manifest
+//
+
+#[test]
+fn manifest_diff_reports_added_removed_identical_and_modified_files() {
+    use ssdeep::manifest::ModifiedFile;
+    use ssdeep::sigfile::SignatureFile;
+
+    let data = std::fs::read("tests/file.txt").unwrap();
+    let mut modified_data = data.clone();
+    modified_data[100..110].copy_from_slice(b"0123456789");
+    let h1 = hash(&data).unwrap();
+    let h2 = hash(&modified_data).unwrap();
+    let mut old = SignatureFile::new();
+    old.push(&h1, "same.txt");
+    old.push(&h1, "modified.txt");
+    old.push("3:aNRn:aNRn", "rewritten.txt");
+    old.push(&h1, "removed.txt");
+    let mut new = SignatureFile::new();
+    new.push(&h1, "added.txt");
+    new.push(&h2, "modified.txt");
+    new.push(&h1, "rewritten.txt");
+    new.push(&h1, "same.txt");
+
+    let diff = ssdeep::manifest::diff(&old, &new).unwrap();
+    assert_eq!(diff.added, ["added.txt"]);
+    assert_eq!(diff.removed, ["removed.txt"]);
+    assert_eq!(diff.identical, ["same.txt"]);
+    assert_eq!(diff.modified.len(), 2);
+    let modified = &diff.modified[0];
+    assert_eq!(modified.filename, "modified.txt");
+    assert_eq!((&modified.old_hash, &modified.new_hash), (&h1, &h2));
+    assert_eq!(modified.score, compare(&h1, &h2).unwrap());
+    assert!(modified.score > 50);
+    assert_eq!(
+        diff.modified[1],
+        ModifiedFile {
+            filename: "rewritten.txt".to_string(),
+            old_hash: "3:aNRn:aNRn".to_string(),
+            new_hash: h1.clone(),
+            score: 0,
+        }
+    );
+    let similar: Vec<_> = diff.similar(50).map(|f| f.filename.as_str()).collect();
+    assert_eq!(similar, ["modified.txt"]);
+}
+
+#[test]
+fn manifest_diff_files_loads_manifests_from_paths() {
+    use ssdeep::sigfile::SignatureFile;
+
+    let old_path = std::env::temp_dir().join("ssdeep-rs-manifest-old");
+    let new_path = std::env::temp_dir().join("ssdeep-rs-manifest-new");
+    let mut old = SignatureFile::new();
+    old.push("3:aNRn:aNRn", "a.txt");
+    old.save(&old_path).unwrap();
+    SignatureFile::new().save(&new_path).unwrap();
+    let diff = ssdeep::manifest::diff_files(&old_path, &new_path);
+    std::fs::remove_file(&old_path).unwrap();
+    std::fs::remove_file(&new_path).unwrap();
+    assert_eq!(diff.unwrap().removed, ["a.txt"]);
+}
+
+#[test]
+fn manifest_diff_returns_error_when_hash_of_modified_file_is_invalid() {
+    use ssdeep::sigfile::SignatureFile;
+
+    let mut old = SignatureFile::new();
+    old.push("invalid", "a.txt");
+    let mut new = SignatureFile::new();
+    new.push("3:aNRn:aNRn", "a.txt");
+    assert!(ssdeep::manifest::diff(&old, &new).is_err());
+}
+
+//
+// MatchSet
+//
+
+fn match_set() -> MatchSet {
+    let mut set = MatchSet::new();
+    set.insert("dropper.exe", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C")
+        .unwrap();
+    set.insert("hello.txt", "3:aNRn:aNRn").unwrap();
+    set.insert("dropper2.exe", "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx")
+        .unwrap();
+    set
+}
+
+#[test]
+fn match_set_match_hash_returns_all_matches_above_threshold_in_order() {
+    let matches = match_set()
+        .match_hash("3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx")
+        .unwrap();
+    let matches: Vec<_> = matches
+        .iter()
+        .map(|m| (m.label.as_str(), m.score))
+        .collect();
+    assert_eq!(matches, [("dropper.exe", 22), ("dropper2.exe", 100)]);
+}
+
+#[test]
+fn match_set_match_hash_respects_threshold() {
+    let mut set = match_set();
+    set.threshold = 22;
+    let matches = set
+        .match_hash("3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx")
+        .unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].label, "dropper2.exe");
+    assert_eq!(matches[0].hash, "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx");
+}
+
+#[test]
+fn match_set_match_buffer_and_match_file_hash_their_inputs() {
+    let mut set = match_set();
+    set.insert("file.txt", &hash_from_file("tests/file.txt").unwrap())
+        .unwrap();
+    assert_eq!(
+        set.match_buffer(b"Hello there!").unwrap()[0].label,
+        "hello.txt"
+    );
+    assert_eq!(
+        set.match_file("tests/file.txt").unwrap()[0].label,
+        "file.txt"
+    );
+}
+
+#[test]
+fn match_set_insert_returns_error_when_hash_is_invalid() {
+    let mut set = MatchSet::new();
+    assert!(set.insert("bad", "XYZ").is_err());
+    assert!(set.is_empty());
+}
+
+#[test]
+fn match_set_is_loaded_from_signature_file() {
+    use ssdeep::sigfile::SignatureFile;
+
+    let mut sigfile = SignatureFile::new();
+    sigfile.push(&hash_from_file("tests/file.txt").unwrap(), "known.txt");
+    let path = std::env::temp_dir().join("ssdeep-rs-match-set.txt");
+    sigfile.save(&path).unwrap();
+    let set = MatchSet::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    let set = set.unwrap();
+    assert_eq!(set.len(), 1);
+    let matches = set.match_file("tests/file.txt").unwrap();
+    assert_eq!(matches[0].label, "known.txt");
+    assert_eq!(matches[0].score, 100);
+}
+
+//
+// index::FuzzyHashIndex
+//
+
+fn index_test_hashes() -> Vec<String> {
+    use ssdeep::mutation::{mutate, MutationKind};
+
+    let data = pseudo_random_bytes(400_000);
+    let mut hashes = vec!["3:aNRn:aNRn".to_string(), "3:u+N:u+N".to_string()];
+    for i in 0..10 {
+        let input = &data[i * 20_000..i * 20_000 + 5_000 + i * 3_000];
+        hashes.push(hash(input).unwrap());
+        for (j, &rate) in [0.001, 0.01, 0.05].iter().enumerate() {
+            let mutated = mutate(input, MutationKind::ByteFlip, rate, (i * 3 + j) as u64);
+            hashes.push(hash(&mutated).unwrap());
+        }
+        hashes.push(hash(&input[..input.len() / 2]).unwrap());
+    }
+    hashes
+}
+
+#[test]
+fn fuzzy_hash_index_insert_many_returns_identifiers_and_reports_progress() {
+    use ssdeep::index::FuzzyHashIndex;
+
+    let hashes: Vec<_> = (0..1500).map(|_| "3:aNRn:aNRn").collect();
+    let mut index = FuzzyHashIndex::new();
+    let mut reports = Vec::new();
+    let ids = index
+        .insert_many_with_progress(&hashes, |progress| reports.push(progress.clone()))
+        .unwrap();
+    assert_eq!(ids, (0..1500).collect::<Vec<_>>());
+    assert_eq!(index.len(), 1500);
+    let done: Vec<_> = reports.iter().map(|r| (r.phase, r.done, r.total)).collect();
+    assert_eq!(
+        done,
+        [
+            (Phase::Indexing, 1024, Some(1500)),
+            (Phase::Indexing, 1500, Some(1500))
+        ]
+    );
+}
+
+#[test]
+fn fuzzy_hash_index_insert_many_returns_error_when_hash_is_invalid() {
+    use ssdeep::index::FuzzyHashIndex;
+
+    let mut index = FuzzyHashIndex::new();
+    assert!(index.insert_many(&["3:aNRn:aNRn", "invalid"]).is_err());
+    assert_eq!(index.len(), 1);
+}
+
+#[test]
+fn fuzzy_hash_index_search_returns_same_matches_as_comparing_all_hashes() {
+    use ssdeep::index::FuzzyHashIndex;
+
+    let hashes = index_test_hashes();
+    let mut index = FuzzyHashIndex::new();
+    for h in &hashes {
+        index.insert(h).unwrap();
+    }
+    assert_eq!(index.len(), hashes.len());
+    for query in &hashes {
+        let mut expected: Vec<(usize, u8)> = hashes
+            .iter()
+            .enumerate()
+            .map(|(id, h)| (id, compare(query, h).unwrap()))
+            .filter(|&(_, score)| score > 0)
+            .collect();
+        expected.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let found: Vec<(usize, u8)> = index
+            .search(query, 0)
+            .unwrap()
+            .iter()
+            .map(|m| (m.id, m.score))
+            .collect();
+        assert_eq!(found, expected, "query {}", query);
+    }
+}
+
+#[test]
+fn fuzzy_hash_index_candidates_skip_hashes_that_cannot_match() {
+    use ssdeep::index::FuzzyHashIndex;
+
+    let hashes = index_test_hashes();
+    let mut index = FuzzyHashIndex::new();
+    for h in &hashes {
+        index.insert(h).unwrap();
+    }
+    let candidates = index.candidates(&hashes[2]).unwrap();
+    assert!(candidates.contains(&2));
+    assert!(candidates.len() < hashes.len() / 2);
+}
+
+#[test]
+fn fuzzy_hash_index_candidates_have_comparable_block_sizes() {
+    use ssdeep::FuzzyHash;
+
+    let hashes = index_test_hashes();
+    let index = index_with_test_hashes();
+    for query in &hashes {
+        let parsed: FuzzyHash = query.parse().unwrap();
+        for id in index.candidates(query).unwrap() {
+            assert!(parsed.comparable_with(index.get(id).unwrap()));
+        }
+    }
+    let mut block_sizes: Vec<u64> = hashes
+        .iter()
+        .map(|h| h.parse::<FuzzyHash>().unwrap().block_size())
+        .collect();
+    block_sizes.sort_unstable();
+    block_sizes.dedup();
+    assert!(block_sizes.len() > 2);
+}
+
+#[test]
+fn fuzzy_hash_index_finds_identical_hashes_with_short_parts() {
+    use ssdeep::index::FuzzyHashIndex;
+
+    let mut index = FuzzyHashIndex::new();
+    index.insert("3:aNRn:aNRn").unwrap();
+    index.insert("3:aNRnnnnn:aNRn").unwrap();
+    index.insert("6:aNRn:aNRn").unwrap();
+    let matches = index.search("3:aNRnnn:aNRn", 0).unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].id, 1);
+    assert_eq!(matches[0].score, 100);
+    assert_eq!(index.get(1).unwrap().as_str(), "3:aNRnnnnn:aNRn");
+    assert!(index.get(3).is_none());
+}
+
+#[test]
+fn fuzzy_hash_index_top_k_returns_first_k_matches_of_search() {
+    let hashes = index_test_hashes();
+    let index = index_with_test_hashes();
+    for query in &hashes {
+        for &(k, min_score) in &[(1, 0), (3, 0), (5, 50), (1000, 1)] {
+            let expected: Vec<_> = index
+                .search(query, min_score.max(1) - 1)
+                .unwrap()
+                .into_iter()
+                .take(k)
+                .collect();
+            assert_eq!(index.top_k(query, k, min_score).unwrap(), expected);
+        }
+    }
+}
+
+#[test]
+fn fuzzy_hash_index_top_k_returns_nothing_for_zero_k() {
+    let index = index_with_test_hashes();
+    assert!(index.top_k("3:aNRn:aNRn", 0, 0).unwrap().is_empty());
+}
+
+#[test]
+fn fuzzy_hash_index_search_respects_threshold() {
+    use ssdeep::index::FuzzyHashIndex;
+
+    let mut index = FuzzyHashIndex::new();
+    index
+        .insert("3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C")
+        .unwrap();
+    index
+        .insert("3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx")
+        .unwrap();
+    let matches = index
+        .search("3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx", 22)
+        .unwrap();
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].id, 1);
+}
+
+#[test]
+fn fuzzy_hash_index_returns_error_when_hash_is_invalid() {
+    use ssdeep::index::FuzzyHashIndex;
+
+    let mut index = FuzzyHashIndex::new();
+    assert!(matches!(
+        index.insert("XYZ"),
+        Err(Error::InvalidHash { .. })
+    ));
+    assert!(index.is_empty());
+    assert!(matches!(
+        index.search("XYZ", 0),
+        Err(Error::InvalidHash { .. })
+    ));
+}
+
+fn index_with_test_hashes() -> ssdeep::index::FuzzyHashIndex {
+    let mut index = ssdeep::index::FuzzyHashIndex::new();
+    for h in &index_test_hashes() {
+        index.insert(h).unwrap();
+    }
+    index
+}
+
+#[test]
+#[cfg(feature = "mmap")]
+fn mapped_index_returns_same_results_as_saved_index() {
+    use ssdeep::index::MappedIndex;
+
+    let hashes = index_test_hashes();
+    let mut index = index_with_test_hashes();
+    index.insert("3:aNRn:aNRn").unwrap();
+    index.remove(3).unwrap();
+    let path = std::env::temp_dir().join("ssdeep-rs-mapped-index.bin");
+    index.save_mapped(&path).unwrap();
+    let mapped = MappedIndex::open(&path).unwrap();
+    assert_eq!(mapped.len(), index.len());
+    for id in 0..hashes.len() + 2 {
+        assert_eq!(mapped.get(id), index.get(id).map(|h| h.as_str()));
+    }
+    for query in hashes
+        .iter()
+        .map(|h| h.as_str())
+        .chain(vec!["3:aNRnnnnn:aNRn"])
+    {
+        assert_eq!(
+            mapped.candidates(query).unwrap(),
+            index.candidates(query).unwrap()
+        );
+        assert_eq!(
+            mapped.search(query, 0).unwrap(),
+            index.search(query, 0).unwrap()
+        );
+    }
+    drop(mapped);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+#[cfg(feature = "mmap")]
+fn mapped_index_rejects_invalid_files() {
+    use ssdeep::index::{FuzzyHashIndex, MappedIndex};
+
+    let index = index_with_test_hashes();
+    let mut data = Vec::new();
+    index.write_mapped(&mut data).unwrap();
+    let path = std::env::temp_dir().join("ssdeep-rs-invalid-mapped-index.bin");
+    let invalid = vec![
+        data[..data.len() - 1].to_vec(),
+        [&data[..], b"x"].concat(),
+        [&b"SSDEEPIX"[..], &data[8..]].concat(),
+        [&data[..8], &2u32.to_le_bytes(), &data[12..]].concat(),
+    ];
+    for bytes in invalid {
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(MappedIndex::open(&path), Err(Error::Io(_))));
+    }
+    index.save(&path).unwrap();
+    assert!(matches!(MappedIndex::open(&path), Err(Error::Io(_))));
+    FuzzyHashIndex::new().save_mapped(&path).unwrap();
+    let empty = MappedIndex::open(&path).unwrap();
+    assert!(empty.is_empty());
+    assert!(empty.search("3:aNRn:aNRn", 0).unwrap().is_empty());
+    drop(empty);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn fuzzy_hash_index_with_bloom_filters_returns_same_matches() {
+    let hashes = index_test_hashes();
+    let index = index_with_test_hashes();
+    let mut filtered = index_with_test_hashes();
+    assert!(!filtered.has_bloom_filters());
+    filtered.set_bloom_filters(true);
+    assert!(filtered.has_bloom_filters());
+    filtered.remove(5).unwrap();
+    filtered.insert(&hashes[5]).unwrap();
+    for h in &hashes {
+        filtered.insert(h).unwrap();
+    }
+    for query in &hashes {
+        let expected: Vec<usize> = index
+            .candidates(query)
+            .unwrap()
+            .into_iter()
+            .filter(|&id| id != 5)
+            .collect();
+        let found: Vec<usize> = filtered
+            .candidates(query)
+            .unwrap()
+            .into_iter()
+            .filter(|&id| id < hashes.len())
+            .collect();
+        assert_eq!(found, expected);
+    }
+    assert!(filtered
+        .search("96:AXGBicFlgVNhBGcL6wCrFQEvAXGBicFlgVNhBGcL6wCrFQEv:XX", 0)
+        .unwrap()
+        .is_empty());
+    filtered.set_bloom_filters(false);
+    assert!(!filtered.has_bloom_filters());
+    assert_eq!(filtered.search(&hashes[2], 0).unwrap()[0].score, 100);
+}
+
+#[test]
+fn fuzzy_hash_index_save_and_load_round_trip() {
+    use ssdeep::index::FuzzyHashIndex;
+
+    let index = index_with_test_hashes();
+    let path = std::env::temp_dir().join("ssdeep-rs-index.bin");
+    index.save(&path).unwrap();
+    let loaded = FuzzyHashIndex::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    let loaded = loaded.unwrap();
+    assert_eq!(loaded, index);
+    let query = index.get(2).unwrap().as_str();
+    assert_eq!(loaded.search(query, 0), index.search(query, 0));
+}
+
+#[test]
+fn fuzzy_hash_index_write_produces_same_bytes_for_same_index() {
+    let (mut bytes1, mut bytes2) = (Vec::new(), Vec::new());
+    index_with_test_hashes().write(&mut bytes1).unwrap();
+    index_with_test_hashes().write(&mut bytes2).unwrap();
+    assert_eq!(bytes1, bytes2);
+    assert_eq!(&bytes1[..12], b"SSDEEPIX\x01\x00\x00\x00");
+}
+
+#[test]
+fn fuzzy_hash_index_read_returns_error_when_data_are_invalid() {
+    use ssdeep::index::FuzzyHashIndex;
+
+    let mut index = FuzzyHashIndex::new();
+    index.insert("3:aNRn:aNRn").unwrap();
+    index
+        .insert("3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C")
+        .unwrap();
+    let mut valid = Vec::new();
+    index.write(&mut valid).unwrap();
+    assert_eq!(FuzzyHashIndex::read(&valid[..]).unwrap(), index);
+
+    let mut other_version = valid.clone();
+    other_version[8] = 2;
+    let mut invalid_hash = valid.clone();
+    invalid_hash[24] = b'x';
+    let mut invalid_id = valid.clone();
+    let len = invalid_id.len();
+    invalid_id[len - 4] = 7;
+    let mut trailing = valid.clone();
+    trailing.push(0);
+    let cases: Vec<(&[u8], std::io::ErrorKind)> = vec![
+        (b"SSDEEPXX\x01\x00\x00\x00", std::io::ErrorKind::InvalidData),
+        (&other_version, std::io::ErrorKind::InvalidData),
+        (&invalid_hash, std::io::ErrorKind::InvalidData),
+        (&invalid_id, std::io::ErrorKind::InvalidData),
+        (&trailing, std::io::ErrorKind::InvalidData),
+        (&valid[..valid.len() - 1], std::io::ErrorKind::UnexpectedEof),
+        (&valid[..30], std::io::ErrorKind::UnexpectedEof),
+    ];
+    for (i, (bytes, kind)) in cases.into_iter().enumerate() {
+        match FuzzyHashIndex::read(bytes) {
+            Err(Error::Io(e)) => assert_eq!(e.kind(), kind, "case {}", i),
+            r => panic!("case {}: unexpected result: {:?}", i, r),
+        }
+    }
+}
+
+#[test]
+fn fuzzy_hash_index_remove_removes_hash_from_search_results() {
+    let hashes = index_test_hashes();
+    let mut index = index_with_test_hashes();
+    let removed = index.remove(2).unwrap();
+    assert_eq!(removed.as_str(), hashes[2]);
+    assert_eq!(index.len(), hashes.len() - 1);
+    assert!(index.get(2).is_none());
+    assert!(index.remove(2).is_none());
+    assert!(index.remove(hashes.len()).is_none());
+    assert!(index
+        .search(&hashes[2], 0)
+        .unwrap()
+        .iter()
+        .all(|m| m.id != 2));
+    assert!(!index.contains(&hashes[2]).unwrap());
+}
+
+#[test]
+fn fuzzy_hash_index_insert_after_remove_assigns_new_identifier() {
+    let hashes = index_test_hashes();
+    let mut index = index_with_test_hashes();
+    index.remove(2).unwrap();
+    let id = index.insert(&hashes[2]).unwrap();
+    assert_eq!(id, hashes.len());
+    assert_eq!(index.len(), hashes.len());
+    assert!(index.contains(&hashes[2]).unwrap());
+    let matches = index.search(&hashes[2], 99).unwrap();
+    assert_eq!(matches[0].id, id);
+}
+
+#[test]
+fn fuzzy_hash_index_remove_removes_hash_with_short_parts() {
+    use ssdeep::index::FuzzyHashIndex;
+
+    let mut index = FuzzyHashIndex::new();
+    index.insert("3:aNRn:aNRn").unwrap();
+    assert!(index.contains("3:aNRn:aNRn").unwrap());
+    index.remove(0).unwrap();
+    assert!(!index.contains("3:aNRn:aNRn").unwrap());
+    assert!(index.search("3:aNRn:aNRn", 0).unwrap().is_empty());
+    assert!(index.is_empty());
+}
+
+#[test]
+fn fuzzy_hash_index_contains_returns_false_for_similar_hash() {
+    let hashes = index_test_hashes();
+    let index = index_with_test_hashes();
+    assert!(index.contains(&hashes[0]).unwrap());
+    assert!(!index
+        .contains("3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2")
+        .unwrap());
+    assert!(matches!(
+        index.contains("XYZ"),
+        Err(Error::InvalidHash { .. })
+    ));
+}
+
+#[test]
+fn fuzzy_hash_index_save_and_load_round_trip_with_removed_hashes() {
+    use ssdeep::index::FuzzyHashIndex;
+
+    let hashes = index_test_hashes();
+    let mut index = index_with_test_hashes();
+    index.remove(0).unwrap();
+    index.remove(3).unwrap();
+    let mut bytes = Vec::new();
+    index.write(&mut bytes).unwrap();
+    let loaded = FuzzyHashIndex::read(&bytes[..]).unwrap();
+    assert_eq!(loaded, index);
+    assert_eq!(loaded.len(), hashes.len() - 2);
+    assert!(loaded.get(0).is_none());
+    assert!(!loaded.contains(&hashes[3]).unwrap());
+    assert_eq!(loaded.search(&hashes[2], 0), index.search(&hashes[2], 0));
+}
+
+//
+// classify::Classifier::classify_hash()
+//
+
+fn classifier() -> ssdeep::classify::Classifier {
+    let mut classifier = ssdeep::classify::Classifier::new();
+    classifier
+        .add_known_bad("bad", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C")
+        .unwrap();
+    classifier.add_known_good("good", "3:u+N:u+N").unwrap();
+    classifier
+}
+
+#[test]
+fn classify_hash_returns_known_bad_when_hash_matches_bad_reference() {
+    let classification = classifier()
+        .classify_hash("3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C")
+        .unwrap();
+    assert_eq!(
+        classification,
+        ssdeep::classify::Classification::KnownBad {
+            reference: ssdeep::classify::Reference {
+                name: "bad".to_string(),
+                hash: "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C".to_string(),
+            },
+            score: 100,
+        }
+    );
+}
+
+#[test]
+fn classify_hash_returns_known_good_when_hash_matches_good_reference() {
+    let classification = classifier().classify_hash("3:u+N:u+N").unwrap();
+    assert!(matches!(
+        classification,
+        ssdeep::classify::Classification::KnownGood { score: 100, .. }
+    ));
+}
+
+#[test]
+fn classify_hash_returns_similar_to_bad_when_score_is_above_threshold() {
+    let mut classifier = classifier();
+    classifier.thresholds.similar_to_bad = 20;
+    let classification = classifier
+        .classify_hash("3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx")
+        .unwrap();
+    assert!(matches!(
+        classification,
+        ssdeep::classify::Classification::SimilarToBad { score: 22, .. }
+    ));
+}
+
+#[test]
+fn classify_hash_returns_unknown_when_score_is_below_thresholds() {
+    let classification = classifier()
+        .classify_hash("3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx")
+        .unwrap();
+    assert_eq!(classification, ssdeep::classify::Classification::Unknown);
+}
+
+#[test]
+fn classify_hash_prefers_known_bad_over_known_good() {
+    let mut classifier = classifier();
+    classifier
+        .add_known_good("also-good", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C")
+        .unwrap();
+    let classification = classifier
+        .classify_hash("3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C")
+        .unwrap();
+    assert!(matches!(
+        classification,
+        ssdeep::classify::Classification::KnownBad { .. }
+    ));
+}
+
+#[test]
+fn add_known_bad_returns_error_when_hash_is_invalid() {
+    let mut classifier = ssdeep::classify::Classifier::new();
+    assert!(classifier.add_known_bad("bad", "XYZ").is_err());
+    assert!(classifier.known_bad().is_empty());
+}
+
+//
+// dedupe
+//
+
+fn dedupe_item(name: &str, hash: &str, size: u64) -> ssdeep::dedupe::Item {
+    ssdeep::dedupe::Item {
+        name: name.to_string(),
+        hash: hash.to_string(),
+        size,
+    }
+}
+
+fn dedupe_test_items() -> Vec<ssdeep::dedupe::Item> {
+    vec![
+        dedupe_item("a", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C", 100),
+        dedupe_item("b", "3:u+N:u+N", 10),
+        dedupe_item("c", "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx", 120),
+        dedupe_item("d", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C", 90),
+        dedupe_item("e", "3:u+N:u+N", 20),
+    ]
+}
+
+#[test]
+fn find_duplicates_returns_groups_with_redundant_bytes() {
+    use ssdeep::dedupe::{find_duplicates, DedupeOptions};
+
+    let options = DedupeOptions {
+        threshold: 20,
+        ..Default::default()
+    };
+    let report = find_duplicates(&dedupe_test_items(), &options).unwrap();
+    assert_eq!(report.groups.len(), 2);
+    let group = &report.groups[0];
+    assert_eq!(group.representative.name, "c");
+    let members: Vec<_> = group
+        .members
+        .iter()
+        .map(|m| (m.item.name.as_str(), m.score))
+        .collect();
+    assert_eq!(members, vec![("a", 22), ("d", 22)]);
+    assert_eq!(group.redundant_bytes, 190);
+    assert_eq!(report.groups[1].representative.name, "e");
+    assert_eq!(report.groups[1].redundant_bytes, 10);
+    assert_eq!(report.redundant_bytes, 200);
+}
+
+#[test]
+fn find_duplicates_chooses_representative_by_options() {
+    use ssdeep::dedupe::{find_duplicates, DedupeOptions, Representative};
+
+    let representative_names = |representative| {
+        let options = DedupeOptions {
+            threshold: 20,
+            representative,
+        };
+        find_duplicates(&dedupe_test_items(), &options)
+            .unwrap()
+            .groups
+            .iter()
+            .map(|g| g.representative.name.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(representative_names(Representative::First), vec!["a", "b"]);
+    assert_eq!(representative_names(Representative::Medoid), vec!["a", "b"]);
+}
+
+#[test]
+fn find_duplicates_returns_no_groups_for_distinct_items() {
+    use ssdeep::dedupe::{find_duplicates, DedupeOptions};
+
+    let report = find_duplicates(&dedupe_test_items(), &DedupeOptions::default()).unwrap();
+    assert_eq!(report.groups.len(), 2);
+    assert!(report.groups.iter().all(|g| g.members[0].score == 100));
+    let options = DedupeOptions {
+        threshold: 100,
+        ..Default::default()
+    };
+    let report = find_duplicates(&dedupe_test_items(), &options).unwrap();
+    assert!(report.groups.is_empty());
+    assert_eq!(report.redundant_bytes, 0);
+}
+
+#[test]
+fn find_duplicates_returns_error_when_hash_is_invalid() {
+    use ssdeep::dedupe::{find_duplicates, DedupeOptions};
+
+    let items = vec![dedupe_item("a", "XYZ", 1)];
+    assert!(matches!(
+        find_duplicates(&items, &DedupeOptions::default()),
+        Err(Error::InvalidHash { .. })
+    ));
+}
+
+#[test]
+fn find_duplicate_files_uses_paths_and_sizes_of_files() {
+    use ssdeep::dedupe::{find_duplicate_files, DedupeOptions};
+
+    let dir = std::env::temp_dir().join("ssdeep-rs-dedupe");
+    std::fs::create_dir_all(&dir).unwrap();
+    let data = pseudo_random_bytes(20_000);
+    let paths = vec![dir.join("1.bin"), dir.join("2.bin"), dir.join("3.bin")];
+    std::fs::write(&paths[0], &data).unwrap();
+    std::fs::write(&paths[1], &data[..19_900]).unwrap();
+    std::fs::write(&paths[2], b"Hello there!").unwrap();
+    let report = find_duplicate_files(&paths, &DedupeOptions::default()).unwrap();
+    assert_eq!(report.groups.len(), 1);
+    let group = &report.groups[0];
+    assert_eq!(group.representative.name, paths[0].display().to_string());
+    assert_eq!(group.members[0].item.name, paths[1].display().to_string());
+    assert!(group.members[0].score > 90);
+    assert_eq!(report.redundant_bytes, 19_900);
+}
+
+//
+// dot
+//
+
+#[test]
+fn write_dot_renders_nodes_and_edges_above_threshold() {
+    use ssdeep::dot::{write_dot, DotOptions};
+
+    let hashes = [
+        "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+        "3:u+N:u+N",
+        "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx",
+    ];
+    let mut dot = Vec::new();
+    write_dot(&mut dot, &hashes, 0, &DotOptions::default()).unwrap();
+    assert_eq!(
+        String::from_utf8(dot).unwrap(),
+        "graph \"similarities\" {\n\
+         \x20 n0 [label=\"3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C\"];\n\
+         \x20 n1 [label=\"3:u+N:u+N\"];\n\
+         \x20 n2 [label=\"3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx\"];\n\
+         \x20 n0 -- n2 [label=\"22\", penwidth=1.88];\n\
+         }\n"
+    );
+    let mut dot = Vec::new();
+    write_dot(&mut dot, &hashes, 22, &DotOptions::default()).unwrap();
+    assert!(!String::from_utf8(dot).unwrap().contains("--"));
+}
+
+#[test]
+fn write_dot_uses_configured_labels_and_edge_styling() {
+    use ssdeep::dot::{write_dot, DotOptions, NodeLabels};
+
+    let hashes = ["3:u+N:u+N", "3:u+N:u+N"];
+    let options = DotOptions {
+        graph_name: "my \"graph\"".to_string(),
+        node_labels: NodeLabels::Custom(vec!["a\\b.exe".to_string()]),
+        edge_labels: false,
+        max_edge_width: 1.0,
+    };
+    let mut dot = Vec::new();
+    write_dot(&mut dot, &hashes, 0, &options).unwrap();
+    let dot = String::from_utf8(dot).unwrap();
+    assert!(dot.starts_with("graph \"my \\\"graph\\\"\" {\n"));
+    assert!(dot.contains("  n0 [label=\"a\\\\b.exe\"];\n"));
+    assert!(dot.contains("  n1 [label=\"3:u+N:u+N\"];\n"));
+    assert!(dot.contains("  n0 -- n1 [penwidth=1.00];\n"));
+
+    let options = DotOptions {
+        node_labels: NodeLabels::Indexes,
+        ..Default::default()
+    };
+    let mut dot = Vec::new();
+    write_dot(&mut dot, &hashes, 0, &options).unwrap();
+    let dot = String::from_utf8(dot).unwrap();
+    assert!(dot.contains("  n1 [label=\"1\"];\n"));
+    assert!(dot.contains("  n0 -- n1 [label=\"100\", penwidth=5.00];\n"));
+}
+
+#[test]
+fn write_clusters_dot_renders_clusters_as_subgraphs() {
+    use ssdeep::cluster::single_linkage;
+    use ssdeep::dot::{write_clusters_dot, DotOptions, NodeLabels};
+
+    let hashes = [
+        "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C",
+        "3:u+N:u+N",
+        "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx",
+    ];
+    let clusters = single_linkage(&hashes, 0).unwrap();
+    let options = DotOptions {
+        node_labels: NodeLabels::Indexes,
+        ..Default::default()
+    };
+    let mut dot = Vec::new();
+    write_clusters_dot(&mut dot, &hashes, &clusters[..1], 0, &options).unwrap();
+    assert_eq!(
+        String::from_utf8(dot).unwrap(),
+        "graph \"similarities\" {\n\
+         \x20 subgraph cluster_0 {\n\
+         \x20   n0 [label=\"0\"];\n\
+         \x20   n2 [label=\"2\"];\n\
+         \x20 }\n\
+         \x20 n1 [label=\"1\"];\n\
+         \x20 n0 -- n2 [label=\"22\", penwidth=1.88];\n\
+         }\n"
+    );
+}
+
+#[test]
+fn write_dot_returns_error_when_hash_is_invalid() {
+    use ssdeep::dot::{write_dot, DotOptions};
+
+    assert!(matches!(
+        write_dot(Vec::new(), &["XYZ"], 0, &DotOptions::default()),
+        Err(Error::InvalidHash { .. })
+    ));
+}
+
+//
+// export::summarize_cluster()
+//
+
+fn sample(name: &str, hash: &str, metadata: &[(&str, &str)]) -> ssdeep::export::Sample {
+    ssdeep::export::Sample {
+        name: name.to_string(),
+        hash: hash.to_string(),
+        metadata: metadata
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    }
+}
+
+#[test]
+fn summarize_cluster_selects_medoid_as_first_representative() {
+    let samples = vec![
+        sample("a", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C", &[]),
+        sample("b", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C", &[]),
+        sample("c", "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx", &[]),
+    ];
+    let summary = ssdeep::export::summarize_cluster(&samples, 1).unwrap();
+    assert_eq!(summary.sample_count, 3);
+    assert_eq!(summary.representatives, vec![samples[0].clone()]);
+    assert_eq!(summary.min_score, 22);
+}
+
+#[test]
+fn summarize_cluster_selects_least_covered_sample_as_next_representative() {
+    let samples = vec![
+        sample("a", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C", &[]),
+        sample("b", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C", &[]),
+        sample("c", "3:u+N:u+N", &[]),
+    ];
+    let summary = ssdeep::export::summarize_cluster(&samples, 5).unwrap();
+    let names: Vec<_> = summary
+        .representatives
+        .iter()
+        .map(|s| s.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["a", "c"]);
+    assert_eq!(summary.min_score, 100);
+}
+
+#[test]
+fn summarize_cluster_returns_metadata_common_to_all_samples() {
+    let samples = vec![
+        sample("a", "3:u+N:u+N", &[("family", "x"), ("source", "1")]),
+        sample("b", "3:u+N:u+N", &[("family", "x"), ("source", "2")]),
+    ];
+    let summary = ssdeep::export::summarize_cluster(&samples, 1).unwrap();
+    assert_eq!(summary.common_metadata.len(), 1);
+    assert_eq!(summary.common_metadata["family"], "x");
+}
+
+#[test]
+fn summarize_cluster_with_progress_reports_comparisons_and_summarizing() {
+    let samples = vec![
+        sample("a", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C", &[]),
+        sample("b", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C", &[]),
+        sample("c", "3:u+N:u+N", &[]),
+    ];
+    let mut reports = Vec::new();
+    ssdeep::export::summarize_cluster_with_progress(&samples, 5, |progress| {
+        reports.push((progress.phase, progress.done, progress.total))
+    })
+    .unwrap();
+    assert_eq!(
+        reports,
+        vec![
+            (Phase::Comparing, 2, Some(3)),
+            (Phase::Comparing, 3, Some(3)),
+            (Phase::Comparing, 3, Some(3)),
+            (Phase::Summarizing, 1, None),
+            (Phase::Summarizing, 2, None),
+        ]
+    );
+}
+
+#[test]
+fn summarize_cluster_returns_error_when_hash_is_invalid() {
+    let samples = vec![sample("a", "XYZ", &[]), sample("b", "3:u+N:u+N", &[])];
+    assert!(ssdeep::export::summarize_cluster(&samples, 1).is_err());
+}
+
+//
+// export::ClusterSummary::to_yara_rule()
+//
+
+#[test]
+fn to_yara_rule_embeds_hashes_and_escapes_strings() {
+    let samples = vec![sample("a \"quoted\"", "3:u+N:u+N", &[("1st key", "v")])];
+    let summary = ssdeep::export::summarize_cluster(&samples, 1).unwrap();
+    let rule = summary.to_yara_rule("1 cluster");
+    assert!(rule.starts_with("rule _1_cluster\n{\n"));
+    assert!(rule.contains("        ssdeep_1 = \"3:u+N:u+N\"\n"));
+    assert!(rule.contains("        sample_1 = \"a \\\"quoted\\\"\"\n"));
+    assert!(rule.contains("        _1st_key = \"v\"\n"));
+    assert!(rule.ends_with(
+        "    condition:\n        // TODO: Add strings and a condition.\n        false\n}\n"
+    ));
+}
+
+//
+// report::Report
+//
+
+#[test]
+fn report_renders_matches_sorted_by_score_with_signature_diffs() {
+    use ssdeep::report::{Match, Report};
+
+    let m = |name: &str, score| Match {
+        name1: "query".to_string(),
+        hash1: "3:abc:x".to_string(),
+        name2: name.to_string(),
+        hash2: "3:aXc:x".to_string(),
+        score,
+    };
+    let mut report = Report::new("Scan");
+    report.matches = vec![m("low", 10), m("high", 90)];
+
+    let md = report.to_markdown();
+    let high = md.find("| query | high | 90 | `3:a[-b-]{+X+}c`<br>`6:x` |");
+    let low = md.find("| query | low | 10 |");
+    assert!(high.unwrap() < low.unwrap());
+    let html = report.to_html();
+    assert!(html.contains("<del>b</del><ins>X</ins>"));
+    assert!(!html.contains("Scan results"));
+}
+
+#[test]
+fn report_renders_scan_results_and_escapes_them() {
+    use ssdeep::classify::{Classification, ScanResult};
+    use ssdeep::report::Report;
+
+    let mut report = Report::new("<Scan>");
+    report.scan_results.push(ScanResult {
+        path: "a|<b>.txt".to_string(),
+        hash: "3:aNRn:aNRn".to_string(),
+        classification: Classification::Unknown,
+    });
+
+    let html = report.to_html();
+    assert!(html.contains("<title>&lt;Scan&gt;</title>"));
+    assert!(html.contains("<td>a|&lt;b&gt;.txt</td>"));
+    assert!(report
+        .to_markdown()
+        .contains("| a\\|\\<b\\>.txt | `3:aNRn:aNRn` | unknown |  |  |"));
+}
+
+#[test]
+fn report_renders_cluster_summaries() {
+    use ssdeep::export::{summarize_cluster, Sample};
+    use ssdeep::report::Report;
+
+    let samples = vec![Sample {
+        name: "a".to_string(),
+        hash: "3:aNRn:aNRn".to_string(),
+        metadata: vec![("family".to_string(), "x".to_string())]
+            .into_iter()
+            .collect(),
+    }];
+    let mut report = Report::new("Clusters");
+    report
+        .clusters
+        .push(summarize_cluster(&samples, 1).unwrap());
+
+    let md = report.to_markdown();
+    assert!(md.contains("## Cluster 1"));
+    assert!(md.contains("| a | `3:aNRn:aNRn` |"));
+    assert!(md.contains("* family: x"));
+}
+
+//
+// ssdeep report
+//
+
+#[test]
+#[cfg(feature = "cli")]
+fn ssdeep_report_prints_markdown_report_of_matches_between_files() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_ssdeep"))
+        .args(["report", "--markdown", "--title", "Test"])
+        .args(["tests/file.txt", "tests/file.txt"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report = String::from_utf8(output.stdout).unwrap();
+    assert!(report.starts_with("# Test\n"));
+    assert!(report.contains("| tests/file.txt | tests/file.txt | 100 |"));
+}
+
+#[test]
+#[cfg(feature = "cli")]
+fn ssdeep_report_prints_html_report_by_default() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_ssdeep"))
+        .args(["report", "tests/file.txt"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.starts_with(b"<!DOCTYPE html>"));
+}
+
+#[test]
+#[cfg(feature = "cli")]
+fn ssdeep_report_fails_when_file_does_not_exist() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_ssdeep"))
+        .args(["report", "tests/nonexistent.txt"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("tests/nonexistent.txt"));
+}
+
+//
+// ssdeep mutation
+//
+
+#[test]
+#[cfg(feature = "cli")]
+fn ssdeep_mutation_prints_score_distribution_per_kind_and_rate() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_ssdeep"))
+        .args(["mutation", "--trials", "2", "tests/file.txt"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines[0], "kind\trate\tmin\tmax\tmean");
+    assert_eq!(lines.len(), 1 + 4 * 5);
+    assert!(lines[1].starts_with("ByteFlip\t0.01\t"));
+}
+
+#[test]
+#[cfg(feature = "cli")]
+fn ssdeep_mutation_fails_when_trials_are_invalid() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_ssdeep"))
+        .args(["mutation", "--trials", "many", "tests/file.txt"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+//
+// fields::compare_records()
+//
+
+fn record(fields: &[(&str, &str)]) -> ssdeep::fields::RecordHash {
+    ssdeep::fields::RecordHash {
+        fields: fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    }
+}
+
+#[test]
+fn compare_records_returns_weighted_average_of_field_scores() {
+    let r1 = record(&[
+        ("body", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C"),
+        ("subject", "3:u+N:u+N"),
+    ]);
+    let r2 = record(&[
+        ("body", "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx"),
+        ("subject", "3:u+N:u+N"),
+    ]);
+    let mut weights = std::collections::BTreeMap::new();
+    weights.insert("body".to_string(), 3.0);
+    let aggregate = ssdeep::fields::compare_records(&r1, &r2, &weights).unwrap();
+    // (3 * 22 + 1 * 100) / 4 = 41.5
+    assert_eq!(aggregate.score, 42);
+    assert_eq!(
+        aggregate.fields,
+        vec![
+            ssdeep::fields::FieldScore {
+                name: "body".to_string(),
+                weight: 3.0,
+                score: 22,
+            },
+            ssdeep::fields::FieldScore {
+                name: "subject".to_string(),
+                weight: 1.0,
+                score: 100,
+            },
+        ]
+    );
+}
+
+#[test]
+fn compare_records_scores_field_present_in_only_one_record_as_zero() {
+    let r1 = record(&[("body", "3:u+N:u+N"), ("attachment", "3:aNRn:aNRn")]);
+    let r2 = record(&[("body", "3:u+N:u+N")]);
+    let aggregate = ssdeep::fields::compare_records(&r1, &r2, &Default::default()).unwrap();
+    assert_eq!(aggregate.score, 50);
+    assert_eq!(aggregate.fields[0].name, "attachment");
+    assert_eq!(aggregate.fields[0].score, 0);
+}
+
+#[test]
+fn compare_records_returns_zero_when_records_have_no_fields() {
+    let aggregate =
+        ssdeep::fields::compare_records(&record(&[]), &record(&[]), &Default::default()).unwrap();
+    assert_eq!(aggregate.score, 0);
+    assert!(aggregate.fields.is_empty());
+}
+
+#[test]
+fn compare_records_returns_error_when_hash_is_invalid() {
+    let r1 = record(&[("body", "XYZ")]);
+    let r2 = record(&[("body", "3:u+N:u+N")]);
+    assert!(ssdeep::fields::compare_records(&r1, &r2, &Default::default()).is_err());
+}
+
+//
+// mutation
+//
+
+#[test]
+fn mutate_affects_given_fraction_of_bytes() {
+    use ssdeep::mutation::{mutate, MutationKind};
+
+    let data = pseudo_random_bytes(10_000);
+    let flipped = mutate(&data, MutationKind::ByteFlip, 0.1, 1);
+    let changed = data.iter().zip(&flipped).filter(|(a, b)| a != b).count();
+    assert_eq!(flipped.len(), data.len());
+    assert!(changed > 900 && changed <= 1000);
+    assert_eq!(mutate(&data, MutationKind::Insertion, 0.1, 1).len(), 11_000);
+    assert_eq!(mutate(&data, MutationKind::Truncation, 0.1, 1).len(), 9_000);
+    let swapped = mutate(&data, MutationKind::BlockSwap, 0.5, 1);
+    let mut sorted_data = data.clone();
+    let mut sorted_swapped = swapped.clone();
+    sorted_data.sort();
+    sorted_swapped.sort();
+    assert_ne!(swapped, data);
+    assert_eq!(sorted_swapped, sorted_data);
+}
+
+#[test]
+fn mutate_is_deterministic_for_given_seed() {
+    use ssdeep::mutation::{mutate, MutationKind};
+
+    let data = pseudo_random_bytes(1000);
+    assert_eq!(
+        mutate(&data, MutationKind::Insertion, 0.1, 7),
+        mutate(&data, MutationKind::Insertion, 0.1, 7)
+    );
+    assert_ne!(
+        mutate(&data, MutationKind::Insertion, 0.1, 7),
+        mutate(&data, MutationKind::Insertion, 0.1, 8)
+    );
+}
+
+#[test]
+fn run_experiment_returns_distribution_for_each_kind_and_rate() {
+    use ssdeep::mutation::{run_experiment, ExperimentOptions, MutationKind};
+
+    let data = pseudo_random_bytes(64 * 1024);
+    let options = ExperimentOptions {
+        kinds: vec![MutationKind::ByteFlip, MutationKind::Truncation],
+        rates: vec![0.0, 0.5],
+        trials: 3,
+        seed: 0,
+    };
+    let distributions = run_experiment(&data, &options).unwrap();
+    assert_eq!(distributions.len(), 4);
+    assert_eq!(distributions[0].kind, MutationKind::ByteFlip);
+    assert_eq!(distributions[0].rate, 0.0);
+    assert_eq!(distributions[0].scores, vec![100, 100, 100]);
+    assert_eq!(distributions[0].mean, 100.0);
+    for distribution in &distributions {
+        assert_eq!(distribution.scores.len(), 3);
+        assert!(distribution.min <= distribution.max);
+    }
+    assert!(distributions[1].mean < 100.0);
+}
+
+//
+// normalize_text()
+//
+
+#[test]
+fn normalize_text_performs_all_normalizations_by_default() {
+    let text = normalize_text(
+        "  <html><!-- <b>x</b> --><p>Hello\r\n\tTHERE!</p> ",
+        &NormalizationOptions::default(),
+    );
+    assert_eq!(text, "hello there!");
+}
+
+#[test]
+fn normalize_text_performs_only_requested_normalizations() {
+    let options = NormalizationOptions {
+        lowercase: false,
+        collapse_whitespace: true,
+        strip_html_tags: false,
+    };
+    assert_eq!(
+        normalize_text("<b>Hello</b>   there", &options),
+        "<b>Hello</b> there"
+    );
+}
+
+#[test]
+fn normalize_text_keeps_unterminated_tags() {
+    assert_eq!(
+        normalize_text("a < b", &NormalizationOptions::default()),
+        "a < b"
+    );
+}
+
+//
+// hash_text_normalized()
+//
+
+#[test]
+fn hash_text_normalized_returns_hash_of_normalized_text() {
+    let h = hash_text_normalized("<p>HELLO   there!</p>", &NormalizationOptions::default());
+    assert_eq!(h, hash(b"hello there!"));
+}
+
+//
+// mail::hash_eml()
+//
+
+#[cfg(feature = "mail")]
+const MULTIPART_EML: &str = "Subject: Test\r\n\
+Content-Type: multipart/mixed; boundary=XYZ\r\n\
+\r\n\
+--XYZ\r\n\
+Content-Type: text/plain\r\n\
+\r\n\
+Hello there!\r\n\
+--XYZ\r\n\
+Content-Type: application/octet-stream; name=\"hello.bin\"\r\n\
+Content-Disposition: attachment; filename=\"hello.txt\"\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+SGVsbG8gdGhlcmUh\r\n\
+--XYZ--\r\n";
+
+#[test]
+#[cfg(feature = "mail")]
+fn hash_eml_returns_hashes_of_all_leaf_parts() {
+    let parts = ssdeep::mail::hash_eml(MULTIPART_EML.as_bytes()).unwrap();
+    assert_eq!(parts.len(), 2);
+    assert_eq!(parts[0].content_type, "text/plain");
+    assert!(!parts[0].is_attachment);
+    assert_eq!(parts[0].filename, None);
+    assert_eq!(parts[1].part_index, 2);
+}
+
+#[test]
+#[cfg(feature = "mail")]
+fn hash_eml_hashes_decoded_attachments() {
+    let parts = ssdeep::mail::hash_eml(MULTIPART_EML.as_bytes()).unwrap();
+    assert_eq!(parts[1].content_type, "application/octet-stream");
+    assert!(parts[1].is_attachment);
+    assert_eq!(parts[1].filename, Some("hello.txt".to_string()));
+    assert_eq!(parts[1].size, 12);
+    assert_eq!(parts[1].hash, "3:aNRn:aNRn");
+}
+
+//
+// mail::hash_mbox()
+//
+
+#[test]
+#[cfg(feature = "mail")]
+fn hash_mbox_returns_hashes_of_parts_of_all_messages() {
+    let mbox = b"From alice@example.com Thu Jan  1 00:00:00 2015\n\
+Subject: First\n\
+\n\
+Hello there!\n\
+From bob@example.com Thu Jan  1 00:00:00 2015\n\
+Subject: Second\n\
+\n\
+>From the other side\n";
+    let parts = ssdeep::mail::hash_mbox(mbox).unwrap();
+    assert_eq!(parts.len(), 2);
+    assert_eq!(parts[0].message_index, 0);
+    assert_eq!(parts[1].message_index, 1);
+    assert_eq!(parts[1].size, "From the other side\n".len());
+}
+
+//
+// archive::hash_archive()
+//
+
+#[cfg(feature = "archive")]
+fn create_zip(members: &[(&str, &[u8])]) -> Vec<u8> {
+    use std::io::Write;
+
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for (name, data) in members {
+        writer
+            .start_file(*name, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(data).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+#[test]
+#[cfg(feature = "archive")]
+fn hash_archive_returns_tree_of_member_hashes() {
+    let inner = create_zip(&[("inner.txt", b"Hello there!")]);
+    let outer = create_zip(&[("outer.txt", b"Hello there!"), ("inner.jar", &inner)]);
+
+    let members =
+        ssdeep::archive::hash_archive(std::io::Cursor::new(outer), &Default::default()).unwrap();
+
+    assert_eq!(members.len(), 2);
+    assert_eq!(members[0].path, "outer.txt");
+    assert_eq!(members[0].hash, "3:aNRn:aNRn");
+    assert!(members[0].members.is_empty());
+    assert_eq!(members[1].path, "inner.jar");
+    assert_eq!(members[1].size, inner.len() as u64);
+    assert_eq!(members[1].members.len(), 1);
+    assert_eq!(members[1].members[0].path, "inner.txt");
+    assert_eq!(members[1].members[0].hash, "3:aNRn:aNRn");
+}
+
+#[test]
+#[cfg(feature = "archive")]
+fn hash_archive_does_not_recurse_deeper_than_max_depth() {
+    let inner = create_zip(&[("inner.txt", b"Hello there!")]);
+    let outer = create_zip(&[("inner.zip", &inner)]);
+    let limits = ssdeep::archive::ArchiveLimits {
+        max_depth: 0,
+        ..Default::default()
+    };
+
+    let members = ssdeep::archive::hash_archive(std::io::Cursor::new(outer), &limits).unwrap();
+
+    assert_eq!(members.len(), 1);
+    assert!(members[0].members.is_empty());
+}
+
+#[test]
+#[cfg(feature = "archive")]
+fn hash_archive_returns_error_when_there_are_too_many_members() {
+    let inner = create_zip(&[("a.txt", b"a"), ("b.txt", b"b")]);
+    let outer = create_zip(&[("inner.zip", &inner)]);
+    let limits = ssdeep::archive::ArchiveLimits {
+        max_file_count: 2,
+        ..Default::default()
+    };
+
+    let result = ssdeep::archive::hash_archive(std::io::Cursor::new(outer), &limits);
+
+    assert_eq!(
+        result,
+        Err(Error::LimitExceeded {
+            name: "max_file_count".to_string(),
+            limit: 2,
+        })
+    );
+}
+
+#[test]
+#[cfg(feature = "archive")]
+fn hash_archive_returns_error_when_members_are_too_large() {
+    let archive = create_zip(&[("a.txt", &[0; 100]), ("b.txt", &[0; 100])]);
+    let limits = ssdeep::archive::ArchiveLimits {
+        max_total_size: 150,
+        ..Default::default()
+    };
+
+    let result = ssdeep::archive::hash_archive(std::io::Cursor::new(archive), &limits);
+
+    assert_eq!(
+        result,
+        Err(Error::LimitExceeded {
+            name: "max_total_size".to_string(),
+            limit: 150,
+        })
+    );
+}
+
+#[test]
+#[cfg(feature = "archive")]
+fn hash_archive_returns_error_when_archive_is_invalid() {
+    let result = ssdeep::archive::hash_archive(
+        std::io::Cursor::new(b"Hello there!".to_vec()),
+        &Default::default(),
+    );
+    assert!(matches!(result, Err(Error::ArchiveParsingFailed { .. })));
+}
+
+//
+// decompress::hash_decompressed()
+//
+
+#[cfg(feature = "decompress")]
+fn gzip(data: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[test]
+#[cfg(feature = "decompress")]
+fn hash_decompressed_returns_hash_of_uncompressed_data_as_they_are() {
+    let h = ssdeep::decompress::hash_decompressed(&b"Hello there!"[..]);
+    assert_eq!(h, Ok("3:aNRn:aNRn".to_string()));
+}
+
+#[test]
+#[cfg(feature = "decompress")]
+fn hash_decompressed_returns_hash_of_decompressed_gzip_data() {
+    let compressed = gzip(b"Hello there!");
+    let h = ssdeep::decompress::hash_decompressed(&compressed[..]);
+    assert_eq!(h, Ok("3:aNRn:aNRn".to_string()));
+}
+
+#[test]
+#[cfg(feature = "decompress")]
+fn hash_decompressed_returns_hash_of_decompressed_zstd_data() {
+    let compressed = zstd::encode_all(&b"Hello there!"[..], 0).unwrap();
+    let h = ssdeep::decompress::hash_decompressed(&compressed[..]);
+    assert_eq!(h, Ok("3:aNRn:aNRn".to_string()));
+}
+
+#[test]
+#[cfg(feature = "decompress")]
+fn hash_decompressed_returns_hash_of_decompressed_xz_data() {
+    use std::io::Write;
+
+    let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+    encoder.write_all(b"Hello there!").unwrap();
+    let compressed = encoder.finish().unwrap();
+    let h = ssdeep::decompress::hash_decompressed(&compressed[..]);
+    assert_eq!(h, Ok("3:aNRn:aNRn".to_string()));
+}
+
+#[test]
+#[cfg(feature = "decompress")]
+fn hash_decompressed_returns_hash_of_decompressed_bzip2_data() {
+    use std::io::Write;
+
+    let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+    encoder.write_all(b"Hello there!").unwrap();
+    let compressed = encoder.finish().unwrap();
+    let h = ssdeep::decompress::hash_decompressed(&compressed[..]);
+    assert_eq!(h, Ok("3:aNRn:aNRn".to_string()));
+}
+
+#[test]
+#[cfg(feature = "decompress")]
+fn hash_decompressed_returns_error_when_data_are_corrupted() {
+    let mut compressed = gzip(b"Hello there!");
+    compressed.truncate(15);
+    let h = ssdeep::decompress::hash_decompressed(&compressed[..]);
+    assert!(matches!(h, Err(Error::Io(_))));
+}
+
+//
+// decompress::hash_decompressed_with_limits()
+//
+
+#[test]
+#[cfg(feature = "decompress")]
+fn hash_decompressed_with_limits_returns_error_when_output_is_too_large() {
+    let compressed = gzip(&[0; 1000]);
+    let limits = ssdeep::decompress::DecompressionLimits {
+        max_output_size: 999,
+        ..Default::default()
+    };
+    let h = ssdeep::decompress::hash_decompressed_with_limits(&compressed[..], &limits);
+    assert_eq!(
+        h,
+        Err(Error::LimitExceeded {
+            name: "max_output_size".to_string(),
+            limit: 999,
+        })
+    );
+}
+
+#[test]
+#[cfg(feature = "decompress")]
+fn hash_decompressed_with_limits_returns_error_when_ratio_is_too_high() {
+    let compressed = gzip(&vec![0; 10 * 1024 * 1024]);
+    let limits = ssdeep::decompress::DecompressionLimits {
+        max_ratio: 100,
+        ..Default::default()
+    };
+    let h = ssdeep::decompress::hash_decompressed_with_limits(&compressed[..], &limits);
+    assert_eq!(
+        h,
+        Err(Error::LimitExceeded {
+            name: "max_ratio".to_string(),
+            limit: 100,
+        })
+    );
+}
+
+//
+// http::hash_response()
+//
+
+#[cfg(any(feature = "http", feature = "misp", feature = "vt"))]
+fn serve_once(body: &'static [u8]) -> String {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0; 1024];
+        let _ = stream.read(&mut request).unwrap();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(body).unwrap();
+    });
+    url
+}
+
+#[test]
+#[cfg(feature = "http")]
+fn hash_response_returns_hash_of_response_body() {
+    let url = serve_once(b"Hello there!");
+    let response = reqwest::blocking::get(&url).unwrap();
+    assert_eq!(
+        ssdeep::http::hash_response(response),
+        Ok("3:aNRn:aNRn".to_string())
+    );
+}
+
+//
+// http::hash_response_to_file()
+//
+
+#[test]
+#[cfg(feature = "http")]
+fn hash_response_to_file_returns_hash_and_saves_response_body() {
+    let url = serve_once(b"Hello there!");
+    let file_path = std::env::temp_dir().join("ssdeep-rs-hash-response-to-file.txt");
+    let response = reqwest::blocking::get(&url).unwrap();
+    let h = ssdeep::http::hash_response_to_file(response, &file_path);
+    let contents = std::fs::read(&file_path).unwrap();
+    std::fs::remove_file(&file_path).unwrap();
+    assert_eq!(h, Ok("3:aNRn:aNRn".to_string()));
+    assert_eq!(contents, b"Hello there!");
+}
+
+//
+// misp::parse_attributes()
+//
+
+#[test]
+#[cfg(feature = "misp")]
+fn parse_attributes_returns_ssdeep_attributes_from_rest_search_response() {
+    let response = br#"{"response": {"Attribute": [
+        {"uuid": "a1", "type": "ssdeep", "category": "Payload delivery",
+         "comment": "dropper", "to_ids": true, "value": "3:aNRn:aNRn",
+         "Event": {"uuid": "e1", "info": "Campaign"}},
+        {"uuid": "a2", "type": "md5", "value": "d41d8cd98f00b204e9800998ecf8427e"}
+    ]}}"#;
+    let attributes = ssdeep::misp::parse_attributes(response).unwrap();
+    assert_eq!(
+        attributes,
+        vec![ssdeep::misp::MispAttribute {
+            uuid: "a1".to_string(),
+            event_uuid: Some("e1".to_string()),
+            event_info: Some("Campaign".to_string()),
+            category: "Payload delivery".to_string(),
+            comment: "dropper".to_string(),
+            to_ids: true,
+            hash: "3:aNRn:aNRn".to_string(),
+        }]
+    );
+}
+
+#[test]
+#[cfg(feature = "misp")]
+fn parse_attributes_returns_ssdeep_attributes_from_event_including_objects() {
+    let event = br#"{"Event": {"uuid": "e1", "info": "Campaign",
+        "Attribute": [{"uuid": "a1", "type": "ssdeep", "value": "3:aNRn:aNRn", "to_ids": "0"}],
+        "Object": [{"Attribute": [{"uuid": "a2", "type": "ssdeep", "value": "3:u+N:u+N", "to_ids": "1"}]}]
+    }}"#;
+    let attributes = ssdeep::misp::parse_attributes(event).unwrap();
+    assert_eq!(attributes.len(), 2);
+    assert!(!attributes[0].to_ids);
+    assert_eq!(attributes[1].uuid, "a2");
+    assert_eq!(attributes[1].event_uuid, Some("e1".to_string()));
+    assert!(attributes[1].to_ids);
+}
+
+#[test]
+#[cfg(feature = "misp")]
+fn parse_attributes_returns_error_when_document_is_invalid() {
+    assert!(matches!(
+        ssdeep::misp::parse_attributes(b"{}"),
+        Err(Error::HttpRequestFailed { .. })
+    ));
+    assert!(matches!(
+        ssdeep::misp::parse_attributes(b"not JSON"),
+        Err(Error::HttpRequestFailed { .. })
+    ));
+}
+
+//
+// misp::index_attributes()
+//
+
+#[test]
+#[cfg(feature = "misp")]
+fn index_attributes_allows_attributing_matches_to_attributes() {
+    let event = br#"{"Event": {"uuid": "e1", "Attribute": [
+        {"uuid": "a1", "type": "ssdeep", "value": "3:u+N:u+N"},
+        {"uuid": "a2", "type": "ssdeep", "value": "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C"}
+    ]}}"#;
+    let attributes = ssdeep::misp::parse_attributes(event).unwrap();
+    let mut index = ssdeep::index::FuzzyHashIndex::new();
+    let by_id = ssdeep::misp::index_attributes(attributes, &mut index).unwrap();
+    assert_eq!(index.len(), 2);
+
+    let matches = index
+        .search("3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx", 0)
+        .unwrap();
+    assert_eq!(matches.len(), 1);
+    let attribute = &by_id[&matches[0].id];
+    assert_eq!(attribute.uuid, "a2");
+    assert_eq!(attribute.event_uuid, Some("e1".to_string()));
+}
+
+#[test]
+#[cfg(feature = "misp")]
+fn index_attributes_returns_error_when_hash_is_invalid() {
+    let event =
+        br#"{"Event": {"Attribute": [{"uuid": "a1", "type": "ssdeep", "value": "invalid"}]}}"#;
+    let attributes = ssdeep::misp::parse_attributes(event).unwrap();
+    let mut index = ssdeep::index::FuzzyHashIndex::new();
+    assert!(matches!(
+        ssdeep::misp::index_attributes(attributes, &mut index),
+        Err(Error::InvalidHash { .. })
+    ));
+}
+
+//
+// misp::MispClient::fetch_ssdeep_attributes()
+//
+
+#[test]
+#[cfg(feature = "misp")]
+fn fetch_ssdeep_attributes_returns_attributes_from_misp_instance() {
+    let url = serve_once(
+        br#"{"response": {"Attribute": [{"uuid": "a1", "type": "ssdeep", "value": "3:aNRn:aNRn"}]}}"#,
+    );
+    let client = ssdeep::misp::MispClient::new(&url, "API-KEY");
+    let attributes = client.fetch_ssdeep_attributes().unwrap();
+    assert_eq!(attributes.len(), 1);
+    assert_eq!(attributes[0].hash, "3:aNRn:aNRn");
+}
+
+//
+// misp::MispClient::load_into()
+//
+
+#[test]
+#[cfg(feature = "misp")]
+fn load_into_adds_attributes_from_misp_instance_into_index() {
+    let url = serve_once(
+        br#"{"response": {"Attribute": [{"uuid": "a1", "type": "ssdeep", "value": "3:aNRn:aNRn"}]}}"#,
+    );
+    let client = ssdeep::misp::MispClient::new(&url, "API-KEY");
+    let mut index = ssdeep::index::FuzzyHashIndex::new();
+    let by_id = client.load_into(&mut index).unwrap();
+    let matches = index.search("3:aNRn:aNRn", 0).unwrap();
+    assert_eq!(by_id[&matches[0].id].uuid, "a1");
+}
+
+//
+// office::hash_document()
+//
+
+#[cfg(feature = "office")]
+fn create_ole2_document() -> Vec<u8> {
+    use std::io::Write;
+
+    let mut file = cfb::CompoundFile::create(std::io::Cursor::new(Vec::new())).unwrap();
+    file.create_stream("WordDocument")
+        .unwrap()
+        .write_all(b"Hello there!")
+        .unwrap();
+    file.create_stream("\x05SummaryInformation")
+        .unwrap()
+        .write_all(b"metadata")
+        .unwrap();
+    file.create_storage_all("Macros/VBA").unwrap();
+    file.create_stream("Macros/VBA/Module1")
+        .unwrap()
+        .write_all(b"Sub AutoOpen()")
+        .unwrap();
+    file.flush().unwrap();
+    file.into_inner().into_inner()
+}
+
+#[test]
+#[cfg(feature = "office")]
+fn hash_document_returns_hashes_of_ole2_streams_without_metadata() {
+    let document = create_ole2_document();
+    let streams = ssdeep::office::hash_document(std::io::Cursor::new(document)).unwrap();
+    let mut paths: Vec<_> = streams.iter().map(|s| s.path.as_str()).collect();
+    paths.sort();
+    assert_eq!(paths, vec!["Macros/VBA/Module1", "WordDocument"]);
+    let word_document = streams.iter().find(|s| s.path == "WordDocument").unwrap();
+    assert_eq!(word_document.size, 12);
+    assert_eq!(word_document.hash, "3:aNRn:aNRn");
+}
+
+#[test]
+#[cfg(feature = "office")]
+fn hash_document_returns_hashes_of_ooxml_parts_and_embedded_ole2_streams() {
+    use std::io::Write;
+
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default();
+    writer.start_file("docProps/core.xml", options).unwrap();
+    writer.write_all(b"<coreProperties/>").unwrap();
+    writer.start_file("word/document.xml", options).unwrap();
+    writer.write_all(b"Hello there!").unwrap();
+    writer.start_file("word/vbaProject.bin", options).unwrap();
+    writer.write_all(&create_ole2_document()).unwrap();
+    let document = writer.finish().unwrap().into_inner();
+
+    let streams = ssdeep::office::hash_document(std::io::Cursor::new(document)).unwrap();
+    let paths: Vec<_> = streams.iter().map(|s| s.path.as_str()).collect();
+    assert_eq!(paths[0], "word/document.xml");
+    assert_eq!(paths[1], "word/vbaProject.bin");
+    assert!(paths.contains(&"word/vbaProject.bin/Macros/VBA/Module1"));
+    assert!(!paths.contains(&"docProps/core.xml"));
+    assert_eq!(streams[0].hash, "3:aNRn:aNRn");
+}
+
+#[test]
+#[cfg(feature = "office")]
+fn hash_document_returns_error_when_format_is_not_supported() {
+    let result = ssdeep::office::hash_document(std::io::Cursor::new(b"Hello there!"));
+    assert_eq!(
+        result,
+        Err(Error::DocumentParsingFailed {
+            reason: "unsupported document format".to_string(),
+        })
+    );
+}
+
+//
+// sqlite::SqliteHashStore
+//
+
+#[test]
+#[cfg(feature = "sqlite")]
+fn sqlite_hash_store_search_returns_same_matches_as_comparing_all_hashes() {
+    use ssdeep::sqlite::SqliteHashStore;
+
+    let hashes = index_test_hashes();
+    let labels: Vec<String> = (0..hashes.len()).map(|i| format!("{:03}", i)).collect();
+    let mut store = SqliteHashStore::open_in_memory().unwrap();
+    store
+        .upsert_many(
+            labels
+                .iter()
+                .map(|l| l.as_str())
+                .zip(hashes.iter().map(|h| h.as_str())),
+        )
+        .unwrap();
+    assert_eq!(store.len().unwrap(), hashes.len());
+    for query in &hashes {
+        let mut expected: Vec<(String, u8)> = hashes
+            .iter()
+            .enumerate()
+            .map(|(i, h)| (labels[i].clone(), compare(query, h).unwrap()))
+            .filter(|&(_, score)| score > 0)
+            .collect();
+        expected.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let found: Vec<(String, u8)> = store
+            .search(query, 0)
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.label, m.score))
+            .collect();
+        assert_eq!(found, expected, "query {}", query);
+    }
+}
+
+#[test]
+#[cfg(feature = "sqlite")]
+fn sqlite_hash_store_persists_hashes_and_replaces_them_on_upsert() {
+    use ssdeep::sqlite::SqliteHashStore;
+
+    let path = std::env::temp_dir().join("ssdeep-rs-store.sqlite");
+    let _ = std::fs::remove_file(&path);
+    {
+        let mut store = SqliteHashStore::open(&path).unwrap();
+        assert!(store.is_empty().unwrap());
+        store
+            .upsert("a", "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C")
+            .unwrap();
+        store.upsert("b", "3:aNRn:aNRn").unwrap();
+        store.upsert("a", "3:u+N:u+N").unwrap();
+    }
+    let mut store = SqliteHashStore::open(&path).unwrap();
+    assert_eq!(store.len().unwrap(), 2);
+    assert_eq!(store.get("a").unwrap().as_deref(), Some("3:u+N:u+N"));
+    assert!(store
+        .search("3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx", 0)
+        .unwrap()
+        .is_empty());
+    assert_eq!(store.search("3:u+N:u+N", 0).unwrap()[0].label, "a");
+    assert!(store.remove("a").unwrap());
+    assert!(!store.remove("a").unwrap());
+    assert_eq!(store.get("a").unwrap(), None);
+    assert!(store.search("3:u+N:u+N", 0).unwrap().is_empty());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+#[cfg(feature = "sqlite")]
+fn sqlite_hash_store_stores_nothing_when_hash_is_invalid() {
+    use ssdeep::sqlite::SqliteHashStore;
+
+    let mut store = SqliteHashStore::open_in_memory().unwrap();
+    let result = store.upsert_many(vec![("a", "3:aNRn:aNRn"), ("b", "XYZ")]);
+    assert!(matches!(result, Err(Error::InvalidHash { .. })));
+    assert!(store.is_empty().unwrap());
+    assert!(matches!(
+        store.search("XYZ", 0),
+        Err(Error::InvalidHash { .. })
+    ));
+}
+
+//
+// vt::VtClient::enrich()
+//
+
+#[test]
+#[cfg(feature = "vt")]
+fn enrich_merges_similar_samples_sorted_by_score_into_record() {
+    let url = serve_once(
+        br#"{"data": [
+            {"id": "aa", "type": "file", "attributes": {"ssdeep": "3:u+N:u+N"}},
+            {"id": "bb", "type": "file", "attributes": {
+                "ssdeep": "3:aNRn:aNRn", "meaningful_name": "hello.txt",
+                "type_description": "Text", "size": 12,
+                "last_analysis_stats": {"malicious": 3, "undetected": 60}}}
+        ]}"#,
+    );
+    let client = ssdeep::vt::VtClient::with_base_url("API-KEY", &url);
+    let enriched = client.enrich("record", "3:aNRn:aNRn", 10).unwrap();
+    assert_eq!(enriched.record, "record");
+    assert_eq!(enriched.vt_samples.len(), 2);
+    assert_eq!(
+        enriched.vt_samples[0],
+        ssdeep::vt::VtSample {
+            sha256: "bb".to_string(),
+            meaningful_name: Some("hello.txt".to_string()),
+            type_description: Some("Text".to_string()),
+            size: Some(12),
+            malicious_detections: Some(3),
+            ssdeep: Some("3:aNRn:aNRn".to_string()),
+            score: Some(100),
+        }
+    );
+    assert_eq!(enriched.vt_samples[1].sha256, "aa");
+    assert_eq!(enriched.vt_samples[1].score, Some(0));
+}
+
+#[test]
+#[cfg(feature = "vt")]
+fn enrich_returns_error_when_response_is_invalid() {
+    let url = serve_once(b"{}");
+    let client = ssdeep::vt::VtClient::with_base_url("API-KEY", &url);
+    assert!(matches!(
+        client.enrich((), "3:aNRn:aNRn", 10),
+        Err(Error::HttpRequestFailed { .. })
+    ));
+}
+
+//
+// cluster::single_linkage()
+//
+
+#[test]
+fn single_linkage_returns_connected_components_of_similar_hashes() {
+    use ssdeep::cluster::single_linkage;
+
+    let hashes = index_test_hashes();
+    let clusters = single_linkage(&hashes, 30).unwrap();
+    let mut all: Vec<usize> = clusters.iter().flatten().cloned().collect();
+    all.sort_unstable();
+    assert_eq!(all, (0..hashes.len()).collect::<Vec<_>>());
+    let cluster_of = |i: usize| clusters.iter().position(|c| c.contains(&i)).unwrap();
+    for i in 0..hashes.len() {
+        for j in i + 1..hashes.len() {
+            if compare(&hashes[i], &hashes[j]).unwrap() > 30 {
+                assert_eq!(cluster_of(i), cluster_of(j));
+            }
+        }
+    }
+    for cluster in &clusters {
+        assert!(cluster.windows(2).all(|w| w[0] < w[1]));
+        if cluster.len() > 1 {
+            // Every hash of a cluster is similar to another hash of it.
+            for &i in cluster {
+                assert!(cluster
+                    .iter()
+                    .any(|&j| j != i && compare(&hashes[i], &hashes[j]).unwrap() > 30));
+            }
+        }
+    }
+    assert!(clusters.len() > 1 && clusters.len() < hashes.len());
+}
+
+#[test]
+fn single_linkage_links_hashes_via_chains_of_similar_hashes() {
+    use ssdeep::cluster::single_linkage;
+
+    let data = pseudo_random_bytes(60_000);
+    let hashes = vec![
+        hash(&data[..30_000]).unwrap(),
+        hash(&data[10_000..40_000]).unwrap(),
+        hash(&data[20_000..50_000]).unwrap(),
+    ];
+    assert_eq!(compare(&hashes[0], &hashes[2]).unwrap(), 0);
+    let clusters = single_linkage(&hashes, 0).unwrap();
+    assert_eq!(clusters, vec![vec![0, 1, 2]]);
+}
+
+#[test]
+fn single_linkage_returns_error_when_hash_is_invalid() {
+    use ssdeep::cluster::single_linkage;
+
+    assert!(matches!(
+        single_linkage(&["3:u+N:u+N", "XYZ"], 0),
+        Err(Error::InvalidHash { .. })
+    ));
+}
+
+#[test]
+fn single_linkage_with_progress_reports_comparisons_and_clustering() {
+    use ssdeep::cluster::{single_linkage, single_linkage_with_progress};
+
+    let hashes = index_test_hashes();
+    let n = hashes.len() as u64;
+    let mut reports = Vec::new();
+    let clusters =
+        single_linkage_with_progress(&hashes, 30, |progress| reports.push(progress.clone()))
+            .unwrap();
+    assert_eq!(clusters, single_linkage(&hashes, 30).unwrap());
+    assert_eq!(reports.len(), hashes.len() + 1);
+    let (comparing, clustering) = reports.split_at(hashes.len());
+    assert!(comparing.iter().all(|r| r.phase == Phase::Comparing));
+    assert!(comparing.windows(2).all(|w| w[0].done <= w[1].done));
+    assert_eq!(comparing.last().unwrap().done, n * (n - 1) / 2);
+    assert_eq!(
+        (clustering[0].phase, clustering[0].done, clustering[0].total),
+        (Phase::Clustering, n, Some(n))
+    );
+}
+
+//
+// cluster::dbscan()
+//
+
+#[test]
+fn dbscan_returns_dense_clusters_and_noise() {
+    use ssdeep::cluster::dbscan;
+
+    let hashes = index_test_hashes();
+    let result = dbscan(&hashes, 30, 2).unwrap();
+    let mut all: Vec<usize> = result.clusters.iter().flatten().cloned().collect();
+    all.extend(&result.noise);
+    all.sort_unstable();
+    assert_eq!(all, (0..hashes.len()).collect::<Vec<_>>());
+    assert!(!result.clusters.is_empty());
+    let neighbors = |i: usize| {
+        (0..hashes.len())
+            .filter(|&j| j != i && compare(&hashes[i], &hashes[j]).unwrap() >= 30)
+            .collect::<Vec<_>>()
+    };
+    for &i in &result.noise {
+        assert!(neighbors(i).len() < 2);
+        for j in neighbors(i) {
+            assert!(neighbors(j).len() < 2);
+        }
+    }
+    for cluster in &result.clusters {
+        assert!(cluster.windows(2).all(|w| w[0] < w[1]));
+        for &i in cluster {
+            if neighbors(i).len() >= 2 {
+                assert!(neighbors(i).iter().all(|j| cluster.contains(j)));
+            }
+        }
+    }
+}
+
+#[test]
+fn dbscan_requires_core_hashes_with_enough_neighbors() {
+    use ssdeep::cluster::dbscan;
+    use ssdeep::cluster::single_linkage;
+
+    let data = pseudo_random_bytes(60_000);
+    let hashes = vec![
+        hash(&data[..30_000]).unwrap(),
+        hash(&data[10_000..40_000]).unwrap(),
+        hash(&data[20_000..50_000]).unwrap(),
+    ];
+    assert_eq!(single_linkage(&hashes, 0).unwrap().len(), 1);
+    let result = dbscan(&hashes, 1, 2).unwrap();
+    assert_eq!(result.clusters, vec![vec![0, 1, 2]]);
+    let result = dbscan(&hashes, 1, 3).unwrap();
+    assert!(result.clusters.is_empty());
+    assert_eq!(result.noise, vec![0, 1, 2]);
+}
+
+#[test]
+fn dbscan_returns_error_when_hash_is_invalid() {
+    use ssdeep::cluster::dbscan;
+
+    assert!(matches!(
+        dbscan(&["3:u+N:u+N", "XYZ"], 0, 1),
+        Err(Error::InvalidHash { .. })
+    ));
+}
+
+#[test]
+fn dbscan_with_progress_reports_comparisons_and_clustering() {
+    use ssdeep::cluster::{dbscan, dbscan_with_progress};
+
+    let hashes = index_test_hashes();
+    let n = hashes.len() as u64;
+    let mut reports = Vec::new();
+    let result =
+        dbscan_with_progress(&hashes, 30, 2, |progress| reports.push(progress.clone())).unwrap();
+    assert_eq!(result, dbscan(&hashes, 30, 2).unwrap());
+    let (comparing, clustering) = reports.split_at(hashes.len());
+    assert!(comparing.iter().all(|r| r.phase == Phase::Comparing));
+    assert_eq!(comparing.last().unwrap().done, n * (n - 1) / 2);
+    // One report after each cluster and one at the end.
+    assert_eq!(clustering.len(), result.clusters.len() + 1);
+    assert!(clustering.iter().all(|r| r.phase == Phase::Clustering));
+    assert!(clustering.windows(2).all(|w| w[0].done <= w[1].done));
+    let last = clustering.last().unwrap();
+    assert_eq!((last.done, last.total), (n, Some(n)));
+}
+
+//
+// cluster::to_graph()
+//
+
+#[test]
+#[cfg(feature = "graph")]
+fn to_graph_returns_graph_of_hashes_with_weighted_edges() {
+    use ssdeep::cluster::{single_linkage, to_graph};
+
+    let hashes = index_test_hashes();
+    let graph = to_graph(&hashes, 30).unwrap();
+    assert_eq!(graph.node_count(), hashes.len());
+    for (i, h) in hashes.iter().enumerate() {
+        assert_eq!(&graph[petgraph::graph::NodeIndex::new(i)], h);
+    }
+    let mut edges: Vec<_> = graph
+        .raw_edges()
+        .iter()
+        .map(|e| (e.source().index(), e.target().index(), e.weight))
+        .collect();
+    edges.sort_unstable();
+    let expected: Vec<_> = ssdeep::matrix::pairs_above(&hashes, 30)
+        .unwrap()
+        .map(|p| (p.i, p.j, p.score))
+        .collect();
+    assert_eq!(edges, expected);
+    assert_eq!(
+        petgraph::algo::connected_components(&graph),
+        single_linkage(&hashes, 30).unwrap().len()
+    );
+}
+
+//
+// tracing
+//
+
+#[cfg(feature = "tracing")]
+struct RecordingSubscriber {
+    records: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    next_id: std::sync::atomic::AtomicU64,
+}
+
+#[cfg(feature = "tracing")]
+impl tracing::Subscriber for RecordingSubscriber {
+    fn enabled(&self, _: &tracing::Metadata) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &tracing::span::Attributes) -> tracing::span::Id {
+        let fields: Vec<_> = span.fields().iter().map(|f| f.name()).collect();
+        let record = format!("span {}({})", span.metadata().name(), fields.join(", "));
+        self.records.lock().unwrap().push(record);
+        let id = self
+            .next_id
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        tracing::span::Id::from_u64(id)
+    }
+
+    fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record) {}
+
+    fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+    fn event(&self, event: &tracing::Event) {
+        let fields: Vec<_> = event.fields().map(|f| f.name()).collect();
+        let record = format!("event ({})", fields.join(", "));
+        self.records.lock().unwrap().push(record);
+    }
+
+    fn enter(&self, _: &tracing::span::Id) {}
+
+    fn exit(&self, _: &tracing::span::Id) {}
+}
+
+#[test]
+#[cfg(feature = "tracing")]
+fn tracing_records_spans_of_hashing_comparisons_and_index_queries() {
+    use ssdeep::index::FuzzyHashIndex;
+
+    let records = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let subscriber = RecordingSubscriber {
+        records: records.clone(),
+        next_id: std::sync::atomic::AtomicU64::new(1),
+    };
+    tracing::subscriber::with_default(subscriber, || {
+        let h = hash(b"Hello there!").unwrap();
+        assert!(hash_from_file("tests/non-existing-file.txt").is_err());
+        compare_batch(&h, &[&h, &h]).unwrap();
+        let mut index = FuzzyHashIndex::new();
+        index.insert(&h).unwrap();
+        index.search(&h, 0).unwrap();
+    });
+    assert_eq!(
+        *records.lock().unwrap(),
+        [
+            "span hash(len)",
+            "span hash_from_file(path)",
+            "event (error)",
+            "span compare_batch(batch_size)",
+            "span search(threshold)",
+            "event (message, candidates)",
+            "event (message, matches)",
+        ]
+    );
+}
+
+//
+// progress_bar
+//
+
+#[test]
+#[cfg(feature = "indicatif")]
+fn progress_bar_reporter_reports_progress_to_bar() {
+    use ssdeep::progress_bar::ProgressBarReporter;
+
+    let reporter = ProgressBarReporter::new(indicatif::ProgressBar::hidden());
+    let mut callback = reporter.callback();
+    callback(&Progress {
+        phase: Phase::Hashing,
+        done: 1,
+        total: Some(2),
+        current_path: Some("tests/file.txt".into()),
+    });
+    let bar = reporter.bar();
+    assert_eq!((bar.position(), bar.length()), (1, Some(2)));
+    assert_eq!(bar.message(), "tests/file.txt");
+    assert!(!bar.is_finished());
+    callback(&Progress {
+        phase: Phase::Hashing,
+        done: 2,
+        total: Some(2),
+        current_path: None,
+    });
+    assert_eq!(bar.position(), 2);
+    assert_eq!(bar.message(), "tests/file.txt");
+    assert!(bar.is_finished());
+}
+
+//
+// matrix::similarity_matrix()
+//
+
+#[test]
+#[cfg(feature = "parallel")]
+fn similarity_matrix_returns_same_pairs_as_comparing_all_pairs() {
+    use ssdeep::matrix::similarity_matrix;
+
+    let hashes = index_test_hashes();
+    let mut expected = Vec::new();
+    for i in 0..hashes.len() {
+        for j in i + 1..hashes.len() {
+            let score = compare(&hashes[i], &hashes[j]).unwrap();
+            if score > 10 {
+                expected.push((i, j, score));
+            }
+        }
+    }
+    assert!(!expected.is_empty());
+    let pairs: Vec<_> = similarity_matrix(&hashes, 10)
+        .unwrap()
+        .into_iter()
+        .map(|p| (p.i, p.j, p.score))
+        .collect();
+    assert_eq!(pairs, expected);
+}
+
+#[test]
+#[cfg(feature = "parallel")]
+fn similarity_matrix_returns_error_when_hash_is_invalid() {
+    use ssdeep::matrix::similarity_matrix;
+
+    assert!(matches!(
+        similarity_matrix(&["3:u+N:u+N", "XYZ"], 0),
+        Err(Error::InvalidHash { .. })
+    ));
+}
+
+#[test]
+#[cfg(feature = "parallel")]
+fn similarity_matrix_with_progress_reports_comparisons_of_each_row() {
+    use ssdeep::matrix::similarity_matrix_with_progress;
+
+    let hashes = index_test_hashes();
+    let n = hashes.len() as u64;
+    let mut reports = Vec::new();
+    let pairs =
+        similarity_matrix_with_progress(&hashes, 0, |progress| reports.push(progress.clone()))
+            .unwrap();
+    assert_eq!(
+        pairs,
+        ssdeep::matrix::similarity_matrix(&hashes, 0).unwrap()
+    );
+    assert_eq!(reports.len(), hashes.len());
+    assert!(reports.windows(2).all(|w| w[0].done <= w[1].done));
+    let last = reports.last().unwrap();
+    assert_eq!(last.phase, Phase::Comparing);
+    assert_eq!(last.done, n * (n - 1) / 2);
+    assert_eq!(last.total, Some(n * (n - 1) / 2));
+}
+
+//
+// matrix::pairs_above()
+//
+
+#[test]
+fn pairs_above_returns_same_pairs_as_comparing_all_pairs() {
+    use ssdeep::matrix::pairs_above;
+
+    let hashes = index_test_hashes();
+    let mut expected = Vec::new();
+    for i in 0..hashes.len() {
+        for j in i + 1..hashes.len() {
+            let score = compare(&hashes[i], &hashes[j]).unwrap();
+            if score > 10 {
+                expected.push((i, j, score));
+            }
+        }
+    }
+    assert!(!expected.is_empty());
+    let pairs: Vec<_> = pairs_above(&hashes, 10)
+        .unwrap()
+        .map(|p| (p.i, p.j, p.score))
+        .collect();
+    assert_eq!(pairs, expected);
+}
+
+#[test]
+fn pairs_above_returns_no_pairs_for_no_hashes() {
+    use ssdeep::matrix::pairs_above;
+
+    let hashes: [&str; 0] = [];
+    assert_eq!(pairs_above(&hashes, 0).unwrap().count(), 0);
+}
+
+#[test]
+fn pairs_above_returns_error_when_hash_is_invalid() {
+    use ssdeep::matrix::pairs_above;
+
+    assert!(matches!(
+        pairs_above(&["3:u+N:u+N", "XYZ"], 0),
+        Err(Error::InvalidHash { .. })
+    ));
+}
+
+#[test]
+fn pairs_above_with_progress_reports_comparisons_of_each_row_lazily() {
+    use ssdeep::matrix::{pairs_above, pairs_above_with_progress};
+
+    let hashes = index_test_hashes();
+    let n = hashes.len() as u64;
+    let mut reports = Vec::new();
+    let pairs: Vec<_> =
+        pairs_above_with_progress(&hashes, 10, |progress| reports.push(progress.clone()))
+            .unwrap()
+            .collect();
+    assert_eq!(pairs, pairs_above(&hashes, 10).unwrap().collect::<Vec<_>>());
+    assert_eq!(reports.len(), hashes.len());
+    assert!(reports.iter().all(|r| r.phase == Phase::Comparing));
+    assert!(reports.windows(2).all(|w| w[0].done <= w[1].done));
+    let last = reports.last().unwrap();
+    assert_eq!(
+        (last.done, last.total),
+        (n * (n - 1) / 2, Some(n * (n - 1) / 2))
+    );
+
+    let mut reports = 0;
+    let _pairs = pairs_above_with_progress(&hashes, 10, |_| reports += 1).unwrap();
+    assert_eq!(reports, 0);
+}
+
+//
+// matrix::pairs_above_out_of_core()
+//
+
+#[test]
+fn pairs_above_out_of_core_returns_same_pairs_as_comparing_all_pairs() {
+    use ssdeep::matrix::{pairs_above_out_of_core, OutOfCoreOptions};
+
+    let hashes = index_test_hashes();
+    let mut expected = Vec::new();
+    for i in 0..hashes.len() {
+        for j in i + 1..hashes.len() {
+            let score = compare(&hashes[i], &hashes[j]).unwrap();
+            if score > 10 {
+                expected.push((i, j, score));
+            }
+        }
+    }
+    assert!(!expected.is_empty());
+    let dir = std::env::temp_dir().join("ssdeep-rs-pairs-out-of-core");
+    std::fs::create_dir_all(&dir).unwrap();
+    let options = OutOfCoreOptions {
+        temp_dir: dir.clone(),
+        tile_size: 3,
+    };
+    let mut pairs = Vec::new();
+    pairs_above_out_of_core(&hashes, 10, &options, |p| {
+        pairs.push((p.i, p.j, p.score));
+        Ok(())
+    })
+    .unwrap();
+    pairs.sort_unstable();
+    assert_eq!(pairs, expected);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+}
+
+#[test]
+fn pairs_above_out_of_core_returns_error_returned_by_callback() {
+    use ssdeep::matrix::{pairs_above_out_of_core, OutOfCoreOptions};
+
+    let hashes = ["3:u+N:u+N", "3:u+N:u+N"];
+    let result = pairs_above_out_of_core(hashes, 0, &OutOfCoreOptions::default(), |_| {
+        Err(Error::InvalidHash {
+            reason: "test".to_string(),
+        })
+    });
+    assert!(matches!(result, Err(Error::InvalidHash { .. })));
+}
+
+#[test]
+fn pairs_above_out_of_core_returns_error_when_hash_is_invalid() {
+    use ssdeep::matrix::{pairs_above_out_of_core, OutOfCoreOptions};
+
+    let result = pairs_above_out_of_core(
+        ["3:u+N:u+N", "XYZ"],
+        0,
+        &OutOfCoreOptions::default(),
+        |_| Ok(()),
+    );
+    assert!(matches!(result, Err(Error::InvalidHash { .. })));
+}
+
+#[test]
+fn pairs_above_out_of_core_with_progress_reports_comparisons_of_compared_buckets() {
+    use ssdeep::matrix::{pairs_above_out_of_core_with_progress, OutOfCoreOptions};
+
+    let hashes = index_test_hashes();
+    let block_size = |hash: &String| hash.split(':').next().unwrap().parse::<u64>().unwrap();
+    let mut expected_total = 0;
+    for i in 0..hashes.len() {
+        for j in i + 1..hashes.len() {
+            let (b1, b2) = (block_size(&hashes[i]), block_size(&hashes[j]));
+            if b1 == b2 || b1 == 2 * b2 || b2 == 2 * b1 {
+                expected_total += 1;
+            }
+        }
+    }
+    let options = OutOfCoreOptions {
+        tile_size: 3,
+        ..OutOfCoreOptions::default()
+    };
+    let mut reports = Vec::new();
+    pairs_above_out_of_core_with_progress(
+        &hashes,
+        10,
+        &options,
+        |_| Ok(()),
+        |progress| reports.push(progress.clone()),
+    )
+    .unwrap();
+    assert!(reports.len() > 1);
+    assert!(reports.iter().all(|r| r.phase == Phase::Comparing));
+    assert!(reports.windows(2).all(|w| w[0].done <= w[1].done));
+    let last = reports.last().unwrap();
+    assert_eq!(
+        (last.done, last.total),
+        (expected_total, Some(expected_total))
+    );
+}
+
+//
+// matrix::join_above()
+//
+
+#[test]
+fn join_above_returns_same_pairs_as_comparing_every_left_and_right_hash() {
+    use ssdeep::matrix::join_above;
+
+    let hashes = index_test_hashes();
+    let (left, right) = (&hashes[..30], &hashes[20..]);
+    let mut expected = Vec::new();
+    for (i, left_hash) in left.iter().enumerate() {
+        for (j, right_hash) in right.iter().enumerate() {
+            let score = compare(left_hash, right_hash).unwrap();
+            if score > 10 {
+                expected.push((i, j, score));
+            }
+        }
+    }
+    assert!(!expected.is_empty());
+    let pairs: Vec<_> = join_above(left, right, 10)
+        .unwrap()
+        .pairs
+        .into_iter()
+        .map(|p| (p.left, p.right, p.score))
+        .collect();
+    assert_eq!(pairs, expected);
+}
+
+#[test]
+fn join_above_compares_every_unordered_pair_of_distinct_hashes_once() {
+    use ssdeep::matrix::{join_above, ComparisonStats};
+    use std::collections::HashSet;
+
+    let hashes = index_test_hashes();
+    let n = hashes.len() as u64;
+    let distinct = hashes.iter().collect::<HashSet<_>>().len() as u64;
+    let stats = join_above(&hashes, &hashes, 0).unwrap().stats;
+    assert_eq!(stats.pairs, n * n);
+    assert_eq!(stats.comparisons, distinct * (distinct - 1) / 2);
+    assert_eq!(
+        stats.pairs,
+        stats.comparisons + stats.self_pairs + stats.cached_pairs
+    );
+    if distinct == n {
+        assert_eq!(stats, ComparisonStats::all_pairs(n));
+    }
+}
+
+#[test]
+fn join_above_does_not_compare_identical_hashes() {
+    use ssdeep::matrix::join_above;
+
+    let join = join_above(&["3:u+N:u+N", "3:u+N:u+N,file.txt"], &["3:u+N:u+N"], 99).unwrap();
+    let pairs: Vec<_> = join
+        .pairs
+        .into_iter()
+        .map(|p| (p.left, p.right, p.score))
+        .collect();
+    assert_eq!(pairs, [(0, 0, 100), (1, 0, 100)]);
+    assert_eq!(join.stats.comparisons, 0);
+    assert_eq!(join.stats.self_pairs, 2);
+}
+
+#[test]
+fn join_above_returns_error_when_hash_is_invalid() {
+    use ssdeep::matrix::join_above;
+
+    assert!(matches!(
+        join_above(&["3:u+N:u+N"], &["XYZ"], 0),
+        Err(Error::InvalidHash { .. })
+    ));
+}
+
+//
+// pipeline
+//
+
+#[test]
+#[cfg(feature = "pipeline")]
+fn pipeline_stages_hash_and_match_all_files() {
+    use ssdeep::pipeline::{hash_pool, match_hashes, read_files, sink};
+
+    let mut batch = HashBatch::new();
+    batch.push("3:aNRn:aNRn").unwrap();
+    let dir = std::env::temp_dir().join("ssdeep-rs-pipeline");
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut paths = Vec::new();
+    for i in 0..10 {
+        let path = dir.join(format!("{}.txt", i));
+        let contents: &[u8] = if i % 2 == 0 { b"Hello there!" } else { b"Bye" };
+        std::fs::write(&path, contents).unwrap();
+        paths.push(path);
+    }
+    paths.push(dir.join("nonexistent.txt"));
+
+    let files = read_files(paths, 2);
+    let hashes = hash_pool(files, 3, 2);
+    let matches = match_hashes(hashes, std::sync::Arc::new(batch), 50, 2);
+    let mut matched = 0;
+    let mut failed = 0;
+    let mut total = 0;
+    sink(matches, |item| {
+        total += 1;
+        match item.result {
+            Ok(file_matches) if !file_matches.matches.is_empty() => {
+                assert_eq!(file_matches.matches, vec![(0, 100)]);
+                matched += 1;
+            }
+            Ok(_) => {}
+            Err(_) => failed += 1,
+        }
+    });
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(total, 11);
+    assert_eq!(matched, 5);
+    assert_eq!(failed, 1);
+}
+
+//
+// pipeline::IngestQueue
+//
+
+#[test]
+#[cfg(feature = "pipeline")]
+fn ingest_queue_sheds_oldest_job_when_full_and_policy_is_shed_oldest() {
+    use ssdeep::pipeline::{IngestQueue, OverflowPolicy};
+
+    let queue = IngestQueue::new(2, OverflowPolicy::ShedOldest);
+    assert_eq!(queue.submit(1), None);
+    assert_eq!(queue.submit(2), None);
+    assert_eq!(queue.submit(3), Some(1));
+    assert_eq!(queue.try_take(), Some(2));
+    assert_eq!(queue.try_take(), Some(3));
+    assert_eq!(queue.try_take(), None);
+}
+
+#[test]
+#[cfg(feature = "pipeline")]
+fn ingest_queue_sheds_oldest_job_for_every_submission_of_contending_producers() {
+    use ssdeep::pipeline::{IngestQueue, OverflowPolicy};
+
+    let queue = IngestQueue::new(1, OverflowPolicy::ShedOldest);
+    queue.submit(0);
+    let producers: Vec<_> = (0..8)
+        .map(|p| {
+            let queue = queue.clone();
+            std::thread::spawn(move || {
+                (0..100_000)
+                    .filter(|i| queue.submit(1 + p * 100_000 + i).is_some())
+                    .count()
+            })
+        })
+        .collect();
+    let shed: usize = producers.into_iter().map(|p| p.join().unwrap()).sum();
+    // Without consumers, every submission into the full queue sheds a job.
+    assert_eq!(shed, 800_000);
+    assert_eq!(queue.depth(), 1);
+}
+
+#[test]
+#[cfg(feature = "pipeline")]
+fn ingest_queue_can_be_cloned_when_jobs_are_not_clonable() {
+    use ssdeep::pipeline::{IngestQueue, OverflowPolicy};
+
+    struct Job(u32);
+
+    let queue = IngestQueue::new(2, OverflowPolicy::ShedNewest);
+    let clone = queue.clone();
+    assert!(clone.submit(Job(1)).is_none());
+    assert_eq!(queue.depth(), 1);
+    assert_eq!(queue.take().map(|job| job.0), Some(1));
+}
+
+#[test]
+#[cfg(feature = "pipeline")]
+fn ingest_queue_sheds_job_after_timeout_when_policy_is_park_with_timeout() {
+    use ssdeep::pipeline::{IngestQueue, OverflowPolicy};
+
+    let queue = IngestQueue::new(
+        1,
+        OverflowPolicy::ParkWithTimeout(std::time::Duration::from_millis(10)),
+    );
+    assert_eq!(queue.submit(1), None);
+    assert_eq!(queue.submit(2), Some(2));
+    assert_eq!(queue.capacity(), 1);
+}
+
+#[test]
+#[cfg(feature = "pipeline")]
+fn ingest_queue_parks_producer_until_consumer_takes_job_when_policy_is_park() {
+    use ssdeep::pipeline::{IngestQueue, OverflowPolicy};
+
+    let queue = IngestQueue::new(1, OverflowPolicy::Park);
+    let consumer = queue.clone();
+    let handle = std::thread::spawn(move || (0..3).map(|_| consumer.take().unwrap()).collect());
+    for job in 0..3 {
+        assert_eq!(queue.submit(job), None);
+    }
+    let jobs: Vec<i32> = handle.join().unwrap();
+    assert_eq!(jobs, vec![0, 1, 2]);
+}
+
+#[test]
+#[cfg(feature = "pipeline")]
+fn ingest_queue_reports_depth_via_hook() {
+    use ssdeep::pipeline::{IngestQueue, OverflowPolicy};
+    use std::sync::{Arc, Mutex};
+
+    let depths = Arc::new(Mutex::new(Vec::new()));
+    let hook_depths = depths.clone();
+    let queue = IngestQueue::new(2, OverflowPolicy::ShedNewest)
+        .with_depth_hook(move |depth| hook_depths.lock().unwrap().push(depth));
+    queue.submit("a");
+    queue.submit("b");
+    queue.submit("c");
+    queue.take();
+    assert_eq!(*depths.lock().unwrap(), vec![1, 2, 2, 1]);
+}
+
+//
+// pipeline::report_progress()
+//
+
+#[test]
+#[cfg(feature = "pipeline")]
+fn report_progress_reports_items_and_passes_them_through() {
+    use ssdeep::pipeline::{hash_pool, read_files, report_progress, sink};
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let files = read_files(vec!["tests/file.txt".into(), "tests/file.txt".into()], 1);
+    let hashes = report_progress(
+        hash_pool(files, 1, 1),
+        Phase::Hashing,
+        Some(2),
+        move |progress| sender.send(progress.clone()).unwrap(),
+        1,
+    );
+    let mut items = 0;
+    sink(hashes, |item| {
+        assert!(item.result.is_ok());
+        items += 1;
+    });
+    assert_eq!(items, 2);
+    let reports: Vec<_> = receiver
+        .iter()
+        .map(|p| (p.phase, p.done, p.total))
+        .collect();
+    assert_eq!(
+        reports,
+        vec![(Phase::Hashing, 1, Some(2)), (Phase::Hashing, 2, Some(2))]
+    );
+}
+
+//
+// tuning
+//
+
+#[test]
+#[cfg(feature = "pipeline")]
+fn tuning_profile_uses_threads_needed_to_keep_up_with_disk() {
+    use ssdeep::tuning::TuningProfile;
+
+    let profile = TuningProfile::from_measurements(500e6, 100e6, 16);
+    assert_eq!(profile.threads, 5);
+    assert_eq!(profile.capacity, 10);
+    assert_eq!(TuningProfile::from_measurements(500e6, 100e6, 2).threads, 2);
+    assert_eq!(TuningProfile::from_measurements(10e6, 100e6, 16).threads, 1);
+}
+
+#[test]
+#[cfg(feature = "pipeline")]
+fn tuning_profile_can_be_saved_and_loaded() {
+    use ssdeep::tuning::{calibrate, TuningProfile};
+
+    let profile = calibrate("tests").unwrap();
+    assert!(profile.threads >= 1 && profile.threads <= profile.cores);
+    let file_path = std::env::temp_dir().join("ssdeep-rs-tuning.txt");
+    profile.save(&file_path).unwrap();
+    let loaded = TuningProfile::load(&file_path);
+    std::fs::remove_file(&file_path).unwrap();
+    assert_eq!(loaded, Ok(profile));
+}
+
+#[test]
+#[cfg(feature = "pipeline")]
+fn tuning_profile_parse_returns_error_when_value_is_missing() {
+    use ssdeep::tuning::TuningProfile;
+
+    assert!(TuningProfile::parse("threads = 4\n").is_err());
+}
+
+//
+// sandbox
+//
+
+#[cfg(all(target_os = "linux", feature = "sandbox"))]
+fn sandbox_options() -> ssdeep::sandbox::SandboxOptions {
+    ssdeep::sandbox::SandboxOptions {
+        worker: env!("CARGO_BIN_EXE_ssdeep-sandbox-worker").into(),
+        ..Default::default()
+    }
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "sandbox"))]
+fn hash_in_sandbox_returns_same_hash_as_hash() {
+    use ssdeep::sandbox::{hash_file_in_sandbox, hash_in_sandbox, Operation};
+
+    let options = sandbox_options();
+    let h = hash_in_sandbox(b"Hello there!", Operation::Hash, &options);
+    assert_eq!(h, Ok("3:aNRn:aNRn".to_string()));
+    let h = hash_file_in_sandbox("tests/file.txt", Operation::Hash, &options);
+    assert_eq!(h, hash_from_file("tests/file.txt"));
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "sandbox", feature = "decompress"))]
+fn hash_in_sandbox_hashes_decompressed_data() {
+    use ssdeep::sandbox::{hash_in_sandbox, Operation};
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(b"Hello there!").unwrap();
+    let compressed = encoder.finish().unwrap();
+    let h = hash_in_sandbox(&compressed, Operation::HashDecompressed, &sandbox_options());
+    assert_eq!(h, Ok("3:aNRn:aNRn".to_string()));
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "sandbox"))]
+fn hash_in_sandbox_returns_error_when_worker_exceeds_memory_limit() {
+    use ssdeep::sandbox::{hash_in_sandbox, Operation, SandboxOptions};
+
+    let options = SandboxOptions {
+        memory_limit: 64 * 1024 * 1024,
+        ..sandbox_options()
+    };
+    let data = vec![0; 128 * 1024 * 1024];
+    let h = hash_in_sandbox(&data, Operation::Hash, &options);
+    assert!(matches!(h, Err(Error::SandboxFailed { .. })));
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "sandbox"))]
+fn hash_in_sandbox_returns_error_when_worker_does_not_exist() {
+    use ssdeep::sandbox::{hash_in_sandbox, Operation, SandboxOptions};
+
+    let options = SandboxOptions {
+        worker: "/nonexistent/ssdeep-sandbox-worker".into(),
+        ..Default::default()
+    };
+    let h = hash_in_sandbox(b"Hello there!", Operation::Hash, &options);
+    assert!(matches!(h, Err(Error::Io(_))));
+}
+
+//
+// sarif::to_sarif()
+//
+
+#[test]
+#[cfg(feature = "sarif")]
+fn to_sarif_reports_bad_and_similar_to_bad_files_only() {
+    use ssdeep::classify::{Classification, Reference};
+    use ssdeep::sarif::{to_sarif, ScanResult};
+
+    let reference = Reference {
+        name: "dropper".to_string(),
+        hash: "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C".to_string(),
+    };
+    let result = |path: &str, classification| ScanResult {
+        path: path.to_string(),
+        hash: "3:aNRn:aNRn".to_string(),
+        classification,
+    };
+    let results = vec![
+        result(
+            "bin\\bad file.exe",
+            Classification::KnownBad {
+                reference: reference.clone(),
+                score: 100,
+            },
+        ),
+        result(
+            "lib/similar.dll",
+            Classification::SimilarToBad {
+                reference: reference.clone(),
+                score: 55,
+            },
+        ),
+        result(
+            "lib/good.dll",
+            Classification::KnownGood {
+                reference: reference.clone(),
+                score: 100,
+            },
+        ),
+        result("lib/unknown.dll", Classification::Unknown),
+    ];
+    let sarif: serde_json::Value = serde_json::from_str(&to_sarif(&results)).unwrap();
+    assert_eq!(sarif["version"], "2.1.0");
+    let run = &sarif["runs"][0];
+    assert_eq!(run["tool"]["driver"]["name"], "ssdeep-rs");
+    let findings = run["results"].as_array().unwrap();
+    assert_eq!(findings.len(), 2);
+    assert_eq!(findings[0]["ruleId"], "known-bad");
+    assert_eq!(findings[0]["level"], "error");
+    assert_eq!(
+        findings[0]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+        "bin/bad%20file.exe"
+    );
+    assert_eq!(findings[0]["properties"]["reference"], "dropper");
+    assert_eq!(findings[1]["ruleId"], "similar-to-bad");
+    assert_eq!(findings[1]["level"], "warning");
+    assert_eq!(findings[1]["properties"]["score"], 55);
+}
+
+//
+// ads::hash_alternate_data_streams()
+//
+
+#[test]
+#[cfg(windows)]
+fn hash_alternate_data_streams_returns_hashes_of_named_streams() {
+    let file_path = std::env::temp_dir().join("ssdeep-rs-ads.txt");
+    std::fs::write(&file_path, b"main stream").unwrap();
+    let mut stream_path = file_path.clone().into_os_string();
+    stream_path.push(":hidden");
+    std::fs::write(&stream_path, b"Hello there!").unwrap();
+    let hashes = ssdeep::ads::hash_alternate_data_streams(&file_path);
+    std::fs::remove_file(&file_path).unwrap();
+    assert_eq!(
+        hashes,
+        Ok(vec![ssdeep::ads::StreamHash {
+            path: stream_path.into(),
+            stream_name: "hidden".to_string(),
+            size: 12,
+            hash: "3:aNRn:aNRn".to_string(),
+        }])
+    );
+}
+
+//
+// fast_scan::enumerate_files()
+//
+
+#[test]
+#[cfg(all(windows, feature = "fast-scan"))]
+fn enumerate_files_returns_error_when_volume_does_not_exist() {
+    let files = ssdeep::fast_scan::enumerate_files("ZZ:");
+
+    assert!(matches!(files, Err(Error::Io(_))));
+}
+
+//
+// fanotify::Monitor
+//
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "fanotify"))]
+fn monitor_reports_verdicts_about_written_files() {
+    use ssdeep::classify::{Classification, Classifier};
+    use ssdeep::fanotify::{Monitor, Trigger};
+
+    let mut classifier = Classifier::new();
+    classifier.add_known_good("hello", "3:aNRn:aNRn").unwrap();
+    let mut monitor = match Monitor::new(classifier) {
+        Ok(monitor) => monitor,
+        // The fanotify API requires privileges that may not be available.
+        Err(Error::Io(_)) => return,
+        Err(e) => panic!("unexpected error: {}", e),
+    };
+    let dir = std::env::temp_dir().join("ssdeep-rs-fanotify");
+    std::fs::create_dir_all(&dir).unwrap();
+    monitor.watch_path(&dir).unwrap();
+    let file_path = dir.join("file.txt");
+    let writer = std::thread::spawn({
+        let file_path = file_path.clone();
+        move || {
+            std::process::Command::new("sh")
+                .arg("-c")
+                .arg("printf 'Hello there!' > \"$0\"")
+                .arg(&file_path)
+                .status()
+                .unwrap()
+        }
+    });
+    let mut verdict = None;
+    monitor
+        .run(|event| {
+            if event.trigger == Trigger::CloseWrite {
+                verdict = Some((event.path, event.verdict));
+                return false;
+            }
+            true
+        })
+        .unwrap();
+    writer.join().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let (path, verdict) = verdict.unwrap();
+    let verdict = verdict.unwrap();
+    assert_eq!(path, file_path);
+    assert_eq!(verdict.hash, "3:aNRn:aNRn");
+    assert!(matches!(
+        verdict.classification,
+        Classification::KnownGood { score: 100, .. }
+    ));
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "fanotify"))]
+fn monitor_does_not_hash_fifos() {
+    use ssdeep::classify::Classifier;
+    use ssdeep::fanotify::{Monitor, Trigger};
+
+    let mut monitor = match Monitor::new(Classifier::new()) {
+        Ok(monitor) => monitor,
+        // The fanotify API requires privileges that may not be available.
+        Err(Error::Io(_)) => return,
+        Err(e) => panic!("unexpected error: {}", e),
+    };
+    let dir = std::env::temp_dir().join("ssdeep-rs-fanotify-fifo");
+    std::fs::create_dir_all(&dir).unwrap();
+    let fifo_path = dir.join("fifo");
+    let file_path = dir.join("file.txt");
+    let status = std::process::Command::new("mkfifo")
+        .arg(&fifo_path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    monitor.watch_path(&dir).unwrap();
+    // Keep the FIFO open for writing (so reading it would block) and then
+    // write a regular file, whose event has to be reached.
+    let mut opener = std::process::Command::new("sh")
+        .arg("-c")
+        .arg("exec 3<>\"$0\"; printf 'Hello there!' > \"$1\"; sleep 30")
+        .arg(&fifo_path)
+        .arg(&file_path)
+        .spawn()
+        .unwrap();
+    let mut fifo_verdict = None;
+    monitor
+        .run(|event| {
+            if event.path == fifo_path {
+                fifo_verdict = Some(event.verdict);
+                return true;
+            }
+            !(event.path == file_path && event.trigger == Trigger::CloseWrite)
+        })
+        .unwrap();
+    opener.kill().unwrap();
+    opener.wait().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // Recent kernels do not report accesses to FIFOs at all.
+    match fifo_verdict {
+        None => {}
+        Some(Err(Error::Io(e))) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput),
+        Some(r) => panic!("unexpected verdict: {:?}", r),
+    }
+}
+
+//
+// serde
+//
+
+#[test]
+#[cfg(feature = "serde")]
+fn fuzzy_hash_is_serialized_as_string() {
+    let h: FuzzyHash = "3:aNRn:aNRn".parse().unwrap();
+    assert_eq!(serde_json::to_string(&h).unwrap(), "\"3:aNRn:aNRn\"");
+    let deserialized: FuzzyHash = serde_json::from_str("\"3:aNRn:aNRn\"").unwrap();
+    assert_eq!(deserialized, h);
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn fuzzy_hash_deserialization_fails_when_hash_is_invalid() {
+    let result = serde_json::from_str::<FuzzyHash>("\"3:aNRn\"");
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("missing second part"));
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn report_round_trips_through_json() {
+    use ssdeep::classify::{Classification, Reference, ScanResult};
+    use ssdeep::report::{Match, Report};
+
+    let mut report = Report::new("Scan");
+    report.scan_results.push(ScanResult {
+        path: "a.exe".to_string(),
+        hash: "3:aNRn:aNRn".to_string(),
+        classification: Classification::KnownBad {
+            reference: Reference {
+                name: "bad.exe".to_string(),
+                hash: "3:aNRn:aNRn".to_string(),
+            },
+            score: 100,
+        },
+    });
+    report.matches.push(Match {
+        name1: "a.exe".to_string(),
+        hash1: "3:AXGBicFlgVNhBGcL6wCrFQEv:AXGHsNhxLsr2C".to_string(),
+        name2: "b.exe".to_string(),
+        hash2: "3:AXGBicFlIHBGcL6wCrFQEv:AXGH6xLsr2Cx".to_string(),
+        score: 22,
+    });
+    let json = serde_json::to_string(&report).unwrap();
+    assert_eq!(serde_json::from_str::<Report>(&json).unwrap(), report);
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn composite_score_is_serialized_with_its_breakdown() {
+    let input1 = CompositeInput {
+        hash: "3:aNRn:aNRn",
+        size: 12,
+        digest: None,
+    };
+    let score = CompositeScorer::default().score(&input1, &input1).unwrap();
+    let json: serde_json::Value = serde_json::to_value(score).unwrap();
+    assert_eq!(json["confidence"], 100);
+    assert_eq!(json["ssdeep_score"], 100);
+    assert_eq!(json["digest_match"], serde_json::Value::Null);
+}